@@ -0,0 +1,57 @@
+#![no_main]
+
+// Decodes arbitrary bytes as a small frame. Decoding has to either succeed with a complete frame or
+// fail cleanly, without panicking, looping unboundedly, or allocating more than
+// WIDTH * HEIGHT * 4 * 2 bytes at once.
+
+use hello_video_codec::{codec::Codec, frame::RGB48Frame};
+use libfuzzer_sys::fuzz_target;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+const WIDTH: usize = 16;
+const HEIGHT: usize = 12;
+
+// Tracks the most memory in use at once since the last reset.
+struct PeakAlloc {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let current = self.current.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        self.peak.fetch_max(current, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.current.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: PeakAlloc = PeakAlloc {
+    current: AtomicUsize::new(0),
+    peak: AtomicUsize::new(0),
+};
+
+fuzz_target!(|data: &[u8]| {
+    let baseline = ALLOC.current.load(Ordering::SeqCst);
+    ALLOC.peak.store(baseline, Ordering::SeqCst);
+
+    if let Ok(frame) = RGB48Frame::decode::<Codec, _>(data, WIDTH, HEIGHT) {
+        assert_eq!((frame.width, frame.height), (WIDTH, HEIGHT));
+        assert_eq!(frame.data.len(), WIDTH * HEIGHT * 3);
+    }
+
+    let used = ALLOC.peak.load(Ordering::SeqCst) - baseline;
+    assert!(
+        used <= WIDTH * HEIGHT * 4 * 2,
+        "decoding allocated {} bytes",
+        used
+    );
+});
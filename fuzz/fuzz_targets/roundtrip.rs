@@ -0,0 +1,70 @@
+#![no_main]
+
+// Encodes arbitrary plane data and checks that it decodes back exactly, and that quantized encoding
+// reports the distortion that decoding actually produces.
+
+use hello_video_codec::{
+    codec::Codec,
+    frame::{Codec as _, Plane},
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let width = 1 + data[0] as usize % 64;
+    let q = 1 + data[1] as u16 % 64;
+    let samples: Vec<u16> = data[2..]
+        .chunks_exact(2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    let height = samples.len() / width;
+    let samples = &samples[..width * height];
+    let plane = Plane {
+        data: samples,
+        width,
+        height,
+        row_stride: width,
+        sample_stride: 1,
+    };
+
+    let mut encoded = Vec::new();
+    Codec::encode(&plane, &mut encoded).unwrap();
+    let mut decoded = vec![0; samples.len()];
+    let mut source = &*encoded;
+    Codec::decode(
+        &mut source,
+        &mut Plane {
+            data: &mut decoded[..],
+            width,
+            height,
+            row_stride: width,
+            sample_stride: 1,
+        },
+    )
+    .unwrap();
+    assert!(source.is_empty());
+    assert_eq!(decoded, samples);
+
+    let mut encoded = Vec::new();
+    let squared_error = Codec::encode_quantized(&plane, q, &mut encoded).unwrap();
+    Codec::decode_quantized(
+        &*encoded,
+        &mut Plane {
+            data: &mut decoded[..],
+            width,
+            height,
+            row_stride: width,
+            sample_stride: 1,
+        },
+        q,
+    )
+    .unwrap();
+    let actual: u64 = decoded
+        .iter()
+        .zip(samples)
+        .map(|(&a, &b)| (a as i64 - b as i64).pow(2) as u64)
+        .sum();
+    assert_eq!(actual, squared_error);
+});
@@ -0,0 +1,50 @@
+#ifndef HVC_H
+#define HVC_H
+
+/* This file is generated by cbindgen from src/ffi.rs. Don't edit it by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+enum HvcError {
+  HVC_ERROR_OK = 0,
+  HVC_ERROR_IO = 1,
+  HVC_ERROR_TRUNCATED_STREAM = 2,
+  HVC_ERROR_INVALID_HEADER = 3,
+  HVC_ERROR_CHECKSUM_MISMATCH = 4,
+  HVC_ERROR_UNSUPPORTED_VERSION = 5,
+  HVC_ERROR_CANCELLED = 6,
+  HVC_ERROR_INVALID_DATA = 7,
+  HVC_ERROR_VERIFICATION_FAILED = 8,
+  HVC_ERROR_CONTENT_HASH_MISMATCH = 9,
+  HVC_ERROR_LIMIT_EXCEEDED = 10,
+  HVC_ERROR_INVALID_INPUT = 11,
+  HVC_ERROR_NULL_POINTER = 12,
+  HVC_ERROR_BUFFER_TOO_SMALL = 13,
+  HVC_ERROR_PANIC = 14,
+};
+typedef int32_t HvcError;
+
+typedef struct HvcFrameInfo {
+  size_t width;
+  size_t height;
+  size_t channels;
+  uint8_t bit_depth;
+} HvcFrameInfo;
+
+int32_t hvc_encode_rgb48(const uint16_t *data,
+                         size_t width,
+                         size_t height,
+                         size_t channels,
+                         uint8_t **out,
+                         size_t *out_len);
+
+int32_t hvc_decode_rgb48(const uint8_t *data,
+                         size_t len,
+                         uint16_t *out,
+                         size_t out_capacity,
+                         HvcFrameInfo *info);
+
+void hvc_free(uint8_t *buffer);
+
+#endif /* HVC_H */
@@ -0,0 +1,238 @@
+use super::frame::{raw_sample_count, FrameError, Plane, RGB48Frame};
+
+// A frame whose rows each start at an address aligned to a given number of bytes, as SIMD code and
+// GPU uploads like. Samples are interleaved as in RGB48Frame, but each row is padded out to a
+// multiple of the alignment, so row_stride is generally more than width * channels. The padding is
+// zeroed and is never read or written by the frame's own methods.
+pub struct AlignedFrame {
+    // The allocation, which is larger than the frame so that it can start at an aligned offset.
+    buf: Vec<u16>,
+    offset: usize,
+    width: usize,
+    height: usize,
+    channels: usize,
+    row_stride: usize,
+    align_bytes: usize,
+    pub bit_depth: u8,
+}
+
+// Allocates a zeroed frame whose rows all start at a multiple of align_bytes, which must be a power
+// of two of at least 2 (the size of a sample).
+pub fn aligned_frame(
+    width: usize,
+    height: usize,
+    channels: usize,
+    align_bytes: usize,
+) -> Result<AlignedFrame, FrameError> {
+    if align_bytes < 2 || !align_bytes.is_power_of_two() {
+        return Err(FrameError::InvalidAlignment(align_bytes));
+    }
+    raw_sample_count(width, height, channels)?;
+    let too_large = || FrameError::TooLarge {
+        width,
+        height,
+        channels,
+    };
+    let align = align_bytes / 2;
+    let row_stride = (width * channels)
+        .checked_next_multiple_of(align)
+        .ok_or_else(too_large)?;
+    let len = row_stride
+        .checked_mul(height)
+        .and_then(|len| len.checked_add(align - 1))
+        .filter(|&len| len <= isize::MAX as usize / 2)
+        .ok_or_else(too_large)?;
+    let buf = vec![0; len];
+    // the allocation is only guaranteed to be aligned for u16, so skip ahead to the first aligned
+    // sample. buf is never resized, so this stays valid.
+    let offset = buf.as_ptr().align_offset(align_bytes);
+    Ok(AlignedFrame {
+        buf,
+        offset,
+        width,
+        height,
+        channels,
+        row_stride,
+        align_bytes,
+        bit_depth: 16,
+    })
+}
+
+impl AlignedFrame {
+    // Copies a frame into aligned rows.
+    pub fn from_frame(frame: &RGB48Frame, align_bytes: usize) -> Result<Self, FrameError> {
+        let channels = frame.validate()?;
+        let mut ret = aligned_frame(frame.width, frame.height, channels, align_bytes)?;
+        for (y, row) in frame.data.chunks_exact(frame.width * channels).enumerate() {
+            ret.row_mut(y).copy_from_slice(row);
+        }
+        ret.bit_depth = frame.bit_depth;
+        Ok(ret)
+    }
+
+    // Copies the frame into an unpadded RGB48Frame.
+    pub fn to_frame(&self) -> RGB48Frame {
+        let mut data = Vec::with_capacity(self.width * self.height * self.channels);
+        for y in 0..self.height {
+            data.extend_from_slice(self.row(y));
+        }
+        RGB48Frame {
+            data,
+            width: self.width,
+            height: self.height,
+            bit_depth: self.bit_depth,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    // The distance between the starts of consecutive rows, in samples.
+    pub fn row_stride(&self) -> usize {
+        self.row_stride
+    }
+
+    pub fn align_bytes(&self) -> usize {
+        self.align_bytes
+    }
+
+    // All of the rows, padding included, starting at an aligned address.
+    pub fn as_slice(&self) -> &[u16] {
+        &self.buf[self.offset..self.offset + self.row_stride * self.height]
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u16] {
+        let len = self.row_stride * self.height;
+        &mut self.buf[self.offset..self.offset + len]
+    }
+
+    // The interleaved samples of row y, without padding.
+    pub fn row(&self, y: usize) -> &[u16] {
+        let start = y * self.row_stride;
+        &self.as_slice()[start..start + self.width * self.channels]
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut [u16] {
+        let start = y * self.row_stride;
+        let len = self.width * self.channels;
+        &mut self.as_mut_slice()[start..start + len]
+    }
+
+    pub fn plane(&self, i: usize) -> Plane<&[u16]> {
+        assert!(i < self.channels, "plane {} of {}", i, self.channels);
+        Plane {
+            data: &self.as_slice()[i..],
+            width: self.width,
+            height: self.height,
+            sample_stride: self.channels,
+            row_stride: self.row_stride,
+        }
+    }
+
+    pub fn plane_mut(&mut self, i: usize) -> Plane<&mut [u16]> {
+        assert!(i < self.channels, "plane {} of {}", i, self.channels);
+        let (width, height, channels, row_stride) =
+            (self.width, self.height, self.channels, self.row_stride);
+        Plane {
+            data: &mut self.as_mut_slice()[i..],
+            width,
+            height,
+            sample_stride: channels,
+            row_stride,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{codec, frame::Codec, synth},
+        *,
+    };
+
+    #[test]
+    fn test_aligned_frame() {
+        for &align_bytes in [2, 4, 16, 64, 256, 4096].iter() {
+            for &(width, channels) in [(1, 1), (7, 3), (33, 4), (64, 3)].iter() {
+                let frame = aligned_frame(width, 5, channels, align_bytes).unwrap();
+                assert_eq!(frame.row_stride() * 2 % align_bytes, 0);
+                assert!(frame.row_stride() >= width * channels);
+                assert!(frame.row_stride() < width * channels + align_bytes / 2);
+                for y in 0..5 {
+                    let address = frame.row(y).as_ptr() as usize;
+                    assert_eq!(address % align_bytes, 0, "{} {}", align_bytes, width);
+                }
+            }
+        }
+
+        for &align_bytes in [0, 1, 3, 24].iter() {
+            assert_eq!(
+                aligned_frame(4, 4, 3, align_bytes).map(|_| ()),
+                Err(FrameError::InvalidAlignment(align_bytes))
+            );
+        }
+        assert_eq!(
+            aligned_frame(0, 4, 3, 16).map(|_| ()),
+            Err(FrameError::EmptyFrame {
+                width: 0,
+                height: 4
+            })
+        );
+        assert_eq!(
+            aligned_frame(4, 4, 5, 16).map(|_| ()),
+            Err(FrameError::InvalidChannelCount(5))
+        );
+        assert!(matches!(
+            aligned_frame(usize::MAX / 4, 4, 3, 16),
+            Err(FrameError::TooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_aligned_frame_round_trip() {
+        let frame = synth::zone_plate(37, 19);
+        for &align_bytes in [2, 32, 128].iter() {
+            let mut aligned = AlignedFrame::from_frame(&frame, align_bytes).unwrap();
+            assert!(aligned.to_frame() == frame);
+
+            // encoding an aligned frame's planes gives the same bytes as encoding the frame
+            let mut decoded = aligned_frame(37, 19, 3, align_bytes).unwrap();
+            for i in 0..3 {
+                let mut expected = Vec::new();
+                codec::Codec::encode(&frame.plane(i), &mut expected).unwrap();
+                let mut encoded = Vec::new();
+                codec::Codec::encode(&aligned.plane(i), &mut encoded).unwrap();
+                assert_eq!(encoded, expected);
+                codec::Codec::decode(encoded.as_slice(), &mut decoded.plane_mut(i)).unwrap();
+            }
+            assert!(decoded.to_frame() == frame);
+
+            // the padding is left alone
+            let (row_len, row_stride) = (37 * 3, aligned.row_stride());
+            for row in aligned.as_slice().chunks(row_stride) {
+                assert!(row[row_len..].iter().all(|&x| x == 0));
+            }
+            aligned.row_mut(3)[0] = 1;
+            assert_eq!(aligned.plane(0).sample(0, 3), 1);
+        }
+
+        let mut frame = frame;
+        frame.bit_depth = 10;
+        assert_eq!(
+            AlignedFrame::from_frame(&frame, 16)
+                .unwrap()
+                .to_frame()
+                .bit_depth,
+            10
+        );
+    }
+}
@@ -0,0 +1,322 @@
+use super::{
+    error::{CodecError, Result},
+    frame::{Codec, Limits, Plane, PlaneCoding, RGB48Frame},
+    header::FrameHeader,
+    tile::TileLayout,
+};
+use std::{
+    io::{Read, Write},
+    marker::PhantomData,
+};
+
+// Banded frames are interleaved by rows rather than by planes, so that a receiver can show the
+// frame top to bottom as it arrives instead of waiting for all but the last plane. The frame is cut
+// into bands of band_height rows (the last band may be shorter), and for each band in turn, each
+// plane's rows in it are coded as their own bitstream, in plane order. Each of those payloads is
+// preceded by its length, 4 bytes, big-endian. Like tiles, every band starts with no rows above it
+// to predict from, so smaller bands cost more.
+//
+// The bands are laid out like full-width tiles, and are numbered from the top.
+pub fn layout(width: usize, height: usize, band_height: usize) -> TileLayout {
+    TileLayout::new(width, height, width.max(1), band_height)
+}
+
+// Encodes one plane's rows in a band, which is the whole of the given plane, with its length in
+// front. The band is coded as coding says, and its sum of squared errors is returned (see
+// Codec::encode_quantized).
+pub fn encode_band<C: Codec, T: AsRef<[u16]>, W: Write>(
+    band: &Plane<T>,
+    coding: PlaneCoding,
+    mut dest: W,
+) -> Result<u64> {
+    let mut encoded = Vec::new();
+    let squared_error = coding.encode::<C, _, _>(band, &mut encoded)?;
+    if encoded.len() > u32::MAX as usize {
+        return Err(CodecError::InvalidInput("band is too large".to_string()));
+    }
+    dest.write_all(&(encoded.len() as u32).to_be_bytes())?;
+    dest.write_all(&encoded)?;
+    Ok(squared_error)
+}
+
+// Reads a band's length and payload into payload. Running out of data is reported as a truncation
+// at the band's first row, which the caller places.
+pub fn read_band<R: Read>(mut source: R, payload: &mut Vec<u8>) -> Result<()> {
+    let truncated = || CodecError::TruncatedStream {
+        plane: 0,
+        row: 0,
+        col: 0,
+    };
+    let mut len = [0; 4];
+    source.read_exact(&mut len).map_err(|_| truncated())?;
+    let len = u32::from_be_bytes(len) as usize;
+    payload.clear();
+    source.take(len as u64).read_to_end(payload)?;
+    if payload.len() != len {
+        return Err(truncated());
+    }
+    Ok(())
+}
+
+// Decodes a band's payload into a plane the size of the band, verifying that the payload is
+// exactly as long as its length claimed. Truncation is reported relative to the band.
+pub fn decode_band<C: Codec, T: AsMut<[u16]>>(
+    payload: &[u8],
+    band: &mut Plane<T>,
+    coding: PlaneCoding,
+) -> Result<()> {
+    let mut source = payload;
+    coding.decode::<C, _, _>(&mut source, band)?;
+    if !source.is_empty() {
+        return Err(CodecError::InvalidData(format!(
+            "a band is {} bytes according to its length, but only {} were used",
+            payload.len(),
+            payload.len() - source.len()
+        )));
+    }
+    Ok(())
+}
+
+// Reads and decodes the band of every plane that covers rows y to y + height of the frame, into
+// the same number of rows of dest starting at dest_y.
+pub(crate) fn decode_bands<C: Codec, R: Read>(
+    mut source: R,
+    header: &FrameHeader,
+    (y, height): (usize, usize),
+    dest: &mut RGB48Frame,
+    dest_y: usize,
+    payload: &mut Vec<u8>,
+) -> Result<()> {
+    for i in 0..header.n_planes {
+        read_band(&mut source, payload)
+            .and_then(|()| {
+                decode_band::<C, _>(
+                    payload,
+                    &mut dest.plane_mut(i).view_mut(0, dest_y, header.width, height),
+                    header.plane_coding(i),
+                )
+            })
+            .map_err(|e| e.in_plane(i, 0, y))?;
+    }
+    Ok(())
+}
+
+// Rows of a frame, decoded and color-converted, starting at row y.
+#[derive(Clone, PartialEq)]
+pub struct RowBand {
+    pub y: usize,
+    pub rows: RGB48Frame,
+}
+
+// Decodes a frame written by RGB48Frame::encode_with_header a band at a time, yielding each band
+// of every plane as soon as its bytes have been read, in display order. Frames that aren't banded
+// can't be shown until they've been read in full, so they're yielded as a single band.
+//
+// After an error, such as the stream ending early, the iterator ends, and the bands yielded
+// before it are still good.
+pub struct BandDecoder<C, R> {
+    header: FrameHeader,
+    source: R,
+    next_band: usize,
+    done: bool,
+    payload: Vec<u8>,
+    _codec: PhantomData<C>,
+}
+
+impl<C: Codec, R: Read> BandDecoder<C, R> {
+    pub fn new(mut source: R) -> Result<Self> {
+        let header = RGB48Frame::read_header(&mut source)?;
+        Ok(Self {
+            header,
+            source,
+            next_band: 0,
+            done: false,
+            payload: Vec::new(),
+            _codec: PhantomData,
+        })
+    }
+
+    pub fn header(&self) -> &FrameHeader {
+        &self.header
+    }
+
+    fn decode_next(&mut self) -> Result<Option<RowBand>> {
+        let header = &self.header;
+        let band_height = match header.band_height {
+            Some(band_height) => band_height,
+            None => {
+                if self.next_band > 0 {
+                    return Ok(None);
+                }
+                self.next_band = 1;
+                let rows = RGB48Frame::decode_after_header::<C, _>(
+                    header,
+                    &mut self.source,
+                    &Limits::default(),
+                    &mut |_| false,
+                )?;
+                return Ok(Some(RowBand { y: 0, rows }));
+            }
+        };
+
+        let layout = layout(header.width, header.height, band_height);
+        if self.next_band >= layout.len() {
+            return Ok(None);
+        }
+        let (_, y, width, height) = layout.tile_rect(self.next_band);
+        Limits::default().check(width * height * header.n_planes)?;
+        let mut rows = RGB48Frame {
+            data: vec![0; width * height * header.n_planes],
+            width,
+            height,
+            bit_depth: header.bit_depth,
+        };
+        decode_bands::<C, _>(
+            &mut self.source,
+            header,
+            (y, height),
+            &mut rows,
+            0,
+            &mut self.payload,
+        )?;
+        header
+            .color_transform
+            .inverse(&mut rows.data, header.n_planes);
+        self.next_band += 1;
+        Ok(Some(RowBand { y, rows }))
+    }
+}
+
+impl<C: Codec, R: Read> Iterator for BandDecoder<C, R> {
+    type Item = Result<RowBand>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let ret = self.decode_next().transpose();
+        self.done = !matches!(ret, Some(Ok(_)));
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{codec::Codec, color::ColorTransform, frame::EncodeOptions, synth},
+        *,
+    };
+
+    fn encode(frame: &RGB48Frame, options: &EncodeOptions) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        frame
+            .encode_with_header::<Codec, _>(&mut encoded, options)
+            .unwrap();
+        encoded
+    }
+
+    #[test]
+    fn test_banded_roundtrip() {
+        let frame = synth::zone_plate(37, 29);
+        for &band_height in [1, 4, 7, 29, 100].iter() {
+            for color_transform in [ColorTransform::None, ColorTransform::YCoCgR] {
+                let options = EncodeOptions {
+                    color_transform,
+                    band_height: Some(band_height),
+                    ..Default::default()
+                };
+                let encoded = encode(&frame, &options);
+                let header = FrameHeader::read(&*encoded).unwrap();
+                assert_eq!(header.band_height, Some(band_height));
+                let decoded = RGB48Frame::decode_with_header::<Codec, _>(&*encoded).unwrap();
+                assert!(decoded == frame, "band height = {}", band_height);
+
+                // the bands come out in order and cover the frame
+                let mut y = 0;
+                for band in BandDecoder::<Codec, _>::new(&*encoded).unwrap() {
+                    let band = band.unwrap();
+                    assert_eq!(band.y, y);
+                    assert!(band.rows == frame.crop_rect((0, y, 37, band.rows.height)));
+                    y += band.rows.height;
+                }
+                assert_eq!(y, 29);
+            }
+        }
+
+        // quantized bands decode to the same frame as quantized planes
+        let quantization = Some(vec![4, 8, 8]);
+        let planar = encode(
+            &frame,
+            &EncodeOptions {
+                quantization: quantization.clone(),
+                ..Default::default()
+            },
+        );
+        let banded = encode(
+            &frame,
+            &EncodeOptions {
+                quantization,
+                band_height: Some(37),
+                ..Default::default()
+            },
+        );
+        assert!(
+            RGB48Frame::decode_with_header::<Codec, _>(&*planar).unwrap()
+                == RGB48Frame::decode_with_header::<Codec, _>(&*banded).unwrap()
+        );
+
+        // frames that aren't banded come out as a single band
+        let bands = BandDecoder::<Codec, _>::new(&*encode(&frame, &Default::default()))
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert!(bands == vec![RowBand { y: 0, rows: frame }]);
+    }
+
+    #[test]
+    fn test_banded_prefix() {
+        // a receiver with the first quarter of the stream can show about the top quarter of the
+        // frame, in full color
+        let (width, height) = (64, 128);
+        let frame = synth::noise(width, height, 3, 10);
+        let encoded = encode(
+            &frame,
+            &EncodeOptions {
+                color_transform: ColorTransform::YCoCgR,
+                band_height: Some(8),
+                ..Default::default()
+            },
+        );
+        let mut decoder = BandDecoder::<Codec, _>::new(&encoded[..encoded.len() / 4]).unwrap();
+        let mut rows = 0;
+        for band in &mut decoder {
+            match band {
+                Ok(band) => {
+                    assert!(band.rows == frame.crop_rect((0, band.y, width, band.rows.height)));
+                    rows += band.rows.height;
+                }
+                Err(e) => {
+                    assert!(
+                        matches!(e, CodecError::TruncatedStream { row, .. } if row == rows),
+                        "{:?}",
+                        e
+                    );
+                }
+            }
+        }
+        assert!(decoder.next().is_none());
+        assert!((24..=40).contains(&rows), "{} rows", rows);
+
+        // the same stream in planar order has nothing to show for its first quarter
+        let planar = encode(
+            &frame,
+            &EncodeOptions {
+                color_transform: ColorTransform::YCoCgR,
+                ..Default::default()
+            },
+        );
+        let mut decoder = BandDecoder::<Codec, _>::new(&planar[..planar.len() / 4]).unwrap();
+        assert!(matches!(decoder.next(), Some(Err(_))));
+        assert!(decoder.next().is_none());
+    }
+}
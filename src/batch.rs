@@ -0,0 +1,325 @@
+use super::{
+    error::CodecError,
+    frame::{Codec, EncodeOptions, RGB48Frame},
+};
+use std::{
+    collections::BTreeMap,
+    io,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{channel, sync_channel},
+        Mutex,
+    },
+    thread,
+};
+use thiserror::Error;
+
+// A frame of a batch failed to encode, or the sink failed to take it. The index is the one the frame
+// was given along with it.
+#[derive(Error, Debug)]
+#[error("frame {index}: {source}")]
+pub struct BatchError {
+    pub index: usize,
+    pub source: CodecError,
+}
+
+// How many threads a batch is encoded on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Threading {
+    // As many threads as there are CPUs.
+    #[default]
+    Auto,
+    // This many threads, which the batch starts and stops itself. With 1 (or 0), no threads are
+    // started, and the frames are encoded one at a time on the calling thread.
+    Threads(usize),
+}
+
+impl Threading {
+    // The number of frames encoded at once, which is at least 1.
+    pub fn threads(self) -> usize {
+        match self {
+            Self::Auto => thread::available_parallelism().map_or(1, |n| n.get()),
+            Self::Threads(n) => n.max(1),
+        }
+    }
+}
+
+// Encodes frames on the threads `threading` asks for, and passes each encoded frame, as written by
+// RGB48Frame::encode_with_header, to the sink along with the index it came with. Frames reach the
+// sink in the order they came from the iterator, which is index order if the indices increase, and
+// no more frames are taken from the iterator than there are threads before those ahead of them have
+// been passed to the sink, so at most that many frames, encoded or not, are held at once.
+//
+// An error, from encoding or from the sink, stops the batch: no more frames are taken from the
+// iterator, the frames before the one that failed are still passed to the sink, and then the error
+// is returned with the index of the frame it's about, once the frames already being encoded are
+// done. If several frames fail, the error is the first of them's.
+pub fn encode_frames<C, I, F>(frames: I, threading: Threading, sink: F) -> Result<(), BatchError>
+where
+    C: Codec,
+    I: IntoIterator<Item = (usize, RGB48Frame)>,
+    F: FnMut(usize, Vec<u8>) -> io::Result<()>,
+{
+    encode_frames_with_options::<C, _, _>(frames, &EncodeOptions::default(), threading, sink)
+}
+
+// Like encode_frames, but with the given options rather than the defaults.
+pub fn encode_frames_with_options<C, I, F>(
+    frames: I,
+    options: &EncodeOptions,
+    threading: Threading,
+    mut sink: F,
+) -> Result<(), BatchError>
+where
+    C: Codec,
+    I: IntoIterator<Item = (usize, RGB48Frame)>,
+    F: FnMut(usize, Vec<u8>) -> io::Result<()>,
+{
+    run(
+        frames,
+        threading,
+        |frame| {
+            let mut encoded = Vec::new();
+            frame.encode_with_header::<C, _>(&mut encoded, options)?;
+            Ok(encoded)
+        },
+        |index, encoded| Ok(sink(index, encoded)?),
+    )
+}
+
+// Does the work of encode_frames with any encoding function, so that VideoWriter can have its
+// workers check the frames and hash them too.
+pub(crate) fn run<I, T, E, F>(
+    frames: I,
+    threading: Threading,
+    encode: E,
+    mut sink: F,
+) -> Result<(), BatchError>
+where
+    I: IntoIterator<Item = (usize, RGB48Frame)>,
+    T: Send,
+    E: Fn(&RGB48Frame) -> Result<T, CodecError> + Sync,
+    F: FnMut(usize, T) -> Result<(), CodecError>,
+{
+    let parallelism = threading.threads();
+    let mut frames = frames.into_iter();
+    if parallelism == 1 {
+        for (index, frame) in frames {
+            let encoded = encode(&frame).map_err(|source| BatchError { index, source })?;
+            sink(index, encoded).map_err(|source| BatchError { index, source })?;
+        }
+        return Ok(());
+    }
+
+    // frames are numbered by their position in the batch, which is the order they're delivered in,
+    // and travel with their own index for the sink and errors
+    let (job_sender, job_receiver) = sync_channel::<(usize, usize, RGB48Frame)>(parallelism);
+    let job_receiver = Mutex::new(job_receiver);
+    thread::scope(|scope| {
+        // returning drops the sender, which stops the workers once they're done with what they have
+        let job_sender = job_sender;
+        let (done_sender, done_receiver) = channel();
+        for worker in 0..parallelism {
+            let (jobs, done, encode) = (&job_receiver, done_sender.clone(), &encode);
+            let builder = thread::Builder::new().name(format!("hvc-batch-{}", worker));
+            let spawned = builder.spawn_scoped(scope, move || loop {
+                let job = jobs
+                    .lock()
+                    .expect("no worker panics holding the lock")
+                    .recv();
+                let Ok((position, index, frame)) = job else {
+                    break;
+                };
+                // a panic is passed back rather than left to kill the worker, which would leave
+                // the batch waiting for its frame forever
+                let result = panic::catch_unwind(AssertUnwindSafe(|| encode(&frame)));
+                if done.send((position, index, result)).is_err() {
+                    break;
+                }
+            });
+            spawned.expect("worker threads can be started");
+        }
+        drop(done_sender);
+
+        // finished frames waiting for the ones before them, by position
+        let mut finished = BTreeMap::new();
+        let (mut taken, mut delivered) = (0, 0);
+        let (mut exhausted, mut failed) = (false, false);
+        loop {
+            while !exhausted && !failed && taken - delivered < parallelism {
+                match frames.next() {
+                    Some((index, frame)) => {
+                        job_sender
+                            .send((taken, index, frame))
+                            .expect("the workers outlive the batch");
+                        taken += 1;
+                    }
+                    None => exhausted = true,
+                }
+            }
+            if taken == delivered {
+                return Ok(());
+            }
+
+            let (position, index, result) = done_receiver
+                .recv()
+                .expect("a worker has every frame not yet finished");
+            let result = result.unwrap_or_else(|panic| panic::resume_unwind(panic));
+            failed |= result.is_err();
+            finished.insert(position, (index, result));
+            while let Some((index, result)) = finished.remove(&delivered) {
+                let encoded = result.map_err(|source| BatchError { index, source })?;
+                sink(index, encoded).map_err(|source| BatchError { index, source })?;
+                delivered += 1;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{codec::Codec, synth},
+        *,
+    };
+    use std::cell::Cell;
+
+    // Frames of different sizes, so that they take different times to encode and finish out of
+    // order.
+    fn test_frames() -> Vec<RGB48Frame> {
+        (0..6)
+            .map(|i| synth::noise(16 + (5 - i) * 24, 16, i as u32, 12))
+            .collect()
+    }
+
+    #[test]
+    fn test_encode_frames() {
+        let frames = test_frames();
+        let (in_flight, peak) = (Cell::new(0), Cell::new(0));
+        let mut arrived = Vec::new();
+        let batch = frames.iter().cloned().enumerate().map(|(i, frame)| {
+            in_flight.set(in_flight.get() + 1);
+            peak.set(peak.get().max(in_flight.get()));
+            (i * 10, frame)
+        });
+        encode_frames::<Codec, _, _>(batch, Threading::Threads(3), |index, encoded| {
+            in_flight.set(in_flight.get() - 1);
+            arrived.push((index, encoded));
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(peak.get(), 3);
+        assert_eq!(
+            arrived.iter().map(|(index, _)| *index).collect::<Vec<_>>(),
+            vec![0, 10, 20, 30, 40, 50]
+        );
+        for (frame, (_, encoded)) in frames.iter().zip(&arrived) {
+            let mut expected = Vec::new();
+            frame
+                .encode_with_header::<Codec, _>(&mut expected, &EncodeOptions::default())
+                .unwrap();
+            assert_eq!(*encoded, expected);
+        }
+
+        // a parallelism of 1 encodes one frame at a time, and 0 is the same
+        for parallelism in 0..2 {
+            in_flight.set(0);
+            peak.set(0);
+            let batch = frames.iter().cloned().enumerate().inspect(|_| {
+                in_flight.set(in_flight.get() + 1);
+                peak.set(peak.get().max(in_flight.get()));
+            });
+            let mut count = 0;
+            encode_frames::<Codec, _, _>(batch, Threading::Threads(parallelism), |_, _| {
+                in_flight.set(in_flight.get() - 1);
+                count += 1;
+                Ok(())
+            })
+            .unwrap();
+            assert_eq!((peak.get(), count), (1, 6));
+        }
+    }
+
+    #[test]
+    fn test_encode_frames_errors() {
+        // a frame that can't be encoded stops the batch, and the error says which it was
+        let mut frames = test_frames();
+        frames[3].data.pop();
+        let mut delivered = Vec::new();
+        let taken = Cell::new(0);
+        let batch = frames.into_iter().enumerate().inspect(|_| {
+            taken.set(taken.get() + 1);
+        });
+        let err = encode_frames::<Codec, _, _>(batch, Threading::Threads(2), |index, _| {
+            delivered.push(index);
+            Ok(())
+        })
+        .unwrap_err();
+        assert_eq!(err.index, 3);
+        assert!(matches!(err.source, CodecError::InvalidInput(_)));
+        assert_eq!(delivered, vec![0, 1, 2]);
+        assert!(taken.get() < 6);
+
+        // and so does a sink that fails
+        let err = encode_frames::<Codec, _, _>(
+            test_frames().into_iter().enumerate(),
+            Threading::Threads(3),
+            |index, _| {
+                if index == 1 {
+                    Err(io::Error::other("disk full"))
+                } else {
+                    Ok(())
+                }
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err.index, 1);
+        assert!(matches!(err.source, CodecError::Io(_)));
+        assert_eq!(err.to_string(), "frame 1: disk full");
+    }
+
+    #[test]
+    fn test_threading() {
+        assert_eq!(Threading::Threads(0).threads(), 1);
+        assert_eq!(Threading::Threads(5).threads(), 5);
+        assert!(Threading::Auto.threads() >= 1);
+
+        // encoding on several threads gives the same frames as encoding them one at a time, and
+        // the frames are encoded on the batch's own threads, or on the caller's if there's one
+        let frames = test_frames();
+        let encode = |threading| {
+            let names = Mutex::new(Vec::new());
+            let mut encoded = Vec::new();
+            run(
+                frames.iter().cloned().enumerate(),
+                threading,
+                |frame| {
+                    let current = thread::current();
+                    names
+                        .lock()
+                        .unwrap()
+                        .push(current.name().map(str::to_string));
+                    let mut encoded = Vec::new();
+                    frame
+                        .encode_with_header::<Codec, _>(&mut encoded, &EncodeOptions::default())?;
+                    Ok(encoded)
+                },
+                |_, frame| {
+                    encoded.push(frame);
+                    Ok(())
+                },
+            )
+            .unwrap();
+            (encoded, names.into_inner().unwrap())
+        };
+        let (sequential, names) = encode(Threading::Threads(1));
+        let caller = thread::current().name().map(str::to_string);
+        assert!(names.iter().all(|name| *name == caller));
+        let (parallel, names) = encode(Threading::Threads(2));
+        assert_eq!(parallel, sequential);
+        assert!(names
+            .iter()
+            .all(|name| matches!(name.as_deref(), Some("hvc-batch-0") | Some("hvc-batch-1"))));
+    }
+}
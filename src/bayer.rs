@@ -0,0 +1,278 @@
+use super::{
+    error::{CodecError, Result},
+    frame::{Codec, Plane},
+};
+use std::io::{Read, Write};
+
+// The arrangement of a color filter array, named after the colors of its top-left 2x2 block in
+// reading order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BayerPattern {
+    RGGB = 0,
+    BGGR = 1,
+    GRBG = 2,
+    GBRG = 3,
+}
+
+impl BayerPattern {
+    pub fn from_u8(b: u8) -> Option<Self> {
+        match b {
+            0 => Some(Self::RGGB),
+            1 => Some(Self::BGGR),
+            2 => Some(Self::GRBG),
+            3 => Some(Self::GBRG),
+            _ => None,
+        }
+    }
+
+    // Returns the row and column within each 2x2 block of the R, Gr, Gb, and B sites, where Gr is
+    // the green sharing a row with red and Gb the green sharing a row with blue.
+    pub fn sites(self) -> [(usize, usize); 4] {
+        match self {
+            Self::RGGB => [(0, 0), (0, 1), (1, 0), (1, 1)],
+            Self::BGGR => [(1, 1), (1, 0), (0, 1), (0, 0)],
+            Self::GRBG => [(0, 1), (0, 0), (1, 1), (1, 0)],
+            Self::GBRG => [(1, 0), (1, 1), (0, 0), (0, 1)],
+        }
+    }
+}
+
+// A raw sensor mosaic, with one sample per pixel whose color depends on its position. Neighboring
+// samples are different colors, which defeats prediction, so the frame is encoded as four
+// half-resolution planes, one per site, each of which looks like an ordinary image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BayerFrame {
+    pub data: Vec<u16>,
+    pub width: usize,
+    pub height: usize,
+    pub pattern: BayerPattern,
+}
+
+fn invalid_input<S: Into<String>>(message: S) -> CodecError {
+    CodecError::InvalidInput(message.into())
+}
+
+impl BayerFrame {
+    // Creates a frame, verifying that it holds width * height samples and is at least one 2x2
+    // block. Odd dimensions are allowed, in which case some sites have an extra row or column.
+    pub fn new(data: Vec<u16>, width: usize, height: usize, pattern: BayerPattern) -> Result<Self> {
+        if width < 2 || height < 2 {
+            return Err(invalid_input(format!(
+                "a {}x{} mosaic doesn't hold every site",
+                width, height
+            )));
+        }
+        if Some(data.len()) != width.checked_mul(height) {
+            return Err(invalid_input(format!(
+                "{} samples isn't a {}x{} mosaic",
+                data.len(),
+                width,
+                height
+            )));
+        }
+        Ok(Self {
+            data,
+            width,
+            height,
+            pattern,
+        })
+    }
+
+    // Returns the width, height, and offset of the first sample of plane i, in R, Gr, Gb, B order.
+    fn plane_layout(&self, i: usize) -> (usize, usize, usize) {
+        let (row, col) = self.pattern.sites()[i];
+        (
+            (self.width - col).div_ceil(2),
+            (self.height - row).div_ceil(2),
+            row * self.width + col,
+        )
+    }
+
+    // Returns views of the R, Gr, Gb, and B sites. No samples are copied.
+    pub fn planes(&self) -> [Plane<&[u16]>; 4] {
+        let plane = |i| {
+            let (width, height, offset) = self.plane_layout(i);
+            Plane {
+                data: &self.data[offset..],
+                width,
+                height,
+                sample_stride: 2,
+                row_stride: self.width * 2,
+            }
+        };
+        [plane(0), plane(1), plane(2), plane(3)]
+    }
+
+    fn plane_mut(&mut self, i: usize) -> Plane<&mut [u16]> {
+        let (width, height, offset) = self.plane_layout(i);
+        Plane {
+            data: &mut self.data[offset..],
+            width,
+            height,
+            sample_stride: 2,
+            row_stride: self.width * 2,
+        }
+    }
+
+    // Writes the pattern and dimensions, followed by the four planes:
+    //
+    //   pattern    1 byte
+    //   width      4 bytes  big-endian
+    //   height     4 bytes  big-endian
+    pub fn encode<C: Codec, W: Write>(&self, mut dest: W) -> Result<()> {
+        if self.width > u32::MAX as usize || self.height > u32::MAX as usize {
+            return Err(invalid_input("frame dimensions are too large"));
+        }
+        dest.write_all(&[self.pattern as u8])?;
+        dest.write_all(&(self.width as u32).to_be_bytes())?;
+        dest.write_all(&(self.height as u32).to_be_bytes())?;
+        for plane in self.planes().iter() {
+            C::encode(plane, &mut dest)?;
+        }
+        Ok(())
+    }
+
+    pub fn decode<C: Codec, R: Read>(mut source: R) -> Result<Self> {
+        let mut header = [0; 9];
+        source.read_exact(&mut header)?;
+        let pattern = BayerPattern::from_u8(header[0]).ok_or_else(|| {
+            CodecError::InvalidHeader(format!("invalid bayer pattern: {}", header[0]))
+        })?;
+        let width = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        let height = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) as usize;
+        let len = width
+            .checked_mul(height)
+            .filter(|&len| len <= isize::MAX as usize / 2)
+            .ok_or_else(|| {
+                CodecError::InvalidHeader(format!("a {}x{} mosaic is too large", width, height))
+            })?;
+        let mut ret = Self::new(vec![0; len], width, height, pattern).map_err(|e| match e {
+            CodecError::InvalidInput(message) => CodecError::InvalidHeader(message),
+            e => e,
+        })?;
+        for i in 0..4 {
+            C::decode(&mut source, &mut ret.plane_mut(i)).map_err(|e| e.in_plane(i, 0, 0))?;
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::codec, *};
+
+    const PATTERNS: [BayerPattern; 4] = [
+        BayerPattern::RGGB,
+        BayerPattern::BGGR,
+        BayerPattern::GRBG,
+        BayerPattern::GBRG,
+    ];
+
+    // Builds a mosaic whose sites hold very different images, plus some noise.
+    fn test_frame(width: usize, height: usize, pattern: BayerPattern, noise: u32) -> BayerFrame {
+        let mut state = 12345u32;
+        let mut data = vec![0; width * height];
+        for (i, &(row, col)) in pattern.sites().iter().enumerate() {
+            for y in (row..height).step_by(2) {
+                for x in (col..width).step_by(2) {
+                    state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    let n = if noise > 0 { (state >> 16) % noise } else { 0 };
+                    data[y * width + x] = site_value(i, x, y) + n as u16;
+                }
+            }
+        }
+        BayerFrame::new(data, width, height, pattern).unwrap()
+    }
+
+    // A smooth scene whose color varies from place to place, seen through site i's filter.
+    fn site_value(i: usize, x: usize, y: usize) -> u16 {
+        let (x, y) = (x as f64, y as f64);
+        let luma = 0.5 + 0.4 * (x / 200.0).sin() * (y / 150.0).cos();
+        let hue = 1.0 + 0.5 * (x / 120.0 + y / 170.0 + i as f64 * 1.7).sin();
+        let gain = [0.9, 0.6, 0.6, 0.35][i];
+        (luma * hue * gain * 40000.0) as u16
+    }
+
+    #[test]
+    fn test_bayer_roundtrip() {
+        for &pattern in PATTERNS.iter() {
+            for &(width, height) in [(2, 2), (16, 8), (17, 9), (3, 30)].iter() {
+                let frame = test_frame(width, height, pattern, 0);
+
+                // each plane sees exactly one site
+                let planes = frame.planes();
+                assert_eq!(
+                    planes.iter().map(|p| p.width * p.height).sum::<usize>(),
+                    width * height
+                );
+                for (i, plane) in planes.iter().enumerate() {
+                    let (row, col) = pattern.sites()[i];
+                    for y in 0..plane.height {
+                        for x in 0..plane.width {
+                            assert_eq!(plane.sample(x, y), site_value(i, col + x * 2, row + y * 2));
+                        }
+                    }
+                }
+
+                let mut encoded = Vec::new();
+                frame.encode::<codec::Codec, _>(&mut encoded).unwrap();
+                assert_eq!(encoded[0], pattern as u8);
+                let decoded = BayerFrame::decode::<codec::Codec, _>(&*encoded).unwrap();
+                assert_eq!(decoded, frame);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bayer_beats_mosaic() {
+        let frame = test_frame(128, 96, BayerPattern::GRBG, 64);
+        let mut encoded = Vec::new();
+        frame.encode::<codec::Codec, _>(&mut encoded).unwrap();
+
+        let mut mosaic = Vec::new();
+        codec::Codec::encode(
+            &Plane {
+                data: &frame.data,
+                width: frame.width,
+                height: frame.height,
+                sample_stride: 1,
+                row_stride: frame.width,
+            },
+            &mut mosaic,
+        )
+        .unwrap();
+        assert!(
+            encoded.len() < mosaic.len(),
+            "{} >= {}",
+            encoded.len(),
+            mosaic.len()
+        );
+    }
+
+    #[test]
+    fn test_bayer_invalid() {
+        assert!(matches!(
+            BayerFrame::new(vec![0; 3], 3, 1, BayerPattern::RGGB),
+            Err(CodecError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            BayerFrame::new(vec![0; 5], 2, 2, BayerPattern::RGGB),
+            Err(CodecError::InvalidInput(_))
+        ));
+
+        let mut encoded = Vec::new();
+        test_frame(4, 4, BayerPattern::BGGR, 0)
+            .encode::<codec::Codec, _>(&mut encoded)
+            .unwrap();
+        let mut bad = encoded.clone();
+        bad[0] = 4;
+        assert!(matches!(
+            BayerFrame::decode::<codec::Codec, _>(&*bad),
+            Err(CodecError::InvalidHeader(_))
+        ));
+        match BayerFrame::decode::<codec::Codec, _>(&encoded[..encoded.len() - 1]) {
+            Err(CodecError::TruncatedStream { plane: 3, .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use super::{
+    dyn_codec::DynCodec,
+    error::CodecError,
+    frame::{EncodeOptions, RGB48Frame},
+    metrics::{FramePsnr, MS_SSIM_WINDOW},
+    report::{object, FromJson, Json, JsonError, ToJson},
+};
+use std::time::Instant;
+
+// What encoding and decoding a frame with one codec and set of options gave, for comparing codecs
+// on a particular kind of content.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BenchResult {
+    pub codec: String,
+    pub options: EncodeOptions,
+    // The size of the frame's samples at 16 bits each.
+    pub uncompressed_size: usize,
+    // The size of the frame as encoded by RGB48Frame::encode_with_header.
+    pub encoded_size: usize,
+    // The times encoding and decoding took, in seconds.
+    pub encode_time: f64,
+    pub decode_time: f64,
+    // Whether the frame decoded to exactly the samples it was encoded from.
+    pub lossless: bool,
+    // The quality of the decoded frame, which is only measured if it isn't lossless. SSIM is the mean
+    // over the planes, with an MS_SSIM_WINDOW window.
+    pub psnr: Option<FramePsnr>,
+    pub ssim: Option<f64>,
+}
+
+impl BenchResult {
+    // The uncompressed size over the encoded size.
+    pub fn ratio(&self) -> f64 {
+        self.uncompressed_size as f64 / self.encoded_size.max(1) as f64
+    }
+}
+
+// Encodes and decodes a frame with a codec, timing both and comparing what's decoded with the
+// original.
+pub fn bench(
+    frame: &RGB48Frame,
+    codec: &DynCodec,
+    options: &EncodeOptions,
+) -> Result<BenchResult, CodecError> {
+    let start = Instant::now();
+    let encoded = codec.encode(frame, options)?;
+    let encode_time = start.elapsed().as_secs_f64();
+    let start = Instant::now();
+    let decoded = codec.decode(&encoded)?;
+    let decode_time = start.elapsed().as_secs_f64();
+
+    let lossless = decoded.data == frame.data;
+    let (psnr, ssim) = if lossless {
+        (None, None)
+    } else {
+        let invalid = |e: super::frame::FrameError| CodecError::InvalidData(e.to_string());
+        (
+            Some(frame.psnr(&decoded).map_err(invalid)?),
+            Some(frame.ssim(&decoded, MS_SSIM_WINDOW).map_err(invalid)?),
+        )
+    };
+    Ok(BenchResult {
+        codec: codec.name().to_string(),
+        options: options.clone(),
+        uncompressed_size: frame.data.len() * 2,
+        encoded_size: encoded.len(),
+        encode_time,
+        decode_time,
+        lossless,
+        psnr,
+        ssim,
+    })
+}
+
+impl ToJson for BenchResult {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("codec", self.codec.to_json()),
+            ("options", self.options.to_json()),
+            ("uncompressed_size", self.uncompressed_size.to_json()),
+            ("encoded_size", self.encoded_size.to_json()),
+            ("encode_time", self.encode_time.to_json()),
+            ("decode_time", self.decode_time.to_json()),
+            ("lossless", self.lossless.to_json()),
+            ("psnr", self.psnr.to_json()),
+            ("ssim", self.ssim.to_json()),
+        ])
+    }
+}
+
+impl FromJson for BenchResult {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        Ok(Self {
+            codec: json.field("codec")?,
+            options: json.field("options")?,
+            uncompressed_size: json.field("uncompressed_size")?,
+            encoded_size: json.field("encoded_size")?,
+            encode_time: json.field("encode_time")?,
+            decode_time: json.field("decode_time")?,
+            lossless: json.field("lossless")?,
+            psnr: json.field("psnr")?,
+            ssim: json.field("ssim")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{header::FrameHeader, synth},
+        *,
+    };
+
+    #[test]
+    fn test_bench() {
+        let frame = synth::gradient(32, 16, synth::Direction::Diagonal);
+        let (rice, store) = (
+            DynCodec::by_name("rice").unwrap(),
+            DynCodec::by_name("store").unwrap(),
+        );
+        let lossless = EncodeOptions::default();
+        let results = [
+            bench(&frame, &rice, &lossless).unwrap(),
+            bench(&frame, &store, &lossless).unwrap(),
+        ];
+        for result in results.iter() {
+            assert!(result.lossless, "{}", result.codec);
+            assert_eq!(result.uncompressed_size, 32 * 16 * 3 * 2);
+            assert!(result.encode_time >= 0.0 && result.decode_time >= 0.0);
+            assert_eq!((result.psnr.as_ref(), result.ssim), (None, None));
+            assert_eq!(
+                BenchResult::from_json(&Json::parse(&result.to_json().to_string()).unwrap())
+                    .unwrap(),
+                *result
+            );
+        }
+        assert_eq!(results[0].codec, "rice");
+        assert_eq!(
+            results[0].encoded_size,
+            rice.encode(&frame, &lossless).unwrap().len()
+        );
+        // storing takes 16 bits per sample, plus the header
+        assert_eq!(results[1].codec, "store");
+        assert_eq!(
+            results[1].encoded_size,
+            results[1].uncompressed_size
+                + FrameHeader::read(&*store.encode(&frame, &lossless).unwrap())
+                    .unwrap()
+                    .encoded_len()
+        );
+        assert!(results[1].ratio() < 1.0);
+
+        // quantization loses quality, which is measured, and the store codec can't do it
+        let frame = synth::noise(32, 16, 4, 16);
+        let quantized = EncodeOptions {
+            quantization: Some(vec![16; 3]),
+            ..Default::default()
+        };
+        let result = bench(&frame, &rice, &quantized).unwrap();
+        assert!(!result.lossless);
+        assert!(result.encoded_size < bench(&frame, &rice, &lossless).unwrap().encoded_size);
+        let psnr = result.psnr.unwrap().combined;
+        assert!(psnr > 30.0 && psnr.is_finite(), "{}", psnr);
+        assert!(result.ssim.unwrap() < 1.0);
+        assert!(matches!(
+            bench(&frame, &store, &quantized),
+            Err(CodecError::InvalidInput(_))
+        ));
+    }
+}
@@ -0,0 +1,908 @@
+use hello_video_codec::{
+    batch::Threading,
+    bench,
+    codec::{Codec, KMode, MAX_FIXED_K},
+    container::{self, FrameStatus, VideoReader, VideoWriter},
+    dyn_codec::DynCodec,
+    frame::{EncodeOptions, RGB48Frame},
+    frame16::Frame16,
+    header::FrameHeader,
+    image_sequence::ImageSequence,
+    metadata::SequenceMetadata,
+    rawvideo::{PartialFrame, PixelFormat, RawVideoError, RawVideoReader, RawVideoWriter},
+    report::{RecordWriter, Report},
+};
+use std::{
+    cell::RefCell,
+    fs,
+    io::{self, Seek, Write},
+    iter,
+    ops::RangeInclusive,
+    path::Path,
+    process,
+    time::{Duration, Instant},
+};
+
+const USAGE: &str = "usage:
+  hvc encode [--quiet] [--stats] [--k <mode>] [--report <report.jsonl>] <input.tif|input.png>
+             <output.hvc>
+  hvc decode [--quiet] [--stats] [--width <w> --height <h>] <input.hvc> <output.tif|output.png>
+  hvc info <input.hvc>
+  hvc bench [--codecs <name,...>] [--q <step,...>] [--k <mode>] [--report <report.jsonl>]
+            <input.tif|input.png>
+  hvc encode-seq [--quiet] [--stats] [--fps <rate>] [--start <n>] [--end <n>] [--jobs <n>]
+                 [--k <mode>] [--report <report.jsonl>] <frames/%06d.tif|frames/> <output.hvcv>
+  hvc decode-seq [--quiet] [--format tif|png] [--start <i>] [--end <i>] <input.hvcv> <outdir/>
+  hvc verify [--quiet] <input.hvcv>
+  hvc splice [--quiet] <input.hvcv[:start-end]>... <output.hvcv>
+  hvc encode-pipe [--quiet] [--stats] --width <w> --height <h> [--pix-fmt <format>] [--fps <rate>]
+                  [--k <mode>] [--discard-partial] <output.hvcv>
+  hvc decode-pipe [--pix-fmt <format>] [--start <i>] [--end <i>] <input.hvcv>
+
+encode and decode print the sizes involved unless --quiet is given, and --stats adds timing.
+decode reads the frame's header unless --width and --height are given, which are for
+headerless 3-plane streams.
+
+bench encodes and decodes a frame with each of the codecs --codecs lists, which can be rice,
+store, and deflate where it's built in, and are all of them by default. It prints the sizes and
+times, checks that decoding is lossless, and measures the quality of lossy results, which come
+from the quantization steps --q lists. --report writes each result as a JSON record.
+
+--k sets how the encoders choose the Rice parameter k: adaptive, the default, adapts it to each
+sample's neighbors, a number from 0 to 16 fixes it for every sample, which codes faster at some
+cost in size, and auto picks a fixed k for each plane from a sample of its rows. Fixed k values
+are lossless only.
+
+encode and encode-seq write a JSON record for each frame to the file --report names, one per
+line, with its dimensions, sizes, and encoding time. For encode-seq, which encodes several frames
+at once, the time is that since the frame before it was written.
+
+encode-seq encodes numbered images, in numeric order, into one container, reporting any numbers
+missing from the sequence. --start and --end limit it to the frames numbered from start to end,
+inclusive, and --fps sets the frame rate, like 24 or 24000/1001, which is 24 by default.
+--jobs sets how many frames are encoded at once, which is the number of CPUs by default, and with
+--jobs 1 they are encoded one after another without starting any threads.
+decode-seq writes a container's frames to outdir as 000000.tif, 000001.tif, and so on, where
+--start and --end are positions in the container rather than the original numbers.
+verify decodes every frame of a container and checks it against the content hash stored with it
+when it was written, listing the frames that fail, and exits with an error if any do.
+splice copies frames from one or more containers into a new one without decoding them, all of
+each input's frames or those at positions start to end, inclusive. The inputs must all have the
+same dimensions and frame rate, and the output takes the first one's metadata.
+
+encode-pipe encodes raw frames read from standard input, as written by ffmpeg with
+`-f rawvideo -pix_fmt rgb48le`, into one container. The pixel format can be gray16le, rgb48le,
+rgba64le, or their big-endian versions, and is rgb48le by default. A partial final frame is an
+error unless --discard-partial is given. decode-pipe writes a container's frames to standard
+output in the same way, as gray16le, rgb48le, or rgba64le by default, and stops early without
+an error if whatever is reading them goes away.";
+
+type Error = Box<dyn std::error::Error>;
+
+// How much encode and decode print.
+#[derive(Clone, Copy, PartialEq)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Stats,
+}
+
+struct Args {
+    verbosity: Verbosity,
+    width: Option<usize>,
+    height: Option<usize>,
+    frame_rate: Option<(u32, u32)>,
+    start: Option<u64>,
+    end: Option<u64>,
+    format: Option<String>,
+    pixel_format: Option<PixelFormat>,
+    discard_partial: bool,
+    jobs: Option<usize>,
+    report: Option<String>,
+    codecs: Option<Vec<DynCodec>>,
+    steps: Option<Vec<u16>>,
+    k_mode: KMode,
+    paths: Vec<String>,
+}
+
+// Parses a frame rate given as a whole number or a fraction.
+fn parse_frame_rate(rate: &str) -> Option<(u32, u32)> {
+    let (num, den) = match rate.split_once('/') {
+        Some((num, den)) => (num.parse().ok()?, den.parse().ok()?),
+        None => (rate.parse().ok()?, 1),
+    };
+    if num == 0 || den == 0 {
+        return None;
+    }
+    Some((num, den))
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut ret = Self {
+            verbosity: Verbosity::Normal,
+            width: None,
+            height: None,
+            frame_rate: None,
+            start: None,
+            end: None,
+            format: None,
+            pixel_format: None,
+            discard_partial: false,
+            jobs: None,
+            report: None,
+            codecs: None,
+            steps: None,
+            k_mode: KMode::Adaptive,
+            paths: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || -> Result<&String, String> {
+                args.next().ok_or_else(|| format!("{} needs a value", arg))
+            };
+            let invalid = |value: &str| format!("invalid {}: {}", arg, value);
+            match arg.as_str() {
+                "--quiet" | "-q" => ret.verbosity = Verbosity::Quiet,
+                "--stats" => ret.verbosity = Verbosity::Stats,
+                "--width" | "--height" => {
+                    let value = value()?;
+                    let dimension = match value.parse() {
+                        Ok(dimension) if dimension > 0 => Some(dimension),
+                        _ => return Err(invalid(value)),
+                    };
+                    if arg == "--width" {
+                        ret.width = dimension;
+                    } else {
+                        ret.height = dimension;
+                    }
+                }
+                "--fps" => {
+                    let value = value()?;
+                    ret.frame_rate = Some(parse_frame_rate(value).ok_or_else(|| invalid(value))?);
+                }
+                "--start" | "--end" => {
+                    let value = value()?;
+                    let n = Some(value.parse().map_err(|_| invalid(value))?);
+                    if arg == "--start" {
+                        ret.start = n;
+                    } else {
+                        ret.end = n;
+                    }
+                }
+                "--format" => match value()?.as_str() {
+                    format @ "tif" | format @ "png" => ret.format = Some(format.to_string()),
+                    format => return Err(invalid(format)),
+                },
+                "--codecs" => {
+                    let value = value()?;
+                    let codecs = value
+                        .split(',')
+                        .map(|name| DynCodec::by_name(name).ok_or_else(|| invalid(name)))
+                        .collect::<Result<_, _>>()?;
+                    ret.codecs = Some(codecs);
+                }
+                "--q" => {
+                    let value = value()?;
+                    let steps = value
+                        .split(',')
+                        .map(|step| match step.parse() {
+                            Ok(step) if step > 0 => Ok(step),
+                            _ => Err(invalid(step)),
+                        })
+                        .collect::<Result<_, _>>()?;
+                    ret.steps = Some(steps);
+                }
+                "--k" => {
+                    let value = value()?;
+                    ret.k_mode = match value.as_str() {
+                        "adaptive" => KMode::Adaptive,
+                        "auto" => KMode::PerPlaneAuto,
+                        k => match k.parse() {
+                            Ok(k) if k <= MAX_FIXED_K => KMode::Fixed(k),
+                            _ => return Err(invalid(value)),
+                        },
+                    };
+                }
+                "--pix-fmt" => {
+                    let value = value()?;
+                    ret.pixel_format =
+                        Some(PixelFormat::from_name(value).ok_or_else(|| invalid(value))?);
+                }
+                "--discard-partial" => ret.discard_partial = true,
+                "--report" => ret.report = Some(value()?.to_string()),
+                "--jobs" | "-j" => {
+                    let value = value()?;
+                    ret.jobs = match value.parse() {
+                        Ok(jobs) if jobs > 0 => Some(jobs),
+                        _ => return Err(invalid(value)),
+                    };
+                }
+                flag if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(format!("unknown option: {}", flag))
+                }
+                path => ret.paths.push(path.to_string()),
+            }
+        }
+        Ok(ret)
+    }
+
+    // The input and output paths, which every command but info takes.
+    fn input_output(&self) -> Result<(&str, &str), String> {
+        match self.paths.as_slice() {
+            [input, output] => Ok((input, output)),
+            _ => Err("expected an input and an output path".to_string()),
+        }
+    }
+
+    // The options the encoders use.
+    fn encode_options(&self) -> EncodeOptions {
+        EncodeOptions {
+            k_mode: self.k_mode,
+            ..Default::default()
+        }
+    }
+
+    // The only path, for the commands that stream through standard input or output.
+    fn path(&self) -> Result<&str, String> {
+        match self.paths.as_slice() {
+            [path] => Ok(path),
+            _ => Err("expected a single path".to_string()),
+        }
+    }
+}
+
+// Opens the file --report names, if it was given.
+fn open_report(args: &Args) -> Result<Option<RecordWriter<io::BufWriter<fs::File>>>, Error> {
+    Ok(match &args.report {
+        Some(path) => Some(RecordWriter::new(io::BufWriter::new(fs::File::create(
+            path,
+        )?))),
+        None => None,
+    })
+}
+
+fn ratio(uncompressed: usize, compressed: usize) -> f64 {
+    uncompressed as f64 / compressed.max(1) as f64
+}
+
+fn encode(args: &Args) -> Result<(), Error> {
+    let (input, output) = args.input_output()?;
+    let start = Instant::now();
+    let frame = RGB48Frame::open(input)?;
+    let loaded = start.elapsed();
+    let options = args.encode_options();
+    let mut encoded = Vec::new();
+    frame.encode_with_header::<Codec, _>(&mut encoded, &options)?;
+    let encoding = start.elapsed() - loaded;
+    fs::write(output, &encoded)?;
+    if let Some(mut records) = open_report(args)? {
+        let mut report = Report::new(0, &frame, &options);
+        report.input = Some(input.to_string());
+        report.encoded_size = encoded.len();
+        report.wall_time = encoding.as_secs_f64();
+        records.write_record(&report)?;
+        records.flush()?;
+    }
+
+    if args.verbosity != Verbosity::Quiet {
+        let uncompressed = frame.data.len() * 2;
+        println!(
+            "{}x{}, {} planes, {}-bit: {} bytes -> {} bytes ({:.3}:1)",
+            frame.width,
+            frame.height,
+            frame.n_planes(),
+            frame.bit_depth,
+            uncompressed,
+            encoded.len(),
+            ratio(uncompressed, encoded.len())
+        );
+    }
+    if args.verbosity == Verbosity::Stats {
+        println!(
+            "{:.3} bits per sample, loaded in {:.3?}, encoded in {:.3?}",
+            encoded.len() as f64 * 8.0 / frame.data.len().max(1) as f64,
+            loaded,
+            encoding
+        );
+    }
+    Ok(())
+}
+
+// Saves a decoded frame in the format its path's extension names. Frame16 does the writing, since it
+// can write gray and RGBA frames as well as RGB ones.
+fn save<P: AsRef<Path>>(frame: RGB48Frame, path: P) -> Result<(), Error> {
+    Ok(Frame16::from_rgb48(frame)?.save(path)?)
+}
+
+fn decode(args: &Args) -> Result<(), Error> {
+    let (input, output) = args.input_output()?;
+    let encoded = fs::read(input)?;
+    let start = Instant::now();
+    let frame = match (args.width, args.height) {
+        (Some(width), Some(height)) => RGB48Frame::decode::<Codec, _>(&*encoded, width, height)?,
+        (None, None) => RGB48Frame::decode_with_header::<Codec, _>(&*encoded)?,
+        _ => return Err("--width and --height must be given together".into()),
+    };
+    let decoding = start.elapsed();
+    let summary = format!(
+        "{}x{}, {} planes, {}-bit: {} bytes -> {} bytes",
+        frame.width,
+        frame.height,
+        frame.n_planes(),
+        frame.bit_depth,
+        encoded.len(),
+        frame.data.len() * 2
+    );
+    save(frame, output)?;
+
+    if args.verbosity != Verbosity::Quiet {
+        println!("{}", summary);
+    }
+    if args.verbosity == Verbosity::Stats {
+        println!("decoded in {:.3?}", decoding);
+    }
+    Ok(())
+}
+
+fn bench(args: &Args) -> Result<(), Error> {
+    let input = args.path()?;
+    let frame = RGB48Frame::open(input)?;
+    let codecs = args.codecs.clone().unwrap_or_else(DynCodec::all);
+    let steps = args.steps.clone().unwrap_or_else(|| vec![1]);
+    let mut records = open_report(args)?;
+
+    println!(
+        "{:<8} {:>5} {:>12} {:>9} {:>10} {:>10}  quality",
+        "codec", "q", "size", "ratio", "encode", "decode"
+    );
+    for codec in &codecs {
+        for &step in &steps {
+            let options = EncodeOptions {
+                quantization: if step > 1 {
+                    Some(vec![step; frame.n_planes()])
+                } else {
+                    None
+                },
+                ..args.encode_options()
+            };
+            let result = match bench::bench(&frame, codec, &options) {
+                Ok(result) => result,
+                Err(e) => {
+                    println!("{:<8} {:>5} {}", codec.name(), step, e);
+                    continue;
+                }
+            };
+            let quality = match (&result.psnr, result.ssim) {
+                (Some(psnr), Some(ssim)) => {
+                    format!("PSNR {:.2} dB, SSIM {:.4}", psnr.combined, ssim)
+                }
+                _ => "lossless".to_string(),
+            };
+            println!(
+                "{:<8} {:>5} {:>12} {:>7.3}:1 {:>8.1}ms {:>8.1}ms  {}",
+                result.codec,
+                step,
+                result.encoded_size,
+                result.ratio(),
+                result.encode_time * 1000.0,
+                result.decode_time * 1000.0,
+                quality
+            );
+            if let Some(records) = &mut records {
+                records.write_record(&result)?;
+            }
+        }
+    }
+    if let Some(records) = &mut records {
+        records.flush()?;
+    }
+    Ok(())
+}
+
+fn info(args: &Args) -> Result<(), Error> {
+    let input = match args.paths.as_slice() {
+        [input] => input,
+        _ => return Err("expected an input path".into()),
+    };
+    let encoded = fs::read(input)?;
+    let header = FrameHeader::read(&*encoded)?;
+    let mut out = io::stdout().lock();
+    writeln!(out, "dimensions:      {}x{}", header.width, header.height)?;
+    writeln!(out, "planes:          {}", header.n_planes)?;
+    writeln!(out, "bit depth:       {}", header.bit_depth)?;
+    writeln!(out, "sample width:    {}", header.sample_width)?;
+    writeln!(out, "color transform: {:?}", header.color_transform)?;
+    match header.tile_size {
+        Some((width, height)) => writeln!(out, "tiles:           {}x{}", width, height)?,
+        None => writeln!(out, "tiles:           none")?,
+    }
+    match &header.quantization {
+        Some(steps) => writeln!(out, "quantization:    {:?}", steps)?,
+        None => writeln!(out, "quantization:    none (lossless)")?,
+    }
+    match header.preview_factor {
+        Some(factor) => writeln!(out, "preview factor:  {}", factor)?,
+        None => writeln!(out, "preview factor:  none")?,
+    }
+    match header.band_height {
+        Some(rows) => writeln!(out, "bands:           {} rows", rows)?,
+        None => writeln!(out, "bands:           none")?,
+    }
+    match &header.fixed_k {
+        Some(k) => writeln!(out, "k:               fixed {:?}", k)?,
+        None => writeln!(out, "k:               adaptive")?,
+    }
+    let payload = encoded.len() - header.encoded_len();
+    let uncompressed =
+        header.width * header.height * header.n_planes * header.sample_width as usize / 8;
+    writeln!(
+        out,
+        "size:            {} bytes ({} of payload, {:.3}:1)",
+        encoded.len(),
+        payload,
+        ratio(uncompressed, encoded.len())
+    )?;
+    Ok(())
+}
+
+// Formats a duration as whole hours, minutes, and seconds.
+fn hms(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn encode_sequence(args: &Args) -> Result<(), Error> {
+    let (input, output) = args.input_output()?;
+    let sequence = ImageSequence::open(input)?;
+    let (start, end) = (args.start.unwrap_or(0), args.end.unwrap_or(u64::MAX));
+    let selected: Vec<usize> = (0..sequence.len())
+        .filter(|&i| (start..=end).contains(&sequence.number(i)))
+        .collect();
+    if selected.is_empty() {
+        return Err(format!(
+            "no frames of {} are numbered from {} to {}",
+            input, start, end
+        )
+        .into());
+    }
+    let (first, last) = (
+        sequence.number(selected[0]),
+        sequence.number(*selected.last().unwrap()),
+    );
+    for gap in sequence.gaps() {
+        if gap.start > first && gap.end <= last {
+            eprintln!(
+                "hvc: warning: frames {} to {} are missing",
+                gap.start,
+                gap.end - 1
+            );
+        }
+    }
+
+    let started = Instant::now();
+    let load = |i: usize| {
+        RGB48Frame::open(sequence.path(i))
+            .map_err(|e| format!("failed to load {}: {}", sequence.path(i).display(), e))
+    };
+    let frame = load(selected[0])?;
+    let metadata = SequenceMetadata::new(args.frame_rate.unwrap_or((24, 1)))
+        .with_tag("first_frame", first.to_string());
+    let mut writer = VideoWriter::<Codec, _>::new(
+        io::BufWriter::new(fs::File::create(output)?),
+        frame.width,
+        frame.height,
+        frame.n_planes(),
+        metadata,
+    )?
+    .with_options(args.encode_options());
+
+    // the rest of the frames are loaded as the writer takes them, and one that fails to load ends
+    // the sequence there
+    let frame_size = frame.data.len() * 2;
+    let mut records = open_report(args)?;
+    let mut report = Report::new(0, &frame, &args.encode_options());
+    let mut report_error = None;
+    let bit_depths = RefCell::new(Vec::new());
+    let mut load_error = None;
+    let frames = iter::once(frame)
+        .chain(
+            selected[1..]
+                .iter()
+                .map_while(|&i| load(i).map_err(|e| load_error = Some(e)).ok()),
+        )
+        .inspect(|frame| bit_depths.borrow_mut().push(frame.bit_depth));
+    let threading = args.jobs.map_or(Threading::Auto, Threading::Threads);
+    let (mut last_written, mut last_time) = (writer.bytes_written()?, Instant::now());
+    writer
+        .write_frames_with(frames, threading, |i, written| {
+            if let Some(records) = &mut records {
+                // each frame is preceded by its length and content hash
+                report.frame = i;
+                report.input = Some(sequence.path(selected[i]).display().to_string());
+                report.bit_depth = bit_depths.borrow()[i];
+                report.encoded_size = (written - last_written) as usize - 16;
+                report.wall_time = last_time.elapsed().as_secs_f64();
+                if let Err(e) = records.write_record(&report) {
+                    report_error.get_or_insert(e);
+                }
+            }
+            last_written = written;
+            last_time = Instant::now();
+
+            if args.verbosity != Verbosity::Quiet {
+                let done = i + 1;
+                let elapsed = started.elapsed();
+                let eta = elapsed.mul_f64((selected.len() - done) as f64 / done as f64);
+                print!(
+                    "\rframe {}/{}, {:.3}:1 so far, {} elapsed, {} left",
+                    done,
+                    selected.len(),
+                    ratio(done * frame_size, written as usize),
+                    hms(elapsed),
+                    hms(eta)
+                );
+                let _ = io::stdout().flush();
+            }
+        })
+        .map_err(|e| {
+            let path = sequence.path(selected[e.index]);
+            format!("failed to encode {}: {}", path.display(), e.source)
+        })?;
+    if let Some(e) = load_error {
+        return Err(e.into());
+    }
+    if let Some(e) = report_error {
+        return Err(e.into());
+    }
+    if let Some(records) = &mut records {
+        records.flush()?;
+    }
+    let uncompressed = selected.len() * frame_size;
+    let size = writer.finalize()?.stream_position()?;
+
+    if args.verbosity != Verbosity::Quiet {
+        println!(
+            "\n{} frames, {} to {}: {} bytes -> {} bytes ({:.3}:1)",
+            selected.len(),
+            first,
+            last,
+            uncompressed,
+            size,
+            ratio(uncompressed, size as usize)
+        );
+    }
+    if args.verbosity == Verbosity::Stats {
+        let elapsed = started.elapsed().as_secs_f64();
+        println!(
+            "{:.3} frames per second over {:.3}s",
+            selected.len() as f64 / elapsed,
+            elapsed
+        );
+    }
+    Ok(())
+}
+
+fn decode_sequence(args: &Args) -> Result<(), Error> {
+    let (input, output) = args.input_output()?;
+    let mut reader = VideoReader::<Codec, _>::open(io::BufReader::new(fs::File::open(input)?))?;
+    let count = reader.frame_count() as u64;
+    let start = args.start.unwrap_or(0);
+    let end = args.end.unwrap_or(u64::MAX).min(count.saturating_sub(1));
+    if count == 0 || start > end {
+        return Err(format!("{} has no frames from {} to {}", input, start, end).into());
+    }
+    fs::create_dir_all(output)?;
+    let format = args.format.as_deref().unwrap_or("tif");
+    for i in start..=end {
+        let frame = reader.read_frame(i as usize)?;
+        save(
+            frame,
+            Path::new(output).join(format!("{:06}.{}", i, format)),
+        )?;
+        if args.verbosity != Verbosity::Quiet {
+            print!("\rframe {}/{}", i - start + 1, end - start + 1);
+            io::stdout().flush()?;
+        }
+    }
+    if args.verbosity != Verbosity::Quiet {
+        println!();
+    }
+    Ok(())
+}
+
+fn verify(args: &Args) -> Result<(), Error> {
+    let input = args.path()?;
+    let mut reader = VideoReader::<Codec, _>::open(io::BufReader::new(fs::File::open(input)?))?;
+    if reader.index_rebuilt() {
+        eprintln!(
+            "hvc: warning: the index is missing or damaged, so the frames were found by scanning"
+        );
+    }
+    let summary = reader.verify(|i, status| {
+        if let FrameStatus::Failed(reason) = status {
+            println!("frame {}: {}", i, reason);
+        }
+    });
+
+    let count = summary.frames.len();
+    let failed = summary.failed().count();
+    if args.verbosity != Verbosity::Quiet {
+        let unchecked = summary
+            .frames
+            .iter()
+            .filter(|&status| *status == FrameStatus::Unchecked)
+            .count();
+        print!(
+            "{} frames: {} passed, {} failed",
+            count,
+            count - failed - unchecked,
+            failed
+        );
+        if unchecked > 0 {
+            print!(", {} without content hashes to check", unchecked);
+        }
+        println!();
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} frames failed verification", failed, count).into());
+    }
+    Ok(())
+}
+
+// Splits a splice input into its path and the frames given after a colon, if any.
+fn parse_splice_input(input: &str) -> Result<(&str, Option<RangeInclusive<usize>>), String> {
+    let frames = input
+        .rsplit_once(':')
+        .and_then(|(path, frames)| Some((path, frames.split_once('-')?)));
+    let (path, (start, end)) = match frames {
+        Some((path, frames)) if !path.is_empty() => (path, frames),
+        _ => return Ok((input, None)),
+    };
+    match (start.parse(), end.parse()) {
+        (Ok(start), Ok(end)) if start <= end => Ok((path, Some(start..=end))),
+        _ => Err(format!("invalid frames for {}: {}-{}", path, start, end)),
+    }
+}
+
+fn splice(args: &Args) -> Result<(), Error> {
+    let (output, inputs) = match args.paths.split_last() {
+        Some((output, inputs)) if !inputs.is_empty() => (output, inputs),
+        _ => return Err("expected at least one input and an output path".into()),
+    };
+    let mut sources = Vec::new();
+    for input in inputs {
+        let (path, frames) = parse_splice_input(input)?;
+        let reader = VideoReader::<Codec, _>::open(io::BufReader::new(fs::File::open(path)?))?;
+        let count = reader.frame_count();
+        let range = match frames {
+            None => 0..count,
+            Some(frames) if *frames.end() < count => *frames.start()..*frames.end() + 1,
+            Some(frames) => {
+                return Err(format!(
+                    "{} has no frames from {} to {}",
+                    path,
+                    frames.start(),
+                    frames.end()
+                )
+                .into())
+            }
+        };
+        sources.push((path, reader, range));
+    }
+
+    // the inputs are checked against each other before the output is created, so a mismatch
+    // leaves nothing behind
+    let (first_path, first, _) = &sources[0];
+    let shape = |reader: &VideoReader<Codec, _>| {
+        (
+            reader.width(),
+            reader.height(),
+            reader.n_planes(),
+            reader.frame_rate(),
+        )
+    };
+    for (path, reader, _) in &sources[1..] {
+        if shape(reader) != shape(first) {
+            return Err(format!(
+                "{} doesn't match {}: {:?} and {:?} (width, height, planes, frame rate)",
+                path,
+                first_path,
+                shape(reader),
+                shape(first)
+            )
+            .into());
+        }
+    }
+    let metadata = first.metadata().clone();
+    let mut writer = VideoWriter::<Codec, _>::new(
+        io::BufWriter::new(fs::File::create(output)?),
+        first.width(),
+        first.height(),
+        first.n_planes(),
+        metadata,
+    )?;
+    let mut sources = sources
+        .into_iter()
+        .map(|(_, reader, range)| (reader, range))
+        .collect::<Vec<_>>();
+    let count = sources.iter().map(|(_, range)| range.len()).sum::<usize>();
+    if let Err(e) = container::splice(&mut writer, &mut sources) {
+        drop(writer);
+        fs::remove_file(output)?;
+        return Err(e.into());
+    }
+    writer.finalize()?;
+
+    if args.verbosity != Verbosity::Quiet {
+        println!("{} frames from {} inputs", count, sources.len());
+    }
+    Ok(())
+}
+
+fn encode_pipe(args: &Args) -> Result<(), Error> {
+    let output = args.path()?;
+    let (width, height) = match (args.width, args.height) {
+        (Some(width), Some(height)) => (width, height),
+        _ => return Err("encode-pipe needs --width and --height".into()),
+    };
+    let format = args.pixel_format.unwrap_or(PixelFormat::Rgb48Le);
+    let partial_frame = if args.discard_partial {
+        PartialFrame::Discard
+    } else {
+        PartialFrame::Error
+    };
+    let stdin = io::stdin();
+    let mut reader =
+        RawVideoReader::new(stdin.lock(), width, height, format)?.with_partial_frame(partial_frame);
+
+    // nothing is created until the first frame arrives, so an empty input leaves no empty output
+    let started = Instant::now();
+    let mut writer: Option<VideoWriter<Codec, _>> = None;
+    let mut count = 0;
+    while let Some(frame) = reader
+        .read_frame()
+        .map_err(|e| format!("failed to read frame {}: {}", count, e))?
+    {
+        let writer = match &mut writer {
+            Some(writer) => writer,
+            None => writer.insert(
+                VideoWriter::new(
+                    io::BufWriter::new(fs::File::create(output)?),
+                    width,
+                    height,
+                    format.channels(),
+                    SequenceMetadata::new(args.frame_rate.unwrap_or((24, 1))),
+                )?
+                .with_options(args.encode_options()),
+            ),
+        };
+        writer
+            .write_frame(&frame)
+            .map_err(|e| format!("failed to encode frame {}: {}", count, e))?;
+        count += 1;
+
+        if args.verbosity != Verbosity::Quiet {
+            let written = writer.bytes_written()?;
+            print!(
+                "\rframe {}, {:.3}:1 so far, {} elapsed",
+                count,
+                ratio(count * reader.frame_size(), written as usize),
+                hms(started.elapsed())
+            );
+            io::stdout().flush()?;
+        }
+    }
+    let writer = writer.ok_or("no frames on standard input")?;
+    let size = writer.finalize()?.stream_position()?;
+
+    let uncompressed = count * reader.frame_size();
+    if args.verbosity != Verbosity::Quiet {
+        println!(
+            "\n{} frames: {} bytes -> {} bytes ({:.3}:1)",
+            count,
+            uncompressed,
+            size,
+            ratio(uncompressed, size as usize)
+        );
+    }
+    if args.verbosity == Verbosity::Stats {
+        let elapsed = started.elapsed().as_secs_f64();
+        println!(
+            "{:.3} frames per second over {:.3}s",
+            count as f64 / elapsed,
+            elapsed
+        );
+    }
+    Ok(())
+}
+
+fn decode_pipe(args: &Args) -> Result<(), Error> {
+    let input = args.path()?;
+    let mut reader = VideoReader::<Codec, _>::open(io::BufReader::new(fs::File::open(input)?))?;
+    let format = match args.pixel_format {
+        Some(format) if format.channels() != reader.n_planes() => {
+            return Err(format!(
+                "{} has {} planes, which can't be written as {}",
+                input,
+                reader.n_planes(),
+                format.name()
+            )
+            .into())
+        }
+        Some(format) => format,
+        None => PixelFormat::for_channels(reader.n_planes()).ok_or_else(|| {
+            format!(
+                "{} has {} planes, which no pixel format has",
+                input,
+                reader.n_planes()
+            )
+        })?,
+    };
+    let count = reader.frame_count() as u64;
+    let start = args.start.unwrap_or(0);
+    let end = args.end.unwrap_or(u64::MAX).min(count.saturating_sub(1));
+    if count == 0 || start > end {
+        return Err(format!("{} has no frames from {} to {}", input, start, end).into());
+    }
+
+    let stdout = io::stdout();
+    let mut writer = RawVideoWriter::new(stdout.lock(), reader.width(), reader.height(), format)?;
+    for i in start..=end {
+        let frame = reader.read_frame(i as usize)?;
+        let result = writer.write_frame(&frame).and_then(|_| writer.flush());
+        match result {
+            // the reader has gone away, which is its way of saying it has all it wants
+            Err(RawVideoError::IO(e)) if e.kind() == io::ErrorKind::BrokenPipe => break,
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if command == "--help" || command == "-h" || command == "help" {
+        println!("{}", USAGE);
+        return;
+    }
+    let args = match Args::parse(rest) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("hvc: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    let result = match command {
+        "encode" => encode(&args),
+        "decode" => decode(&args),
+        "info" => info(&args),
+        "bench" => bench(&args),
+        "encode-seq" => encode_sequence(&args),
+        "decode-seq" => decode_sequence(&args),
+        "verify" => verify(&args),
+        "splice" => splice(&args),
+        "encode-pipe" => encode_pipe(&args),
+        "decode-pipe" => decode_pipe(&args),
+        _ => {
+            eprintln!("hvc: unknown command: {}\n\n{}", command, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("hvc: {}", e);
+        process::exit(1);
+    }
+}
@@ -7,6 +7,10 @@ pub struct Bitstream<T> {
 }
 
 impl<T: Read> Bitstream<T> {
+    // Bytes are pulled from the reader one at a time so that the bitstream never consumes more
+    // than it needs. Callers decoding several consecutive payloads from one reader rely on that,
+    // so buffering is left to them.
+    #[allow(clippy::unbuffered_bytes)]
     pub fn new(inner: T) -> Self {
         Self {
             inner: inner.bytes(),
@@ -81,6 +85,12 @@ impl<T: Write> BitstreamWriter<T> {
     // Writes the remaining bits to the underlying writer if there are any, and flushes it. If the
     // bitstream is not byte-aligned, zero-bits will be appended until it is.
     pub fn flush(&mut self) -> Result<()> {
+        // whole bytes can be left over if an earlier write failed
+        while self.next_bits_length >= 8 {
+            let next_byte = (self.next_bits >> (self.next_bits_length - 8)) as u8;
+            self.inner.write_all(&[next_byte])?;
+            self.next_bits_length -= 8;
+        }
         if self.next_bits_length > 0 {
             let next_byte = (self.next_bits << (8 - self.next_bits_length)) as u8;
             self.inner.write_all(&[next_byte])?;
@@ -96,3 +106,51 @@ impl<T: Write> Drop for BitstreamWriter<T> {
         let _ = self.flush();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Accepts `limit` bytes, then fails.
+    struct FailingWriter {
+        written: Vec<u8>,
+        limit: usize,
+    }
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            if self.written.len() >= self.limit {
+                return Err(Error::other("disk full"));
+            }
+            self.written.push(buf[0]);
+            Ok(1)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_writer_error() {
+        let mut bitstream = BitstreamWriter::new(FailingWriter {
+            written: Vec::new(),
+            limit: 1,
+        });
+        assert!(bitstream.write_bits(0xabcdef, 28).is_err());
+        assert!(bitstream.flush().is_err());
+        assert_eq!(bitstream.inner.written, [0x0a]);
+
+        // once the writer recovers, flushing writes whatever was left over
+        bitstream.inner.limit = 10;
+        bitstream.flush().unwrap();
+        assert_eq!(bitstream.inner.written, [0x0a, 0xbc, 0xde, 0xf0]);
+
+        // and dropping a bitstream whose writer has failed doesn't panic
+        let mut bitstream = BitstreamWriter::new(FailingWriter {
+            written: Vec::new(),
+            limit: 0,
+        });
+        assert!(bitstream.write_bits(0xabcdef, 28).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+// Reversible color transforms applied to a frame's samples before its planes are handed to the
+// codec.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorTransform {
+    #[default]
+    None = 0,
+    // The lifting-based YCoCg-R transform, as used by JPEG XR and FFV1. The first three planes are
+    // interpreted as R, G, B and replaced by Y, Co, Cg. Any further planes are left alone.
+    //
+    // The chroma differences need 17 bits, so rather than widening the planes, all arithmetic is
+    // done modulo 2^16 with the chroma planes offset by 0x8000. Each lifting step remains exactly
+    // invertible under modular arithmetic, so the transform is lossless for all inputs, and only
+    // chroma differences beyond ±32767 wrap around (which costs bits but never correctness).
+    YCoCgR = 1,
+}
+
+impl ColorTransform {
+    pub fn from_u8(b: u8) -> Option<Self> {
+        match b {
+            0 => Some(Self::None),
+            1 => Some(Self::YCoCgR),
+            _ => None,
+        }
+    }
+
+    // Applies the transform to interleaved samples with the given number of channels.
+    pub fn forward(self, data: &mut [u16], channels: usize) {
+        if self == Self::YCoCgR {
+            for sample in data.chunks_exact_mut(channels) {
+                let (y, co, cg) = rgb_to_ycocg_r(sample[0], sample[1], sample[2]);
+                sample[0] = y;
+                sample[1] = co;
+                sample[2] = cg;
+            }
+        }
+    }
+
+    pub fn inverse(self, data: &mut [u16], channels: usize) {
+        if self == Self::YCoCgR {
+            for sample in data.chunks_exact_mut(channels) {
+                let (r, g, b) = ycocg_r_to_rgb(sample[0], sample[1], sample[2]);
+                sample[0] = r;
+                sample[1] = g;
+                sample[2] = b;
+            }
+        }
+    }
+}
+
+pub fn rgb_to_ycocg_r(r: u16, g: u16, b: u16) -> (u16, u16, u16) {
+    let co = r.wrapping_sub(b) as i16;
+    let t = b.wrapping_add((co >> 1) as u16);
+    let cg = g.wrapping_sub(t) as i16;
+    let y = t.wrapping_add((cg >> 1) as u16);
+    (y, (co as u16) ^ 0x8000, (cg as u16) ^ 0x8000)
+}
+
+pub fn ycocg_r_to_rgb(y: u16, co: u16, cg: u16) -> (u16, u16, u16) {
+    let (co, cg) = ((co ^ 0x8000) as i16, (cg ^ 0x8000) as i16);
+    let t = y.wrapping_sub((cg >> 1) as u16);
+    let g = t.wrapping_add(cg as u16);
+    let b = t.wrapping_sub((co >> 1) as u16);
+    let r = b.wrapping_add(co as u16);
+    (r, g, b)
+}
+
+// The Y'CbCr matrices supported by RGB48Frame::to_ycbcr and to_rgb. BT.2020 is its
+// non-constant-luminance form, which is the same construction as BT.709 with different luma
+// weights.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Matrix {
+    Bt709,
+    Bt2020,
+}
+
+// The conversions are done in fixed point with 24 fractional bits, which keeps coefficient
+// rounding well below a code value, and in integers, so results are the same on every platform.
+const FRACTION_BITS: u32 = 24;
+
+// Coefficients for a matrix with luma weights Kr and Kb, times 2^24 and rounded. Each row of the
+// forward matrix is adjusted to sum to exactly 1 (luma) or 0 (chroma), so grays convert exactly.
+struct Coefficients {
+    // Y, Cb, and Cr from R, G, and B.
+    forward: [[i64; 3]; 3],
+    // 2(1 - Kr), 2Kb(1 - Kb) / Kg, 2Kr(1 - Kr) / Kg, and 2(1 - Kb): R from Cr, G from Cb and Cr,
+    // and B from Cb.
+    inverse: [i64; 4],
+}
+
+impl Matrix {
+    fn coefficients(self) -> Coefficients {
+        match self {
+            // Kr = 0.2126, Kb = 0.0722
+            Self::Bt709 => Coefficients {
+                forward: [
+                    [3566836, 11999065, 1211315],
+                    [-1922201, -6466407, 8388608],
+                    [8388608, -7619421, -769187],
+                ],
+                inverse: [26420760, 3142780, 7853822, 31131802],
+            },
+            // Kr = 0.2627, Kb = 0.0593
+            Self::Bt2020 => Coefficients {
+                forward: [
+                    [4407375, 11374952, 994889],
+                    [-2342604, -6046004, 8388608],
+                    [8388608, -7713924, -674684],
+                ],
+                inverse: [24739683, 2760743, 9585715, 31564654],
+            },
+        }
+    }
+
+    // Converts full-range R'G'B' to Y'CbCr, with chroma offset by 0x8000. Each result is rounded to
+    // the nearest value, with halves rounded up, and clamped to 16 bits.
+    //
+    // Rounding Y, Cb, and Cr means converting back with ycbcr_to_rgb isn't exact: samples can be
+    // off by one code value, but never more.
+    pub fn rgb_to_ycbcr(self, r: u16, g: u16, b: u16) -> (u16, u16, u16) {
+        let m = self.coefficients().forward;
+        let (r, g, b) = (r as i64, g as i64, b as i64);
+        let row = |i: usize| m[i][0] * r + m[i][1] * g + m[i][2] * b;
+        (
+            fixed_to_u16(row(0), 0),
+            fixed_to_u16(row(1), 0x8000),
+            fixed_to_u16(row(2), 0x8000),
+        )
+    }
+
+    // The inverse of rgb_to_ycbcr, rounded and clamped the same way.
+    pub fn ycbcr_to_rgb(self, y: u16, cb: u16, cr: u16) -> (u16, u16, u16) {
+        let [r_cr, g_cb, g_cr, b_cb] = self.coefficients().inverse;
+        let y = (y as i64) << FRACTION_BITS;
+        let (cb, cr) = (cb as i64 - 0x8000, cr as i64 - 0x8000);
+        (
+            fixed_to_u16(y + r_cr * cr, 0),
+            fixed_to_u16(y - g_cb * cb - g_cr * cr, 0),
+            fixed_to_u16(y + b_cb * cb, 0),
+        )
+    }
+
+    // Converts the first three channels of interleaved samples from RGB to Y'CbCr. Any further
+    // channels are left alone.
+    pub fn forward(self, data: &mut [u16], channels: usize) {
+        for sample in data.chunks_exact_mut(channels) {
+            let (y, cb, cr) = self.rgb_to_ycbcr(sample[0], sample[1], sample[2]);
+            sample[0] = y;
+            sample[1] = cb;
+            sample[2] = cr;
+        }
+    }
+
+    pub fn inverse(self, data: &mut [u16], channels: usize) {
+        for sample in data.chunks_exact_mut(channels) {
+            let (r, g, b) = self.ycbcr_to_rgb(sample[0], sample[1], sample[2]);
+            sample[0] = r;
+            sample[1] = g;
+            sample[2] = b;
+        }
+    }
+}
+
+// Full-range BT.709 conversions, as used by SubsampledFrame.
+pub fn rgb_to_ycbcr_709(r: u16, g: u16, b: u16) -> (u16, u16, u16) {
+    Matrix::Bt709.rgb_to_ycbcr(r, g, b)
+}
+
+pub fn ycbcr_709_to_rgb(y: u16, cb: u16, cr: u16) -> (u16, u16, u16) {
+    Matrix::Bt709.ycbcr_to_rgb(y, cb, cr)
+}
+
+fn fixed_to_u16(x: i64, offset: i64) -> u16 {
+    (((x + (1 << (FRACTION_BITS - 1))) >> FRACTION_BITS) + offset).clamp(0, 0xffff) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ycocg_r_roundtrip() {
+        let values = [0, 1, 2, 255, 0x7fff, 0x8000, 0x8001, 0xfffe, 0xffff];
+        for &r in values.iter() {
+            for &g in values.iter() {
+                for &b in values.iter() {
+                    let (y, co, cg) = rgb_to_ycocg_r(r, g, b);
+                    assert_eq!(ycocg_r_to_rgb(y, co, cg), (r, g, b));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_ycbcr_roundtrip() {
+        let mut state = 1u64;
+        let mut random = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 48) as u16
+        };
+        let extremes = [0, 1, 0x8000, 0xfffe, 0xffff];
+        for &matrix in [Matrix::Bt709, Matrix::Bt2020].iter() {
+            let mut samples: Vec<(u16, u16, u16)> = (0..200_000)
+                .map(|_| (random(), random(), random()))
+                .collect();
+            for &r in extremes.iter() {
+                for &g in extremes.iter() {
+                    for &b in extremes.iter() {
+                        samples.push((r, g, b));
+                    }
+                }
+            }
+            for &(r, g, b) in samples.iter() {
+                let (y, cb, cr) = matrix.rgb_to_ycbcr(r, g, b);
+                let (r2, g2, b2) = matrix.ycbcr_to_rgb(y, cb, cr);
+                let error = (r2 as i32 - r as i32)
+                    .abs()
+                    .max((g2 as i32 - g as i32).abs())
+                    .max((b2 as i32 - b as i32).abs());
+                assert!(
+                    error <= 1,
+                    "{:?} {:?} -> {:?}",
+                    matrix,
+                    (r, g, b),
+                    (r2, g2, b2)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_ycbcr_known_colors() {
+        // pure red: Y = Kr * 65535, Cb = -Kr / (2(1 - Kb)) * 65535, and Cr = 65535 / 2, all
+        // offset and rounded, with Cr clamped from 65535.5
+        assert_eq!(
+            Matrix::Bt709.rgb_to_ycbcr(65535, 0, 0),
+            (13933, 25260, 65535)
+        );
+        assert_eq!(
+            Matrix::Bt2020.rgb_to_ycbcr(65535, 0, 0),
+            (17216, 23617, 65535)
+        );
+        for &matrix in [Matrix::Bt709, Matrix::Bt2020].iter() {
+            for &x in [0, 1234, 0x8000, 0xffff].iter() {
+                assert_eq!(matrix.rgb_to_ycbcr(x, x, x), (x, 0x8000, 0x8000));
+                assert_eq!(matrix.ycbcr_to_rgb(x, 0x8000, 0x8000), (x, x, x));
+            }
+        }
+    }
+
+    #[test]
+    fn test_ycocg_r_gray() {
+        // gray has no chroma, which lands mid-range
+        assert_eq!(rgb_to_ycocg_r(1234, 1234, 1234), (1234, 0x8000, 0x8000));
+    }
+}
@@ -0,0 +1,1123 @@
+use super::{
+    batch::{self, BatchError, Threading},
+    error::{CodecError, Result},
+    frame::{Codec, EncodeOptions, RGB48Frame},
+    header::FrameHeader,
+    metadata::SequenceMetadata,
+    progress::Progress,
+};
+use std::{
+    io::{self, Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    ops::Range,
+};
+
+pub const MAGIC: [u8; 4] = *b"HVCV";
+pub const VERSION: u8 = 3;
+pub const INDEX_MAGIC: [u8; 4] = *b"HVCI";
+
+// The size of the file header:
+//
+//   magic          4 bytes  "HVCV"
+//   version        1 byte
+//   width          4 bytes  big-endian
+//   height         4 bytes  big-endian
+//   planes         1 byte
+//   bit depth      1 byte
+//   frame rate     8 bytes  numerator and denominator, 4 bytes each, big-endian
+//   frame count    8 bytes  big-endian
+//   index offset   8 bytes  big-endian
+//
+// The frame count and index offset are zero until the writer is finalized. The header is followed
+// by the metadata section (see SequenceMetadata), which version 1 files don't have, then the
+// frames, each an 8-byte big-endian length, the frame's 8-byte big-endian content hash (see
+// RGB48Frame::content_hash), which versions 1 and 2 don't have, and then the frame as encoded by
+// RGB48Frame::encode_with_header. The length is just that of the encoded frame. The index follows
+// the last frame:
+//
+//   magic          4 bytes  "HVCI"
+//   frame count    8 bytes  big-endian
+//   offsets        8 bytes each, big-endian, the position of each frame's length
+const HEADER_LEN: u64 = 39;
+
+fn invalid_data<S: Into<String>>(message: S) -> CodecError {
+    CodecError::InvalidData(message.into())
+}
+
+fn read_u64<R: Read>(mut source: R) -> Result<u64> {
+    let mut buf = [0; 8];
+    source.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+// Writes a sequence of independently coded frames into a seekable container.
+pub struct VideoWriter<C: Codec, W: Write + Seek> {
+    dest: W,
+    width: usize,
+    height: usize,
+    n_planes: usize,
+    options: EncodeOptions,
+    offsets: Vec<u64>,
+    progress: Option<Box<ProgressCallback>>,
+    _codec: PhantomData<C>,
+}
+
+// See progress::Progress.
+pub type ProgressCallback = dyn FnMut(&Progress) -> bool;
+
+impl<C: Codec, W: Write + Seek> VideoWriter<C, W> {
+    // Writes the file header and metadata at the start of the destination. Every frame must have
+    // the given dimensions and number of planes. The metadata can be just a frame rate, as a
+    // fraction like (24000, 1001).
+    pub fn new<M: Into<SequenceMetadata>>(
+        mut dest: W,
+        width: usize,
+        height: usize,
+        n_planes: usize,
+        metadata: M,
+    ) -> Result<Self> {
+        let metadata = metadata.into();
+        let frame_rate = metadata.frame_rate;
+        if width == 0 || height == 0 || width > u32::MAX as usize || height > u32::MAX as usize {
+            return Err(CodecError::InvalidInput(format!(
+                "invalid frame dimensions: {}x{}",
+                width, height
+            )));
+        }
+        if !(1..=4).contains(&n_planes) {
+            return Err(CodecError::InvalidInput(format!(
+                "unsupported number of planes: {}",
+                n_planes
+            )));
+        }
+        if frame_rate.1 == 0 {
+            return Err(CodecError::InvalidInput(
+                "the frame rate's denominator must be nonzero".to_string(),
+            ));
+        }
+
+        dest.seek(SeekFrom::Start(0))?;
+        dest.write_all(&MAGIC)?;
+        dest.write_all(&[VERSION])?;
+        dest.write_all(&(width as u32).to_be_bytes())?;
+        dest.write_all(&(height as u32).to_be_bytes())?;
+        dest.write_all(&[n_planes as u8, 16])?;
+        dest.write_all(&frame_rate.0.to_be_bytes())?;
+        dest.write_all(&frame_rate.1.to_be_bytes())?;
+        dest.write_all(&0u64.to_be_bytes())?;
+        dest.write_all(&0u64.to_be_bytes())?;
+        metadata.write(&mut dest)?;
+        Ok(Self {
+            dest,
+            width,
+            height,
+            n_planes,
+            options: EncodeOptions::default(),
+            offsets: Vec::new(),
+            progress: None,
+            _codec: PhantomData,
+        })
+    }
+
+    // Sets the options that frames are encoded with.
+    pub fn with_options(mut self, options: EncodeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    // Sets a callback to be called after each plane of each frame is encoded, with the frame's index
+    // in the container. If it returns true, write_frame returns CodecError::Cancelled without
+    // writing anything for that frame, and the destination is flushed. The frames written before
+    // it remain, and the container can still be finalized.
+    pub fn with_progress<F: FnMut(&Progress) -> bool + 'static>(mut self, progress: F) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    // Checks that a frame has the dimensions and number of planes given to new.
+    fn check_frame(frame: &RGB48Frame, width: usize, height: usize, n_planes: usize) -> Result<()> {
+        if frame.width != width
+            || frame.height != height
+            || frame.data.len() != width * height * n_planes
+        {
+            return Err(CodecError::InvalidInput(format!(
+                "expected a {}x{} frame with {} planes",
+                width, height, n_planes
+            )));
+        }
+        Ok(())
+    }
+
+    // Writes a frame already encoded by RGB48Frame::encode_with_header, with its content hash.
+    fn write_encoded(&mut self, encoded: &[u8], content_hash: u64) -> Result<()> {
+        self.offsets.push(self.dest.stream_position()?);
+        self.dest.write_all(&(encoded.len() as u64).to_be_bytes())?;
+        self.dest.write_all(&content_hash.to_be_bytes())?;
+        Ok(self.dest.write_all(encoded)?)
+    }
+
+    // Writes a frame as read by VideoReader::read_encoded_frame, copying it through without decoding
+    // it. Its header must describe a frame that fits the container, and match info, which must have
+    // a content hash: frames from containers older than version 3 have to be decoded and written
+    // again instead. Nothing is written if any of that doesn't hold.
+    pub fn append_encoded_frame(&mut self, encoded: &[u8], info: &FrameInfo) -> Result<()> {
+        let header = RGB48Frame::read_header(encoded)?;
+        if header != info.header {
+            return Err(CodecError::InvalidInput(
+                "the frame's header doesn't match its info".to_string(),
+            ));
+        }
+        if (header.width, header.height, header.n_planes)
+            != (self.width, self.height, self.n_planes)
+        {
+            return Err(CodecError::InvalidInput(format!(
+                "expected a {}x{} frame with {} planes, got {}x{} with {}",
+                self.width,
+                self.height,
+                self.n_planes,
+                header.width,
+                header.height,
+                header.n_planes
+            )));
+        }
+        let content_hash = info.content_hash.ok_or_else(|| {
+            CodecError::InvalidInput("the frame has no content hash to copy".to_string())
+        })?;
+        self.write_encoded(encoded, content_hash)
+    }
+
+    pub fn write_frame(&mut self, frame: &RGB48Frame) -> Result<()> {
+        Self::check_frame(frame, self.width, self.height, self.n_planes)?;
+
+        let mut encoded = Vec::new();
+        match &mut self.progress {
+            None => frame.encode_with_header::<C, _>(&mut encoded, &self.options)?,
+            Some(progress) => {
+                let index = self.offsets.len();
+                let result = frame.encode_with_progress::<C, _, _>(
+                    &mut encoded,
+                    &self.options,
+                    |p: &Progress| progress(&Progress { frame: index, ..*p }),
+                );
+                if let Err(e) = result {
+                    self.dest.flush()?;
+                    return Err(e);
+                }
+            }
+        }
+        self.write_encoded(&encoded, frame.content_hash())
+    }
+
+    // Encodes frames on the threads `threading` asks for, as batch::encode_frames does, and
+    // writes them in the order they come. The returned error's index is the position in the
+    // container of the frame it's about. The frames before it are written, and the container can
+    // still be finalized. The progress callback isn't called.
+    pub fn write_frames<I>(
+        &mut self,
+        frames: I,
+        threading: Threading,
+    ) -> std::result::Result<(), BatchError>
+    where
+        I: IntoIterator<Item = RGB48Frame>,
+    {
+        self.write_frames_with(frames, threading, |_, _| ())
+    }
+
+    // Like write_frames, but calls written after each frame is written, with the frame's position in
+    // the container and the number of bytes written so far.
+    pub fn write_frames_with<I, F>(
+        &mut self,
+        frames: I,
+        threading: Threading,
+        mut written: F,
+    ) -> std::result::Result<(), BatchError>
+    where
+        I: IntoIterator<Item = RGB48Frame>,
+        F: FnMut(usize, u64),
+    {
+        let (width, height, n_planes) = (self.width, self.height, self.n_planes);
+        let options = self.options.clone();
+        let start = self.offsets.len();
+        batch::run(
+            frames
+                .into_iter()
+                .enumerate()
+                .map(|(i, frame)| (start + i, frame)),
+            threading,
+            |frame| {
+                Self::check_frame(frame, width, height, n_planes)?;
+                let mut encoded = Vec::new();
+                frame.encode_with_header::<C, _>(&mut encoded, &options)?;
+                Ok((encoded, frame.content_hash()))
+            },
+            |index, (encoded, content_hash)| {
+                self.write_encoded(&encoded, content_hash)?;
+                written(index, self.bytes_written()?);
+                Ok(())
+            },
+        )
+    }
+
+    // The number of bytes written so far, from the start of the file header.
+    pub fn bytes_written(&mut self) -> Result<u64> {
+        Ok(self.dest.stream_position()?)
+    }
+
+    // Writes the index and fills in the file header. A container that's never finalized is still
+    // readable, but VideoReader has to scan it to find the frames.
+    pub fn finalize(mut self) -> Result<W> {
+        let index_offset = self.dest.stream_position()?;
+        self.dest.write_all(&INDEX_MAGIC)?;
+        self.dest
+            .write_all(&(self.offsets.len() as u64).to_be_bytes())?;
+        for offset in &self.offsets {
+            self.dest.write_all(&offset.to_be_bytes())?;
+        }
+        let end = self.dest.stream_position()?;
+
+        self.dest.seek(SeekFrom::Start(HEADER_LEN - 16))?;
+        self.dest
+            .write_all(&(self.offsets.len() as u64).to_be_bytes())?;
+        self.dest.write_all(&index_offset.to_be_bytes())?;
+        self.dest.seek(SeekFrom::Start(end))?;
+        self.dest.flush()?;
+        Ok(self.dest)
+    }
+}
+
+// Reads frames from a container written by VideoWriter, in any order.
+pub struct VideoReader<C: Codec, R: Read + Seek> {
+    source: R,
+    width: usize,
+    height: usize,
+    n_planes: usize,
+    metadata: SequenceMetadata,
+    version: u8,
+    // Where the first frame starts, after the header and metadata.
+    data_start: u64,
+    offsets: Vec<u64>,
+    index_rebuilt: bool,
+    progress: Option<Box<ProgressCallback>>,
+    _codec: PhantomData<C>,
+}
+
+impl<C: Codec, R: Read + Seek> VideoReader<C, R> {
+    // Reads the file header, metadata, and index. Version 1 files have no metadata section, so
+    // everything but their frame rate is left unspecified. If the index is missing (because the writer was never
+    // finalized) or inconsistent with the frames, it's rebuilt by scanning the frames' lengths from
+    // the start, which recovers every complete frame.
+    pub fn open(mut source: R) -> Result<Self> {
+        let mut buf = [0; HEADER_LEN as usize];
+        source.seek(SeekFrom::Start(0))?;
+        source.read_exact(&mut buf)?;
+        if buf[..4] != MAGIC {
+            return Err(CodecError::InvalidHeader(
+                "not a video container".to_string(),
+            ));
+        }
+        let version = buf[4];
+        if !(1..=VERSION).contains(&version) {
+            return Err(CodecError::UnsupportedVersion(buf[4]));
+        }
+        let u32_at = |i: usize| u32::from_be_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        let u64_at = |i: usize| (u32_at(i) as u64) << 32 | u32_at(i + 4) as u64;
+        let (width, height) = (u32_at(5) as usize, u32_at(9) as usize);
+        let n_planes = buf[13] as usize;
+        if width == 0 || height == 0 || !(1..=4).contains(&n_planes) || buf[14] != 16 {
+            return Err(CodecError::InvalidHeader(
+                "invalid video header".to_string(),
+            ));
+        }
+        let frame_rate = (u32_at(15), u32_at(19));
+        let (frame_count, index_offset) = (u64_at(23), u64_at(31));
+        let (metadata, data_start) = if version == 1 {
+            (SequenceMetadata::new(frame_rate), HEADER_LEN)
+        } else {
+            let (metadata, len) = SequenceMetadata::read(&mut source, frame_rate)?;
+            (metadata, HEADER_LEN + len)
+        };
+
+        let len = source.seek(SeekFrom::End(0))?;
+        let mut ret = Self {
+            source,
+            width,
+            height,
+            n_planes,
+            metadata,
+            version,
+            data_start,
+            offsets: Vec::new(),
+            index_rebuilt: false,
+            progress: None,
+            _codec: PhantomData,
+        };
+        match ret.read_index(frame_count, index_offset, len) {
+            Ok(offsets) => ret.offsets = offsets,
+            Err(_) => {
+                let end = if index_offset >= data_start && index_offset <= len {
+                    index_offset
+                } else {
+                    len
+                };
+                ret.offsets = ret.scan(end)?;
+                ret.index_rebuilt = true;
+            }
+        }
+        Ok(ret)
+    }
+
+    // Reads the index, verifying that the frames it points to are laid out back to back between
+    // the header and the index.
+    fn read_index(&mut self, frame_count: u64, index_offset: u64, len: u64) -> Result<Vec<u64>> {
+        if index_offset < self.data_start
+            || index_offset > len
+            || frame_count > (len - index_offset) / 8
+        {
+            return Err(invalid_data("invalid index offset or frame count"));
+        }
+        self.source.seek(SeekFrom::Start(index_offset))?;
+        let mut magic = [0; 4];
+        self.source.read_exact(&mut magic)?;
+        if magic != INDEX_MAGIC || read_u64(&mut self.source)? != frame_count {
+            return Err(invalid_data("invalid index"));
+        }
+        let mut offsets = Vec::with_capacity(frame_count as usize);
+        for _ in 0..frame_count {
+            offsets.push(read_u64(&mut self.source)?);
+        }
+
+        let mut expected = self.data_start;
+        for &offset in &offsets {
+            if offset != expected {
+                return Err(invalid_data("index doesn't match the frames"));
+            }
+            self.source.seek(SeekFrom::Start(offset))?;
+            expected = read_u64(&mut self.source)?
+                .checked_add(self.frame_header_len())
+                .and_then(|len| offset.checked_add(len))
+                .ok_or_else(|| invalid_data("invalid frame length"))?;
+        }
+        if expected != index_offset {
+            return Err(invalid_data("index doesn't match the frames"));
+        }
+        Ok(offsets)
+    }
+
+    // Finds the frames by following their lengths from the metadata up to end. A frame that would
+    // run past end is incomplete, and it and anything after it are dropped.
+    fn scan(&mut self, end: u64) -> Result<Vec<u64>> {
+        let mut offsets = Vec::new();
+        let mut offset = self.data_start;
+        let header_len = self.frame_header_len();
+        while offset + header_len <= end {
+            self.source.seek(SeekFrom::Start(offset))?;
+            let next = match (offset + header_len).checked_add(read_u64(&mut self.source)?) {
+                Some(next) if next <= end => next,
+                _ => break,
+            };
+            offsets.push(offset);
+            offset = next;
+        }
+        Ok(offsets)
+    }
+
+    // Sets a callback to be called after each plane of each frame is decoded, with the frame's index
+    // in the container. If it returns true, read_frame returns CodecError::Cancelled.
+    pub fn with_progress<F: FnMut(&Progress) -> bool + 'static>(mut self, progress: F) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn n_planes(&self) -> usize {
+        self.n_planes
+    }
+
+    pub fn frame_rate(&self) -> (u32, u32) {
+        self.metadata.frame_rate
+    }
+
+    pub fn metadata(&self) -> &SequenceMetadata {
+        &self.metadata
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.offsets.len()
+    }
+
+    // Whether the index was missing or corrupt, and the frames had to be found by scanning.
+    pub fn index_rebuilt(&self) -> bool {
+        self.index_rebuilt
+    }
+
+    // The size of what precedes each encoded frame: its length and, from version 3 on, its content
+    // hash.
+    fn frame_header_len(&self) -> u64 {
+        if self.version < 3 {
+            8
+        } else {
+            16
+        }
+    }
+
+    // Seeks to a frame and reads its length and content hash, if it has one.
+    fn seek_frame(&mut self, index: usize) -> Result<(u64, Option<u64>)> {
+        let offset = *self.offsets.get(index).ok_or_else(|| {
+            CodecError::InvalidInput(format!(
+                "frame {} is out of range for {} frames",
+                index,
+                self.offsets.len()
+            ))
+        })?;
+        self.source.seek(SeekFrom::Start(offset))?;
+        let len = read_u64(&mut self.source)?;
+        let hash = if self.version < 3 {
+            None
+        } else {
+            Some(read_u64(&mut self.source)?)
+        };
+        Ok((len, hash))
+    }
+
+    // The content hash stored with a frame (see RGB48Frame::content_hash), which containers older
+    // than version 3 don't have.
+    pub fn content_hash(&mut self, index: usize) -> Result<Option<u64>> {
+        Ok(self.seek_frame(index)?.1)
+    }
+
+    // Like read_frame, but also checks that the frame's content hash matches the one stored with
+    // it, returning CodecError::ContentHashMismatch if not. Frames without a stored hash aren't
+    // checked.
+    pub fn verify_frame(&mut self, index: usize) -> Result<RGB48Frame> {
+        let frame = self.read_frame(index)?;
+        match self.content_hash(index)? {
+            Some(hash) if hash != frame.content_hash() => {
+                Err(CodecError::ContentHashMismatch { frame: index })
+            }
+            _ => Ok(frame),
+        }
+    }
+
+    // Decodes every frame in turn, checking each against its stored content hash as verify_frame
+    // does, and calls progress with each frame's index and how it fared. A frame that fails doesn't
+    // stop the others from being checked, and only one decoded frame is held at a time.
+    pub fn verify<F: FnMut(usize, &FrameStatus)>(&mut self, mut progress: F) -> VerifySummary {
+        let mut frames = Vec::with_capacity(self.frame_count());
+        for index in 0..self.frame_count() {
+            let status = match self.verify_frame(index) {
+                Ok(_) if self.version < 3 => FrameStatus::Unchecked,
+                Ok(_) => FrameStatus::Passed,
+                Err(e) => FrameStatus::Failed(e.to_string()),
+            };
+            progress(index, &status);
+            frames.push(status);
+        }
+        VerifySummary { frames }
+    }
+
+    // Reads a frame's bytes and content hash, if it has one.
+    fn read_encoded(&mut self, index: usize) -> Result<(Vec<u8>, Option<u64>)> {
+        let (len, hash) = self.seek_frame(index)?;
+        let mut encoded = Vec::new();
+        (&mut self.source).take(len).read_to_end(&mut encoded)?;
+        if encoded.len() as u64 != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("frame {} is truncated", index),
+            )
+            .into());
+        }
+        Ok((encoded, hash))
+    }
+
+    // Reads a frame as encoded by RGB48Frame::encode_with_header, without decoding it, along with
+    // its header and content hash, for VideoWriter::append_encoded_frame. Only the header is
+    // checked against the container.
+    pub fn read_encoded_frame(&mut self, index: usize) -> Result<(Vec<u8>, FrameInfo)> {
+        let (encoded, content_hash) = self.read_encoded(index)?;
+        let header = RGB48Frame::read_header(&*encoded)?;
+        if (header.width, header.height, header.n_planes)
+            != (self.width, self.height, self.n_planes)
+        {
+            return Err(invalid_data(format!(
+                "frame {} doesn't match the container's dimensions",
+                index
+            )));
+        }
+        Ok((
+            encoded,
+            FrameInfo {
+                header,
+                content_hash,
+            },
+        ))
+    }
+
+    pub fn read_frame(&mut self, index: usize) -> Result<RGB48Frame> {
+        let (encoded, _) = self.read_encoded(index)?;
+
+        let frame = match &mut self.progress {
+            None => RGB48Frame::decode_with_header::<C, _>(&*encoded)?,
+            Some(progress) => RGB48Frame::decode_with_progress::<C, _, _>(&*encoded, |p| {
+                progress(&Progress { frame: index, ..*p })
+            })?,
+        };
+        if frame.width != self.width
+            || frame.height != self.height
+            || frame.data.len() != self.width * self.height * self.n_planes
+        {
+            return Err(invalid_data(format!(
+                "frame {} doesn't match the container's dimensions",
+                index
+            )));
+        }
+        Ok(frame)
+    }
+
+    // Returns an iterator over the frames in order.
+    pub fn frames(&mut self) -> Frames<'_, C, R> {
+        Frames {
+            reader: self,
+            next: 0,
+        }
+    }
+}
+
+// What VideoReader::read_encoded_frame knows about a frame without decoding it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameInfo {
+    pub header: FrameHeader,
+    // The frame's content hash (see RGB48Frame::content_hash), which containers older than version
+    // 3 don't store.
+    pub content_hash: Option<u64>,
+}
+
+// Copies the frames in the given range of each source, one source after another, to the end of
+// dest, without decoding or re-encoding them. Every source must have the dimensions and number of
+// planes of dest, and every range must be within its source, which is all checked before anything
+// is written. A frame that can't be copied stops the splice with the frames before it written, and
+// dest can still be finalized.
+pub fn splice<C: Codec, R: Read + Seek, W: Write + Seek>(
+    dest: &mut VideoWriter<C, W>,
+    sources: &mut [(VideoReader<C, R>, Range<usize>)],
+) -> Result<()> {
+    for (i, (source, range)) in sources.iter().enumerate() {
+        if (source.width, source.height, source.n_planes)
+            != (dest.width, dest.height, dest.n_planes)
+        {
+            return Err(CodecError::InvalidInput(format!(
+                "source {} is {}x{} with {} planes, but the destination is {}x{} with {}",
+                i,
+                source.width,
+                source.height,
+                source.n_planes,
+                dest.width,
+                dest.height,
+                dest.n_planes
+            )));
+        }
+        if range.start > range.end || range.end > source.frame_count() {
+            return Err(CodecError::InvalidInput(format!(
+                "frames {:?} are out of range for source {}, which has {}",
+                range,
+                i,
+                source.frame_count()
+            )));
+        }
+        if source.version < 3 && !range.is_empty() {
+            return Err(CodecError::InvalidInput(format!(
+                "source {} is a version {} container, whose frames have no content hashes to copy",
+                i, source.version
+            )));
+        }
+    }
+    for (source, range) in sources.iter_mut() {
+        for index in range.clone() {
+            let (encoded, info) = source.read_encoded_frame(index)?;
+            dest.append_encoded_frame(&encoded, &info)?;
+        }
+    }
+    Ok(())
+}
+
+// How a frame fared in VideoReader::verify.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrameStatus {
+    // The frame decoded to samples that match its stored content hash.
+    Passed,
+    // The frame decoded, but is from a container older than version 3, which stores no hashes to
+    // check it against.
+    Unchecked,
+    // The frame couldn't be read or decoded, or didn't match its hash, for the given reason.
+    Failed(String),
+}
+
+// The outcome of VideoReader::verify, with the status of each frame in order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifySummary {
+    pub frames: Vec<FrameStatus>,
+}
+
+impl VerifySummary {
+    // True if no frame failed.
+    pub fn passed(&self) -> bool {
+        self.failed().next().is_none()
+    }
+
+    // The indices of the frames that failed, with the reasons.
+    pub fn failed(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.frames
+            .iter()
+            .enumerate()
+            .filter_map(|(i, status)| match status {
+                FrameStatus::Failed(reason) => Some((i, reason.as_str())),
+                _ => None,
+            })
+    }
+}
+
+pub struct Frames<'a, C: Codec, R: Read + Seek> {
+    reader: &'a mut VideoReader<C, R>,
+    next: usize,
+}
+
+impl<C: Codec, R: Read + Seek> Iterator for Frames<'_, C, R> {
+    type Item = Result<RGB48Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == self.reader.frame_count() {
+            return None;
+        }
+        self.next += 1;
+        Some(self.reader.read_frame(self.next - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{
+            codec::Codec,
+            frame::{self, Plane},
+            metadata::{ColorRange, ColorStandard},
+        },
+        *,
+    };
+    use std::{convert::TryInto, io::Cursor};
+
+    fn test_frames(n: usize) -> Vec<RGB48Frame> {
+        (0..n)
+            .map(|i| RGB48Frame {
+                data: (0..24 * 16 * 3)
+                    .map(|j| ((i * 1000 + j * 37) % 4096) as u16)
+                    .collect(),
+                width: 24,
+                height: 16,
+                bit_depth: 16,
+            })
+            .collect()
+    }
+
+    fn write(frames: &[RGB48Frame]) -> VideoWriter<Codec, Cursor<Vec<u8>>> {
+        let mut writer = VideoWriter::new(Cursor::new(Vec::new()), 24, 16, 3, (24, 1)).unwrap();
+        for frame in frames {
+            writer.write_frame(frame).unwrap();
+        }
+        writer
+    }
+
+    fn read_all(encoded: Vec<u8>) -> (bool, Vec<RGB48Frame>) {
+        let mut reader = VideoReader::<Codec, _>::open(Cursor::new(encoded)).unwrap();
+        let frames = reader.frames().collect::<Result<Vec<_>>>().unwrap();
+        (reader.index_rebuilt(), frames)
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_container_roundtrip() {
+        let frames = [
+            RGB48Frame::open("src/testdata/tears_of_steel_12130.tif").unwrap(),
+            RGB48Frame::open("src/testdata/tears_of_steel_12209.tif").unwrap(),
+        ];
+        let mut writer =
+            VideoWriter::<Codec, _>::new(Cursor::new(Vec::new()), 4096, 1714, 3, (24, 1)).unwrap();
+        for frame in frames.iter() {
+            writer.write_frame(frame).unwrap();
+        }
+        let encoded = writer.finalize().unwrap().into_inner();
+
+        let mut reader = VideoReader::<Codec, _>::open(Cursor::new(encoded)).unwrap();
+        assert_eq!(reader.frame_count(), 2);
+        assert_eq!((reader.width(), reader.height()), (4096, 1714));
+        assert_eq!(reader.n_planes(), 3);
+        assert_eq!(reader.frame_rate(), (24, 1));
+        assert!(!reader.index_rebuilt());
+
+        // out of order
+        assert!(reader.read_frame(1).unwrap() == frames[1]);
+        assert!(reader.read_frame(0).unwrap() == frames[0]);
+        let err = reader.read_frame(2).map(|_| ()).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidInput(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_container_write_frames() {
+        // writing frames in parallel gives the same container as writing them one by one
+        let frames = test_frames(7);
+        let expected = write(&frames).finalize().unwrap().into_inner();
+        let mut writer =
+            VideoWriter::<Codec, _>::new(Cursor::new(Vec::new()), 24, 16, 3, (24, 1)).unwrap();
+        writer.write_frame(&frames[0]).unwrap();
+        let mut written = Vec::new();
+        writer
+            .write_frames_with(
+                frames[1..].iter().cloned(),
+                Threading::Threads(3),
+                |index, bytes| written.push((index, bytes)),
+            )
+            .unwrap();
+        assert_eq!(
+            written.iter().map(|&(index, _)| index).collect::<Vec<_>>(),
+            (1..7).collect::<Vec<_>>()
+        );
+        assert_eq!(written[5].1, writer.bytes_written().unwrap());
+        assert_eq!(writer.finalize().unwrap().into_inner(), expected);
+
+        // a frame of the wrong size stops it, and the frames before it can still be read
+        let mut frames = test_frames(4);
+        frames[2].width = 12;
+        let mut writer =
+            VideoWriter::<Codec, _>::new(Cursor::new(Vec::new()), 24, 16, 3, (24, 1)).unwrap();
+        let err = writer
+            .write_frames(frames.iter().cloned(), Threading::Threads(2))
+            .unwrap_err();
+        assert_eq!(err.index, 2);
+        assert!(matches!(err.source, CodecError::InvalidInput(_)));
+        let (_, decoded) = read_all(writer.finalize().unwrap().into_inner());
+        assert!(decoded == frames[..2]);
+    }
+
+    #[test]
+    fn test_container_verify() {
+        let frames = test_frames(2);
+        let mut encoded = write(&frames).finalize().unwrap().into_inner();
+        let mut reader = VideoReader::<Codec, _>::open(Cursor::new(encoded.clone())).unwrap();
+        let mut seen = Vec::new();
+        let summary = reader.verify(|index, status| seen.push((index, status.clone())));
+        assert!(summary.passed());
+        assert_eq!(summary.frames, vec![FrameStatus::Passed; 2]);
+        assert_eq!(
+            seen,
+            vec![(0, FrameStatus::Passed), (1, FrameStatus::Passed)]
+        );
+
+        // flip a byte in the middle of the second frame's payload
+        let (offset, len) = (reader.offsets[1], reader.seek_frame(1).unwrap().0);
+        encoded[(offset + 16 + len / 2) as usize] ^= 0x10;
+        let mut reader = VideoReader::<Codec, _>::open(Cursor::new(encoded)).unwrap();
+        let summary = reader.verify(|_, _| ());
+        assert!(!summary.passed());
+        assert_eq!(summary.frames[0], FrameStatus::Passed);
+        assert_eq!(
+            summary.failed().map(|(index, _)| index).collect::<Vec<_>>(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_container_iterator() {
+        let frames = test_frames(5);
+        let encoded = write(&frames).finalize().unwrap().into_inner();
+        let (rebuilt, decoded) = read_all(encoded);
+        assert!(!rebuilt);
+        assert!(decoded == frames);
+
+        // an empty container
+        let encoded = write(&[]).finalize().unwrap().into_inner();
+        let (rebuilt, decoded) = read_all(encoded);
+        assert!(!rebuilt);
+        assert!(decoded.is_empty());
+    }
+
+    #[test]
+    fn test_container_recovery() {
+        let frames = test_frames(3);
+        let encoded = write(&frames).finalize().unwrap().into_inner();
+        let index_offset = u64::from_be_bytes(encoded[31..39].try_into().unwrap()) as usize;
+
+        // never finalized
+        let unfinalized = write(&frames).dest.into_inner();
+        assert_eq!(unfinalized.len(), index_offset);
+        let (rebuilt, decoded) = read_all(unfinalized.clone());
+        assert!(rebuilt);
+        assert!(decoded == frames);
+
+        // never finalized, and the last frame was cut short
+        let (rebuilt, decoded) = read_all(unfinalized[..unfinalized.len() - 10].to_vec());
+        assert!(rebuilt);
+        assert!(decoded[..] == frames[..2]);
+
+        // an index entry that points to the wrong place
+        let mut corrupt = encoded.clone();
+        corrupt[index_offset + 12 + 15] ^= 1;
+        let (rebuilt, decoded) = read_all(corrupt);
+        assert!(rebuilt);
+        assert!(decoded == frames);
+
+        // a frame count that runs past the end of the file
+        let mut corrupt = encoded.clone();
+        corrupt[23] = 0xff;
+        let (rebuilt, decoded) = read_all(corrupt);
+        assert!(rebuilt);
+        assert!(decoded == frames);
+
+        // a frame length so large that the frame's end overflows, which leaves nothing readable
+        let mut corrupt = encoded.clone();
+        let first = u64::from_be_bytes(
+            corrupt[index_offset + 12..index_offset + 20]
+                .try_into()
+                .unwrap(),
+        ) as usize;
+        corrupt[first..first + 8].copy_from_slice(&u64::MAX.to_be_bytes());
+        let (rebuilt, decoded) = read_all(corrupt);
+        assert!(rebuilt);
+        assert!(decoded.is_empty());
+
+        // a truncated index
+        let (rebuilt, decoded) = read_all(encoded[..encoded.len() - 4].to_vec());
+        assert!(rebuilt);
+        assert!(decoded == frames);
+
+        // a bad file header is an error
+        let mut corrupt = encoded;
+        corrupt[0] = b'X';
+        let err = VideoReader::<Codec, _>::open(Cursor::new(corrupt))
+            .map(|_| ())
+            .unwrap_err();
+        assert!(matches!(err, CodecError::InvalidHeader(_)), "{:?}", err);
+    }
+
+    // Replaces a finalized container's metadata section with section, moving the frames and
+    // fixing up the index to match.
+    fn replace_metadata(encoded: &[u8], section: &[u8]) -> Vec<u8> {
+        let old_len = 4 + u32::from_be_bytes(encoded[39..43].try_into().unwrap()) as usize;
+        let mut ret = encoded[..39].to_vec();
+        ret.extend_from_slice(section);
+        ret.extend_from_slice(&encoded[39 + old_len..]);
+        let shift = |offset: u64| offset + section.len() as u64 - old_len as u64;
+        let index_offset = shift(u64::from_be_bytes(ret[31..39].try_into().unwrap()));
+        ret[31..39].copy_from_slice(&index_offset.to_be_bytes());
+        let frame_count = u64::from_be_bytes(ret[23..31].try_into().unwrap()) as usize;
+        for i in 0..frame_count {
+            let at = index_offset as usize + 12 + i * 8;
+            let offset = shift(u64::from_be_bytes(ret[at..at + 8].try_into().unwrap()));
+            ret[at..at + 8].copy_from_slice(&offset.to_be_bytes());
+        }
+        ret
+    }
+
+    #[test]
+    fn test_container_metadata() {
+        let frames = test_frames(2);
+        let metadata = SequenceMetadata::new((30000, 1001))
+            .with_color(ColorStandard::Bt709)
+            .with_range(ColorRange::Limited)
+            .with_tag("camera serial", "XR-0042");
+        let mut writer =
+            VideoWriter::<Codec, _>::new(Cursor::new(Vec::new()), 24, 16, 3, metadata.clone())
+                .unwrap();
+        for frame in frames.iter() {
+            writer.write_frame(frame).unwrap();
+        }
+        let encoded = writer.finalize().unwrap().into_inner();
+        let reader = VideoReader::<Codec, _>::open(Cursor::new(encoded.clone())).unwrap();
+        assert_eq!(reader.metadata(), &metadata);
+        assert_eq!(reader.frame_rate(), (30000, 1001));
+
+        // a record from a newer version, added to the metadata section, survives a rewrite
+        let record = [0x80, 0, 0, 0, 2, 0xab, 0xcd];
+        let section_len = u32::from_be_bytes(encoded[39..43].try_into().unwrap()) as usize;
+        let mut section = ((section_len + record.len()) as u32).to_be_bytes().to_vec();
+        section.extend_from_slice(&encoded[43..43 + section_len]);
+        section.extend_from_slice(&record);
+        let mut reader =
+            VideoReader::<Codec, _>::open(Cursor::new(replace_metadata(&encoded, &section)))
+                .unwrap();
+        assert!(!reader.index_rebuilt());
+        assert_eq!(reader.metadata().tags, metadata.tags);
+        let decoded = reader.frames().collect::<Result<Vec<_>>>().unwrap();
+        assert!(decoded == frames);
+
+        let mut writer = VideoWriter::<Codec, _>::new(
+            Cursor::new(Vec::new()),
+            24,
+            16,
+            3,
+            reader.metadata().clone(),
+        )
+        .unwrap();
+        for frame in decoded.iter() {
+            writer.write_frame(frame).unwrap();
+        }
+        let rewritten = writer.finalize().unwrap().into_inner();
+        assert_eq!(&rewritten[39..39 + section.len()], &section[..]);
+        let reader = VideoReader::<Codec, _>::open(Cursor::new(rewritten)).unwrap();
+        assert_eq!(reader.metadata().range, ColorRange::Limited);
+        assert!(!reader.index_rebuilt());
+    }
+
+    // Converts a finalized container to version 2 by removing the frames' content hashes.
+    fn to_version_2(encoded: &[u8]) -> Vec<u8> {
+        let u64_at = |at: usize| u64::from_be_bytes(encoded[at..at + 8].try_into().unwrap());
+        let data_start = 43 + u32::from_be_bytes(encoded[39..43].try_into().unwrap()) as usize;
+        let (frame_count, index_offset) = (u64_at(23) as usize, u64_at(31) as usize);
+        let mut ret = encoded[..data_start].to_vec();
+        let mut offsets = Vec::new();
+        for i in 0..frame_count {
+            let offset = u64_at(index_offset + 12 + i * 8) as usize;
+            let len = u64_at(offset) as usize;
+            offsets.push(ret.len() as u64);
+            ret.extend_from_slice(&encoded[offset..offset + 8]);
+            ret.extend_from_slice(&encoded[offset + 16..offset + 16 + len]);
+        }
+        let index_offset = ret.len() as u64;
+        ret.extend_from_slice(&INDEX_MAGIC);
+        ret.extend_from_slice(&(frame_count as u64).to_be_bytes());
+        for offset in offsets {
+            ret.extend_from_slice(&offset.to_be_bytes());
+        }
+        ret[4] = 2;
+        ret[31..39].copy_from_slice(&index_offset.to_be_bytes());
+        ret
+    }
+
+    #[test]
+    fn test_container_content_hash() {
+        let frames = test_frames(3);
+        let encoded = write(&frames).finalize().unwrap().into_inner();
+        let mut reader = VideoReader::<Codec, _>::open(Cursor::new(encoded.clone())).unwrap();
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(reader.content_hash(i).unwrap(), Some(frame.content_hash()));
+            assert!(reader.verify_frame(i).unwrap() == *frame);
+        }
+
+        // a frame whose stored hash doesn't match
+        let offset = u64::from_be_bytes(encoded[31..39].try_into().unwrap()) as usize + 12 + 8;
+        let offset = u64::from_be_bytes(encoded[offset..offset + 8].try_into().unwrap()) as usize;
+        let mut corrupt = encoded.clone();
+        corrupt[offset + 15] ^= 1;
+        let mut reader = VideoReader::<Codec, _>::open(Cursor::new(corrupt)).unwrap();
+        assert!(reader.verify_frame(0).unwrap() == frames[0]);
+        assert!(reader.read_frame(1).unwrap() == frames[1]);
+        let err = reader.verify_frame(1).map(|_| ()).unwrap_err();
+        assert!(
+            matches!(err, CodecError::ContentHashMismatch { frame: 1 }),
+            "{:?}",
+            err
+        );
+
+        // version 2 containers have no hashes to check
+        let mut reader =
+            VideoReader::<Codec, _>::open(Cursor::new(to_version_2(&encoded))).unwrap();
+        assert!(!reader.index_rebuilt());
+        assert_eq!(reader.content_hash(2).unwrap(), None);
+        assert!(reader.verify_frame(2).unwrap() == frames[2]);
+    }
+
+    #[test]
+    fn test_container_version_1() {
+        // version 1 containers are like version 2, but without a metadata section
+        let frames = test_frames(3);
+        let encoded = write(&frames).finalize().unwrap().into_inner();
+        let mut old = replace_metadata(&to_version_2(&encoded), &[]);
+        old[4] = 1;
+
+        let mut reader = VideoReader::<Codec, _>::open(Cursor::new(old)).unwrap();
+        assert!(!reader.index_rebuilt());
+        assert_eq!(reader.metadata(), &SequenceMetadata::new((24, 1)));
+        let decoded = reader.frames().collect::<Result<Vec<_>>>().unwrap();
+        assert!(decoded == frames);
+    }
+
+    // A codec for containers that must never be encoded or decoded, only copied.
+    struct Untouched;
+
+    impl frame::Codec for Untouched {
+        fn encode<T: AsRef<[u16]>, W: Write>(_: &Plane<T>, _: W) -> Result<()> {
+            panic!("encoded a frame")
+        }
+
+        fn decode<T: AsMut<[u16]>, R: Read>(_: R, _: &mut Plane<T>) -> Result<()> {
+            panic!("decoded a frame")
+        }
+    }
+
+    #[test]
+    fn test_splice() {
+        let frames = test_frames(8);
+        let a = write(&frames[..5]).finalize().unwrap().into_inner();
+        let b = write(&frames[5..]).finalize().unwrap().into_inner();
+        let open = |encoded: &Vec<u8>| {
+            VideoReader::<Untouched, _>::open(Cursor::new(encoded.clone())).unwrap()
+        };
+        let new_writer = || {
+            VideoWriter::<Untouched, _>::new(Cursor::new(Vec::new()), 24, 16, 3, (24, 1)).unwrap()
+        };
+
+        // the frames are copied as they are, so the codec is never used
+        let mut writer = new_writer();
+        splice(&mut writer, &mut [(open(&b), 1..3), (open(&a), 0..2)]).unwrap();
+        let spliced = writer.finalize().unwrap().into_inner();
+        let (index_rebuilt, decoded) = read_all(spliced.clone());
+        assert!(!index_rebuilt);
+        assert!(decoded == [&frames[6..8], &frames[..2]].concat());
+        let mut reader = open(&spliced);
+        let (encoded, info) = reader.read_encoded_frame(2).unwrap();
+        assert_eq!(info.content_hash, Some(frames[0].content_hash()));
+        assert_eq!(
+            (info.header.width, info.header.height, info.header.n_planes),
+            (24, 16, 3)
+        );
+        assert_eq!(open(&a).read_encoded_frame(0).unwrap(), (encoded, info));
+
+        // mismatched sources and ranges are caught before anything is written
+        let other = VideoWriter::<Codec, _>::new(Cursor::new(Vec::new()), 16, 16, 3, (24, 1))
+            .unwrap()
+            .finalize()
+            .unwrap()
+            .into_inner();
+        for (source, range) in [(&other, 0..0), (&a, 3..6), (&a, 6..6)].iter() {
+            let mut writer = new_writer();
+            let start = writer.bytes_written().unwrap();
+            let err = splice(
+                &mut writer,
+                &mut [(open(&b), 0..3), (open(source), range.clone())],
+            )
+            .unwrap_err();
+            assert!(matches!(err, CodecError::InvalidInput(_)), "{:?}", err);
+            assert_eq!(writer.bytes_written().unwrap(), start);
+        }
+
+        // and so are frames that don't fit, or whose info doesn't match them
+        let mut writer =
+            VideoWriter::<Untouched, _>::new(Cursor::new(Vec::new()), 16, 16, 3, (24, 1)).unwrap();
+        let start = writer.bytes_written().unwrap();
+        let (encoded, mut info) = open(&a).read_encoded_frame(1).unwrap();
+        assert!(writer.append_encoded_frame(&encoded, &info).is_err());
+        let mut writer = new_writer();
+        info.header.bit_depth = 12;
+        assert!(writer.append_encoded_frame(&encoded, &info).is_err());
+        info = open(&a).read_encoded_frame(1).unwrap().1;
+        info.content_hash = None;
+        assert!(writer.append_encoded_frame(&encoded, &info).is_err());
+        assert!(writer.append_encoded_frame(&encoded[..10], &info).is_err());
+        assert_eq!(writer.bytes_written().unwrap(), start);
+    }
+}
@@ -0,0 +1,267 @@
+use super::frame::RGB48Frame;
+
+// How RGB48Frame::to_8bit reduces samples to 8 bits. Every mode is deterministic: the same frame
+// always produces the same bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DitherMode {
+    // Rounds each sample to the nearest 8-bit value, (x * 255 + 32767) / 65535. Smooth 16-bit
+    // gradients turn into visible bands.
+    None,
+    // Adds an 8x8 Bayer matrix threshold before truncating, trading bands for a fine regular
+    // pattern.
+    Ordered,
+    // Floyd-Steinberg error diffusion, scanning each row left to right. Each sample's rounding
+    // error is passed on to its unvisited neighbors, so local averages match the original.
+    FloydSteinberg,
+}
+
+// Thresholds for ordered dithering, from 0 to 63.
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+// Reduces the given channels of interleaved samples to 8 bits, writing them interleaved in the
+// order given.
+fn downconvert(frame: &RGB48Frame, selected: &[usize], dither: DitherMode) -> Vec<u8> {
+    let (width, height) = (frame.width, frame.height);
+    let channels = frame.n_planes();
+    let n = selected.len();
+    let mut ret = vec![0; width * height * n];
+    for (i, &channel) in selected.iter().enumerate() {
+        assert!(
+            channel < channels,
+            "channel {} of a {}-channel frame",
+            channel,
+            channels
+        );
+        let samples = frame.data[channel..].iter().step_by(channels);
+        let dest = ret[i..].iter_mut().step_by(n);
+        match dither {
+            DitherMode::None => {
+                for (dest, &x) in dest.zip(samples) {
+                    *dest = ((x as u32 * 255 + 32767) / 65535) as u8;
+                }
+            }
+            DitherMode::Ordered => {
+                for (j, (dest, &x)) in dest.zip(samples).enumerate() {
+                    let (row, col) = (j / width, j % width);
+                    // the threshold, as a fraction of an 8-bit step, is (2t + 1) / 128
+                    let t = BAYER_8X8[row % 8][col % 8] as u32;
+                    *dest = ((x as u32 * 255 + (2 * t + 1) * 65535 / 128) / 65535) as u8;
+                }
+            }
+            DitherMode::FloydSteinberg => {
+                let samples: Vec<u16> = samples.cloned().collect();
+                for (dest, x) in dest.zip(floyd_steinberg(&samples, width)) {
+                    *dest = x;
+                }
+            }
+        }
+    }
+    ret
+}
+
+// Error diffusion over a single plane. Values and errors are in units of 1/65535 of an 8-bit step,
+// so all of the arithmetic is exact, and each error is split 7/16, 3/16, 5/16, and whatever's
+// left over, so none of it is lost to rounding.
+fn floyd_steinberg(samples: &[u16], width: usize) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(samples.len());
+    // errors for the current and next rows, with a column of padding on each side
+    let mut cur = vec![0i64; width + 2];
+    let mut next = vec![0i64; width + 2];
+    for row in samples.chunks_exact(width) {
+        for (col, &x) in row.iter().enumerate() {
+            let want = x as i64 * 255 + cur[col + 1];
+            let q = ((want + 32767).div_euclid(65535)).clamp(0, 255);
+            ret.push(q as u8);
+            let error = want - q * 65535;
+            let (e7, e3, e5) = (error * 7 / 16, error * 3 / 16, error * 5 / 16);
+            cur[col + 2] += e7;
+            next[col] += e3;
+            next[col + 1] += e5;
+            next[col + 2] += error - e7 - e3 - e5;
+        }
+        std::mem::swap(&mut cur, &mut next);
+        next.iter_mut().for_each(|e| *e = 0);
+    }
+    ret
+}
+
+impl RGB48Frame {
+    // Reduces the frame to interleaved 8-bit RGB, for viewing with ordinary tools. Alpha, if any,
+    // is dropped.
+    pub fn to_8bit(&self, dither: DitherMode) -> Vec<u8> {
+        assert!(self.n_planes() >= 3, "to_8bit needs an RGB frame");
+        downconvert(self, &[0, 1, 2], dither)
+    }
+
+    // Reduces a single channel to width * height 8-bit samples.
+    pub fn to_8bit_channel(&self, channel: usize, dither: DitherMode) -> Vec<u8> {
+        downconvert(self, &[channel], dither)
+    }
+
+    // Reduces the frame to interleaved 8-bit RGBA, the layout of a canvas's ImageData. Frames with
+    // one or two channels are gray, with alpha in the second, and frames without alpha are opaque.
+    pub fn to_rgba8(&self, dither: DitherMode) -> Vec<u8> {
+        let selected: &[usize] = match self.n_planes() {
+            1 => &[0, 0, 0],
+            2 => &[0, 0, 0, 1],
+            3 => &[0, 1, 2],
+            _ => &[0, 1, 2, 3],
+        };
+        let samples = downconvert(self, selected, dither);
+        if selected.len() == 4 {
+            return samples;
+        }
+        let mut ret = Vec::with_capacity(self.width * self.height * 4);
+        for rgb in samples.chunks_exact(3) {
+            ret.extend_from_slice(rgb);
+            ret.push(255);
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A gradient across 8 8-bit levels, so undithered output has long runs.
+    fn gradient(width: usize, height: usize) -> RGB48Frame {
+        let mut data = Vec::with_capacity(width * height * 3);
+        for _ in 0..height {
+            for col in 0..width {
+                let x = (100 * 257 + col * 8 * 257 / width) as u16;
+                data.extend_from_slice(&[x, x, 65535 - x]);
+            }
+        }
+        RGB48Frame::from_raw(data, width, height, 3).unwrap()
+    }
+
+    fn longest_run(samples: &[u8]) -> usize {
+        let mut longest = 0;
+        let mut run = 0;
+        for (i, &x) in samples.iter().enumerate() {
+            run = if i > 0 && samples[i - 1] == x {
+                run + 1
+            } else {
+                1
+            };
+            longest = longest.max(run);
+        }
+        longest
+    }
+
+    #[test]
+    fn test_to_8bit() {
+        let (width, height) = (1024, 16);
+        let frame = gradient(width, height);
+
+        let plain = frame.to_8bit(DitherMode::None);
+        assert_eq!(plain.len(), width * height * 3);
+        assert_eq!(plain, frame.to_8bit(DitherMode::None));
+        for row in plain.chunks_exact(width * 3) {
+            let red: Vec<u8> = row.iter().step_by(3).cloned().collect();
+            let mut distinct = red.clone();
+            distinct.dedup();
+            assert!(distinct.len() <= 256);
+            assert!(red.windows(2).all(|w| w[0] <= w[1]));
+            assert!(longest_run(&red) >= 100);
+        }
+        // green matches red, and blue is its mirror image
+        assert_eq!(plain[0], plain[1]);
+        assert_eq!(plain[2], 255 - plain[0]);
+
+        // over 8x8 blocks, dithered output tracks the gradient, where plain rounding is off by up
+        // to half a step
+        let block_error = |samples: &[u8]| {
+            let mut worst = 0f64;
+            for block_row in (0..height).step_by(8) {
+                for block_col in (0..width).step_by(8) {
+                    let (mut actual, mut expected) = (0.0, 0.0);
+                    for row in block_row..block_row + 8 {
+                        for col in block_col..block_col + 8 {
+                            let i = (row * width + col) * 3;
+                            actual += samples[i] as f64;
+                            expected += frame.data[i] as f64 / 257.0;
+                        }
+                    }
+                    worst = worst.max((actual - expected).abs() / 64.0);
+                }
+            }
+            worst
+        };
+        assert!(block_error(&plain) > 0.4);
+        for &mode in [DitherMode::Ordered, DitherMode::FloydSteinberg].iter() {
+            let dithered = frame.to_8bit(mode);
+            assert_eq!(dithered, frame.to_8bit(mode), "{:?}", mode);
+            let error = block_error(&dithered);
+            assert!(error < 0.125, "{:?}: {}", mode, error);
+        }
+    }
+
+    #[test]
+    fn test_to_8bit_rounding() {
+        let samples = [0, 128, 129, 257, 385, 386, 32896, 65534, 65535];
+        let frame = RGB48Frame::from_raw(samples.to_vec(), samples.len(), 1, 1).unwrap();
+        assert_eq!(
+            frame.to_8bit_channel(0, DitherMode::None),
+            vec![0, 0, 1, 1, 1, 2, 128, 255, 255]
+        );
+
+        // exact 8-bit values survive every mode
+        let samples: Vec<u16> = (0..=255).map(|x| x * 257).collect();
+        let frame = RGB48Frame::from_raw(samples, 256, 1, 1).unwrap();
+        let expected: Vec<u8> = (0..=255).collect();
+        for &mode in [
+            DitherMode::None,
+            DitherMode::Ordered,
+            DitherMode::FloydSteinberg,
+        ]
+        .iter()
+        {
+            assert_eq!(frame.to_8bit_channel(0, mode), expected, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_to_rgba8() {
+        let gray = RGB48Frame::from_raw(vec![0, 257, 65535], 3, 1, 1).unwrap();
+        assert_eq!(
+            gray.to_rgba8(DitherMode::None),
+            vec![0, 0, 0, 255, 1, 1, 1, 255, 255, 255, 255, 255]
+        );
+        let gray_alpha = RGB48Frame::from_raw(vec![32896, 0], 1, 1, 2).unwrap();
+        assert_eq!(
+            gray_alpha.to_rgba8(DitherMode::None),
+            vec![128, 128, 128, 0]
+        );
+
+        let frame = gradient(64, 8);
+        let rgba = frame.to_rgba8(DitherMode::Ordered);
+        assert_eq!(rgba.len(), 64 * 8 * 4);
+        let rgb: Vec<u8> = rgba
+            .chunks_exact(4)
+            .flat_map(|pixel| pixel[..3].iter().cloned())
+            .collect();
+        assert_eq!(rgb, frame.to_8bit(DitherMode::Ordered));
+        assert!(rgba.iter().skip(3).step_by(4).all(|&a| a == 255));
+
+        let mut data = Vec::with_capacity(64 * 8 * 4);
+        for (i, pixel) in frame.data.chunks_exact(3).enumerate() {
+            data.extend_from_slice(pixel);
+            data.push((i * 128) as u16);
+        }
+        let frame = RGB48Frame::from_raw(data, 64, 8, 4).unwrap();
+        let rgba = frame.to_rgba8(DitherMode::None);
+        assert_eq!(rgba[4..8], [rgb[3], rgb[4], rgb[5], 0]);
+        assert_eq!(rgba[4 * 511 + 3], 255);
+    }
+}
@@ -0,0 +1,288 @@
+use super::frame::{FrameOpenError, RGB48Frame};
+use std::io::{self, Read, Seek, SeekFrom};
+
+// Reads DPX (SMPTE 268M) files, which film scanners and grading tools write. Only the most common
+// layout is supported: a first image element of 10-bit RGB, uncompressed, "filled" so that each
+// pixel's three samples share one 32-bit word with 2 bits of padding.
+//
+// Samples are widened to 16 bits by replicating their top bits into the bottom ones, the 10-bit
+// equivalent of multiplying 8-bit samples by 257, so that 1023 becomes 65535. The frame's
+// bit_depth is 10.
+
+// The first word of a DPX file, read big-endian. Little-endian files have it byte-swapped.
+pub(crate) const MAGIC: [u8; 4] = *b"SDPX";
+
+// The file and image information headers, through the first image element's data offset.
+const HEADER_LEN: usize = 812;
+
+// Offsets of the fields we use within the header.
+const OFFSET_TO_DATA: usize = 4;
+const N_ELEMENTS: usize = 770;
+const PIXELS_PER_LINE: usize = 772;
+const LINES_PER_ELEMENT: usize = 776;
+const DATA_SIGN: usize = 780;
+const DESCRIPTOR: usize = 800;
+const BIT_SIZE: usize = 803;
+const PACKING: usize = 804;
+const ENCODING: usize = 806;
+const ELEMENT_DATA_OFFSET: usize = 808;
+
+const DESCRIPTOR_RGB: u8 = 50;
+
+// Where the R, G, and B samples start in each word, for packing methods A and B.
+const METHOD_A_SHIFTS: [u32; 3] = [22, 12, 2];
+const METHOD_B_SHIFTS: [u32; 3] = [20, 10, 0];
+
+fn unsupported<S: Into<String>>(message: S) -> FrameOpenError {
+    FrameOpenError::UnsupportedDpx(message.into())
+}
+
+fn invalid<S: Into<String>>(message: S) -> FrameOpenError {
+    FrameOpenError::InvalidDpx(message.into())
+}
+
+struct Header {
+    big_endian: bool,
+    buf: [u8; HEADER_LEN],
+}
+
+impl Header {
+    fn u16(&self, offset: usize) -> u16 {
+        let b = [self.buf[offset], self.buf[offset + 1]];
+        if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        }
+    }
+
+    fn u32(&self, offset: usize) -> u32 {
+        self.u32_from_bytes([
+            self.buf[offset],
+            self.buf[offset + 1],
+            self.buf[offset + 2],
+            self.buf[offset + 3],
+        ])
+    }
+
+    fn u32_from_bytes(&self, b: [u8; 4]) -> u32 {
+        if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    }
+}
+
+pub(crate) fn read<R: Read + Seek>(mut source: R) -> Result<RGB48Frame, FrameOpenError> {
+    let mut header = Header {
+        big_endian: true,
+        buf: [0; HEADER_LEN],
+    };
+    source.seek(SeekFrom::Start(0))?;
+    source.read_exact(&mut header.buf)?;
+    header.big_endian = match header.buf[..4] {
+        [b'S', b'D', b'P', b'X'] => true,
+        [b'X', b'P', b'D', b'S'] => false,
+        _ => return Err(invalid("bad magic number")),
+    };
+
+    if header.u16(N_ELEMENTS) == 0 {
+        return Err(invalid("no image elements"));
+    }
+    let descriptor = header.buf[DESCRIPTOR];
+    if descriptor != DESCRIPTOR_RGB {
+        return Err(unsupported(format!("descriptor {}", descriptor)));
+    }
+    let bit_size = header.buf[BIT_SIZE];
+    if bit_size != 10 {
+        return Err(unsupported(format!("{}-bit samples", bit_size)));
+    }
+    let shifts = match header.u16(PACKING) {
+        1 => METHOD_A_SHIFTS,
+        2 => METHOD_B_SHIFTS,
+        packing => return Err(unsupported(format!("packing {}", packing))),
+    };
+    match header.u16(ENCODING) {
+        0 => {}
+        encoding => return Err(unsupported(format!("encoding {}", encoding))),
+    }
+    if header.u32(DATA_SIGN) != 0 {
+        return Err(unsupported("signed samples"));
+    }
+
+    let width = header.u32(PIXELS_PER_LINE) as usize;
+    let height = header.u32(LINES_PER_ELEMENT) as usize;
+    let len = width
+        .checked_mul(height)
+        .and_then(|n| n.checked_mul(3))
+        .filter(|&len| len <= isize::MAX as usize / 2);
+    let len = match len {
+        Some(len) if len > 0 => len,
+        _ => return Err(invalid(format!("invalid dimensions {}x{}", width, height))),
+    };
+    // the element's offset is optional, in which case the file's applies
+    let offset = match header.u32(ELEMENT_DATA_OFFSET) {
+        0 | u32::MAX => header.u32(OFFSET_TO_DATA),
+        offset => offset,
+    };
+
+    source.seek(SeekFrom::Start(offset as u64))?;
+    let mut bytes = Vec::new();
+    source.take(len as u64 / 3 * 4).read_to_end(&mut bytes)?;
+    if bytes.len() != len / 3 * 4 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the dpx file ended before its image data",
+        )
+        .into());
+    }
+
+    let mut data = Vec::with_capacity(len);
+    for b in bytes.chunks_exact(4) {
+        let word = header.u32_from_bytes([b[0], b[1], b[2], b[3]]);
+        data.extend(shifts.iter().map(|&shift| {
+            let x = (word >> shift) as u16 & 0x3ff;
+            (x << 6) | (x >> 4)
+        }));
+    }
+    Ok(RGB48Frame {
+        data,
+        width,
+        height,
+        bit_depth: 10,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds a DPX file with the given 10-bit samples, in the layout read supports unless header
+    // bytes are overridden.
+    fn dpx(
+        samples: &[u16],
+        (width, height): (u32, u32),
+        big_endian: bool,
+        packing: u16,
+        overrides: &[(usize, u8)],
+    ) -> Vec<u8> {
+        let u16_bytes = |x: u16| {
+            if big_endian {
+                x.to_be_bytes()
+            } else {
+                x.to_le_bytes()
+            }
+        };
+        let u32_bytes = |x: u32| {
+            if big_endian {
+                x.to_be_bytes()
+            } else {
+                x.to_le_bytes()
+            }
+        };
+        let mut buf = vec![0; 2048];
+        buf[..4].copy_from_slice(&u32_bytes(u32::from_be_bytes(MAGIC)));
+        buf[OFFSET_TO_DATA..OFFSET_TO_DATA + 4].copy_from_slice(&u32_bytes(2048));
+        buf[N_ELEMENTS..N_ELEMENTS + 2].copy_from_slice(&u16_bytes(1));
+        buf[PIXELS_PER_LINE..PIXELS_PER_LINE + 4].copy_from_slice(&u32_bytes(width));
+        buf[LINES_PER_ELEMENT..LINES_PER_ELEMENT + 4].copy_from_slice(&u32_bytes(height));
+        buf[DESCRIPTOR] = DESCRIPTOR_RGB;
+        buf[BIT_SIZE] = 10;
+        buf[PACKING..PACKING + 2].copy_from_slice(&u16_bytes(packing));
+        buf[ELEMENT_DATA_OFFSET..ELEMENT_DATA_OFFSET + 4].copy_from_slice(&u32_bytes(2048));
+        for &(offset, value) in overrides {
+            buf[offset] = value;
+        }
+        let shifts = if packing == 2 {
+            METHOD_B_SHIFTS
+        } else {
+            METHOD_A_SHIFTS
+        };
+        for pixel in samples.chunks_exact(3) {
+            let word = pixel
+                .iter()
+                .zip(shifts.iter())
+                .fold(0, |word, (&x, &shift)| word | (x as u32) << shift);
+            buf.extend_from_slice(&u32_bytes(word));
+        }
+        buf
+    }
+
+    fn test_samples() -> Vec<u16> {
+        (0..4 * 3 * 3)
+            .map(|i| (i * 29 + i / 3 * 7) % 1024)
+            .collect()
+    }
+
+    fn widen(x: u16) -> u16 {
+        (x << 6) | (x >> 4)
+    }
+
+    #[test]
+    fn test_dpx() {
+        let samples = test_samples();
+        for &big_endian in [true, false].iter() {
+            for &packing in [1, 2].iter() {
+                let file = dpx(&samples, (4, 3), big_endian, packing, &[]);
+                let frame = read(io::Cursor::new(file)).unwrap();
+                assert_eq!((frame.width, frame.height, frame.bit_depth), (4, 3, 10));
+                assert_eq!(frame.channels(), 3);
+                for (i, (&actual, &x)) in frame.data.iter().zip(samples.iter()).enumerate() {
+                    assert_eq!(actual, widen(x), "sample {}", i);
+                }
+            }
+        }
+        assert_eq!(widen(0), 0);
+        assert_eq!(widen(1023), 65535);
+        assert_eq!(widen(512), 0x8020);
+
+        // RGB48Frame::open recognizes either byte order
+        for &big_endian in [true, false].iter() {
+            let path = std::env::temp_dir().join(format!(
+                "hvc-dpx-{}-{}.dpx",
+                big_endian,
+                std::process::id()
+            ));
+            std::fs::write(&path, dpx(&samples, (4, 3), big_endian, 1, &[])).unwrap();
+            let frame = RGB48Frame::open(&path);
+            std::fs::remove_file(&path).unwrap();
+            let frame = frame.unwrap();
+            assert_eq!(frame.bit_depth, 10);
+            assert_eq!(frame.data[5], widen(samples[5]));
+        }
+    }
+
+    #[test]
+    fn test_dpx_unsupported() {
+        let samples = test_samples();
+        for &(offset, value, expected) in [
+            (DESCRIPTOR, 6, "unsupported dpx file: descriptor 6"),
+            (DESCRIPTOR, 51, "unsupported dpx file: descriptor 51"),
+            (BIT_SIZE, 12, "unsupported dpx file: 12-bit samples"),
+            (PACKING + 1, 0, "unsupported dpx file: packing 0"),
+            (ENCODING + 1, 1, "unsupported dpx file: encoding 1"),
+            (DATA_SIGN + 3, 1, "unsupported dpx file: signed samples"),
+            (0, b'T', "invalid dpx file: bad magic number"),
+            (
+                PIXELS_PER_LINE + 3,
+                0,
+                "invalid dpx file: invalid dimensions 0x3",
+            ),
+        ]
+        .iter()
+        {
+            let file = dpx(&samples, (4, 3), true, 1, &[(offset, value)]);
+            let err = read(io::Cursor::new(file)).map(|_| ()).unwrap_err();
+            assert_eq!(err.to_string(), expected);
+        }
+
+        // a file that ends partway through its image data
+        let mut file = dpx(&samples, (4, 3), false, 1, &[]);
+        file.pop();
+        match read(io::Cursor::new(file)) {
+            Err(FrameOpenError::IO(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+}
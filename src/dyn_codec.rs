@@ -0,0 +1,112 @@
+use super::{
+    codec,
+    error::CodecError,
+    frame::{Codec, EncodeOptions, RGB48Frame},
+    store::StoreCodec,
+};
+use std::fmt;
+
+// A codec chosen at run time. Codec's methods are generic, so it can't be made into a trait object;
+// this holds a codec's frame-level encoding and decoding as plain functions instead. Frames are
+// coded with RGB48Frame::encode_with_header and decode_with_header.
+#[derive(Clone, Copy)]
+pub struct DynCodec {
+    name: &'static str,
+    encode: fn(&RGB48Frame, &EncodeOptions) -> Result<Vec<u8>, CodecError>,
+    decode: fn(&[u8]) -> Result<RGB48Frame, CodecError>,
+}
+
+fn encode_with<C: Codec>(
+    frame: &RGB48Frame,
+    options: &EncodeOptions,
+) -> Result<Vec<u8>, CodecError> {
+    let mut encoded = Vec::new();
+    frame.encode_with_header::<C, _>(&mut encoded, options)?;
+    Ok(encoded)
+}
+
+fn decode_with<C: Codec>(encoded: &[u8]) -> Result<RGB48Frame, CodecError> {
+    RGB48Frame::decode_with_header::<C, _>(encoded)
+}
+
+impl DynCodec {
+    pub fn new<C: Codec>(name: &'static str) -> Self {
+        Self {
+            name,
+            encode: encode_with::<C>,
+            decode: decode_with::<C>,
+        }
+    }
+
+    // The codecs the crate provides, under the names by_name knows them by: "rice" for
+    // codec::Codec, "store" for StoreCodec, and, with the deflate feature, "deflate" for
+    // DeflateResidualCodec at its default level.
+    pub fn all() -> Vec<Self> {
+        vec![
+            Self::new::<codec::Codec>("rice"),
+            Self::new::<StoreCodec>("store"),
+            #[cfg(feature = "deflate")]
+            Self::new::<super::residual::DeflateResidualCodec>("deflate"),
+        ]
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::all().into_iter().find(|codec| codec.name == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn encode(
+        &self,
+        frame: &RGB48Frame,
+        options: &EncodeOptions,
+    ) -> Result<Vec<u8>, CodecError> {
+        (self.encode)(frame, options)
+    }
+
+    pub fn decode(&self, encoded: &[u8]) -> Result<RGB48Frame, CodecError> {
+        (self.decode)(encoded)
+    }
+}
+
+impl fmt::Debug for DynCodec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DynCodec")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{header::FrameHeader, synth},
+        *,
+    };
+
+    #[test]
+    fn test_dyn_codec() {
+        let frame = synth::gradient(12, 9, synth::Direction::Diagonal);
+        let options = EncodeOptions::default();
+        for codec in DynCodec::all() {
+            let encoded = codec.encode(&frame, &options).unwrap();
+            assert!(codec.decode(&encoded).unwrap() == frame, "{}", codec.name());
+        }
+
+        let rice = DynCodec::by_name("rice").unwrap();
+        let mut expected = Vec::new();
+        frame
+            .encode_with_header::<codec::Codec, _>(&mut expected, &options)
+            .unwrap();
+        assert_eq!(rice.encode(&frame, &options).unwrap(), expected);
+        let stored = DynCodec::by_name("store")
+            .unwrap()
+            .encode(&frame, &options)
+            .unwrap();
+        let header = FrameHeader::read(&*stored).unwrap();
+        assert_eq!(stored.len(), header.encoded_len() + frame.data.len() * 2);
+        assert!(DynCodec::by_name("rans").is_none());
+    }
+}
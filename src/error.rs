@@ -0,0 +1,96 @@
+use super::frame::{FrameError, FrameOpenError, FrameSaveError};
+use std::io;
+use thiserror::Error;
+
+// The error type for encoding and decoding. Failures of the underlying reader or writer are passed
+// through as Io, while problems with the data itself get their own variants so that callers can
+// tell them apart.
+#[derive(Error, Debug)]
+pub enum CodecError {
+    #[error(transparent)]
+    Io(io::Error),
+    // The stream ended while decoding the sample at (col, row) of the given plane.
+    #[error("stream ended early in plane {plane} at row {row}, column {col}")]
+    TruncatedStream {
+        plane: usize,
+        row: usize,
+        col: usize,
+    },
+    #[error("invalid header: {0}")]
+    InvalidHeader(String),
+    #[error("checksum mismatch in plane {plane}")]
+    ChecksumMismatch { plane: usize },
+    #[error("unsupported version: {0}")]
+    UnsupportedVersion(u8),
+    // A progress callback asked for the operation to stop (see progress::Progress).
+    #[error("cancelled")]
+    Cancelled,
+    // The stream is corrupt in some way not covered by the other variants.
+    #[error("invalid data: {0}")]
+    InvalidData(String),
+    // Verifying an encode found that the sample at (col, row) of the given plane didn't decode to
+    // its original value (see RGB48Frame::encode_verified).
+    #[error("verification failed: plane {plane} differs at row {row}, column {col}")]
+    VerificationFailed {
+        plane: usize,
+        row: usize,
+        col: usize,
+    },
+    // A container's frame decoded to samples that don't match the content hash stored with it (see
+    // container::VideoReader::verify_frame).
+    #[error("frame {frame} doesn't match its content hash")]
+    ContentHashMismatch { frame: usize },
+    // Decoding would allocate more than the limit allows (see frame::Limits).
+    #[error("decoding needs {bytes} bytes, more than the limit of {limit}")]
+    LimitExceeded { bytes: usize, limit: usize },
+    // The caller asked for something that can't be done, like encoding a frame with options it
+    // doesn't support.
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+}
+
+impl From<io::Error> for CodecError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl CodecError {
+    // Attributes a truncation reported by a codec to the given plane, with the codec's position
+    // offset by (x, y). Decoders that don't know which plane or tile they're decoding report
+    // positions relative to what they were given, and callers that do know fix them up with this.
+    // Plain end-of-file errors become truncations at (x, y).
+    pub(crate) fn in_plane(self, plane: usize, x: usize, y: usize) -> Self {
+        match self {
+            Self::TruncatedStream { row, col, .. } => Self::TruncatedStream {
+                plane,
+                row: y + row,
+                col: x + col,
+            },
+            Self::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof => Self::TruncatedStream {
+                plane,
+                row: y,
+                col: x,
+            },
+            e => e,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, CodecError>;
+
+// The error type for the crate-level conveniences like encode_file, which can fail anywhere from
+// loading a frame to saving one.
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Open(#[from] FrameOpenError),
+    #[error(transparent)]
+    Save(#[from] FrameSaveError),
+    #[error(transparent)]
+    Codec(#[from] CodecError),
+    #[error(transparent)]
+    Frame(#[from] FrameError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
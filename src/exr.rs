@@ -0,0 +1,650 @@
+use flate2::read::ZlibDecoder;
+use std::{
+    convert::TryInto,
+    io::{self, Read, Seek, SeekFrom},
+};
+use thiserror::Error;
+
+// Just enough of OpenEXR to load rendered plates. Decoding handles scanline images, including the
+// first part of multi-part files, that are uncompressed or use ZIP or ZIPS compression. Every
+// channel is returned as f32, whatever it was stored as.
+
+pub const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+
+const FLAG_TILED: u32 = 0x200;
+const FLAG_NON_IMAGE: u32 = 0x800;
+const FLAG_MULTIPART: u32 = 0x1000;
+
+// Attribute values larger than this are treated as corrupt rather than allocated.
+const MAX_ATTRIBUTE_SIZE: usize = 1 << 20;
+
+#[derive(Error, Debug)]
+pub enum ExrError {
+    #[error(transparent)]
+    IO(#[from] io::Error),
+    #[error("not an exr file")]
+    InvalidSignature,
+    #[error("invalid exr: {0}")]
+    InvalidData(String),
+    #[error("unsupported exr: {0}")]
+    Unsupported(String),
+}
+
+fn invalid_data<S: Into<String>>(message: S) -> ExrError {
+    ExrError::InvalidData(message.into())
+}
+
+fn unsupported<S: Into<String>>(message: S) -> ExrError {
+    ExrError::Unsupported(message.into())
+}
+
+// How linear values are mapped to 16-bit samples. Either way, values are clamped to [0, 1] first,
+// with NaN treated as 0, so that out-of-range renders convert deterministically.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transfer {
+    // Scales [0, 1] to [0, 65535].
+    Linear,
+    // Applies the sRGB transfer function before scaling, which spends more of the range on dark
+    // values, as 16-bit display-referred material would.
+    Srgb,
+}
+
+impl Transfer {
+    pub fn to_u16(self, x: f32) -> u16 {
+        let x = if x.is_nan() { 0.0 } else { x.clamp(0.0, 1.0) };
+        let x = match self {
+            Self::Linear => x,
+            Self::Srgb if x <= 0.003_130_8 => x * 12.92,
+            Self::Srgb => 1.055 * x.powf(1.0 / 2.4) - 0.055,
+        };
+        (x * 65535.0).round() as u16
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PixelType {
+    Uint = 0,
+    Half = 1,
+    Float = 2,
+}
+
+impl PixelType {
+    fn from_i32(x: i32) -> Option<Self> {
+        match x {
+            0 => Some(Self::Uint),
+            1 => Some(Self::Half),
+            2 => Some(Self::Float),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::Half => 2,
+            Self::Uint | Self::Float => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Compression {
+    None = 0,
+    Zips = 2,
+    Zip = 3,
+}
+
+impl Compression {
+    fn from_u8(x: u8) -> Result<Self, ExrError> {
+        match x {
+            0 => Ok(Self::None),
+            2 => Ok(Self::Zips),
+            3 => Ok(Self::Zip),
+            1 => Err(unsupported("RLE compression")),
+            4 => Err(unsupported("PIZ compression")),
+            5 => Err(unsupported("PXR24 compression")),
+            6 | 7 => Err(unsupported("B44 compression")),
+            8 | 9 => Err(unsupported("DWA compression")),
+            _ => Err(invalid_data(format!("unknown compression {}", x))),
+        }
+    }
+
+    fn lines_per_chunk(self) -> usize {
+        match self {
+            Self::None | Self::Zips => 1,
+            Self::Zip => 16,
+        }
+    }
+
+    // The most a chunk of the given number of bytes can expand to. Deflate can't do better than
+    // 1032 to 1.
+    fn max_decompressed_size(self, size: u64) -> u64 {
+        match self {
+            Self::None => size,
+            Self::Zips | Self::Zip => size.saturating_mul(1032),
+        }
+    }
+}
+
+pub struct Channel {
+    pub name: String,
+    pub samples: Vec<f32>,
+}
+
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    // In the order they're stored, which is sorted by name.
+    pub channels: Vec<Channel>,
+}
+
+impl Image {
+    pub fn channel(&self, name: &str) -> Option<&Channel> {
+        self.channels.iter().find(|c| c.name == name)
+    }
+}
+
+struct ChannelInfo {
+    name: String,
+    pixel_type: PixelType,
+}
+
+#[derive(Default)]
+struct Header {
+    channels: Option<Vec<ChannelInfo>>,
+    compression: Option<Compression>,
+    data_window: Option<[i32; 4]>,
+    part_type: Option<String>,
+    chunk_count: Option<usize>,
+}
+
+fn read_i32<R: Read>(mut source: R) -> io::Result<i32> {
+    let mut buf = [0; 4];
+    source.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(mut source: R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    source.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+// Reads a null-terminated string of up to 255 bytes.
+fn read_string<R: Read>(mut source: R) -> Result<String, ExrError> {
+    let mut bytes = Vec::new();
+    loop {
+        let mut b = [0];
+        source.read_exact(&mut b)?;
+        match b[0] {
+            0 => break,
+            _ if bytes.len() == 255 => return Err(invalid_data("name too long")),
+            b => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| invalid_data("names must be utf-8"))
+}
+
+fn parse_channels(mut value: &[u8]) -> Result<Vec<ChannelInfo>, ExrError> {
+    let mut channels = Vec::new();
+    loop {
+        let name = read_string(&mut value)?;
+        if name.is_empty() {
+            return Ok(channels);
+        }
+        let pixel_type = read_i32(&mut value)?;
+        let pixel_type = PixelType::from_i32(pixel_type)
+            .ok_or_else(|| invalid_data(format!("unknown pixel type {}", pixel_type)))?;
+        // the perceptually linear flag and reserved bytes
+        value = value
+            .get(4..)
+            .ok_or_else(|| invalid_data("truncated channel list"))?;
+        let (x_sampling, y_sampling) = (read_i32(&mut value)?, read_i32(&mut value)?);
+        if (x_sampling, y_sampling) != (1, 1) {
+            return Err(unsupported(format!(
+                "channel {} is subsampled {}x{}",
+                name, x_sampling, y_sampling
+            )));
+        }
+        channels.push(ChannelInfo { name, pixel_type });
+    }
+}
+
+// Reads a header's attributes, returning None for the empty header that ends a multi-part file's
+// list of headers.
+fn read_header<R: Read>(mut source: R) -> Result<Option<Header>, ExrError> {
+    let mut header = Header::default();
+    let mut empty = true;
+    loop {
+        let name = read_string(&mut source)?;
+        if name.is_empty() {
+            return Ok(if empty { None } else { Some(header) });
+        }
+        empty = false;
+        let type_name = read_string(&mut source)?;
+        let size = read_i32(&mut source)?;
+        if size < 0 || size as usize > MAX_ATTRIBUTE_SIZE {
+            return Err(invalid_data(format!(
+                "attribute {} has size {}",
+                name, size
+            )));
+        }
+        let mut value = vec![0; size as usize];
+        source.read_exact(&mut value)?;
+        match (name.as_str(), type_name.as_str()) {
+            ("channels", "chlist") => header.channels = Some(parse_channels(&value)?),
+            ("compression", "compression") if size == 1 => {
+                header.compression = Some(Compression::from_u8(value[0])?)
+            }
+            ("dataWindow", "box2i") if size == 16 => {
+                let mut window = [0; 4];
+                for (x, b) in window.iter_mut().zip(value.chunks_exact(4)) {
+                    *x = i32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                }
+                header.data_window = Some(window);
+            }
+            ("type", "string") => {
+                header.part_type = Some(String::from_utf8_lossy(&value).into_owned())
+            }
+            ("chunkCount", "int") if size == 4 => {
+                let count = i32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+                header.chunk_count = Some(count.max(0) as usize);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn f16_to_f32(h: u16) -> f32 {
+    let sign = (h as u32 & 0x8000) << 16;
+    let exponent = (h as u32 >> 10) & 0x1f;
+    let mantissa = h as u32 & 0x3ff;
+    match exponent {
+        0 => {
+            // zero or subnormal
+            let x = mantissa as f32 / (1 << 24) as f32;
+            if sign != 0 {
+                -x
+            } else {
+                x
+            }
+        }
+        // infinity or NaN
+        31 => f32::from_bits(sign | 0x7f80_0000 | mantissa << 13),
+        _ => f32::from_bits(sign | (exponent + 112) << 23 | mantissa << 13),
+    }
+}
+
+// Undoes ZIP compression's preprocessing: each byte is stored as its difference from the previous
+// one, after the bytes were split into even and odd halves.
+fn unpredict(data: &mut Vec<u8>) {
+    for i in 1..data.len() {
+        data[i] = data[i - 1].wrapping_add(data[i]).wrapping_sub(128);
+    }
+    let (first, second) = data.split_at(data.len().div_ceil(2));
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &b) in first.iter().enumerate() {
+        out.push(b);
+        if let Some(&b) = second.get(i) {
+            out.push(b);
+        }
+    }
+    *data = out;
+}
+
+// Decodes the first part of an EXR file.
+pub fn decode<R: Read + Seek>(mut source: R) -> Result<Image, ExrError> {
+    let mut magic = [0; 4];
+    source.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(ExrError::InvalidSignature);
+    }
+    let version = read_i32(&mut source)? as u32;
+    if version & 0xff != 2 {
+        return Err(unsupported(format!("version {}", version & 0xff)));
+    }
+    if version & FLAG_NON_IMAGE != 0 {
+        return Err(unsupported("deep data"));
+    }
+    if version & FLAG_TILED != 0 {
+        return Err(unsupported("tiled images"));
+    }
+    let multipart = version & FLAG_MULTIPART != 0;
+
+    let header = read_header(&mut source)?.ok_or_else(|| invalid_data("empty header"))?;
+    if multipart {
+        match header.part_type.as_deref() {
+            Some("scanlineimage") => {}
+            Some(part_type) => return Err(unsupported(format!("{} parts", part_type))),
+            None => return Err(invalid_data("missing part type")),
+        }
+        while read_header(&mut source)?.is_some() {}
+    }
+    let channels = header
+        .channels
+        .ok_or_else(|| invalid_data("missing channel list"))?;
+    let compression = header
+        .compression
+        .ok_or_else(|| invalid_data("missing compression"))?;
+    let [x_min, y_min, x_max, y_max] = header
+        .data_window
+        .ok_or_else(|| invalid_data("missing data window"))?;
+    let (width, height) = match (
+        (x_max as i64 - x_min as i64 + 1).try_into(),
+        (y_max as i64 - y_min as i64 + 1).try_into(),
+    ) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => (width, height),
+        _ => return Err(invalid_data("invalid data window")),
+    };
+    let (width, height): (usize, usize) = (width, height);
+    let pixel_size: usize = channels.iter().map(|c| c.pixel_type.size()).sum();
+    let line_size = width
+        .checked_mul(pixel_size)
+        .filter(|&size| {
+            size.checked_mul(height)
+                .is_some_and(|n| n <= isize::MAX as usize / 2)
+        })
+        .ok_or_else(|| invalid_data("the image is too large"))?;
+
+    let lines_per_chunk = compression.lines_per_chunk();
+    let n_chunks = header
+        .chunk_count
+        .unwrap_or_else(|| height.div_ceil(lines_per_chunk));
+    if n_chunks != height.div_ceil(lines_per_chunk) {
+        return Err(invalid_data(format!("{} chunks", n_chunks)));
+    }
+    // the samples aren't allocated until the rest of the file has been checked to be large enough
+    // to hold them, so that a corrupt data window can't claim more memory than that
+    let position = source.stream_position()?;
+    let available = source.seek(SeekFrom::End(0))?.saturating_sub(position);
+    source.seek(SeekFrom::Start(position))?;
+    if n_chunks as u64 * 8 > available
+        || (line_size * height) as u64 > compression.max_decompressed_size(available)
+    {
+        return Err(invalid_data("the data window is larger than the file"));
+    }
+    let mut offsets = Vec::with_capacity(n_chunks);
+    for _ in 0..n_chunks {
+        offsets.push(read_u64(&mut source)?);
+    }
+
+    let mut ret = Image {
+        width,
+        height,
+        channels: channels
+            .iter()
+            .map(|c| Channel {
+                name: c.name.clone(),
+                samples: vec![0.0; width * height],
+            })
+            .collect(),
+    };
+    let mut data = Vec::new();
+    for offset in offsets {
+        source.seek(SeekFrom::Start(offset))?;
+        if multipart && read_i32(&mut source)? != 0 {
+            return Err(invalid_data("a chunk of the first part belongs to another"));
+        }
+        let y = read_i32(&mut source)? as i64 - y_min as i64;
+        if y < 0 || y >= height as i64 || !(y as usize).is_multiple_of(lines_per_chunk) {
+            return Err(invalid_data(format!("chunk at line {}", y + y_min as i64)));
+        }
+        let y = y as usize;
+        let lines = lines_per_chunk.min(height - y);
+        let size = read_i32(&mut source)?;
+        if size < 0 || size as usize > lines * line_size {
+            return Err(invalid_data(format!("chunk of {} bytes", size)));
+        }
+        data.clear();
+        (&mut source).take(size as u64).read_to_end(&mut data)?;
+        if data.len() != size as usize {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated chunk").into());
+        }
+        // chunks that don't get any smaller are stored as they are
+        if data.len() != lines * line_size {
+            if compression == Compression::None {
+                return Err(invalid_data(format!("chunk of {} bytes", size)));
+            }
+            let mut decompressed = Vec::with_capacity(lines * line_size);
+            ZlibDecoder::new(&data[..])
+                .take((lines * line_size) as u64)
+                .read_to_end(&mut decompressed)
+                .map_err(|e| invalid_data(e.to_string()))?;
+            if decompressed.len() != lines * line_size {
+                return Err(invalid_data("chunk decompressed to the wrong size"));
+            }
+            unpredict(&mut decompressed);
+            data = decompressed;
+        }
+
+        let mut bytes = &data[..];
+        for row in y..y + lines {
+            for (info, channel) in channels.iter().zip(ret.channels.iter_mut()) {
+                let (line, rest) = bytes.split_at(width * info.pixel_type.size());
+                let dest = &mut channel.samples[row * width..(row + 1) * width];
+                match info.pixel_type {
+                    PixelType::Half => {
+                        for (x, b) in dest.iter_mut().zip(line.chunks_exact(2)) {
+                            *x = f16_to_f32(u16::from_le_bytes([b[0], b[1]]));
+                        }
+                    }
+                    PixelType::Float => {
+                        for (x, b) in dest.iter_mut().zip(line.chunks_exact(4)) {
+                            *x = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                        }
+                    }
+                    PixelType::Uint => {
+                        for (x, b) in dest.iter_mut().zip(line.chunks_exact(4)) {
+                            *x = u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32;
+                        }
+                    }
+                }
+                bytes = rest;
+            }
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use flate2::{write::ZlibEncoder, Compression as Level};
+    use std::io::Write;
+
+    pub(crate) fn f32_to_f16(x: f32) -> u16 {
+        let bits = x.to_bits();
+        let sign = (bits >> 16) & 0x8000;
+        let exponent = ((bits >> 23) & 0xff) as i32;
+        let mantissa = bits & 0x7f_ffff;
+        (match exponent {
+            255 => sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 },
+            _ if exponent - 112 >= 31 => sign | 0x7c00,
+            _ if exponent - 112 <= 0 => sign | ((x.abs() * (1 << 24) as f32).round() as u32),
+            // rounding may carry into the exponent, which is what it should do
+            _ => sign | ((((exponent - 112) as u32) << 10) + ((mantissa + 0x1000) >> 13)),
+        }) as u16
+    }
+
+    fn predict(data: &[u8]) -> Vec<u8> {
+        let mut t: Vec<u8> = data.iter().step_by(2).cloned().collect();
+        t.extend(data.iter().skip(1).step_by(2));
+        for i in (1..t.len()).rev() {
+            t[i] = t[i].wrapping_sub(t[i - 1]).wrapping_add(128);
+        }
+        t
+    }
+
+    // Builds a single-part scanline EXR. channels must be sorted by name, and each holds
+    // width * height samples stored as halves or floats.
+    pub(crate) fn exr(
+        width: usize,
+        height: usize,
+        channels: &[(&str, bool, &[f32])],
+        compression: u8,
+        sampling: i32,
+    ) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+        buf.extend_from_slice(&2i32.to_le_bytes());
+        let attribute = |buf: &mut Vec<u8>, name: &str, type_name: &str, value: &[u8]| {
+            buf.extend_from_slice(name.as_bytes());
+            buf.push(0);
+            buf.extend_from_slice(type_name.as_bytes());
+            buf.push(0);
+            buf.extend_from_slice(&(value.len() as i32).to_le_bytes());
+            buf.extend_from_slice(value);
+        };
+        let mut chlist = Vec::new();
+        for &(name, half, _) in channels {
+            chlist.extend_from_slice(name.as_bytes());
+            chlist.push(0);
+            chlist.extend_from_slice(&(if half { 1i32 } else { 2 }).to_le_bytes());
+            chlist.extend_from_slice(&[0; 4]);
+            chlist.extend_from_slice(&sampling.to_le_bytes());
+            chlist.extend_from_slice(&sampling.to_le_bytes());
+        }
+        chlist.push(0);
+        attribute(&mut buf, "channels", "chlist", &chlist);
+        attribute(&mut buf, "compression", "compression", &[compression]);
+        let window: Vec<u8> = [10i32, -3, 10 + width as i32 - 1, height as i32 - 4]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        attribute(&mut buf, "dataWindow", "box2i", &window);
+        attribute(&mut buf, "displayWindow", "box2i", &window);
+        attribute(&mut buf, "lineOrder", "lineOrder", &[0]);
+        buf.push(0);
+
+        let lines_per_chunk = if compression == 3 { 16 } else { 1 };
+        let n_chunks = height.div_ceil(lines_per_chunk);
+        let table = buf.len();
+        buf.resize(table + n_chunks * 8, 0);
+        for chunk in 0..n_chunks {
+            let offset = buf.len() as u64;
+            buf[table + chunk * 8..table + chunk * 8 + 8].copy_from_slice(&offset.to_le_bytes());
+            let y = chunk * lines_per_chunk;
+            let mut data = Vec::new();
+            for row in y..(y + lines_per_chunk).min(height) {
+                for &(_, half, samples) in channels {
+                    for &x in &samples[row * width..(row + 1) * width] {
+                        if half {
+                            data.extend_from_slice(&f32_to_f16(x).to_le_bytes());
+                        } else {
+                            data.extend_from_slice(&x.to_le_bytes());
+                        }
+                    }
+                }
+            }
+            if compression != 0 {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Level::default());
+                encoder.write_all(&predict(&data)).unwrap();
+                let compressed = encoder.finish().unwrap();
+                if compressed.len() < data.len() {
+                    data = compressed;
+                }
+            }
+            buf.extend_from_slice(&(y as i32 - 3).to_le_bytes());
+            buf.extend_from_slice(&(data.len() as i32).to_le_bytes());
+            buf.extend_from_slice(&data);
+        }
+        buf
+    }
+
+    #[test]
+    fn test_f16() {
+        for &(h, x) in [
+            (0x0000, 0.0),
+            (0x3c00, 1.0),
+            (0xc000, -2.0),
+            (0x3555, 0.333_251_95),
+            (0x7bff, 65504.0),
+            (0x0001, 5.960_464_5e-8),
+            (0x7c00, f32::INFINITY),
+        ]
+        .iter()
+        {
+            assert_eq!(f16_to_f32(h), x, "{:#x}", h);
+            assert_eq!(f32_to_f16(x), h, "{}", x);
+        }
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn test_decode() {
+        let (width, height) = (7, 20);
+        let r: Vec<f32> = (0..width * height).map(|i| i as f32 / 256.0).collect();
+        let g: Vec<f32> = (0..width * height)
+            .map(|i| 1.0 - i as f32 / 512.0)
+            .collect();
+        for &compression in [0, 2, 3].iter() {
+            let file = exr(
+                width,
+                height,
+                &[("G", false, &g), ("R", true, &r)],
+                compression,
+                1,
+            );
+            let image = decode(io::Cursor::new(file)).unwrap();
+            assert_eq!((image.width, image.height), (width, height));
+            assert_eq!(image.channels.len(), 2);
+            assert_eq!(image.channel("R").unwrap().samples, r);
+            assert_eq!(image.channel("G").unwrap().samples, g);
+        }
+
+        for &(compression, expected) in [
+            (4, "unsupported exr: PIZ compression"),
+            (200, "invalid exr: unknown compression 200"),
+        ]
+        .iter()
+        {
+            let file = exr(width, height, &[("R", true, &r)], compression, 1);
+            let err = decode(io::Cursor::new(file)).map(|_| ()).unwrap_err();
+            assert_eq!(err.to_string(), expected);
+        }
+        let file = exr(width, height, &[("R", true, &r)], 0, 2);
+        let err = decode(io::Cursor::new(file)).map(|_| ()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unsupported exr: channel R is subsampled 2x2"
+        );
+
+        let mut file = exr(width, height, &[("R", true, &r)], 0, 1);
+        file[5] = 0x02;
+        let err = decode(io::Cursor::new(file)).map(|_| ()).unwrap_err();
+        assert_eq!(err.to_string(), "unsupported exr: tiled images");
+
+        // a data window of 65536x65536, with chunk offsets but no chunks
+        let mut file = exr(1, 1, &[("R", true, &[0.0])], 3, 1);
+        let window = file.windows(10).position(|w| w == b"dataWindow").unwrap() + 21;
+        let table = file.windows(10).position(|w| w == b"lineOrder\0").unwrap() + 25;
+        for (i, x) in [0i32, 0, 65535, 65535].iter().enumerate() {
+            file[window + i * 4..window + i * 4 + 4].copy_from_slice(&x.to_le_bytes());
+        }
+        file.truncate(table);
+        file.resize(table + 4096 * 8, 0);
+        let err = decode(io::Cursor::new(file)).map(|_| ()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid exr: the data window is larger than the file"
+        );
+    }
+
+    #[test]
+    fn test_transfer() {
+        for &(x, linear, srgb) in [
+            (0.0, 0, 0),
+            (1.0, 65535, 65535),
+            (0.5, 32768, 48192),
+            (0.001, 66, 847),
+            (-0.5, 0, 0),
+            (7.0, 65535, 65535),
+            (f32::NAN, 0, 0),
+            (f32::INFINITY, 65535, 65535),
+            (f32::NEG_INFINITY, 0, 0),
+        ]
+        .iter()
+        {
+            assert_eq!(Transfer::Linear.to_u16(x), linear, "{}", x);
+            assert_eq!(Transfer::Srgb.to_u16(x), srgb, "{}", x);
+        }
+    }
+}
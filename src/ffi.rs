@@ -0,0 +1,383 @@
+// Safety requirements are given in plain comments like all the others.
+#![allow(clippy::missing_safety_doc)]
+
+use super::{
+    codec::Codec,
+    error::CodecError,
+    frame::{encode_samples, EncodeOptions, Plane, RGB48Frame},
+    header::FrameHeader,
+};
+use std::{
+    mem,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+// A C interface to the codec, for applications that can't call it from Rust. include/hvc.h is
+// generated from this module with cbindgen (see cbindgen.toml), and the library can be built for
+// linking into them with `cargo rustc --release --features ffi --crate-type staticlib` (or
+// cdylib).
+//
+// Every function returns one of the HvcError codes, with HVC_ERROR_OK (zero) meaning success.
+// Pointers are checked for null and lengths for overflow before anything is read or written, and a
+// panic inside the codec is caught and reported as HVC_ERROR_PANIC rather than unwinding into the
+// caller. Nothing can check that a non-null pointer really points to as much memory as its length
+// says, so that part is up to the caller.
+
+// The error codes the functions return. Those up to HVC_ERROR_INVALID_INPUT correspond to the
+// CodecError variants of the same names.
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HvcError {
+    Ok = 0,
+    Io = 1,
+    TruncatedStream = 2,
+    InvalidHeader = 3,
+    ChecksumMismatch = 4,
+    UnsupportedVersion = 5,
+    Cancelled = 6,
+    InvalidData = 7,
+    VerificationFailed = 8,
+    ContentHashMismatch = 9,
+    LimitExceeded = 10,
+    InvalidInput = 11,
+    // A required pointer was null.
+    NullPointer = 12,
+    // The output buffer is too small for the decoded frame.
+    BufferTooSmall = 13,
+    // The codec panicked. This is a bug, but the caller can carry on.
+    Panic = 14,
+}
+
+impl From<&CodecError> for HvcError {
+    fn from(error: &CodecError) -> Self {
+        match error {
+            CodecError::Io(_) => Self::Io,
+            CodecError::TruncatedStream { .. } => Self::TruncatedStream,
+            CodecError::InvalidHeader(_) => Self::InvalidHeader,
+            CodecError::ChecksumMismatch { .. } => Self::ChecksumMismatch,
+            CodecError::UnsupportedVersion(_) => Self::UnsupportedVersion,
+            CodecError::Cancelled => Self::Cancelled,
+            CodecError::InvalidData(_) => Self::InvalidData,
+            CodecError::VerificationFailed { .. } => Self::VerificationFailed,
+            CodecError::ContentHashMismatch { .. } => Self::ContentHashMismatch,
+            CodecError::LimitExceeded { .. } => Self::LimitExceeded,
+            CodecError::InvalidInput(_) => Self::InvalidInput,
+        }
+    }
+}
+
+impl From<CodecError> for HvcError {
+    fn from(error: CodecError) -> Self {
+        Self::from(&error)
+    }
+}
+
+// What hvc_decode_rgb48 found in a frame's header.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HvcFrameInfo {
+    pub width: usize,
+    pub height: usize,
+    // The number of interleaved channels, 1 to 4.
+    pub channels: usize,
+    pub bit_depth: u8,
+}
+
+// Buffers returned by hvc_encode_rgb48 are preceded by their length, so that hvc_free can
+// reconstruct the allocation from the pointer alone.
+const LENGTH_PREFIX: usize = mem::size_of::<usize>();
+
+fn into_raw_buffer(data: Vec<u8>) -> (*mut u8, usize) {
+    let len = data.len();
+    let mut buffer = Vec::with_capacity(LENGTH_PREFIX + len);
+    buffer.extend_from_slice(&(LENGTH_PREFIX + len).to_ne_bytes());
+    buffer.extend_from_slice(&data);
+    let buffer = Box::into_raw(buffer.into_boxed_slice()) as *mut u8;
+    // safe: the allocation is at least LENGTH_PREFIX bytes long
+    (unsafe { buffer.add(LENGTH_PREFIX) }, len)
+}
+
+// Runs f, turning its result into an error code and any panic into HvcError::Panic.
+fn guard<F: FnOnce() -> Result<(), HvcError>>(f: F) -> i32 {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => HvcError::Ok as i32,
+        Ok(Err(e)) => e as i32,
+        Err(_) => HvcError::Panic as i32,
+    }
+}
+
+fn sample_count(width: usize, height: usize, channels: usize) -> Result<usize, HvcError> {
+    width
+        .checked_mul(height)
+        .and_then(|n| n.checked_mul(channels))
+        .filter(|&n| n.checked_mul(2).is_some())
+        .ok_or(HvcError::InvalidInput)
+}
+
+// Encodes a frame of width×height interleaved samples with the given number of channels (1 to
+// 4), with a header so that hvc_decode_rgb48 needs nothing else to decode it. data must point to
+// width * height * channels samples. On success, *out is set to a buffer holding the encoded frame
+// and *out_len to its length in bytes, and the buffer must be released with hvc_free. On failure,
+// *out is set to null and *out_len to zero if they aren't null themselves.
+//
+// # Safety
+//
+// data must be valid for reads of width * height * channels samples, and out and out_len for a
+// write each.
+#[no_mangle]
+pub unsafe extern "C" fn hvc_encode_rgb48(
+    data: *const u16,
+    width: usize,
+    height: usize,
+    channels: usize,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    if !out.is_null() {
+        *out = ptr::null_mut();
+    }
+    if !out_len.is_null() {
+        *out_len = 0;
+    }
+    guard(|| {
+        if data.is_null() || out.is_null() || out_len.is_null() {
+            return Err(HvcError::NullPointer);
+        }
+        if !(1..=4).contains(&channels) || width == 0 || height == 0 {
+            return Err(HvcError::InvalidInput);
+        }
+        let n = sample_count(width, height, channels)?;
+        // the caller's samples are encoded where they are, rather than copied into an RGB48Frame
+        let samples = Plane::from_raw_parts(data, n, width, height, channels, width * channels)
+            .map_err(|_| HvcError::InvalidInput)?;
+        let mut encoded = Vec::new();
+        encode_samples::<Codec, _>(
+            samples.data,
+            (width, height),
+            16,
+            &mut encoded,
+            &EncodeOptions::default(),
+            &mut |_| false,
+        )?;
+        let (buffer, len) = into_raw_buffer(encoded);
+        *out = buffer;
+        *out_len = len;
+        Ok(())
+    })
+}
+
+// Decodes a frame written by hvc_encode_rgb48 from the len bytes at data into out, as interleaved
+// samples. info, if it isn't null, is filled in from the frame's header before anything else is
+// checked, so a caller that doesn't know how large the frame is can pass a null out and a capacity
+// of zero to find out: the frame needs width * height * channels samples, and anything less gives
+// HVC_ERROR_BUFFER_TOO_SMALL. out is only written to if decoding succeeds.
+//
+// # Safety
+//
+// data must be valid for reads of len bytes, out for writes of out_capacity samples, and info, if
+// it isn't null, for a write.
+#[no_mangle]
+pub unsafe extern "C" fn hvc_decode_rgb48(
+    data: *const u8,
+    len: usize,
+    out: *mut u16,
+    out_capacity: usize,
+    info: *mut HvcFrameInfo,
+) -> i32 {
+    guard(|| {
+        if data.is_null() {
+            return Err(HvcError::NullPointer);
+        }
+        let encoded = slice::from_raw_parts(data, len);
+        let header = FrameHeader::read(encoded)?;
+        if !info.is_null() {
+            *info = HvcFrameInfo {
+                width: header.width,
+                height: header.height,
+                channels: header.n_planes,
+                bit_depth: header.bit_depth,
+            };
+        }
+        let n = sample_count(header.width, header.height, header.n_planes)
+            .map_err(|_| HvcError::InvalidHeader)?;
+        if out_capacity < n {
+            return Err(HvcError::BufferTooSmall);
+        }
+        if out.is_null() {
+            return Err(HvcError::NullPointer);
+        }
+        let frame = RGB48Frame::decode_with_header::<Codec, _>(encoded)?;
+        slice::from_raw_parts_mut(out, n).copy_from_slice(&frame.data);
+        Ok(())
+    })
+}
+
+// Releases a buffer returned by hvc_encode_rgb48. Does nothing if buffer is null.
+//
+// # Safety
+//
+// buffer must be null or a buffer returned by hvc_encode_rgb48 that hasn't already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn hvc_free(buffer: *mut u8) {
+    if buffer.is_null() {
+        return;
+    }
+    let start = buffer.sub(LENGTH_PREFIX);
+    let mut len = [0; LENGTH_PREFIX];
+    ptr::copy_nonoverlapping(start, len.as_mut_ptr(), LENGTH_PREFIX);
+    let len = usize::from_ne_bytes(len);
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(start, len)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::synth, *};
+
+    // The functions are called through pointers of the types their C declarations give them.
+    type Encode =
+        unsafe extern "C" fn(*const u16, usize, usize, usize, *mut *mut u8, *mut usize) -> i32;
+    type Decode = unsafe extern "C" fn(*const u8, usize, *mut u16, usize, *mut HvcFrameInfo) -> i32;
+    type Free = unsafe extern "C" fn(*mut u8);
+
+    const ENCODE: Encode = hvc_encode_rgb48;
+    const DECODE: Decode = hvc_decode_rgb48;
+    const FREE: Free = hvc_free;
+
+    #[test]
+    fn test_ffi() {
+        for &channels in [1, 3, 4].iter() {
+            let frame = RGB48Frame {
+                data: synth::noise_gray(13 * channels, 7, channels as u32, 16).data,
+                width: 13,
+                height: 7,
+                bit_depth: 16,
+            };
+            let (mut out, mut out_len) = (ptr::null_mut(), 0);
+            let status =
+                unsafe { ENCODE(frame.data.as_ptr(), 13, 7, channels, &mut out, &mut out_len) };
+            assert_eq!(status, HvcError::Ok as i32);
+            assert!(!out.is_null());
+
+            let encoded = unsafe { slice::from_raw_parts(out, out_len) }.to_vec();
+            let mut expected = Vec::new();
+            frame
+                .encode_with_header::<Codec, _>(&mut expected, &EncodeOptions::default())
+                .unwrap();
+            assert_eq!(encoded, expected);
+
+            // asking for the size first
+            let mut info = HvcFrameInfo::default();
+            let status = unsafe { DECODE(out, out_len, ptr::null_mut(), 0, &mut info) };
+            assert_eq!(status, HvcError::BufferTooSmall as i32);
+            assert_eq!(
+                info,
+                HvcFrameInfo {
+                    width: 13,
+                    height: 7,
+                    channels,
+                    bit_depth: 16,
+                }
+            );
+
+            let mut decoded = vec![0; 13 * 7 * channels];
+            let status = unsafe {
+                DECODE(
+                    out,
+                    out_len,
+                    decoded.as_mut_ptr(),
+                    decoded.len() - 1,
+                    &mut info,
+                )
+            };
+            assert_eq!(status, HvcError::BufferTooSmall as i32);
+            assert!(decoded.iter().all(|&x| x == 0));
+            let status = unsafe {
+                DECODE(
+                    out,
+                    out_len,
+                    decoded.as_mut_ptr(),
+                    decoded.len(),
+                    ptr::null_mut(),
+                )
+            };
+            assert_eq!(status, HvcError::Ok as i32);
+            assert_eq!(decoded, frame.data);
+
+            unsafe { FREE(out) };
+        }
+        unsafe { FREE(ptr::null_mut()) };
+    }
+
+    #[test]
+    fn test_ffi_errors() {
+        let data = [0u16; 12];
+        let (mut out, mut out_len) = (ptr::null_mut(), 1);
+        let status = unsafe { ENCODE(ptr::null(), 2, 2, 3, &mut out, &mut out_len) };
+        assert_eq!(status, HvcError::NullPointer as i32);
+        assert_eq!((out, out_len), (ptr::null_mut(), 0));
+        let status = unsafe { ENCODE(data.as_ptr(), 2, 2, 3, ptr::null_mut(), &mut out_len) };
+        assert_eq!(status, HvcError::NullPointer as i32);
+        for &(width, height, channels) in
+            [(2, 2, 5), (2, 2, 0), (0, 2, 3), (usize::MAX, 2, 3)].iter()
+        {
+            let status = unsafe {
+                ENCODE(
+                    data.as_ptr(),
+                    width,
+                    height,
+                    channels,
+                    &mut out,
+                    &mut out_len,
+                )
+            };
+            assert_eq!(status, HvcError::InvalidInput as i32);
+            assert!(out.is_null());
+        }
+
+        let mut decoded = [0u16; 12];
+        let status = unsafe { DECODE(ptr::null(), 0, decoded.as_mut_ptr(), 12, ptr::null_mut()) };
+        assert_eq!(status, HvcError::NullPointer as i32);
+        let garbage = [b'x'; 32];
+        let status = unsafe {
+            DECODE(
+                garbage.as_ptr(),
+                garbage.len(),
+                decoded.as_mut_ptr(),
+                12,
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(status, HvcError::InvalidHeader as i32);
+
+        // a truncated stream
+        let (mut out, mut out_len) = (ptr::null_mut(), 0);
+        let frame = synth::noise(2, 2, 1, 16);
+        assert_eq!(
+            unsafe { ENCODE(frame.data.as_ptr(), 2, 2, 3, &mut out, &mut out_len) },
+            HvcError::Ok as i32
+        );
+        let status = unsafe { DECODE(out, out_len - 1, decoded.as_mut_ptr(), 12, ptr::null_mut()) };
+        assert_ne!(status, HvcError::Ok as i32);
+        unsafe { FREE(out) };
+
+        assert_eq!(guard(|| panic!("a bug")), HvcError::Panic as i32);
+    }
+
+    #[test]
+    fn test_ffi_header() {
+        // include/hvc.h has to be regenerated whenever the interface changes
+        let header = include_str!("../include/hvc.h");
+        for declaration in [
+            "int32_t hvc_encode_rgb48(const uint16_t *data,",
+            "int32_t hvc_decode_rgb48(const uint8_t *data,",
+            "void hvc_free(uint8_t *buffer);",
+            "typedef struct HvcFrameInfo {",
+            "HVC_ERROR_PANIC = 14,",
+        ]
+        .iter()
+        {
+            assert!(header.contains(declaration), "hvc.h lacks {}", declaration);
+        }
+    }
+}
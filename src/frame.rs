@@ -1,6 +1,27 @@
+#[cfg(feature = "exr")]
+use super::exr;
+#[cfg(feature = "tiff")]
+use super::planar_tiff;
+#[cfg(feature = "png")]
+use super::png;
+use super::{
+    band,
+    codec::{self, KMode},
+    color::{ColorTransform, Matrix},
+    dpx,
+    error::CodecError,
+    frame16::ChannelLayout,
+    header::FrameHeader,
+    pgm, pipeline,
+    progress::{Counting, Progress},
+    pyramid,
+    tile::{self, TileLayout},
+};
 use std::{
-    io::{self, Read, Write},
+    convert::TryInto,
+    io::{self, Read, Seek, SeekFrom, Write},
     path::Path,
+    slice,
 };
 use thiserror::Error;
 
@@ -13,120 +34,4484 @@ pub struct Plane<T> {
 }
 
 impl<T: AsRef<[u16]>> Plane<T> {
+    // Builds a plane with arbitrary strides, such as one whose rows are padded out to an aligned
+    // size, checking that data holds every sample. Consecutive samples are sample_stride apart and
+    // consecutive rows row_stride apart, which must leave room for a whole row. The last row
+    // doesn't need to be followed by padding.
+    pub fn with_padding(
+        data: T,
+        width: usize,
+        height: usize,
+        sample_stride: usize,
+        row_stride: usize,
+    ) -> Result<Self, FrameError> {
+        if width == 0 || height == 0 {
+            return Err(FrameError::EmptyFrame { width, height });
+        }
+        let row_len = (width - 1)
+            .checked_mul(sample_stride)
+            .and_then(|n| n.checked_add(1));
+        let row_len = match row_len {
+            Some(row_len) if sample_stride > 0 && row_len <= row_stride => row_len,
+            _ => {
+                return Err(FrameError::InvalidStrides {
+                    width,
+                    sample_stride,
+                    row_stride,
+                })
+            }
+        };
+        let actual = data.as_ref().len();
+        match (height - 1)
+            .checked_mul(row_stride)
+            .and_then(|n| n.checked_add(row_len))
+        {
+            Some(required) if required <= actual => {}
+            required => {
+                return Err(FrameError::BufferTooSmall {
+                    required: required.unwrap_or(usize::MAX),
+                    actual,
+                })
+            }
+        }
+        Ok(Self {
+            data,
+            width,
+            height,
+            sample_stride,
+            row_stride,
+        })
+    }
+
     pub fn sample(&self, col: usize, row: usize) -> u16 {
         self.data.as_ref()[row * self.row_stride + col * self.sample_stride]
     }
 }
 
+impl Plane<&[u16]> {
+    // Wraps len samples at ptr, such as a buffer owned by a C caller or pinned for a GPU, as a plane
+    // without copying them, checking the geometry like with_padding does.
+    //
+    // # Safety
+    //
+    // ptr must be non-null, aligned, and valid for reads of len samples, and nothing may write to
+    // them for as long as the plane, or anything borrowed from it, is in use. Only the strides are
+    // checked against len: nothing can check that ptr really points to that many samples.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn from_raw_parts(
+        ptr: *const u16,
+        len: usize,
+        width: usize,
+        height: usize,
+        sample_stride: usize,
+        row_stride: usize,
+    ) -> Result<Self, FrameError> {
+        Self::with_padding(
+            slice::from_raw_parts(ptr, len),
+            width,
+            height,
+            sample_stride,
+            row_stride,
+        )
+    }
+}
+
+impl Plane<&mut [u16]> {
+    // The mutable counterpart to Plane::from_raw_parts, for decoding into a caller's buffer.
+    //
+    // # Safety
+    //
+    // As for from_raw_parts, but ptr must be valid for writes too, and nothing else may read or
+    // write the samples while the plane is in use.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn from_raw_parts_mut(
+        ptr: *mut u16,
+        len: usize,
+        width: usize,
+        height: usize,
+        sample_stride: usize,
+        row_stride: usize,
+    ) -> Result<Self, FrameError> {
+        Self::with_padding(
+            slice::from_raw_parts_mut(ptr, len),
+            width,
+            height,
+            sample_stride,
+            row_stride,
+        )
+    }
+}
+
+impl<T: AsRef<[u16]>> Plane<T> {
+    // Returns a view of the w×h region whose top-left sample is at (x, y). The view shares the
+    // plane's strides, so it works for interleaved planes too. Panics if the region doesn't fit
+    // within the plane.
+    pub fn view(&self, x: usize, y: usize, w: usize, h: usize) -> Plane<&[u16]> {
+        assert!(
+            x.checked_add(w).is_some_and(|right| right <= self.width)
+                && y.checked_add(h).is_some_and(|bottom| bottom <= self.height),
+            "view out of bounds"
+        );
+        Plane {
+            data: &self.data.as_ref()[y * self.row_stride + x * self.sample_stride..],
+            width: w,
+            height: h,
+            sample_stride: self.sample_stride,
+            row_stride: self.row_stride,
+        }
+    }
+
+    // Copies the plane into a new, contiguous one (with a sample_stride of 1 and a row_stride of
+    // width).
+    pub fn to_owned(&self) -> Plane<Vec<u16>> {
+        let mut data = Vec::with_capacity(self.width * self.height);
+        for row in self.rows() {
+            match row.as_slice() {
+                Some(samples) => data.extend_from_slice(samples),
+                None => data.extend(row.iter()),
+            }
+        }
+        Plane {
+            data,
+            width: self.width,
+            height: self.height,
+            sample_stride: 1,
+            row_stride: self.width,
+        }
+    }
+
+    // Calls f with the column, row, and value of each sample, in raster order.
+    pub fn for_each_sample<F: FnMut(usize, usize, u16)>(&self, mut f: F) {
+        for (row, samples) in self.rows().enumerate() {
+            match samples.as_slice() {
+                Some(samples) => {
+                    for (col, &x) in samples.iter().enumerate() {
+                        f(col, row, x);
+                    }
+                }
+                None => {
+                    for (col, x) in samples.iter().enumerate() {
+                        f(col, row, x);
+                    }
+                }
+            }
+        }
+    }
+
+    // The smallest and largest samples, or None if the plane is empty.
+    pub fn min_max(&self) -> Option<(u16, u16)> {
+        let mut ret = None;
+        self.for_each_sample(|_, _, x| {
+            let (min, max) = ret.get_or_insert((x, x));
+            *min = x.min(*min);
+            *max = x.max(*max);
+        });
+        ret
+    }
+
+    // The number of samples with each value, indexed by value.
+    pub fn histogram(&self) -> Vec<u64> {
+        self.histogram_with_bins(1 << 16)
+    }
+
+    // Like histogram, but with each bin counting 65536 / bins consecutive values. Panics unless
+    // bins is a power of two no greater than 65536.
+    pub fn histogram_with_bins(&self, bins: usize) -> Vec<u64> {
+        assert!(
+            bins.is_power_of_two() && bins <= 1 << 16,
+            "can't make a histogram with {} bins",
+            bins
+        );
+        let shift = 16 - bins.trailing_zeros();
+        let mut ret = vec![0; bins];
+        self.for_each_sample(|_, _, x| ret[(x as u32 >> shift) as usize] += 1);
+        ret
+    }
+
+    // Saves the plane as a 16-bit binary PGM, which almost any image viewer can show.
+    pub fn write_pgm<W: Write>(&self, dest: W) -> Result<(), FrameSaveError> {
+        pgm::write(self, dest)?;
+        Ok(())
+    }
+
+    // Saves the plane as a 16-bit grayscale PNG.
+    #[cfg(feature = "png")]
+    pub fn write_png<W: Write>(&self, dest: W) -> Result<(), FrameSaveError> {
+        png::encode(
+            dest,
+            self.width,
+            self.height,
+            png::ColorType::Gray,
+            16,
+            |row, dest| {
+                for (col, bytes) in dest.chunks_exact_mut(2).enumerate() {
+                    bytes.copy_from_slice(&self.sample(col, row).to_be_bytes());
+                }
+            },
+        )?;
+        Ok(())
+    }
+
+    // Iterates over the plane's rows, top to bottom.
+    pub fn rows(&self) -> impl ExactSizeIterator<Item = PlaneRow<'_>> {
+        let data = self.data.as_ref();
+        let (width, row_stride, sample_stride) = (self.width, self.row_stride, self.sample_stride);
+        (0..self.height).map(move |row| PlaneRow {
+            data: &data[row * row_stride..],
+            width,
+            sample_stride,
+        })
+    }
+}
+
+// A row of a plane, as returned by Plane::rows.
+#[derive(Clone, Copy)]
+pub struct PlaneRow<'a> {
+    // Starts with the row's first sample.
+    data: &'a [u16],
+    width: usize,
+    sample_stride: usize,
+}
+
+impl<'a> PlaneRow<'a> {
+    pub fn len(&self) -> usize {
+        self.width
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0
+    }
+
+    pub fn sample(&self, col: usize) -> u16 {
+        assert!(
+            col < self.width,
+            "sample {} of a {}-sample row",
+            col,
+            self.width
+        );
+        self.data[col * self.sample_stride]
+    }
+
+    // The row's samples as a slice, if they're contiguous, which they are when sample_stride is 1.
+    pub fn as_slice(&self) -> Option<&'a [u16]> {
+        if self.sample_stride == 1 || self.width <= 1 {
+            Some(&self.data[..self.width])
+        } else {
+            None
+        }
+    }
+
+    // For contiguous rows, iterating over as_slice() instead may be faster.
+    pub fn iter(&self) -> impl Iterator<Item = u16> + 'a {
+        self.data
+            .iter()
+            .step_by(self.sample_stride.max(1))
+            .take(self.width)
+            .copied()
+    }
+}
+
+impl<T: AsMut<[u16]>> Plane<T> {
+    // Copies src's samples into this plane, which must have the same dimensions. The strides of
+    // the two can differ.
+    pub fn copy_from<U: AsRef<[u16]>>(&mut self, src: &Plane<U>) -> Result<(), FrameError> {
+        if (src.width, src.height) != (self.width, self.height) {
+            return Err(FrameError::DimensionMismatch {
+                expected: (self.width, self.height),
+                actual: (src.width, src.height),
+            });
+        }
+        let (width, row_stride, sample_stride) = (self.width, self.row_stride, self.sample_stride);
+        let data = self.data.as_mut();
+        for (row, src_row) in src.rows().enumerate() {
+            let dest = &mut data[row * row_stride..];
+            match src_row.as_slice() {
+                Some(samples) if sample_stride == 1 => dest[..width].copy_from_slice(samples),
+                _ => {
+                    for (dest, sample) in dest.iter_mut().step_by(sample_stride).zip(src_row.iter())
+                    {
+                        *dest = sample;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Calls f on each of the plane's samples, leaving everything between them alone.
+    fn update_samples<F: FnMut(&mut u16)>(&mut self, mut f: F) {
+        let (width, height) = (self.width, self.height);
+        let (row_stride, sample_stride) = (self.row_stride, self.sample_stride);
+        if width == 0 {
+            return;
+        }
+        let data = self.data.as_mut();
+        for row in 0..height {
+            let row = &mut data[row * row_stride..];
+            if sample_stride == 1 {
+                row[..width].iter_mut().for_each(&mut f);
+            } else {
+                row.iter_mut()
+                    .step_by(sample_stride)
+                    .take(width)
+                    .for_each(&mut f);
+            }
+        }
+    }
+
+    pub fn fill(&mut self, value: u16) {
+        self.update_samples(|x| *x = value);
+    }
+
+    // Replaces each sample x with f(x).
+    pub fn map_samples<F: FnMut(u16) -> u16>(&mut self, mut f: F) {
+        self.update_samples(|x| *x = f(*x));
+    }
+
+    // Replaces each sample x with x * gain_num / gain_den + offset, rounding the product to the
+    // nearest integer and clamping the result to the range of clamp_to_bit_depth bits, which is
+    // 16 for samples that fill the 16-bit range. Panics if gain_den is zero or the bit depth isn't
+    // 1 to 16.
+    pub fn apply_gain_offset(
+        &mut self,
+        gain_num: u32,
+        gain_den: u32,
+        offset: i32,
+        clamp_to_bit_depth: u8,
+    ) {
+        assert!(gain_den > 0, "the gain's denominator must be nonzero");
+        assert!(
+            (1..=16).contains(&clamp_to_bit_depth),
+            "can't clamp to {} bits",
+            clamp_to_bit_depth
+        );
+        let max = (1i64 << clamp_to_bit_depth) - 1;
+        let (num, den, offset) = (gain_num as i64, gain_den as i64, offset as i64);
+        self.update_samples(|x| {
+            let scaled = (*x as i64 * num + den / 2) / den;
+            *x = (scaled + offset).clamp(0, max) as u16;
+        });
+    }
+
+    // The mutable counterpart of view.
+    pub fn view_mut(&mut self, x: usize, y: usize, w: usize, h: usize) -> Plane<&mut [u16]> {
+        assert!(
+            x.checked_add(w).is_some_and(|right| right <= self.width)
+                && y.checked_add(h).is_some_and(|bottom| bottom <= self.height),
+            "view out of bounds"
+        );
+        Plane {
+            data: &mut self.data.as_mut()[y * self.row_stride + x * self.sample_stride..],
+            width: w,
+            height: h,
+            sample_stride: self.sample_stride,
+            row_stride: self.row_stride,
+        }
+    }
+}
+
 pub trait Codec {
-    fn encode<T: AsRef<[u16]>, W: Write>(plane: &Plane<T>, dest: W) -> io::Result<()>;
-    fn decode<T: AsMut<[u16]>, R: Read>(source: R, plane: &mut Plane<T>) -> io::Result<()>;
+    fn encode<T: AsRef<[u16]>, W: Write>(plane: &Plane<T>, dest: W) -> Result<(), CodecError>;
+
+    // Decoders report truncation as CodecError::TruncatedStream with plane 0 and the position
+    // within the plane they were given. Callers that know better fill in the details with
+    // CodecError::in_plane.
+    fn decode<T: AsMut<[u16]>, R: Read>(source: R, plane: &mut Plane<T>) -> Result<(), CodecError>;
+
+    // Lossy encoding, where prediction residuals are quantized with a step of q. Returns the sum of
+    // the squared differences between the plane and its reconstruction. q = 1 must produce exactly
+    // the same output as encode. Codecs that don't support quantization only accept q = 1.
+    fn encode_quantized<T: AsRef<[u16]>, W: Write>(
+        plane: &Plane<T>,
+        q: u16,
+        dest: W,
+    ) -> Result<u64, CodecError> {
+        if q != 1 {
+            return Err(CodecError::InvalidInput(
+                "this codec doesn't support quantization".to_string(),
+            ));
+        }
+        Self::encode(plane, dest)?;
+        Ok(0)
+    }
+
+    fn decode_quantized<T: AsMut<[u16]>, R: Read>(
+        source: R,
+        plane: &mut Plane<T>,
+        q: u16,
+    ) -> Result<(), CodecError> {
+        if q != 1 {
+            return Err(CodecError::InvalidInput(
+                "this codec doesn't support quantization".to_string(),
+            ));
+        }
+        Self::decode(source, plane)
+    }
+
+    // Lossless coding with the same Rice parameter, k, for every sample instead of one adapted to
+    // each sample's neighbors (see codec::KMode). Codecs without Rice parameters only return
+    // CodecError::InvalidInput.
+    fn encode_fixed_k<T: AsRef<[u16]>, W: Write>(
+        _plane: &Plane<T>,
+        _k: u8,
+        _dest: W,
+    ) -> Result<(), CodecError> {
+        Err(CodecError::InvalidInput(
+            "this codec doesn't support a fixed k".to_string(),
+        ))
+    }
+
+    fn decode_fixed_k<T: AsMut<[u16]>, R: Read>(
+        _source: R,
+        _plane: &mut Plane<T>,
+        _k: u8,
+    ) -> Result<(), CodecError> {
+        Err(CodecError::InvalidInput(
+            "this codec doesn't support a fixed k".to_string(),
+        ))
+    }
+
+    // Lossless coding of 32-bit samples. Codecs that don't support them only return
+    // CodecError::InvalidInput.
+    fn encode_u32<T: AsRef<[u32]>, W: Write>(
+        _plane: &Plane<T>,
+        _dest: W,
+    ) -> Result<(), CodecError> {
+        Err(CodecError::InvalidInput(
+            "this codec doesn't support 32-bit samples".to_string(),
+        ))
+    }
+
+    fn decode_u32<T: AsMut<[u32]>, R: Read>(
+        _source: R,
+        _plane: &mut Plane<T>,
+    ) -> Result<(), CodecError> {
+        Err(CodecError::InvalidInput(
+            "this codec doesn't support 32-bit samples".to_string(),
+        ))
+    }
+}
+
+// How a plane, or a tile or band of one, is coded: with its prediction residuals quantized with a
+// step of q, and with a Rice parameter adapted to each sample, or fixed at k. A fixed k is only
+// used for lossless coding.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlaneCoding {
+    pub q: u16,
+    pub k: Option<u8>,
+}
+
+impl Default for PlaneCoding {
+    fn default() -> Self {
+        Self { q: 1, k: None }
+    }
+}
+
+impl PlaneCoding {
+    // Encodes the plane with C, returning the sum of squared errors like Codec::encode_quantized.
+    pub fn encode<C: Codec, T: AsRef<[u16]>, W: Write>(
+        self,
+        plane: &Plane<T>,
+        dest: W,
+    ) -> Result<u64, CodecError> {
+        match self.k {
+            Some(k) => C::encode_fixed_k(plane, k, dest).map(|()| 0),
+            None => C::encode_quantized(plane, self.q, dest),
+        }
+    }
+
+    pub fn decode<C: Codec, T: AsMut<[u16]>, R: Read>(
+        self,
+        source: R,
+        plane: &mut Plane<T>,
+    ) -> Result<(), CodecError> {
+        match self.k {
+            Some(k) => C::decode_fixed_k(source, plane, k),
+            None => C::decode_quantized(source, plane, self.q),
+        }
+    }
 }
 
 #[derive(Error, Debug)]
 pub enum FrameOpenError {
     #[error(transparent)]
     IO(#[from] io::Error),
+    #[cfg(feature = "tiff")]
     #[error(transparent)]
     TiffError(#[from] tiff::TiffError),
+    #[cfg(feature = "tiff")]
     #[error("unsupported color type: {0:?}")]
     UnsupportedColorType(tiff::ColorType),
     #[error("unsupported sample type")]
     UnsupportedSampleType,
+    #[cfg(feature = "png")]
+    #[error(transparent)]
+    PngError(#[from] png::PngError),
+    // Only RGB PNGs can be loaded.
+    #[cfg(feature = "png")]
+    #[error("unsupported png color type: {0:?}")]
+    UnsupportedPngColorType(png::ColorType),
+    // The file is neither a TIFF nor a PNG.
+    #[error("unknown file format")]
+    UnknownFormat,
+    #[error("invalid dpx file: {0}")]
+    InvalidDpx(String),
+    #[error("invalid pgm file: {0}")]
+    InvalidPgm(String),
+    #[cfg(feature = "exr")]
+    #[error(transparent)]
+    ExrError(#[from] exr::ExrError),
+    // Only uncompressed 10-bit RGB DPX files with filled packing can be loaded.
+    #[error("unsupported dpx file: {0}")]
+    UnsupportedDpx(String),
+    // A raw file's size doesn't fit the dimensions it was opened with.
+    #[error(transparent)]
+    InvalidFrame(#[from] FrameError),
+}
+
+#[derive(Error, Debug)]
+pub enum FrameSaveError {
+    #[error(transparent)]
+    IO(#[from] io::Error),
+    #[cfg(feature = "tiff")]
+    #[error(transparent)]
+    TiffError(#[from] tiff::TiffError),
+    #[cfg(feature = "png")]
+    #[error(transparent)]
+    PngError(#[from] png::PngError),
+    // Only frames with 3 planes can be saved as RGB.
+    #[error("unsupported number of planes: {0}")]
+    UnsupportedPlaneCount(usize),
+    // The format has no color type for the frame's channels.
+    #[error("unsupported channel layout: {0:?}")]
+    UnsupportedLayout(ChannelLayout),
+    // The path's extension doesn't name a format that can be saved (see Frame16::save).
+    #[error("can't tell what format to save {0} in")]
+    UnknownFormat(String),
+}
+
+// Returned when a frame is built from samples that don't fit its dimensions.
+#[derive(Error, Debug, PartialEq)]
+pub enum FrameError {
+    #[error("frames have 1 to 4 channels, not {0}")]
+    InvalidChannelCount(usize),
+    #[error("a {width}x{height} frame has no samples")]
+    EmptyFrame { width: usize, height: usize },
+    #[error("a {width}x{height} frame with {channels} channels is too large")]
+    TooLarge {
+        width: usize,
+        height: usize,
+        channels: usize,
+    },
+    #[error("{len} samples isn't a whole number of planes for a {width}x{height} frame")]
+    PartialPlane {
+        len: usize,
+        width: usize,
+        height: usize,
+    },
+    #[error("expected {expected} samples, got {actual}")]
+    LengthMismatch { expected: usize, actual: usize },
+    #[error("expected {expected} bytes, got {actual}")]
+    ByteLengthMismatch { expected: usize, actual: usize },
+    #[error("expected {expected} samples in plane {plane}, got {actual}")]
+    PlaneLengthMismatch {
+        plane: usize,
+        expected: usize,
+        actual: usize,
+    },
+    // Planes being copied between have different dimensions.
+    #[error("expected a {}x{} plane, got {}x{}", expected.0, expected.1, actual.0, actual.1)]
+    DimensionMismatch {
+        expected: (usize, usize),
+        actual: (usize, usize),
+    },
+    #[error("region {rect:?} is empty or outside of the {width}x{height} frame")]
+    InvalidRegion {
+        rect: (usize, usize, usize, usize),
+        width: usize,
+        height: usize,
+    },
+    #[error("a row of {width} samples {sample_stride} apart doesn't fit in a row stride of {row_stride}")]
+    InvalidStrides {
+        width: usize,
+        sample_stride: usize,
+        row_stride: usize,
+    },
+    #[error("expected at least {required} samples, got {actual}")]
+    BufferTooSmall { required: usize, actual: usize },
+    #[error("rows can't be aligned to {0} bytes, which isn't a power of two of at least 2")]
+    InvalidAlignment(usize),
+    // Frames being compared have different numbers of channels.
+    #[error("expected {expected} channels, got {actual}")]
+    ChannelMismatch { expected: usize, actual: usize },
+    // The frame's channels don't hold what the operation needs, such as RGB for a color conversion.
+    #[error("unsupported channel layout: {0:?}")]
+    UnsupportedLayout(ChannelLayout),
+}
+
+// The byte order of the samples in a raw file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+// How RGB48Frame::pad fills the new samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PadMode {
+    // Every channel of every new sample is the given value.
+    Constant(u16),
+    // New samples copy the nearest sample in the frame, so the border continues the edge. This is
+    // the kindest to prediction.
+    ReplicateEdge,
+    // New samples reflect the frame about its last row or column, without repeating it: a row
+    // ending a, b, c continues b, a. Padding larger than the frame keeps reflecting back and forth.
+    Mirror,
+}
+
+// Options for RGB48Frame::encode_with_header. Everything chosen here is recorded in the header, so
+// decoding doesn't need to be told about it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EncodeOptions {
+    pub color_transform: ColorTransform,
+    // If given, each plane is split into tiles of this width and height which are encoded
+    // independently (see the tile module).
+    pub tile_size: Option<(usize, usize)>,
+    // If given, each plane's prediction residuals are quantized with the corresponding step, which
+    // makes encoding lossy (see Codec::encode_quantized). Steps of 1 are lossless, and if every step
+    // is 1 the output is identical to leaving this unset.
+    pub quantization: Option<Vec<u16>>,
+    // If given, each plane is coded hierarchically, with a base layer downsampled by this factor
+    // (2 to 255) coded before any of the full-resolution data, so that RGB48Frame::decode_preview
+    // can stop after it (see the pyramid module). Hierarchical coding is always lossless, and can't
+    // be combined with tiles or quantization.
+    pub preview_factor: Option<usize>,
+    // If given, the planes are interleaved in bands of this many rows instead of following one
+    // another, so the frame can be decoded top to bottom as it arrives (see the band module). Bands
+    // can't be combined with tiles or hierarchical coding.
+    pub band_height: Option<usize>,
+    // How Rice parameters are chosen. Anything but KMode::Adaptive is lossless only, and can't be
+    // combined with quantization or hierarchical coding.
+    pub k_mode: KMode,
+}
+
+// Bounds on what decoding may allocate. Dimensions come from the caller or from the stream's
+// header, and either way nothing checks them against the data until it's been allocated for, so a
+// corrupt or malicious header could otherwise claim an enormous frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Limits {
+    // The largest frame that will be decoded, in bytes of 16-bit samples.
+    pub max_decoded_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_decoded_bytes: 1 << 30,
+        }
+    }
+}
+
+impl Limits {
+    pub fn unlimited() -> Self {
+        Self {
+            max_decoded_bytes: usize::MAX,
+        }
+    }
+
+    // Checks that a frame of the given number of samples can be decoded.
+    pub(crate) fn check(&self, samples: usize) -> Result<(), CodecError> {
+        let bytes = samples.saturating_mul(2);
+        if bytes > self.max_decoded_bytes {
+            return Err(CodecError::LimitExceeded {
+                bytes,
+                limit: self.max_decoded_bytes,
+            });
+        }
+        Ok(())
+    }
+}
+
+// Options for RGB48Frame::decode_with_options and decode_with_header_and_options.
+#[derive(Clone, Debug, Default)]
+pub struct DecodeOptions {
+    pub limits: Limits,
 }
 
-#[derive(PartialEq)]
+// The outcome of RGB48Frame::encode_verified.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifyReport {
+    // The number of bytes written.
+    pub size: usize,
+    // The 64-bit FNV-1a hash of the bytes written.
+    pub hash: u64,
+    // The frame's content hash (see RGB48Frame::content_hash), which decoding reproduces.
+    pub content_hash: u64,
+}
+
+#[derive(Clone, PartialEq)]
 pub struct RGB48Frame {
     pub data: Vec<u16>,
     pub width: usize,
     pub height: usize,
+    // The bit depth of the samples the frame was loaded from. Samples narrower than 16 bits are
+    // widened to fill the 16-bit range when loaded, but the original depth is recorded in the
+    // header when encoding, and restored when decoding.
+    pub bit_depth: u8,
 }
 
 impl RGB48Frame {
-    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, FrameOpenError> {
-        let f = std::fs::File::open(path)?;
-        let mut dec =
-            tiff::decoder::Decoder::new(f)?.with_limits(tiff::decoder::Limits::unlimited());
-        let (width, height) = dec.dimensions()?;
-
-        Ok(match dec.colortype()? {
-            tiff::ColorType::RGB(16) => match dec.read_image()? {
-                tiff::decoder::DecodingResult::U16(data) => RGB48Frame {
-                    data,
-                    width: width as _,
-                    height: height as _,
-                },
-                _ => return Err(FrameOpenError::UnsupportedSampleType),
-            },
-            color_type => return Err(FrameOpenError::UnsupportedColorType(color_type)),
+    // Builds a frame from interleaved samples, checking that there are exactly width * height *
+    // channels of them.
+    pub fn from_raw(
+        data: Vec<u16>,
+        width: usize,
+        height: usize,
+        channels: usize,
+    ) -> Result<Self, FrameError> {
+        let expected = raw_sample_count(width, height, channels)?;
+        if data.len() != expected {
+            return Err(FrameError::LengthMismatch {
+                expected,
+                actual: data.len(),
+            });
+        }
+        Ok(Self {
+            data,
+            width,
+            height,
+            bit_depth: 16,
         })
     }
 
-    pub fn planes(&self) -> Vec<Plane<&[u16]>> {
-        vec![
-            Plane {
-                data: &self.data,
-                width: self.width,
-                height: self.height,
-                row_stride: 3 * self.width,
-                sample_stride: 3,
-            },
-            Plane {
-                data: &self.data[1..],
-                width: self.width,
-                height: self.height,
-                row_stride: 3 * self.width,
-                sample_stride: 3,
-            },
-            Plane {
-                data: &self.data[2..],
-                width: self.width,
-                height: self.height,
-                row_stride: 3 * self.width,
-                sample_stride: 3,
-            },
-        ]
+    // Like from_raw, but for 8-bit samples, which are widened the same way as 8-bit TIFFs.
+    pub fn from_raw_interleaved_u8(
+        data: &[u8],
+        width: usize,
+        height: usize,
+        channels: usize,
+    ) -> Result<Self, FrameError> {
+        let expected = raw_sample_count(width, height, channels)?;
+        if data.len() != expected {
+            return Err(FrameError::LengthMismatch {
+                expected,
+                actual: data.len(),
+            });
+        }
+        Ok(Self {
+            data: data.iter().map(|&x| x as u16 * 257).collect(),
+            width,
+            height,
+            bit_depth: 8,
+        })
     }
 
-    pub fn encode<C: Codec, W: Write>(&self, mut dest: W) -> io::Result<()> {
-        for plane in self.planes() {
-            C::encode(&plane, &mut dest)?;
+    // Builds a frame from separate planes of width * height samples each, one per channel.
+    pub fn from_planar<T: AsRef<[u16]>>(
+        planes: &[T],
+        width: usize,
+        height: usize,
+    ) -> Result<Self, FrameError> {
+        let channels = planes.len();
+        let len = raw_sample_count(width, height, channels)?;
+        let mut data = vec![0; len];
+        for (i, plane) in planes.iter().enumerate() {
+            let plane = plane.as_ref();
+            if plane.len() != width * height {
+                return Err(FrameError::PlaneLengthMismatch {
+                    plane: i,
+                    expected: width * height,
+                    actual: plane.len(),
+                });
+            }
+            for (dest, &sample) in data[i..].iter_mut().step_by(channels).zip(plane) {
+                *dest = sample;
+            }
         }
-        Ok(())
+        Ok(Self {
+            data,
+            width,
+            height,
+            bit_depth: 16,
+        })
     }
 
-    pub fn decode<C: Codec, R: Read>(
-        mut source: R,
+    // Builds a frame from fully planar samples: all of channel 0, then all of channel 1, and so on,
+    // as returned by to_planar.
+    pub fn from_planar_buffer(
+        data: &[u16],
         width: usize,
         height: usize,
-    ) -> io::Result<Self> {
+        channels: usize,
+    ) -> Result<Self, FrameError> {
+        let expected = raw_sample_count(width, height, channels)?;
+        if data.len() != expected {
+            return Err(FrameError::LengthMismatch {
+                expected,
+                actual: data.len(),
+            });
+        }
         let mut ret = Self {
-            data: vec![0; width * height * 3],
+            data: vec![0; expected],
             width,
             height,
+            bit_depth: 16,
         };
-        for plane in 0..3 {
-            C::decode(
-                &mut source,
-                &mut Plane {
-                    data: &mut ret.data[plane..],
-                    width: width,
-                    height: height,
-                    row_stride: 3 * width,
-                    sample_stride: 3,
-                },
-            )?;
-        }
+        interleave(data, &mut ret.data, channels);
         Ok(ret)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // Builds a frame from headerless interleaved 16-bit samples, like the .raw files cameras
+    // produce. The length must be exactly width * height * channels * 2 bytes.
+    pub fn from_raw_bytes(
+        bytes: &[u8],
+        width: usize,
+        height: usize,
+        channels: usize,
+        endianness: Endianness,
+    ) -> Result<Self, FrameError> {
+        let expected = raw_sample_count(width, height, channels)?;
+        Ok(Self {
+            data: samples_from_bytes(bytes, expected, endianness)?,
+            width,
+            height,
+            bit_depth: 16,
+        })
+    }
 
-    #[test]
-    fn test_rgb48_frame_open() {
-        RGB48Frame::open("src/testdata/tears_of_steel_12130.tif").unwrap();
+    // Like from_raw_bytes, but reads the samples from a file. Its size is checked before anything
+    // is read.
+    pub fn from_raw_file<P: AsRef<Path>>(
+        path: P,
+        width: usize,
+        height: usize,
+        channels: usize,
+        endianness: Endianness,
+    ) -> Result<Self, FrameOpenError> {
+        let expected = raw_sample_count(width, height, channels)?;
+        Ok(Self {
+            data: read_raw_file(path, expected, endianness)?,
+            width,
+            height,
+            bit_depth: 16,
+        })
+    }
+
+    // Returns the frame's interleaved samples as bytes, the reverse of from_raw_bytes.
+    pub fn to_raw_bytes(&self, endianness: Endianness) -> Vec<u8> {
+        samples_to_bytes(&self.data, endianness)
+    }
+
+    // Saves the frame's interleaved samples without any header, the reverse of from_raw_file.
+    pub fn write_raw_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        endianness: Endianness,
+    ) -> Result<(), FrameSaveError> {
+        std::fs::write(path, self.to_raw_bytes(endianness))?;
+        Ok(())
+    }
+
+    // Loads a TIFF, PNG, DPX, or PGM file, telling which it is from its first few bytes rather
+    // than its extension. PGMs give single-channel frames.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, FrameOpenError> {
+        match open_image(path)? {
+            #[cfg(feature = "tiff")]
+            (f, ImageFormat::Tiff) => Self::read_tiff(f),
+            #[cfg(feature = "png")]
+            (f, ImageFormat::Png) => Self::read_png(io::BufReader::new(f)),
+            (f, ImageFormat::Dpx) => dpx::read(io::BufReader::new(f)),
+            (f, ImageFormat::Pgm) => pgm::read(io::BufReader::new(f)),
+        }
+    }
+
+    // Like open, but loads an image that's already in memory.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FrameOpenError> {
+        match sniff_image(bytes)? {
+            #[cfg(feature = "tiff")]
+            ImageFormat::Tiff => Self::read_tiff(io::Cursor::new(bytes)),
+            #[cfg(feature = "png")]
+            ImageFormat::Png => Self::read_png(bytes),
+            ImageFormat::Dpx => dpx::read(io::Cursor::new(bytes)),
+            ImageFormat::Pgm => pgm::read(bytes),
+        }
+    }
+
+    // Loads a 10-bit RGB DPX file. The samples are widened to 16 bits, and bit_depth is 10.
+    pub fn from_dpx<P: AsRef<Path>>(path: P) -> Result<Self, FrameOpenError> {
+        dpx::read(io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    // Loads the R, G, B, and if present A channels of an EXR file's first part, converting them to
+    // 16 bits with the given transfer function. Alpha is always converted linearly.
+    #[cfg(feature = "exr")]
+    pub fn from_exr<P: AsRef<Path>>(
+        path: P,
+        transfer: exr::Transfer,
+    ) -> Result<Self, FrameOpenError> {
+        Self::read_exr(io::BufReader::new(std::fs::File::open(path)?), transfer)
+    }
+
+    #[cfg(feature = "exr")]
+    fn read_exr<R: Read + Seek>(
+        source: R,
+        transfer: exr::Transfer,
+    ) -> Result<Self, FrameOpenError> {
+        let image = exr::decode(source)?;
+        let mut channels = Vec::with_capacity(4);
+        for name in ["R", "G", "B"].iter() {
+            match image.channel(name) {
+                Some(channel) => channels.push((channel, transfer)),
+                None => {
+                    return Err(exr::ExrError::Unsupported(format!("no {} channel", name)).into())
+                }
+            }
+        }
+        if let Some(alpha) = image.channel("A") {
+            channels.push((alpha, exr::Transfer::Linear));
+        }
+        let n = channels.len();
+        let mut data = vec![0; image.width * image.height * n];
+        for (i, (channel, transfer)) in channels.into_iter().enumerate() {
+            for (dest, &x) in data[i..].iter_mut().step_by(n).zip(&channel.samples) {
+                *dest = transfer.to_u16(x);
+            }
+        }
+        Ok(Self {
+            data,
+            width: image.width,
+            height: image.height,
+            bit_depth: 16,
+        })
+    }
+
+    #[cfg(feature = "tiff")]
+    pub fn from_tiff<P: AsRef<Path>>(path: P) -> Result<Self, FrameOpenError> {
+        Self::from_tiff_reader(std::fs::File::open(path)?)
+    }
+
+    // Like from_tiff, but reads from source, which is read from its current position.
+    #[cfg(feature = "tiff")]
+    pub fn from_tiff_reader<R: Read + Seek>(source: R) -> Result<Self, FrameOpenError> {
+        Self::read_tiff(source)
+    }
+
+    #[cfg(feature = "tiff")]
+    fn read_tiff<R: Read + Seek>(mut source: R) -> Result<Self, FrameOpenError> {
+        if let Some(frame) = planar_tiff::read(&mut source)? {
+            return Ok(frame);
+        }
+        source.seek(SeekFrom::Start(0))?;
+        let mut dec =
+            tiff::decoder::Decoder::new(source)?.with_limits(tiff::decoder::Limits::unlimited());
+        Self::read_tiff_image(&mut dec)
+    }
+
+    // Loads every page of a multi-page TIFF, one frame per page. Pages can have different
+    // dimensions and color types, and a page that can't be loaded is yielded as an error without
+    // ending the iteration. Unlike from_tiff, this doesn't handle planar files.
+    #[cfg(feature = "tiff")]
+    pub fn from_tiff_pages<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<TiffFrameIter<std::fs::File>, FrameOpenError> {
+        Self::read_tiff_pages(std::fs::File::open(path)?)
+    }
+
+    #[cfg(feature = "tiff")]
+    fn read_tiff_pages<R: Read + Seek>(source: R) -> Result<TiffFrameIter<R>, FrameOpenError> {
+        Ok(TiffFrameIter {
+            decoder: tiff::decoder::Decoder::new(source)?
+                .with_limits(tiff::decoder::Limits::unlimited()),
+            started: false,
+        })
+    }
+
+    // Loads the decoder's current image.
+    #[cfg(feature = "tiff")]
+    pub(crate) fn read_tiff_image<R: Read + Seek>(
+        dec: &mut tiff::decoder::Decoder<R>,
+    ) -> Result<Self, FrameOpenError> {
+        let (width, height) = dec.dimensions()?;
+
+        let (data, bit_depth) = match dec.colortype()? {
+            // RGBA frames carry alpha as a fourth plane, and grayscale frames have just the one.
+            tiff::ColorType::RGB(16) | tiff::ColorType::RGBA(16) | tiff::ColorType::Gray(16) => {
+                match dec.read_image()? {
+                    tiff::decoder::DecodingResult::U16(data) => (data, 16),
+                    _ => return Err(FrameOpenError::UnsupportedSampleType),
+                }
+            }
+            // 8-bit samples are multiplied by 257, mapping 0-255 onto the full 0-65535 range.
+            tiff::ColorType::RGB(8) => match dec.read_image()? {
+                tiff::decoder::DecodingResult::U8(data) => {
+                    (data.into_iter().map(|x| x as u16 * 257).collect(), 8)
+                }
+                tiff::decoder::DecodingResult::U16(data) => {
+                    (data.into_iter().map(|x| x.min(255) * 257).collect(), 8)
+                }
+                _ => return Err(FrameOpenError::UnsupportedSampleType),
+            },
+            color_type => return Err(FrameOpenError::UnsupportedColorType(color_type)),
+        };
+        Ok(RGB48Frame {
+            data,
+            width: width as _,
+            height: height as _,
+            bit_depth,
+        })
+    }
+
+    #[cfg(feature = "png")]
+    pub fn from_png<P: AsRef<Path>>(path: P) -> Result<Self, FrameOpenError> {
+        Self::from_png_reader(io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    // Like from_png, but reads from source. It's read in small pieces, so it should be buffered.
+    #[cfg(feature = "png")]
+    pub fn from_png_reader<R: Read>(source: R) -> Result<Self, FrameOpenError> {
+        Self::read_png(source)
+    }
+
+    // 8-bit PNGs are scaled by 257 so that they fill the 16-bit range (255 becomes 65535).
+    #[cfg(feature = "png")]
+    fn read_png<R: Read>(source: R) -> Result<Self, FrameOpenError> {
+        let image = png::decode(source)?;
+        if image.color_type != png::ColorType::RGB {
+            return Err(FrameOpenError::UnsupportedPngColorType(image.color_type));
+        }
+        let data: Vec<u16> = match image.bit_depth {
+            16 => image
+                .data
+                .chunks_exact(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .collect(),
+            8 => image.data.iter().map(|&x| x as u16 * 257).collect(),
+            bit_depth => {
+                return Err(
+                    png::PngError::Unsupported(format!("{}-bit RGB images", bit_depth)).into(),
+                )
+            }
+        };
+        if data.len() != image.width * image.height * 3 {
+            return Err(png::PngError::InvalidData(format!(
+                "expected {} samples but got {}",
+                image.width * image.height * 3,
+                data.len()
+            ))
+            .into());
+        }
+        Ok(RGB48Frame {
+            data,
+            width: image.width,
+            height: image.height,
+            bit_depth: image.bit_depth,
+        })
+    }
+
+    // Saves the frame as an uncompressed 16-bit RGB TIFF.
+    #[cfg(feature = "tiff")]
+    pub fn to_tiff<P: AsRef<Path>>(&self, path: P) -> Result<(), FrameSaveError> {
+        let mut dest = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_tiff(&mut dest)?;
+        dest.flush()?;
+        Ok(())
+    }
+
+    #[cfg(feature = "tiff")]
+    pub fn write_tiff<W: Write + Seek>(&self, dest: W) -> Result<(), FrameSaveError> {
+        self.check_rgb()?;
+        let (width, height) = match (self.width.try_into(), self.height.try_into()) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the frame is too large for a tiff",
+                )
+                .into())
+            }
+        };
+        tiff::encoder::TiffEncoder::new(dest)?
+            .write_image::<tiff::encoder::colortype::RGB16>(width, height, &self.data)?;
+        Ok(())
+    }
+
+    // Saves the frame as a 16-bit RGB PNG.
+    #[cfg(feature = "png")]
+    pub fn to_png<P: AsRef<Path>>(&self, path: P) -> Result<(), FrameSaveError> {
+        let mut dest = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_png(&mut dest)?;
+        dest.flush()?;
+        Ok(())
+    }
+
+    // Like to_png, but writes to dest. Rows are converted to PNG's big-endian samples one at a time
+    // as they're compressed.
+    #[cfg(feature = "png")]
+    pub fn write_png<W: Write>(&self, dest: W) -> Result<(), FrameSaveError> {
+        self.check_rgb()?;
+        let row_len = self.width * 3;
+        png::encode(
+            dest,
+            self.width,
+            self.height,
+            png::ColorType::RGB,
+            16,
+            |row, dest| {
+                let samples = &self.data[row * row_len..(row + 1) * row_len];
+                for (bytes, sample) in dest.chunks_exact_mut(2).zip(samples) {
+                    bytes.copy_from_slice(&sample.to_be_bytes());
+                }
+            },
+        )?;
+        Ok(())
+    }
+
+    #[cfg(any(feature = "png", feature = "tiff"))]
+    fn check_rgb(&self) -> Result<(), FrameSaveError> {
+        if self.width * self.height * 3 != self.data.len() {
+            return Err(FrameSaveError::UnsupportedPlaneCount(self.channels()));
+        }
+        Ok(())
+    }
+
+    // The number of interleaved channels, which is 3 for RGB and 4 for RGBA. This is also the
+    // number of planes the frame is encoded as. It's only meaningful for frames that pass
+    // validate: for others, it's the number of whole planes that fit in data, which any trailing
+    // samples are left out of.
+    pub fn channels(&self) -> usize {
+        self.data.len() / (self.width * self.height).max(1)
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        self.channels() == 4
+    }
+
+    // The number of bits the samples actually use: the position of the highest bit set in any
+    // sample, ignoring up to outliers samples (such as hot pixels) that need more bits than the
+    // rest. This is at least 1, even if every sample is zero. Unlike bit_depth, this says nothing
+    // about how samples were widened, so a 10-bit DPX's samples fill all 16 bits.
+    pub fn effective_bit_depth(&self, outliers: usize) -> u8 {
+        let bits = if outliers == 0 {
+            let all = self.data.iter().fold(0, |all, &x| all | x);
+            16 - all.leading_zeros() as usize
+        } else {
+            // the number of samples needing each number of bits
+            let mut counts = [0usize; 17];
+            for &x in &self.data {
+                counts[16 - x.leading_zeros() as usize] += 1;
+            }
+            let mut wider = 0;
+            let mut bits = 16;
+            while bits > 0 && wider + counts[bits] <= outliers {
+                wider += counts[bits];
+                bits -= 1;
+            }
+            bits
+        };
+        bits.max(1) as u8
+    }
+
+    // Sets bit_depth to effective_bit_depth(outliers) if that's smaller, so that it's what gets
+    // signaled in the header when the frame is encoded. The samples aren't changed, and outliers
+    // still have all of their bits when decoded.
+    pub fn with_effective_bit_depth(self, outliers: usize) -> Self {
+        let bit_depth = self.effective_bit_depth(outliers).min(self.bit_depth);
+        Self { bit_depth, ..self }
+    }
+
+    // Checks that the frame is nonempty and that its data is 1 to 4 whole planes, returning the
+    // number of planes. The fields are public, so nothing else guarantees it.
+    pub fn validate(&self) -> Result<usize, FrameError> {
+        let (width, height) = (self.width, self.height);
+        if width == 0 || height == 0 {
+            return Err(FrameError::EmptyFrame { width, height });
+        }
+        let len = self.data.len();
+        let n_planes = match width.checked_mul(height) {
+            Some(pixels) if len.is_multiple_of(pixels) => len / pixels,
+            _ => {
+                return Err(FrameError::PartialPlane { len, width, height });
+            }
+        };
+        if !(1..=4).contains(&n_planes) {
+            return Err(FrameError::InvalidChannelCount(n_planes));
+        }
+        Ok(n_planes)
+    }
+
+    // A hash of the frame's dimensions and samples, for checking that a frame decodes to what was
+    // encoded or finding duplicate frames. It's the 64-bit FNV-1a hash of:
+    //
+    //   width          8 bytes  little-endian
+    //   height         8 bytes  little-endian
+    //   channels       8 bytes  little-endian
+    //   samples        2 bytes each, little-endian, interleaved in row-major order
+    //
+    // so other tools can compute it from the same samples. bit_depth isn't included.
+    pub fn content_hash(&self) -> u64 {
+        fnv1a_64(self.content_bytes())
+    }
+
+    // Like content_hash, but the 128-bit FNV-1a hash of the same bytes, for when collisions matter
+    // more. Neither is a cryptographic hash.
+    pub fn content_hash_128(&self) -> u128 {
+        fnv1a_128(self.content_bytes())
+    }
+
+    fn content_bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        IntoIterator::into_iter([self.width, self.height, self.channels()])
+            .flat_map(|x| (x as u64).to_le_bytes())
+            .chain(self.data.iter().flat_map(|x| x.to_le_bytes()))
+    }
+
+    // Returns the samples fully planar: all of channel 0, then all of channel 1, and so on.
+    pub fn to_planar(&self) -> Vec<u16> {
+        let mut ret = vec![0; self.data.len()];
+        deinterleave(&self.data, &mut ret, self.n_planes());
+        ret
+    }
+
+    // Like to_planar, but writes into dest, which must be the same length as the frame's data.
+    pub fn to_planar_into(&self, dest: &mut [u16]) -> Result<(), FrameError> {
+        let channels = self.validate()?;
+        if dest.len() != self.data.len() {
+            return Err(FrameError::LengthMismatch {
+                expected: self.data.len(),
+                actual: dest.len(),
+            });
+        }
+        deinterleave(&self.data, dest, channels);
+        Ok(())
+    }
+
+    // The inverse of to_planar_into: replaces the frame's samples with fully planar ones from src,
+    // which must be the same length as the frame's data.
+    pub fn copy_from_planar(&mut self, src: &[u16]) -> Result<(), FrameError> {
+        let channels = self.validate()?;
+        if src.len() != self.data.len() {
+            return Err(FrameError::LengthMismatch {
+                expected: self.data.len(),
+                actual: src.len(),
+            });
+        }
+        interleave(src, &mut self.data, channels);
+        Ok(())
+    }
+
+    // The same as channels(), but panics if the frame isn't valid (see validate).
+    pub fn n_planes(&self) -> usize {
+        match self.validate() {
+            Ok(n_planes) => n_planes,
+            Err(e) => panic!("invalid frame: {}", e),
+        }
+    }
+
+    // A view of the ith plane. Panics if there's no such plane.
+    pub fn plane(&self, i: usize) -> Plane<&[u16]> {
+        let n_planes = self.n_planes();
+        assert!(i < n_planes, "plane {} of a {}-plane frame", i, n_planes);
+        Plane {
+            data: &self.data[i..],
+            width: self.width,
+            height: self.height,
+            row_stride: n_planes * self.width,
+            sample_stride: n_planes,
+        }
+    }
+
+    // Like plane, but mutable. The planes are interleaved in the same data, so only one of them can
+    // be borrowed mutably at a time.
+    pub fn plane_mut(&mut self, i: usize) -> Plane<&mut [u16]> {
+        let n_planes = self.n_planes();
+        assert!(i < n_planes, "plane {} of a {}-plane frame", i, n_planes);
+        Plane {
+            data: &mut self.data[i..],
+            width: self.width,
+            height: self.height,
+            row_stride: n_planes * self.width,
+            sample_stride: n_planes,
+        }
+    }
+
+    pub fn planes(&self) -> impl ExactSizeIterator<Item = Plane<&[u16]>> {
+        (0..self.n_planes()).map(move |i| self.plane(i))
+    }
+
+    pub fn encode<C: Codec, W: Write>(&self, mut dest: W) -> Result<(), CodecError> {
+        self.validate()
+            .map_err(|e| CodecError::InvalidInput(e.to_string()))?;
+        for plane in self.planes() {
+            C::encode(&plane, &mut dest)?;
+        }
+        Ok(())
+    }
+
+    // Decodes a frame written by encode, which has 3 planes of the given dimensions. Frames larger
+    // than the default Limits are rejected.
+    pub fn decode<C: Codec, R: Read>(
+        source: R,
+        width: usize,
+        height: usize,
+    ) -> Result<Self, CodecError> {
+        Self::decode_with_options::<C, _>(source, width, height, &Default::default())
+    }
+
+    pub fn decode_with_options<C: Codec, R: Read>(
+        mut source: R,
+        width: usize,
+        height: usize,
+        options: &DecodeOptions,
+    ) -> Result<Self, CodecError> {
+        let len = sample_count(width, height, 3).ok_or_else(|| {
+            CodecError::InvalidInput(format!("a {}x{} frame is too large", width, height))
+        })?;
+        options.limits.check(len)?;
+        let mut ret = Self {
+            data: vec![0; len],
+            width,
+            height,
+            bit_depth: 16,
+        };
+        if len == 0 {
+            return Ok(ret);
+        }
+        for plane in 0..3 {
+            C::decode(&mut source, &mut ret.plane_mut(plane))
+                .map_err(|e| e.in_plane(plane, 0, 0))?;
+        }
+        Ok(ret)
+    }
+
+    // Like decode, but decodes into an existing frame instead of allocating a new one, which saves
+    // reallocating for every frame of a sequence. The frame's dimensions say what to expect, and
+    // it must have 3 channels. Nothing is resized: a frame that doesn't match is an error.
+    pub fn decode_into<C: Codec, R: Read>(
+        mut source: R,
+        frame: &mut RGB48Frame,
+    ) -> Result<(), CodecError> {
+        if Some(frame.data.len()) != sample_count(frame.width, frame.height, 3) {
+            return Err(CodecError::InvalidInput(format!(
+                "a {}x{} frame with {} samples can't hold 3 planes",
+                frame.width,
+                frame.height,
+                frame.data.len()
+            )));
+        }
+        frame.bit_depth = 16;
+        if frame.data.is_empty() {
+            return Ok(());
+        }
+        for plane in 0..3 {
+            C::decode(&mut source, &mut frame.plane_mut(plane))
+                .map_err(|e| e.in_plane(plane, 0, 0))?;
+        }
+        Ok(())
+    }
+
+    // Encodes the frame preceded by a header (see header::FrameHeader), so that it can be decoded
+    // without knowing anything about it beforehand.
+    pub fn encode_with_header<C: Codec, W: Write>(
+        &self,
+        dest: W,
+        options: &EncodeOptions,
+    ) -> Result<(), CodecError> {
+        self.encode_with_distortion::<C, _>(dest, options)?;
+        Ok(())
+    }
+
+    // Like encode_with_header, but also returns the mean squared error of each plane as decoded,
+    // which is zero unless quantization is used. The error is measured on the planes as they're
+    // coded, so with a color transform it's in terms of the transformed planes.
+    pub fn encode_with_distortion<C: Codec, W: Write>(
+        &self,
+        dest: W,
+        options: &EncodeOptions,
+    ) -> Result<Vec<f64>, CodecError> {
+        self.encode_planes::<C, _>(dest, options, &mut |_| false)
+    }
+
+    // Like encode_with_header, but calls progress after each plane is written. If it returns true,
+    // encoding stops there, the destination is flushed, and CodecError::Cancelled is returned.
+    pub fn encode_with_progress<C: Codec, W: Write, F: FnMut(&Progress) -> bool>(
+        &self,
+        dest: W,
+        options: &EncodeOptions,
+        mut progress: F,
+    ) -> Result<(), CodecError> {
+        self.encode_planes::<C, _>(dest, options, &mut progress)?;
+        Ok(())
+    }
+
+    // Like encode_with_header, but dest is written to by a background thread while encoding
+    // continues, which helps when dest is slow (see pipeline::pipelined). The output is the same.
+    pub fn encode_pipelined<C: Codec, W: Write + Send>(
+        &self,
+        dest: W,
+        options: &EncodeOptions,
+    ) -> Result<(), CodecError> {
+        pipeline::pipelined(dest, pipeline::BUFFER_SIZE, |dest| {
+            self.encode_with_header::<C, _>(dest, options)
+        })
+    }
+
+    // Encodes the frame like encode_with_header with the default options, then decodes the result
+    // and checks that it matches the frame exactly before writing anything to dest. If it doesn't,
+    // nothing is written and CodecError::VerificationFailed gives the first sample that differs.
+    pub fn encode_verified<C: Codec, W: Write>(
+        &self,
+        mut dest: W,
+    ) -> Result<VerifyReport, CodecError> {
+        let mut encoded = Vec::new();
+        self.encode_with_header::<C, _>(&mut encoded, &Default::default())?;
+        let decoded = Self::decode_with_header::<C, _>(&*encoded)?;
+        if (decoded.width, decoded.height) != (self.width, self.height)
+            || decoded.data.len() != self.data.len()
+        {
+            return Err(CodecError::InvalidData(format!(
+                "decoded a {}x{} frame with {} samples, expected {}x{} with {}",
+                decoded.width,
+                decoded.height,
+                decoded.data.len(),
+                self.width,
+                self.height,
+                self.data.len()
+            )));
+        }
+        if let Some(i) = (0..self.data.len()).find(|&i| decoded.data[i] != self.data[i]) {
+            let n_planes = self.channels();
+            let pixel = i / n_planes;
+            return Err(CodecError::VerificationFailed {
+                plane: i % n_planes,
+                row: pixel / self.width,
+                col: pixel % self.width,
+            });
+        }
+
+        dest.write_all(&encoded)?;
+        dest.flush()?;
+        Ok(VerifyReport {
+            size: encoded.len(),
+            hash: fnv1a(&encoded),
+            content_hash: self.content_hash(),
+        })
+    }
+
+    fn encode_planes<C: Codec, W: Write>(
+        &self,
+        dest: W,
+        options: &EncodeOptions,
+        progress: &mut dyn FnMut(&Progress) -> bool,
+    ) -> Result<Vec<f64>, CodecError> {
+        self.validate()
+            .map_err(|e| CodecError::InvalidInput(e.to_string()))?;
+        encode_samples::<C, _>(
+            &self.data,
+            (self.width, self.height),
+            self.bit_depth,
+            dest,
+            options,
+            progress,
+        )
+    }
+
+    pub(crate) fn read_header<R: Read>(source: R) -> Result<FrameHeader, CodecError> {
+        let header = FrameHeader::read(source)?;
+        if header.sample_width != 16 {
+            return Err(CodecError::InvalidHeader(format!(
+                "{}-bit samples don't fit in an RGB48Frame",
+                header.sample_width
+            )));
+        }
+        if header.width == 0 || header.height == 0 {
+            return Err(CodecError::InvalidHeader("the frame is empty".to_string()));
+        }
+        if sample_count(header.width, header.height, header.n_planes).is_none() {
+            return Err(CodecError::InvalidHeader(format!(
+                "a {}x{} frame is too large",
+                header.width, header.height
+            )));
+        }
+        if header.color_transform != ColorTransform::None && header.n_planes < 3 {
+            return Err(CodecError::InvalidHeader(
+                "color transforms require at least 3 planes".to_string(),
+            ));
+        }
+        if header.preview_factor.is_some()
+            && (header.tile_size.is_some() || header.quantization.is_some())
+        {
+            return Err(CodecError::InvalidHeader(
+                "hierarchical frames can't be tiled or quantized".to_string(),
+            ));
+        }
+        if header.band_height.is_some()
+            && (header.tile_size.is_some() || header.preview_factor.is_some())
+        {
+            return Err(CodecError::InvalidHeader(
+                "banded frames can't be tiled or hierarchical".to_string(),
+            ));
+        }
+        if header.fixed_k.is_some()
+            && (header.quantization.is_some() || header.preview_factor.is_some())
+        {
+            return Err(CodecError::InvalidHeader(
+                "frames with a fixed k can't be quantized or hierarchical".to_string(),
+            ));
+        }
+        Ok(header)
+    }
+
+    // Decodes a frame written by encode_with_header. Frames larger than the default Limits are
+    // rejected before anything is allocated for them.
+    pub fn decode_with_header<C: Codec, R: Read>(source: R) -> Result<Self, CodecError> {
+        Self::decode_with_header_and_options::<C, _>(source, &Default::default())
+    }
+
+    pub fn decode_with_header_and_options<C: Codec, R: Read>(
+        mut source: R,
+        options: &DecodeOptions,
+    ) -> Result<Self, CodecError> {
+        let header = Self::read_header(&mut source)?;
+        Self::decode_after_header::<C, _>(&header, source, &options.limits, &mut |_| false)
+    }
+
+    // Like decode_with_header, but calls progress after each plane is read. If it returns true,
+    // decoding stops there and CodecError::Cancelled is returned.
+    pub fn decode_with_progress<C: Codec, R: Read, F: FnMut(&Progress) -> bool>(
+        source: R,
+        mut progress: F,
+    ) -> Result<Self, CodecError> {
+        let mut source = source;
+        let header = Self::read_header(&mut source)?;
+        Self::decode_after_header::<C, _>(&header, source, &Limits::default(), &mut progress)
+    }
+
+    pub(crate) fn decode_after_header<C: Codec, R: Read>(
+        header: &FrameHeader,
+        source: R,
+        limits: &Limits,
+        progress: &mut dyn FnMut(&Progress) -> bool,
+    ) -> Result<Self, CodecError> {
+        limits.check(header.width * header.height * header.n_planes)?;
+        let mut ret = Self {
+            data: vec![0; header.width * header.height * header.n_planes],
+            width: header.width,
+            height: header.height,
+            bit_depth: header.bit_depth,
+        };
+        Self::decode_after_header_into::<C, _>(header, source, progress, &mut ret)?;
+        Ok(ret)
+    }
+
+    // Like decode_with_header, but decodes into an existing frame instead of allocating a new one.
+    // The frame must have the stream's dimensions and number of planes: it's never resized, so a
+    // mismatch is an error, and the frame is left untouched.
+    pub fn decode_with_header_into<C: Codec, R: Read>(
+        mut source: R,
+        frame: &mut RGB48Frame,
+    ) -> Result<(), CodecError> {
+        let header = Self::read_header(&mut source)?;
+        let expected = (header.width, header.height, header.n_planes);
+        let actual = (frame.width, frame.height, frame.channels());
+        if expected != actual || frame.data.len() != frame.width * frame.height * frame.channels() {
+            return Err(CodecError::InvalidInput(format!(
+                "can't decode a {}x{} frame with {} planes into a {}x{} frame with {} samples",
+                header.width,
+                header.height,
+                header.n_planes,
+                frame.width,
+                frame.height,
+                frame.data.len()
+            )));
+        }
+        Self::decode_after_header_into::<C, _>(&header, source, &mut |_| false, frame)
+    }
+
+    fn decode_after_header_into<C: Codec, R: Read>(
+        header: &FrameHeader,
+        source: R,
+        progress: &mut dyn FnMut(&Progress) -> bool,
+        ret: &mut RGB48Frame,
+    ) -> Result<(), CodecError> {
+        let mut source = Counting::new(source);
+        ret.bit_depth = header.bit_depth;
+        if let Some(band_height) = header.band_height {
+            let layout = band::layout(header.width, header.height, band_height);
+            let mut payload = Vec::new();
+            for band_index in 0..layout.len() {
+                let (_, y, _, band_rows) = layout.tile_rect(band_index);
+                band::decode_bands::<C, _>(
+                    &mut source,
+                    header,
+                    (y, band_rows),
+                    ret,
+                    y,
+                    &mut payload,
+                )?;
+
+                if progress(&Progress {
+                    frame: 0,
+                    plane: header.n_planes - 1,
+                    rows_done: (y + band_rows) * header.n_planes,
+                    rows_total: header.n_planes * header.height,
+                    bytes: header.encoded_len() as u64 + source.count,
+                }) {
+                    return Err(CodecError::Cancelled);
+                }
+            }
+            header
+                .color_transform
+                .inverse(&mut ret.data, header.n_planes);
+            return Ok(());
+        }
+        // the base is smaller than ret, which has already been allocated
+        let base = match header.preview_factor {
+            Some(factor) => Some((
+                Self::decode_base::<C, _>(header, &mut source, &Limits::unlimited())?,
+                factor,
+            )),
+            None => None,
+        };
+        for i in 0..header.n_planes {
+            let coding = header.plane_coding(i);
+            let mut plane = ret.plane_mut(i);
+            match (&base, header.tile_size) {
+                (Some((base, factor)), _) => {
+                    C::decode(&mut source, &mut plane).map_err(|e| e.in_plane(i, 0, 0))?;
+                    pyramid::restore(&mut plane, &base.plane(i), *factor);
+                }
+                (None, None) => coding
+                    .decode::<C, _, _>(&mut source, &mut plane)
+                    .map_err(|e| e.in_plane(i, 0, 0))?,
+                (None, Some((tile_width, tile_height))) => tile::decode_plane::<C, _, _>(
+                    &mut source,
+                    &mut plane,
+                    &TileLayout::new(header.width, header.height, tile_width, tile_height),
+                    coding,
+                )
+                .map_err(|e| e.in_plane(i, 0, 0))?,
+            }
+
+            if progress(&Progress {
+                frame: 0,
+                plane: i,
+                rows_done: (i + 1) * header.height,
+                rows_total: header.n_planes * header.height,
+                bytes: header.encoded_len() as u64 + source.count,
+            }) {
+                return Err(CodecError::Cancelled);
+            }
+        }
+        header
+            .color_transform
+            .inverse(&mut ret.data, header.n_planes);
+        Ok(())
+    }
+
+    // Decodes the base layers of a hierarchically coded frame (see pyramid), without undoing its
+    // color transform. Bases larger than limits allows are rejected before anything is allocated
+    // for them.
+    pub(crate) fn decode_base<C: Codec, R: Read>(
+        header: &FrameHeader,
+        mut source: R,
+        limits: &Limits,
+    ) -> Result<Self, CodecError> {
+        let factor = header.preview_factor.ok_or_else(|| {
+            CodecError::InvalidInput("the frame wasn't coded with a preview".to_string())
+        })?;
+        let (width, height) = pyramid::base_size(header.width, header.height, factor);
+        limits.check(width * height * header.n_planes)?;
+        let mut ret = Self {
+            data: vec![0; width * height * header.n_planes],
+            width,
+            height,
+            bit_depth: header.bit_depth,
+        };
+        for i in 0..header.n_planes {
+            C::decode(&mut source, &mut ret.plane_mut(i)).map_err(|e| e.in_plane(i, 0, 0))?;
+        }
+        Ok(ret)
+    }
+
+    // Decodes just the preview of a frame encoded by encode_with_header with a preview_factor set,
+    // which is the frame box-downsampled by that factor. Only the start of the stream is read.
+    // Previews larger than the default Limits are rejected before anything is allocated for them.
+    pub fn decode_preview<C: Codec, R: Read>(source: R) -> Result<Self, CodecError> {
+        Self::decode_preview_with_options::<C, _>(source, &Default::default())
+    }
+
+    pub fn decode_preview_with_options<C: Codec, R: Read>(
+        mut source: R,
+        options: &DecodeOptions,
+    ) -> Result<Self, CodecError> {
+        let header = Self::read_header(&mut source)?;
+        let mut ret = Self::decode_base::<C, _>(&header, source, &options.limits)?;
+        header
+            .color_transform
+            .inverse(&mut ret.data, header.n_planes);
+        Ok(ret)
+    }
+
+    // Decodes only the (x, y, w, h) region of a frame encoded by encode_with_header. For tiled
+    // frames, only the tiles that intersect the region are read; the rest are skipped by seeking.
+    // Untiled frames have to be decoded in full, and the region is copied out.
+    pub fn decode_region<C: Codec, R: Read + Seek>(
+        mut source: R,
+        rect: (usize, usize, usize, usize),
+    ) -> Result<Self, CodecError> {
+        let header = Self::read_header(&mut source)?;
+        let (x, y, w, h) = rect;
+        if w == 0
+            || h == 0
+            || x.checked_add(w).is_none_or(|right| right > header.width)
+            || y.checked_add(h).is_none_or(|bottom| bottom > header.height)
+        {
+            return Err(CodecError::InvalidInput(format!(
+                "region {:?} is empty or outside of the {}x{} frame",
+                rect, header.width, header.height
+            )));
+        }
+
+        let layout = match header.tile_size {
+            Some((tile_width, tile_height)) => {
+                TileLayout::new(header.width, header.height, tile_width, tile_height)
+            }
+            None => {
+                return Ok(Self::decode_after_header::<C, _>(
+                    &header,
+                    source,
+                    &Limits::default(),
+                    &mut |_| false,
+                )?
+                .crop_rect(rect))
+            }
+        };
+
+        let mut ret = Self {
+            data: vec![0; w * h * header.n_planes],
+            width: w,
+            height: h,
+            bit_depth: header.bit_depth,
+        };
+        for plane in 0..header.n_planes {
+            tile::decode_plane_region::<C, _, _>(
+                &mut source,
+                &layout,
+                rect,
+                header.plane_coding(plane),
+                &mut ret.plane_mut(plane),
+            )
+            .map_err(|e| e.in_plane(plane, 0, 0))?;
+        }
+        header
+            .color_transform
+            .inverse(&mut ret.data, header.n_planes);
+        Ok(ret)
+    }
+
+    // Copies out the w x h region of the frame whose top-left sample is at (x, y), which must be
+    // nonempty and lie within the frame.
+    pub fn crop(&self, x: usize, y: usize, w: usize, h: usize) -> Result<Self, FrameError> {
+        self.validate()?;
+        if w == 0
+            || h == 0
+            || x.checked_add(w).is_none_or(|right| right > self.width)
+            || y.checked_add(h).is_none_or(|bottom| bottom > self.height)
+        {
+            return Err(FrameError::InvalidRegion {
+                rect: (x, y, w, h),
+                width: self.width,
+                height: self.height,
+            });
+        }
+        Ok(self.crop_rect((x, y, w, h)))
+    }
+
+    // Like crop, for regions that are known to be valid. Panics otherwise.
+    pub(crate) fn crop_rect(&self, rect: (usize, usize, usize, usize)) -> Self {
+        let (x, y, w, h) = rect;
+        let n_planes = self.channels();
+        let mut data = Vec::with_capacity(w * h * n_planes);
+        for row in y..y + h {
+            let start = (row * self.width + x) * n_planes;
+            data.extend_from_slice(&self.data[start..start + w * n_planes]);
+        }
+        Self {
+            data,
+            width: w,
+            height: h,
+            bit_depth: self.bit_depth,
+        }
+    }
+
+    // Adds right columns and bottom rows to the frame, filled according to mode.
+    pub fn pad(&self, right: usize, bottom: usize, mode: PadMode) -> Result<Self, FrameError> {
+        let n_planes = self.validate()?;
+        let (width, height) = (self.width, self.height);
+        let too_large = || FrameError::TooLarge {
+            width: width.saturating_add(right),
+            height: height.saturating_add(bottom),
+            channels: n_planes,
+        };
+        let new_width = width.checked_add(right).ok_or_else(too_large)?;
+        let new_height = height.checked_add(bottom).ok_or_else(too_large)?;
+        let len = sample_count(new_width, new_height, n_planes).ok_or_else(too_large)?;
+
+        // the row or column of the frame that row or column i of the result copies
+        let source_index = |i: usize, len: usize| match mode {
+            PadMode::Constant(_) => unreachable!(),
+            PadMode::ReplicateEdge => i.min(len - 1),
+            PadMode::Mirror => {
+                if len == 1 {
+                    return 0;
+                }
+                let i = i % (2 * (len - 1));
+                if i < len {
+                    i
+                } else {
+                    2 * (len - 1) - i
+                }
+            }
+        };
+        let mut data = Vec::with_capacity(len);
+        for row in 0..new_height {
+            if let PadMode::Constant(value) = mode {
+                if row < height {
+                    let start = row * width * n_planes;
+                    data.extend_from_slice(&self.data[start..start + width * n_planes]);
+                    data.resize(data.len() + right * n_planes, value);
+                } else {
+                    data.resize(data.len() + new_width * n_planes, value);
+                }
+                continue;
+            }
+            let start = source_index(row, height) * width * n_planes;
+            data.extend_from_slice(&self.data[start..start + width * n_planes]);
+            for col in width..new_width {
+                let i = start + source_index(col, width) * n_planes;
+                data.extend_from_slice(&self.data[i..i + n_planes]);
+            }
+        }
+        Ok(Self {
+            data,
+            width: new_width,
+            height: new_height,
+            bit_depth: self.bit_depth,
+        })
+    }
+
+    // Builds a width x height frame whose sample at (x, y) is this frame's sample at source(x, y),
+    // with all of its channels.
+    fn remap<F: Fn(usize, usize) -> (usize, usize)>(
+        &self,
+        width: usize,
+        height: usize,
+        source: F,
+    ) -> Self {
+        let n_planes = self.n_planes();
+        let mut data = Vec::with_capacity(self.data.len());
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = source(x, y);
+                let i = (sy * self.width + sx) * n_planes;
+                data.extend_from_slice(&self.data[i..i + n_planes]);
+            }
+        }
+        Self {
+            data,
+            width,
+            height,
+            bit_depth: self.bit_depth,
+        }
+    }
+
+    // Mirrors the frame left to right.
+    pub fn flip_horizontal(&self) -> Self {
+        let (width, height) = (self.width, self.height);
+        self.remap(width, height, |x, y| (width - 1 - x, y))
+    }
+
+    // Mirrors the frame top to bottom.
+    pub fn flip_vertical(&self) -> Self {
+        let (width, height) = (self.width, self.height);
+        self.remap(width, height, |x, y| (x, height - 1 - y))
+    }
+
+    // Rotates the frame a quarter turn clockwise, so a w x h frame becomes h x w and its bottom-left
+    // sample ends up at the top left.
+    pub fn rotate_90(&self) -> Self {
+        let height = self.height;
+        self.remap(self.height, self.width, |x, y| (y, height - 1 - x))
+    }
+
+    pub fn rotate_180(&self) -> Self {
+        let (width, height) = (self.width, self.height);
+        self.remap(width, height, |x, y| (width - 1 - x, height - 1 - y))
+    }
+
+    // Rotates the frame a quarter turn counterclockwise, so its top-right sample ends up at the top
+    // left.
+    pub fn rotate_270(&self) -> Self {
+        let width = self.width;
+        self.remap(self.height, self.width, |x, y| (width - 1 - y, x))
+    }
+
+    // Converts the frame's R, G, and B planes to Y', Cb, and Cr, with chroma offset by 0x8000. The
+    // result is an RGB48Frame in name only. Alpha is left alone. See Matrix::rgb_to_ycbcr for the
+    // rounding. Frames with fewer than 3 channels have no color to convert, and are an error.
+    pub fn to_ycbcr(&self, matrix: Matrix) -> Result<RGB48Frame, FrameError> {
+        let channels = self.color_channels()?;
+        let mut ret = self.clone();
+        matrix.forward(&mut ret.data, channels);
+        Ok(ret)
+    }
+
+    // The inverse of to_ycbcr. Round trips can leave samples off by one code value.
+    pub fn to_rgb(&self, matrix: Matrix) -> Result<RGB48Frame, FrameError> {
+        let channels = self.color_channels()?;
+        let mut ret = self.clone();
+        matrix.inverse(&mut ret.data, channels);
+        Ok(ret)
+    }
+
+    fn color_channels(&self) -> Result<usize, FrameError> {
+        match self.validate()? {
+            channels if channels >= 3 => Ok(channels),
+            channels => Err(FrameError::InvalidChannelCount(channels)),
+        }
+    }
+}
+
+// Plane i of width×height interleaved samples with n_planes channels.
+fn interleaved_plane(
+    data: &[u16],
+    i: usize,
+    n_planes: usize,
+    (width, height): (usize, usize),
+) -> Plane<&[u16]> {
+    Plane {
+        data: &data[i..],
+        width,
+        height,
+        row_stride: n_planes * width,
+        sample_stride: n_planes,
+    }
+}
+
+// Encodes interleaved samples with a header, like RGB48Frame::encode_with_header, returning the
+// mean squared error of each plane. This is shared by the frame types.
+pub(crate) fn encode_samples<C: Codec, W: Write>(
+    data: &[u16],
+    (width, height): (usize, usize),
+    bit_depth: u8,
+    dest: W,
+    options: &EncodeOptions,
+    progress: &mut dyn FnMut(&Progress) -> bool,
+) -> Result<Vec<f64>, CodecError> {
+    let mut dest = Counting::new(dest);
+    // check that the data holds a whole number of channels for every pixel
+    let n_planes = data.len() / (width * height).max(1);
+    if !(1..=4).contains(&n_planes) || data.len() != width * height * n_planes {
+        return Err(CodecError::InvalidInput(format!(
+            "{} samples isn't 1 to 4 channels of a {}x{} frame",
+            data.len(),
+            width,
+            height
+        )));
+    }
+    if options.color_transform != ColorTransform::None && n_planes < 3 {
+        return Err(CodecError::InvalidInput(
+            "color transforms require at least 3 planes".to_string(),
+        ));
+    }
+    if let Some((0, _)) | Some((_, 0)) = options.tile_size {
+        return Err(CodecError::InvalidInput(
+            "tiles must be nonempty".to_string(),
+        ));
+    }
+    if let Some(quantization) = &options.quantization {
+        if quantization.len() != n_planes || quantization.contains(&0) {
+            return Err(CodecError::InvalidInput(format!(
+                "expected a nonzero quantization step for each of {} planes, got {:?}",
+                n_planes, quantization
+            )));
+        }
+    }
+    let quantization = options
+        .quantization
+        .clone()
+        .filter(|quantization| quantization.iter().any(|&q| q != 1));
+    if options.preview_factor.is_some() && (options.tile_size.is_some() || quantization.is_some()) {
+        return Err(CodecError::InvalidInput(
+            "hierarchical coding can't be combined with tiles or quantization".to_string(),
+        ));
+    }
+    if options.band_height == Some(0) {
+        return Err(CodecError::InvalidInput(
+            "bands must be nonempty".to_string(),
+        ));
+    }
+    if options.band_height.is_some()
+        && (options.tile_size.is_some() || options.preview_factor.is_some())
+    {
+        return Err(CodecError::InvalidInput(
+            "bands can't be combined with tiles or hierarchical coding".to_string(),
+        ));
+    }
+    if options.k_mode != KMode::Adaptive
+        && (quantization.is_some() || options.preview_factor.is_some())
+    {
+        return Err(CodecError::InvalidInput(
+            "a fixed k can't be combined with quantization or hierarchical coding".to_string(),
+        ));
+    }
+
+    let transformed;
+    let coded = if options.color_transform == ColorTransform::None {
+        data
+    } else {
+        transformed = {
+            let mut data = data.to_vec();
+            options.color_transform.forward(&mut data, n_planes);
+            data
+        };
+        &transformed[..]
+    };
+
+    let header = FrameHeader {
+        width,
+        height,
+        n_planes,
+        bit_depth,
+        sample_width: 16,
+        color_transform: options.color_transform,
+        tile_size: options.tile_size,
+        quantization,
+        preview_factor: options.preview_factor,
+        band_height: options.band_height,
+        fixed_k: match options.k_mode {
+            KMode::Adaptive => None,
+            KMode::Fixed(k) => Some(vec![k; n_planes]),
+            KMode::PerPlaneAuto => Some(
+                (0..n_planes)
+                    .map(|i| {
+                        codec::best_fixed_k(&interleaved_plane(coded, i, n_planes, (width, height)))
+                    })
+                    .collect(),
+            ),
+        },
+    };
+    header.write(&mut dest)?;
+
+    // For hierarchical coding, every plane's base layer comes first. The base is downsampled before
+    // the color transform and then transformed itself, so that undoing the transform gives a
+    // preview that's exactly the downsampled frame.
+    let base = match options.preview_factor {
+        Some(factor) => {
+            let size = pyramid::base_size(width, height, factor);
+            let mut base = vec![0; size.0 * size.1 * n_planes];
+            for i in 0..n_planes {
+                pyramid::downsample_into(
+                    &interleaved_plane(data, i, n_planes, (width, height)),
+                    factor,
+                    &mut Plane {
+                        data: &mut base[i..],
+                        width: size.0,
+                        height: size.1,
+                        row_stride: n_planes * size.0,
+                        sample_stride: n_planes,
+                    },
+                );
+            }
+            options.color_transform.forward(&mut base, n_planes);
+            for i in 0..n_planes {
+                C::encode(&interleaved_plane(&base, i, n_planes, size), &mut dest)?;
+            }
+            Some((base, size, factor))
+        }
+        None => None,
+    };
+
+    if let Some(band_height) = options.band_height {
+        let layout = band::layout(width, height, band_height);
+        let mut squared_errors = vec![0; n_planes];
+        for band_index in 0..layout.len() {
+            let (_, y, _, band_rows) = layout.tile_rect(band_index);
+            for (i, squared_error) in squared_errors.iter_mut().enumerate() {
+                let plane = interleaved_plane(coded, i, n_planes, (width, height));
+                *squared_error += band::encode_band::<C, _, _>(
+                    &plane.view(0, y, width, band_rows),
+                    header.plane_coding(i),
+                    &mut dest,
+                )?;
+
+                if progress(&Progress {
+                    frame: 0,
+                    plane: i,
+                    rows_done: y * n_planes + (i + 1) * band_rows,
+                    rows_total: n_planes * height,
+                    bytes: dest.count,
+                }) {
+                    dest.flush()?;
+                    return Err(CodecError::Cancelled);
+                }
+            }
+        }
+        return Ok(squared_errors
+            .into_iter()
+            .map(|squared_error| squared_error as f64 / (width * height) as f64)
+            .collect());
+    }
+
+    let mut mse = Vec::with_capacity(n_planes);
+    for i in 0..n_planes {
+        let plane = interleaved_plane(coded, i, n_planes, (width, height));
+        let coding = header.plane_coding(i);
+        let squared_error = match (&base, options.tile_size) {
+            (Some((base, size, factor)), _) => {
+                let residuals = pyramid::residuals(
+                    &plane,
+                    &interleaved_plane(base, i, n_planes, *size),
+                    *factor,
+                );
+                C::encode(&residuals, &mut dest)?;
+                0
+            }
+            (None, None) => coding.encode::<C, _, _>(&plane, &mut dest)?,
+            (None, Some((tile_width, tile_height))) => tile::encode_plane::<C, _, _>(
+                &plane,
+                &TileLayout::new(width, height, tile_width, tile_height),
+                coding,
+                &mut dest,
+            )?,
+        };
+        mse.push(squared_error as f64 / (width * height) as f64);
+
+        if progress(&Progress {
+            frame: 0,
+            plane: i,
+            rows_done: (i + 1) * height,
+            rows_total: n_planes * height,
+            bytes: dest.count,
+        }) {
+            dest.flush()?;
+            return Err(CodecError::Cancelled);
+        }
+    }
+    Ok(mse)
+}
+
+// The image formats that frames can be loaded from.
+pub(crate) enum ImageFormat {
+    #[cfg(feature = "tiff")]
+    Tiff,
+    #[cfg(feature = "png")]
+    Png,
+    Dpx,
+    Pgm,
+}
+
+// Opens the file at path, telling its format from its first few bytes rather than its extension.
+// The file is left at its start.
+pub(crate) fn open_image<P: AsRef<Path>>(
+    path: P,
+) -> Result<(std::fs::File, ImageFormat), FrameOpenError> {
+    let mut f = std::fs::File::open(path)?;
+    let mut magic = [0; 8];
+    let mut len = 0;
+    while len < magic.len() {
+        match f.read(&mut magic[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    f.seek(SeekFrom::Start(0))?;
+    Ok((f, sniff_image(&magic[..len])?))
+}
+
+// Tells an image's format from its first few bytes, of which there may be more.
+pub(crate) fn sniff_image(bytes: &[u8]) -> Result<ImageFormat, FrameOpenError> {
+    match bytes {
+        #[cfg(feature = "tiff")]
+        [b'I', b'I', 42, 0, ..] | [b'M', b'M', 0, 42, ..] => Ok(ImageFormat::Tiff),
+        #[cfg(feature = "png")]
+        bytes if bytes.starts_with(&png::SIGNATURE) => Ok(ImageFormat::Png),
+        [a, b, c, d, ..] if [*a, *b, *c, *d] == dpx::MAGIC || [*d, *c, *b, *a] == dpx::MAGIC => {
+            Ok(ImageFormat::Dpx)
+        }
+        [a, b, c, ..] if [*a, *b] == pgm::MAGIC && c.is_ascii_whitespace() => Ok(ImageFormat::Pgm),
+        _ => Err(FrameOpenError::UnknownFormat),
+    }
+}
+
+// The pages of a TIFF, as returned by RGB48Frame::from_tiff_pages.
+#[cfg(feature = "tiff")]
+pub struct TiffFrameIter<R: Read + Seek> {
+    decoder: tiff::decoder::Decoder<R>,
+    // Whether the first page has been returned. The decoder starts out on it.
+    started: bool,
+}
+
+#[cfg(feature = "tiff")]
+impl<R: Read + Seek> Iterator for TiffFrameIter<R> {
+    type Item = Result<RGB48Frame, FrameOpenError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+        } else if !self.decoder.more_images() {
+            return None;
+        } else if let Err(e) = self.decoder.next_image() {
+            // the decoder moves past a page even if it can't be parsed, and if the page's offset
+            // itself is bad, more_images is false from then on
+            return Some(Err(e.into()));
+        }
+        Some(RGB48Frame::read_tiff_image(&mut self.decoder))
+    }
+}
+
+// Converts raw bytes to expected samples, reporting the byte counts if there are too many or few.
+pub(crate) fn samples_from_bytes(
+    bytes: &[u8],
+    expected: usize,
+    endianness: Endianness,
+) -> Result<Vec<u16>, FrameError> {
+    if bytes.len() != expected * 2 {
+        return Err(FrameError::ByteLengthMismatch {
+            expected: expected * 2,
+            actual: bytes.len(),
+        });
+    }
+    let from_bytes = match endianness {
+        Endianness::Little => u16::from_le_bytes,
+        Endianness::Big => u16::from_be_bytes,
+    };
+    Ok(bytes
+        .chunks_exact(2)
+        .map(|b| from_bytes([b[0], b[1]]))
+        .collect())
+}
+
+pub(crate) fn samples_to_bytes(samples: &[u16], endianness: Endianness) -> Vec<u8> {
+    let to_bytes = match endianness {
+        Endianness::Little => u16::to_le_bytes,
+        Endianness::Big => u16::to_be_bytes,
+    };
+    samples.iter().flat_map(|&x| to_bytes(x)).collect()
+}
+
+pub(crate) fn read_raw_file<P: AsRef<Path>>(
+    path: P,
+    expected: usize,
+    endianness: Endianness,
+) -> Result<Vec<u16>, FrameOpenError> {
+    let mut f = std::fs::File::open(path)?;
+    // a file of the wrong size is probably the wrong file, so don't read all of it to find out
+    let len = f.metadata()?.len();
+    if len != expected as u64 * 2 {
+        return Err(FrameError::ByteLengthMismatch {
+            expected: expected * 2,
+            actual: len.try_into().unwrap_or(usize::MAX),
+        }
+        .into());
+    }
+    let mut bytes = Vec::with_capacity(expected * 2);
+    f.read_to_end(&mut bytes)?;
+    Ok(samples_from_bytes(&bytes, expected, endianness)?)
+}
+
+// The number of samples from_raw and friends expect.
+pub(crate) fn raw_sample_count(
+    width: usize,
+    height: usize,
+    channels: usize,
+) -> Result<usize, FrameError> {
+    if !(1..=4).contains(&channels) {
+        return Err(FrameError::InvalidChannelCount(channels));
+    }
+    if width == 0 || height == 0 {
+        return Err(FrameError::EmptyFrame { width, height });
+    }
+    sample_count(width, height, channels).ok_or(FrameError::TooLarge {
+        width,
+        height,
+        channels,
+    })
+}
+
+// The number of pixels interleave and deinterleave convert at a time. Going one channel at a time
+// over the whole frame reads the interleaved samples once per channel, which for large frames means
+// once per channel from memory. Blocks this size (32 KiB of 4-channel samples) stay in cache across
+// all of the channels, so the interleaved side is only read or written from memory once.
+const INTERLEAVE_BLOCK: usize = 4096;
+
+// Interleaves the planes stored back to back in planar into dest. Each number of channels gets its
+// own loop, so that the strides are constants.
+fn interleave(planar: &[u16], dest: &mut [u16], channels: usize) {
+    match channels {
+        1 => dest.copy_from_slice(planar),
+        2 => interleave_n::<2>(planar, dest),
+        3 => interleave_n::<3>(planar, dest),
+        4 => interleave_n::<4>(planar, dest),
+        _ => unreachable!("{} channels", channels),
+    }
+}
+
+fn interleave_n<const N: usize>(planar: &[u16], dest: &mut [u16]) {
+    let pixels = dest.len() / N;
+    for (block, dest) in dest.chunks_mut(INTERLEAVE_BLOCK * N).enumerate() {
+        let start = block * INTERLEAVE_BLOCK;
+        for c in 0..N {
+            let src = &planar[c * pixels + start..];
+            for (pixel, &x) in dest.chunks_exact_mut(N).zip(src) {
+                pixel[c] = x;
+            }
+        }
+    }
+}
+
+// The inverse of interleave.
+fn deinterleave(interleaved: &[u16], planar: &mut [u16], channels: usize) {
+    match channels {
+        1 => planar.copy_from_slice(interleaved),
+        2 => deinterleave_n::<2>(interleaved, planar),
+        3 => deinterleave_n::<3>(interleaved, planar),
+        4 => deinterleave_n::<4>(interleaved, planar),
+        _ => unreachable!("{} channels", channels),
+    }
+}
+
+fn deinterleave_n<const N: usize>(interleaved: &[u16], planar: &mut [u16]) {
+    let pixels = interleaved.len() / N;
+    for (block, src) in interleaved.chunks(INTERLEAVE_BLOCK * N).enumerate() {
+        let start = block * INTERLEAVE_BLOCK;
+        for c in 0..N {
+            let dest = &mut planar[c * pixels + start..];
+            for (x, pixel) in dest.iter_mut().zip(src.chunks_exact(N)) {
+                *x = pixel[c];
+            }
+        }
+    }
+}
+
+// The number of samples in a frame, or None if the frame would be too large to allocate.
+fn sample_count(width: usize, height: usize, n_planes: usize) -> Option<usize> {
+    width
+        .checked_mul(n_planes)?
+        .checked_mul(height)
+        .filter(|&len| len <= isize::MAX as usize / 2)
+}
+
+// The 64-bit FNV-1a hash of data.
+fn fnv1a(data: &[u8]) -> u64 {
+    fnv1a_64(data.iter().copied())
+}
+
+fn fnv1a_64<I: Iterator<Item = u8>>(bytes: I) -> u64 {
+    bytes.fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn fnv1a_128<I: Iterator<Item = u8>>(bytes: I) -> u128 {
+    bytes.fold(0x6c62272e07bb014262b821756295c58d, |hash, b| {
+        (hash ^ b as u128).wrapping_mul(0x0000000001000000000000000000013b)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{codec, metrics, synth},
+        *,
+    };
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+    };
+
+    // Counts each thread's allocations, so that tests can check that something doesn't allocate.
+    struct CountingAllocator;
+
+    thread_local!(static ALLOCATIONS: Cell<usize> = const { Cell::new(0) });
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(Cell::get)
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_rgb48_frame_open() {
+        let frame = RGB48Frame::open("src/testdata/tears_of_steel_12130.tif").unwrap();
+        assert_eq!(frame.bit_depth, 16);
+    }
+
+    #[cfg(feature = "exr")]
+    #[test]
+    fn test_read_exr() {
+        let (width, height) = (3, 2);
+        let r = [0.0, 0.25, 0.5, 1.0, 2.0, -1.0];
+        let g = [f32::NAN, f32::INFINITY, 0.125, 0.75, 0.0, 1.0];
+        let b = [0.5; 6];
+        let a = [1.0, 0.5, 0.0, 1.0, 1.0, 1.5];
+        let file = exr::tests::exr(
+            width,
+            height,
+            &[
+                ("A", true, &a),
+                ("B", false, &b),
+                ("G", true, &g),
+                ("R", true, &r),
+            ],
+            3,
+            1,
+        );
+
+        let frame = RGB48Frame::read_exr(io::Cursor::new(&file), exr::Transfer::Linear).unwrap();
+        assert_eq!((frame.width, frame.height, frame.channels()), (3, 2, 4));
+        #[rustfmt::skip]
+        assert_eq!(frame.data, vec![
+            0, 0, 32768, 65535,
+            16384, 65535, 32768, 32768,
+            32768, 8192, 32768, 0,
+            65535, 49151, 32768, 65535,
+            65535, 0, 32768, 65535,
+            0, 65535, 32768, 65535,
+        ]);
+
+        // the transfer function applies to color but not alpha
+        let frame = RGB48Frame::read_exr(io::Cursor::new(&file), exr::Transfer::Srgb).unwrap();
+        assert_eq!(&frame.data[4..8], &[35199, 65535, 48192, 32768]);
+
+        // without alpha, the frame is RGB
+        let file = exr::tests::exr(
+            width,
+            height,
+            &[("B", false, &b), ("G", true, &g), ("R", true, &r)],
+            0,
+            1,
+        );
+        let frame = RGB48Frame::read_exr(io::Cursor::new(&file), exr::Transfer::Linear).unwrap();
+        assert_eq!(frame.channels(), 3);
+        assert_eq!(&frame.data[..6], &[0, 0, 32768, 16384, 65535, 32768]);
+
+        let file = exr::tests::exr(width, height, &[("G", true, &g), ("R", true, &r)], 0, 1);
+        match RGB48Frame::read_exr(io::Cursor::new(&file), exr::Transfer::Linear) {
+            Err(FrameOpenError::ExrError(exr::ExrError::Unsupported(message))) => {
+                assert_eq!(message, "no B channel")
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_open_planar_tiff() {
+        // tears_of_steel_12209_crop_planar.tif is the 64x48 region at (2400, 900) of the tiff,
+        // saved big-endian with PlanarConfiguration = 2 and 16 rows per strip
+        let planar = RGB48Frame::open("src/testdata/tears_of_steel_12209_crop_planar.tif").unwrap();
+        let interleaved = RGB48Frame::open("src/testdata/tears_of_steel_12209.tif")
+            .unwrap()
+            .crop(2400, 900, 64, 48)
+            .unwrap();
+        assert!(planar == interleaved);
+
+        // a truncated strip is an error rather than a partial frame
+        let mut truncated =
+            std::fs::read("src/testdata/tears_of_steel_12209_crop_planar.tif").unwrap();
+        truncated.truncate(truncated.len() - 100);
+        assert!(RGB48Frame::read_tiff(io::Cursor::new(truncated)).is_err());
+    }
+
+    #[test]
+    fn test_rgba() {
+        let (width, height) = (40, 30);
+        let samples: Vec<u16> = (0..width * height * 4)
+            .map(|i| {
+                let (pixel, c) = (i / 4, i % 4);
+                let (x, y) = (pixel % width, pixel / width);
+                match c {
+                    // alpha fades across the frame
+                    3 => (x * 65535 / (width - 1)) as u16,
+                    _ => ((x * 1000 + y * 700 + c * 9000) % 65536) as u16,
+                }
+            })
+            .collect();
+        #[cfg(feature = "tiff")]
+        let frame = {
+            let mut tiff = io::Cursor::new(Vec::new());
+            tiff::encoder::TiffEncoder::new(&mut tiff)
+                .unwrap()
+                .write_image::<tiff::encoder::colortype::RGBA16>(
+                    width as u32,
+                    height as u32,
+                    &samples,
+                )
+                .unwrap();
+            tiff.set_position(0);
+            RGB48Frame::read_tiff(tiff).unwrap()
+        };
+        #[cfg(not(feature = "tiff"))]
+        let frame = RGB48Frame {
+            data: samples.clone(),
+            width,
+            height,
+            bit_depth: 16,
+        };
+        assert_eq!((frame.channels(), frame.has_alpha()), (4, true));
+        assert!(frame.data == samples);
+        assert_eq!(frame.planes().len(), 4);
+
+        for options in [
+            EncodeOptions::default(),
+            EncodeOptions {
+                color_transform: ColorTransform::YCoCgR,
+                tile_size: Some((16, 16)),
+                ..Default::default()
+            },
+        ]
+        .iter()
+        {
+            let mut encoded = Vec::new();
+            let mse = frame
+                .encode_with_distortion::<codec::Codec, _>(&mut encoded, options)
+                .unwrap();
+            assert_eq!(mse, vec![0.0; 4]);
+            assert_eq!(RGB48Frame::read_header(&*encoded).unwrap().n_planes, 4);
+            let decoded = RGB48Frame::decode_with_header::<codec::Codec, _>(&*encoded).unwrap();
+            assert!(decoded == frame);
+        }
+
+        // quantization applies to alpha like any other plane
+        let mut encoded = Vec::new();
+        let mse = frame
+            .encode_with_distortion::<codec::Codec, _>(
+                &mut encoded,
+                &EncodeOptions {
+                    quantization: Some(vec![1, 1, 1, 16]),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(&mse[..3], &[0.0; 3]);
+        assert!(mse[3] > 0.0 && metrics::psnr(mse[3]) > 60.0);
+        let decoded = RGB48Frame::decode_with_header::<codec::Codec, _>(&*encoded).unwrap();
+        let mut alpha_error = 0.0;
+        for (i, (&a, &b)) in decoded.data.iter().zip(frame.data.iter()).enumerate() {
+            if i % 4 == 3 {
+                alpha_error += (a as f64 - b as f64).powi(2);
+            } else {
+                assert_eq!(a, b);
+            }
+        }
+        assert_eq!(alpha_error / (width * height) as f64, mse[3]);
+
+        // frames whose data isn't a whole number of channels can't be encoded
+        let mut bad = frame.clone();
+        bad.data.pop();
+        assert!(matches!(
+            bad.encode_with_header::<codec::Codec, _>(&mut Vec::new(), &Default::default()),
+            Err(CodecError::InvalidInput(_))
+        ));
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_gray() {
+        let frame = RGB48Frame::open("src/testdata/gray16.tif").unwrap();
+        assert_eq!((frame.width, frame.height, frame.channels()), (72, 40, 1));
+        // the sample at (6, 9)
+        assert_eq!(frame.data[9 * 72 + 6], 6 * 800 + 9 * 300 + 54 % 17 * 97);
+        assert_eq!(frame.planes().len(), 1);
+        let plane = frame.plane(0);
+        assert_eq!((plane.sample_stride, plane.row_stride), (1, 72));
+
+        for options in [
+            EncodeOptions::default(),
+            EncodeOptions {
+                tile_size: Some((32, 16)),
+                ..Default::default()
+            },
+        ]
+        .iter()
+        {
+            let mut encoded = Vec::new();
+            frame
+                .encode_with_header::<codec::Codec, _>(&mut encoded, options)
+                .unwrap();
+            assert_eq!(RGB48Frame::read_header(&*encoded).unwrap().n_planes, 1);
+            let decoded = RGB48Frame::decode_with_header::<codec::Codec, _>(&*encoded).unwrap();
+            assert!(decoded == frame);
+
+            let region = RGB48Frame::decode_region::<codec::Codec, _>(
+                io::Cursor::new(&encoded),
+                (10, 5, 40, 30),
+            )
+            .unwrap();
+            assert!(region == frame.crop(10, 5, 40, 30).unwrap());
+        }
+
+        // there's nothing for a color transform to work with
+        let err = frame
+            .encode_with_header::<codec::Codec, _>(
+                &mut Vec::new(),
+                &EncodeOptions {
+                    color_transform: ColorTransform::YCoCgR,
+                    ..Default::default()
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(err, CodecError::InvalidInput(_)), "{:?}", err);
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_tiff_pages() {
+        // two_pages.tif has a 40x30 page and a 24x16 page, with sample i of page n (counting from
+        // 1) being (i * 1237 * n + n * 9999) % 65536
+        let pages = RGB48Frame::from_tiff_pages("src/testdata/two_pages.tif")
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(pages.len(), 2);
+        for (page, (n, width, height)) in pages.iter().zip([(1, 40, 30), (2, 24, 16)].iter()) {
+            assert_eq!((page.width, page.height), (*width, *height));
+            for (i, &sample) in page.data.iter().enumerate() {
+                assert_eq!(sample as usize, (i * 1237 * n + n * 9999) % 65536);
+            }
+
+            let mut encoded = Vec::new();
+            page.encode_with_header::<codec::Codec, _>(&mut encoded, &Default::default())
+                .unwrap();
+            let decoded = RGB48Frame::decode_with_header::<codec::Codec, _>(&*encoded).unwrap();
+            assert!(decoded == *page);
+        }
+        // the first page is what from_tiff loads
+        assert!(RGB48Frame::from_tiff("src/testdata/two_pages.tif").unwrap() == pages[0]);
+
+        // an unsupported page doesn't stop the iteration
+        let mut tiff = io::Cursor::new(Vec::new());
+        {
+            let mut encoder = tiff::encoder::TiffEncoder::new(&mut tiff).unwrap();
+            for page in pages.iter() {
+                encoder
+                    .write_image::<tiff::encoder::colortype::RGB16>(
+                        page.width as u32,
+                        page.height as u32,
+                        &page.data,
+                    )
+                    .unwrap();
+                encoder
+                    .write_image::<tiff::encoder::colortype::Gray8>(4, 4, &[0; 16])
+                    .unwrap();
+            }
+        }
+        tiff.set_position(0);
+        let results = RGB48Frame::read_tiff_pages(tiff)
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(results.len(), 4);
+        for (i, result) in results.iter().enumerate() {
+            match (i % 2, result) {
+                (0, Ok(frame)) => assert!(*frame == pages[i / 2]),
+                (1, Err(FrameOpenError::UnsupportedColorType(tiff::ColorType::Gray(8)))) => {}
+                _ => panic!("unexpected result for page {}", i),
+            }
+        }
+    }
+
+    #[test]
+    fn test_plane_views() {
+        // 3 interleaved channels, 7x5
+        let data: Vec<u16> = (0..7 * 5 * 3).collect();
+        let parent = Plane {
+            data: &data[1..],
+            width: 7,
+            height: 5,
+            sample_stride: 3,
+            row_stride: 21,
+        };
+        for &(w, h) in [(1, 1), (3, 2), (7, 5)].iter() {
+            for y in 0..=5 - h {
+                for x in 0..=7 - w {
+                    let view = parent.view(x, y, w, h);
+                    for b in 0..h {
+                        for a in 0..w {
+                            assert_eq!(view.sample(a, b), parent.sample(x + a, y + b));
+                        }
+                    }
+                    let nested = view.view(w / 2, h / 2, w - w / 2, h - h / 2);
+                    assert_eq!(nested.sample(0, 0), parent.sample(x + w / 2, y + h / 2));
+                }
+            }
+        }
+
+        let rows: Vec<Vec<u16>> = parent.rows().map(|row| row.iter().collect()).collect();
+        assert_eq!(rows.len(), 5);
+        for (y, row) in rows.iter().enumerate() {
+            let expected: Vec<u16> = (0..7).map(|x| parent.sample(x, y)).collect();
+            assert_eq!(*row, expected);
+        }
+        let view = parent.view(2, 3, 4, 2);
+        let row = view.rows().nth(1).unwrap();
+        assert_eq!((row.len(), row.sample(3)), (4, parent.sample(5, 4)));
+        assert!(row.as_slice().is_none());
+
+        // contiguous planes have slices for rows
+        let packed = Plane {
+            data: &data[..],
+            width: 7,
+            height: 5,
+            sample_stride: 1,
+            row_stride: 7,
+        };
+        let view = packed.view(1, 2, 5, 3);
+        let row = view.rows().next().unwrap();
+        assert_eq!(row.as_slice(), Some(&data[15..20]));
+        assert!(row.iter().eq(data[15..20].iter().copied()));
+
+        let mut data = data;
+        let mut parent = Plane {
+            data: &mut data[2..],
+            width: 7,
+            height: 5,
+            sample_stride: 3,
+            row_stride: 21,
+        };
+        let view = parent.view_mut(4, 1, 3, 4);
+        let i = 2 * view.row_stride + view.sample_stride;
+        view.data[i] = 9999;
+        assert_eq!(parent.sample(5, 3), 9999);
+    }
+
+    #[test]
+    #[should_panic(expected = "view out of bounds")]
+    fn test_plane_view_out_of_bounds() {
+        let data = [0; 12];
+        let plane = Plane {
+            data: &data[..],
+            width: 4,
+            height: 3,
+            sample_stride: 1,
+            row_stride: 4,
+        };
+        plane.view(2, 0, usize::MAX, 1);
+    }
+
+    #[test]
+    fn test_plane_copies() {
+        // 3 interleaved channels, 7x5
+        let data: Vec<u16> = (0..7 * 5 * 3).map(|i| i * 11).collect();
+        let frame = RGB48Frame::from_raw(data, 7, 5, 3).unwrap();
+        let green = frame.plane(1);
+        let owned = green.to_owned();
+        assert_eq!((owned.sample_stride, owned.row_stride), (1, 7));
+        assert_eq!(owned.data.len(), 35);
+        for y in 0..5 {
+            for x in 0..7 {
+                assert_eq!(owned.sample(x, y), green.sample(x, y));
+            }
+        }
+
+        // back into a 4-channel frame, from both the packed copy and the original
+        let mut rgba = RGB48Frame::from_raw(vec![0; 7 * 5 * 4], 7, 5, 4).unwrap();
+        rgba.plane_mut(2).copy_from(&owned).unwrap();
+        rgba.plane_mut(0).copy_from(&green).unwrap();
+        let mut packed = owned.to_owned();
+        packed.data.iter_mut().for_each(|x| *x = 0);
+        packed.copy_from(&owned).unwrap();
+        for y in 0..5 {
+            for x in 0..7 {
+                assert_eq!(rgba.plane(0).sample(x, y), green.sample(x, y));
+                assert_eq!(rgba.plane(2).sample(x, y), green.sample(x, y));
+                assert_eq!(rgba.plane(1).sample(x, y), 0);
+                assert_eq!(packed.sample(x, y), green.sample(x, y));
+            }
+        }
+
+        // views copy too
+        let mut dest = RGB48Frame::from_raw(vec![0; 7 * 5 * 3], 7, 5, 3).unwrap();
+        dest.plane_mut(1)
+            .view_mut(4, 2, 3, 3)
+            .copy_from(&green.view(0, 0, 3, 3))
+            .unwrap();
+        assert_eq!(dest.plane(1).sample(5, 3), green.sample(1, 1));
+        assert_eq!(dest.plane(1).sample(3, 3), 0);
+
+        let err = rgba
+            .plane_mut(0)
+            .copy_from(&green.view(0, 0, 7, 4))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            FrameError::DimensionMismatch {
+                expected: (7, 5),
+                actual: (7, 4)
+            }
+        );
+        assert_eq!(err.to_string(), "expected a 7x5 plane, got 7x4");
+    }
+
+    #[test]
+    fn test_plane_accessors() {
+        let data: Vec<u16> = (0..5 * 3 * 3).collect();
+        let mut frame = RGB48Frame::from_raw(data, 5, 3, 3).unwrap();
+        assert_eq!(frame.n_planes(), 3);
+        for i in 0..3 {
+            let plane = frame.plane(i);
+            assert_eq!((plane.width, plane.height), (5, 3));
+            for y in 0..3 {
+                for x in 0..5 {
+                    assert_eq!(plane.sample(x, y) as usize, (y * 5 + x) * 3 + i);
+                }
+            }
+        }
+
+        let before = allocations();
+        let mut sum = 0;
+        for plane in frame.planes() {
+            sum += plane.sample(4, 2);
+        }
+        assert_eq!(sum, 42 + 43 + 44);
+        assert_eq!(allocations(), before);
+
+        let plane = frame.plane_mut(1);
+        let i = 2 * plane.row_stride + 3 * plane.sample_stride;
+        plane.data[i] = 1000;
+        assert_eq!(frame.data[(2 * 5 + 3) * 3 + 1], 1000);
+        assert_eq!(frame.plane(1).sample(3, 2), 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "plane 3 of a 3-plane frame")]
+    fn test_plane_out_of_bounds() {
+        RGB48Frame::from_raw(vec![0; 12], 2, 2, 3).unwrap().plane(3);
+    }
+
+    #[test]
+    #[should_panic(expected = "whole number of planes")]
+    fn test_planes_inconsistent() {
+        let mut frame = RGB48Frame::from_raw(vec![0; 12], 2, 2, 3).unwrap();
+        frame.data.pop();
+        frame.n_planes();
+    }
+
+    #[test]
+    fn test_crop() {
+        let frame = synth::noise(40, 30, 1, 16);
+        let direct = frame.crop(13, 7, 10, 12).unwrap();
+        assert_eq!((direct.width, direct.height), (10, 12));
+        let nested = frame
+            .crop(10, 5, 20, 20)
+            .unwrap()
+            .crop(3, 2, 10, 12)
+            .unwrap();
+        assert!(direct == nested);
+        for y in 0..12 {
+            for x in 0..10 {
+                for c in 0..3 {
+                    assert_eq!(
+                        direct.data[(y * 10 + x) * 3 + c],
+                        frame.data[((y + 7) * 40 + x + 13) * 3 + c]
+                    );
+                }
+            }
+        }
+        assert!(frame.crop(0, 0, 40, 30).unwrap() == frame);
+
+        // channels are preserved
+        let rgba =
+            RGB48Frame::from_raw((0..40 * 30 * 4).map(|i| i as u16).collect(), 40, 30, 4).unwrap();
+        assert_eq!(rgba.crop(1, 2, 3, 4).unwrap().channels(), 4);
+
+        for &rect in [
+            (0, 0, 0, 5),
+            (0, 0, 5, 0),
+            (31, 0, 10, 5),
+            (0, 26, 5, 5),
+            (usize::MAX, 0, 2, 2),
+        ]
+        .iter()
+        {
+            let (x, y, w, h) = rect;
+            assert_eq!(
+                frame.crop(x, y, w, h).map(|_| ()).unwrap_err(),
+                FrameError::InvalidRegion {
+                    rect,
+                    width: 40,
+                    height: 30
+                }
+            );
+        }
+        assert_eq!(
+            frame
+                .crop(31, 0, 10, 5)
+                .map(|_| ())
+                .unwrap_err()
+                .to_string(),
+            "region (31, 0, 10, 5) is empty or outside of the 40x30 frame"
+        );
+
+        // a frame with part of a plane left over is an error, rather than losing the samples
+        let mut partial = frame.clone();
+        partial.data.push(0);
+        assert_eq!(
+            partial.crop(0, 0, 5, 5).map(|_| ()).unwrap_err(),
+            FrameError::PartialPlane {
+                len: 40 * 30 * 3 + 1,
+                width: 40,
+                height: 30
+            }
+        );
+    }
+
+    #[test]
+    fn test_pad() {
+        let frame = synth::noise(5, 4, 2, 16);
+        let sample = |frame: &RGB48Frame, x: usize, y: usize| {
+            let i = (y * frame.width + x) * 3;
+            [frame.data[i], frame.data[i + 1], frame.data[i + 2]]
+        };
+        for &mode in [
+            PadMode::Constant(1234),
+            PadMode::ReplicateEdge,
+            PadMode::Mirror,
+        ]
+        .iter()
+        {
+            let padded = frame.pad(3, 6, mode).unwrap();
+            assert_eq!((padded.width, padded.height), (8, 10));
+            assert!(padded.crop(0, 0, 5, 4).unwrap() == frame, "{:?}", mode);
+            assert!(frame.pad(0, 0, mode).unwrap() == frame);
+        }
+
+        let padded = frame.pad(3, 6, PadMode::Constant(1234)).unwrap();
+        assert_eq!(sample(&padded, 5, 0), [1234; 3]);
+        assert_eq!(sample(&padded, 0, 4), [1234; 3]);
+        assert_eq!(sample(&padded, 7, 9), [1234; 3]);
+
+        let padded = frame.pad(3, 6, PadMode::ReplicateEdge).unwrap();
+        for y in 0..10 {
+            for x in 0..8 {
+                assert_eq!(sample(&padded, x, y), sample(&frame, x.min(4), y.min(3)));
+            }
+        }
+
+        // columns 0 through 4 continue 3, 2, 1, and rows 0 through 3 continue 2, 1, 0, 1, 2, 3
+        let padded = frame.pad(3, 6, PadMode::Mirror).unwrap();
+        let columns = [0, 1, 2, 3, 4, 3, 2, 1];
+        let rows = [0, 1, 2, 3, 2, 1, 0, 1, 2, 3];
+        for (y, &row) in rows.iter().enumerate() {
+            for (x, &col) in columns.iter().enumerate() {
+                assert_eq!(sample(&padded, x, y), sample(&frame, col, row));
+            }
+        }
+        // a single column mirrors onto itself
+        let column = frame.crop(2, 0, 1, 4).unwrap();
+        assert!(
+            column.pad(2, 0, PadMode::Mirror).unwrap()
+                == column.pad(2, 0, PadMode::ReplicateEdge).unwrap()
+        );
+
+        let rgba = RGB48Frame::from_raw(vec![7; 2 * 2 * 4], 2, 2, 4).unwrap();
+        let padded = rgba.pad(1, 1, PadMode::Constant(0)).unwrap();
+        assert_eq!((padded.channels(), padded.data.len()), (4, 36));
+
+        assert!(matches!(
+            frame.pad(usize::MAX, 1, PadMode::Mirror),
+            Err(FrameError::TooLarge { .. })
+        ));
+        let mut empty = frame.clone();
+        empty.height = 0;
+        assert!(matches!(
+            empty.pad(1, 1, PadMode::Mirror),
+            Err(FrameError::EmptyFrame { .. })
+        ));
+    }
+
+    #[test]
+    fn test_orientation() {
+        // a 2x3 frame
+        //   1 2
+        //   3 4
+        //   5 6
+        // with green and blue ten and a hundred times red
+        let frame = RGB48Frame::from_raw(
+            (1..=6).flat_map(|x| vec![x, x * 10, x * 100]).collect(),
+            2,
+            3,
+            3,
+        )
+        .unwrap();
+        let red = |frame: &RGB48Frame| -> (usize, usize, Vec<u16>) {
+            for pixel in frame.data.chunks_exact(3) {
+                assert_eq!(pixel, [pixel[0], pixel[0] * 10, pixel[0] * 100]);
+            }
+            (
+                frame.width,
+                frame.height,
+                frame.data.iter().step_by(3).cloned().collect(),
+            )
+        };
+        assert_eq!(
+            red(&frame.flip_horizontal()),
+            (2, 3, vec![2, 1, 4, 3, 6, 5])
+        );
+        assert_eq!(red(&frame.flip_vertical()), (2, 3, vec![5, 6, 3, 4, 1, 2]));
+        assert_eq!(red(&frame.rotate_90()), (3, 2, vec![5, 3, 1, 6, 4, 2]));
+        assert_eq!(red(&frame.rotate_180()), (2, 3, vec![6, 5, 4, 3, 2, 1]));
+        assert_eq!(red(&frame.rotate_270()), (3, 2, vec![2, 4, 6, 1, 3, 5]));
+
+        let frame = synth::noise(7, 4, 3, 12);
+        assert!(frame.rotate_90().rotate_90().rotate_90().rotate_90() == frame);
+        assert!(frame.rotate_90().rotate_90() == frame.rotate_180());
+        assert!(frame.rotate_90().rotate_180() == frame.rotate_270());
+        assert!(frame.rotate_90().rotate_270() == frame);
+        assert!(frame.flip_horizontal().flip_horizontal() == frame);
+        assert!(frame.flip_vertical().flip_vertical() == frame);
+        assert!(frame.flip_horizontal().flip_vertical() == frame.rotate_180());
+        assert_eq!(frame.rotate_90().bit_depth, 12);
+
+        let rgba =
+            RGB48Frame::from_raw((0..7 * 4 * 4).map(|i| i as u16).collect(), 7, 4, 4).unwrap();
+        let rotated = rgba.rotate_270();
+        assert_eq!(
+            (rotated.width, rotated.height, rotated.channels()),
+            (4, 7, 4)
+        );
+        // the top-right pixel moves to the top left, alpha and all
+        assert_eq!(&rotated.data[..4], &rgba.data[6 * 4..7 * 4]);
+    }
+
+    #[test]
+    fn test_padded_planes() {
+        assert_eq!(
+            Plane::with_padding(vec![0; 11], 4, 2, 2, 8).map(|_| ()),
+            Err(FrameError::BufferTooSmall {
+                required: 15,
+                actual: 11
+            })
+        );
+        assert!(Plane::with_padding(vec![0; 15], 4, 2, 2, 8).is_ok());
+        assert_eq!(
+            Plane::with_padding(vec![0; 64], 4, 2, 2, 6).map(|_| ()),
+            Err(FrameError::InvalidStrides {
+                width: 4,
+                sample_stride: 2,
+                row_stride: 6
+            })
+        );
+        assert!(matches!(
+            Plane::with_padding(vec![0; 64], 4, 2, 0, 8),
+            Err(FrameError::InvalidStrides { .. })
+        ));
+        assert!(matches!(
+            Plane::with_padding(vec![0; 64], 4, 0, 1, 8),
+            Err(FrameError::EmptyFrame { .. })
+        ));
+        assert!(matches!(
+            Plane::with_padding(vec![0; 64], usize::MAX, 1, 2, usize::MAX),
+            Err(FrameError::InvalidStrides { .. })
+        ));
+
+        // the plane's samples are every third one of each row, and rows are followed by padding,
+        // which encoders must ignore and decoders must leave alone
+        const PADDING: u16 = 0xbeef;
+        let (width, height, sample_stride) = (13, 6, 3);
+        let source = synth::noise(width, height, 7, 16);
+        let source = source.plane(1);
+        let layout = TileLayout::new(width, height, 5, 4);
+        for padding in 1..=64 {
+            let row_stride = width * sample_stride + padding;
+            let padded = |plane: Option<&Plane<&[u16]>>| {
+                let mut data = vec![PADDING; (height - 1) * row_stride + width * sample_stride];
+                if let Some(plane) = plane {
+                    for row in 0..height {
+                        for col in 0..width {
+                            data[row * row_stride + col * sample_stride] = plane.sample(col, row);
+                        }
+                    }
+                }
+                Plane::with_padding(data, width, height, sample_stride, row_stride).unwrap()
+            };
+            let plane = padded(Some(&source));
+            let check_padding = |plane: &Plane<Vec<u16>>| {
+                for (i, &x) in plane.data.iter().enumerate() {
+                    let (row, col) = (i / row_stride, i % row_stride);
+                    if col % sample_stride != 0 || col / sample_stride >= width {
+                        assert_eq!(x, PADDING, "padding {}, row {}, col {}", padding, row, col);
+                    }
+                }
+            };
+            let expect_eq = |decoded: &Plane<Vec<u16>>, expected: &Plane<Vec<u16>>| {
+                assert!(decoded
+                    .rows()
+                    .zip(expected.rows())
+                    .all(|(a, b)| a.iter().eq(b.iter())));
+                check_padding(decoded);
+            };
+
+            let mut lossless = Vec::new();
+            codec::Codec::encode(&plane, &mut lossless).unwrap();
+            let mut expected = Vec::new();
+            codec::Codec::encode(&plane.to_owned(), &mut expected).unwrap();
+            assert_eq!(lossless, expected, "padding {}", padding);
+            let mut decoded = padded(None);
+            codec::Codec::decode(lossless.as_slice(), &mut decoded).unwrap();
+            expect_eq(&decoded, &plane.to_owned());
+
+            let mut quantized = Vec::new();
+            let error = codec::Codec::encode_quantized(&plane, 16, &mut quantized).unwrap();
+            let mut expected = Vec::new();
+            assert_eq!(
+                codec::Codec::encode_quantized(&plane.to_owned(), 16, &mut expected).unwrap(),
+                error
+            );
+            assert_eq!(quantized, expected, "padding {}", padding);
+            let mut decoded = padded(None);
+            codec::Codec::decode_quantized(quantized.as_slice(), &mut decoded, 16).unwrap();
+            let mut reference = Plane {
+                data: vec![0; width * height],
+                width,
+                height,
+                sample_stride: 1,
+                row_stride: width,
+            };
+            codec::Codec::decode_quantized(expected.as_slice(), &mut reference, 16).unwrap();
+            expect_eq(&decoded, &reference);
+
+            let mut tiled = Vec::new();
+            tile::encode_plane::<codec::Codec, _, _>(
+                &plane,
+                &layout,
+                PlaneCoding::default(),
+                &mut tiled,
+            )
+            .unwrap();
+            let mut expected = Vec::new();
+            tile::encode_plane::<codec::Codec, _, _>(
+                &plane.to_owned(),
+                &layout,
+                PlaneCoding::default(),
+                &mut expected,
+            )
+            .unwrap();
+            assert_eq!(tiled, expected, "padding {}", padding);
+            let mut decoded = padded(None);
+            tile::decode_plane::<codec::Codec, _, _>(
+                tiled.as_slice(),
+                &mut decoded,
+                &layout,
+                PlaneCoding::default(),
+            )
+            .unwrap();
+            expect_eq(&decoded, &plane.to_owned());
+        }
+    }
+
+    #[test]
+    fn test_plane_from_raw_parts() {
+        let data = synth::noise(9, 5, 3, 16).data;
+        let wrap = |len, width, height, sample_stride, row_stride| unsafe {
+            Plane::from_raw_parts(data.as_ptr(), len, width, height, sample_stride, row_stride)
+                .map(|_| ())
+        };
+        // the last sample of the last row must be within len
+        assert_eq!(
+            wrap(132, 9, 5, 3, 27),
+            Err(FrameError::BufferTooSmall {
+                required: 133,
+                actual: 132
+            })
+        );
+        assert!(wrap(133, 9, 5, 3, 27).is_ok());
+        assert!(matches!(
+            wrap(135, 9, 5, 3, 20),
+            Err(FrameError::InvalidStrides { .. })
+        ));
+        // and the index computations can't overflow their way back into bounds
+        assert!(matches!(
+            wrap(135, 3, 5, usize::MAX / 2 + 1, usize::MAX),
+            Err(FrameError::InvalidStrides { .. })
+        ));
+        assert!(matches!(
+            wrap(135, 1, 3, 1, usize::MAX / 2 + 1),
+            Err(FrameError::BufferTooSmall { .. })
+        ));
+
+        // a wrapped buffer encodes and decodes like the frame's own planes
+        let frame = RGB48Frame::from_raw(data.clone(), 9, 5, 3).unwrap();
+        for channel in 0..3 {
+            let plane = unsafe {
+                Plane::from_raw_parts(
+                    data.as_ptr().add(channel),
+                    data.len() - channel,
+                    9,
+                    5,
+                    3,
+                    27,
+                )
+            }
+            .unwrap();
+            let mut encoded = Vec::new();
+            codec::Codec::encode(&plane, &mut encoded).unwrap();
+            let mut expected = Vec::new();
+            codec::Codec::encode(&frame.plane(channel), &mut expected).unwrap();
+            assert_eq!(encoded, expected);
+
+            let mut decoded = vec![0; data.len()];
+            let mut plane = unsafe {
+                Plane::from_raw_parts_mut(decoded.as_mut_ptr(), decoded.len(), 9, 5, 1, 9)
+            }
+            .unwrap();
+            codec::Codec::decode(encoded.as_slice(), &mut plane).unwrap();
+            assert!(decoded[..45]
+                .iter()
+                .copied()
+                .eq(frame.plane(channel).rows().flat_map(|row| row.iter())));
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let frame = RGB48Frame::from_raw((0..60).collect(), 5, 4, 3).unwrap();
+        assert_eq!(frame.validate(), Ok(3));
+        let mut encoded = Vec::new();
+        frame.encode::<codec::Codec, _>(&mut encoded).unwrap();
+        let mut expected = Vec::new();
+        for plane in frame.planes() {
+            codec::Codec::encode(&plane, &mut expected).unwrap();
+        }
+        assert_eq!(encoded, expected);
+
+        let mut truncated = frame.clone();
+        truncated.data.pop();
+        let mut five = frame.clone();
+        five.data.extend(0..40);
+        let mut empty = frame.clone();
+        empty.width = 0;
+        for (frame, expected) in [
+            (
+                truncated,
+                FrameError::PartialPlane {
+                    len: 59,
+                    width: 5,
+                    height: 4,
+                },
+            ),
+            (five, FrameError::InvalidChannelCount(5)),
+            (
+                empty,
+                FrameError::EmptyFrame {
+                    width: 0,
+                    height: 4,
+                },
+            ),
+        ] {
+            assert_eq!(frame.validate().unwrap_err(), expected);
+            match frame.encode::<codec::Codec, _>(&mut Vec::new()) {
+                Err(CodecError::InvalidInput(message)) => assert_eq!(message, expected.to_string()),
+                other => panic!("unexpected result: {:?}", other),
+            }
+            let mut encoded = Vec::new();
+            match frame.encode_with_header::<codec::Codec, _>(&mut encoded, &Default::default()) {
+                Err(CodecError::InvalidInput(message)) => assert_eq!(message, expected.to_string()),
+                other => panic!("unexpected result: {:?}", other),
+            }
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_from_raw() {
+        let data: Vec<u16> = (0..5 * 3 * 4).map(|i| (i * 999) as u16).collect();
+        let frame = RGB48Frame::from_raw(data.clone(), 5, 3, 4).unwrap();
+        assert_eq!((frame.width, frame.height, frame.channels()), (5, 3, 4));
+        let mut encoded = Vec::new();
+        frame
+            .encode_with_header::<codec::Codec, _>(&mut encoded, &Default::default())
+            .unwrap();
+        assert!(RGB48Frame::decode_with_header::<codec::Codec, _>(&*encoded).unwrap() == frame);
+
+        let planes: Vec<Vec<u16>> = (0..4)
+            .map(|c| data[c..].iter().step_by(4).cloned().collect())
+            .collect();
+        assert!(RGB48Frame::from_planar(&planes, 5, 3).unwrap() == frame);
+
+        let bytes: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 5) as u8).collect();
+        let frame = RGB48Frame::from_raw_interleaved_u8(&bytes, 5, 3, 3).unwrap();
+        assert_eq!(frame.bit_depth, 8);
+        assert_eq!(frame.data[10], 50 * 257);
+
+        for &(len, width, height, channels, ref expected) in [
+            (0, 5, 3, 0, FrameError::InvalidChannelCount(0)),
+            (75, 5, 3, 5, FrameError::InvalidChannelCount(5)),
+            (
+                0,
+                0,
+                3,
+                3,
+                FrameError::EmptyFrame {
+                    width: 0,
+                    height: 3,
+                },
+            ),
+            (
+                0,
+                5,
+                0,
+                3,
+                FrameError::EmptyFrame {
+                    width: 5,
+                    height: 0,
+                },
+            ),
+            (
+                0,
+                usize::MAX,
+                2,
+                3,
+                FrameError::TooLarge {
+                    width: usize::MAX,
+                    height: 2,
+                    channels: 3,
+                },
+            ),
+            (
+                44,
+                5,
+                3,
+                3,
+                FrameError::LengthMismatch {
+                    expected: 45,
+                    actual: 44,
+                },
+            ),
+        ]
+        .iter()
+        {
+            let err = RGB48Frame::from_raw(vec![0; len], width, height, channels)
+                .map(|_| ())
+                .unwrap_err();
+            assert_eq!(err, *expected);
+            let err = RGB48Frame::from_raw_interleaved_u8(&vec![0; len], width, height, channels)
+                .map(|_| ())
+                .unwrap_err();
+            assert_eq!(err, *expected);
+        }
+
+        let err = RGB48Frame::from_planar(&[vec![0; 15], vec![0; 14], vec![0; 15]], 5, 3)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            FrameError::PlaneLengthMismatch {
+                plane: 1,
+                expected: 15,
+                actual: 14
+            }
+        );
+        assert_eq!(err.to_string(), "expected 15 samples in plane 1, got 14");
+        let err = RGB48Frame::from_planar::<Vec<u16>>(&[], 5, 3)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, FrameError::InvalidChannelCount(0));
+    }
+
+    #[test]
+    fn test_plane_mutation() {
+        let original = synth::noise(9, 5, 2, 16);
+        let unchanged = |frame: &RGB48Frame, channel: usize| {
+            frame
+                .data
+                .iter()
+                .zip(&original.data)
+                .enumerate()
+                .all(|(i, (a, b))| i % 3 == channel || a == b)
+        };
+
+        let mut frame = original.clone();
+        frame.plane_mut(1).fill(7);
+        assert!(unchanged(&frame, 1));
+        assert!(frame.plane(1).rows().all(|row| row.iter().all(|x| x == 7)));
+
+        let mut frame = original.clone();
+        frame.plane_mut(2).map_samples(|x| !x);
+        assert!(unchanged(&frame, 2));
+        assert!(frame
+            .plane(2)
+            .rows()
+            .zip(original.plane(2).rows())
+            .all(|(a, b)| a.iter().zip(b.iter()).all(|(a, b)| a == !b)));
+
+        let mut frame = original.clone();
+        frame.plane_mut(0).apply_gain_offset(3, 2, -1000, 16);
+        assert!(unchanged(&frame, 0));
+        original.plane(0).for_each_sample(|col, row, x| {
+            let expected = ((x as i64 * 3 + 1) / 2 - 1000).clamp(0, 65535) as u16;
+            assert_eq!(frame.plane(0).sample(col, row), expected);
+        });
+
+        // only the view is touched, and contiguous planes work too
+        let mut frame = original.clone();
+        frame.plane_mut(1).view_mut(2, 1, 3, 2).fill(0);
+        original.plane(1).for_each_sample(|col, row, x| {
+            let inside = (2..5).contains(&col) && (1..3).contains(&row);
+            assert_eq!(frame.plane(1).sample(col, row), if inside { 0 } else { x });
+        });
+        assert!(unchanged(&frame, 1));
+        let mut plane = original.plane(1).to_owned();
+        plane.apply_gain_offset(1, 1, 0, 12);
+        plane.for_each_sample(|col, row, x| {
+            assert_eq!(x, original.plane(1).sample(col, row).min(4095));
+        });
+
+        let mut samples = Vec::new();
+        plane
+            .view(1, 2, 2, 2)
+            .for_each_sample(|col, row, x| samples.push((col, row, x)));
+        assert_eq!(
+            samples,
+            vec![
+                (0, 0, plane.sample(1, 2)),
+                (1, 0, plane.sample(2, 2)),
+                (0, 1, plane.sample(1, 3)),
+                (1, 1, plane.sample(2, 3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_effective_bit_depth() {
+        // a 10-bit ramp, with each pixel's channels offset from each other
+        let data = (0..32 * 32)
+            .flat_map(|i: u16| [i, 1023 - i, i / 2])
+            .collect::<Vec<_>>();
+        let ramp = RGB48Frame::from_raw(data, 32, 32, 3).unwrap();
+        assert_eq!(ramp.plane(0).min_max(), Some((0, 1023)));
+        assert_eq!(ramp.plane(2).min_max(), Some((0, 511)));
+        assert_eq!(ramp.plane(0).view(0, 0, 0, 0).min_max(), None);
+        let histogram = ramp.plane(1).histogram();
+        assert_eq!(histogram.len(), 65536);
+        assert!(histogram[..1024].iter().all(|&n| n == 1));
+        assert_eq!(histogram.iter().sum::<u64>(), 1024);
+        let histogram = ramp.plane(2).view(0, 0, 32, 2).histogram_with_bins(1024);
+        assert_eq!(histogram[..2], [64, 0]);
+        assert_eq!(histogram.iter().sum::<u64>(), 64);
+
+        for outliers in [0, 1, 1000].iter() {
+            assert_eq!(ramp.effective_bit_depth(*outliers), 10);
+        }
+        // rejecting 512 samples drops the 10th bit from plane 0 and plane 1, but not both
+        assert_eq!(ramp.effective_bit_depth(1023), 10);
+        assert_eq!(ramp.effective_bit_depth(1024), 9);
+
+        // one hot pixel
+        let mut hot = ramp.clone();
+        hot.data[3 * 100 + 2] = 65535;
+        assert_eq!(hot.plane(2).min_max(), Some((0, 65535)));
+        assert_eq!(hot.effective_bit_depth(0), 16);
+        assert_eq!(hot.effective_bit_depth(1), 10);
+        let hot = hot.with_effective_bit_depth(1);
+        assert_eq!(hot.bit_depth, 10);
+        let mut encoded = Vec::new();
+        hot.encode_with_header::<codec::Codec, _>(&mut encoded, &EncodeOptions::default())
+            .unwrap();
+        let decoded = RGB48Frame::decode_with_header::<codec::Codec, _>(&*encoded).unwrap();
+        assert!(decoded == hot);
+
+        // frames already known to be narrower keep their depth, and all-zero frames still need a bit
+        assert_eq!(
+            synth::noise(8, 8, 1, 8)
+                .with_effective_bit_depth(0)
+                .bit_depth,
+            8
+        );
+        assert_eq!(synth::flat(4, 4, 0).effective_bit_depth(0), 1);
+    }
+
+    #[test]
+    fn test_planar() {
+        // enough pixels for several blocks, and a partial one at the end
+        for &(width, height) in [(1, 1), (7, 3), (67, 191)].iter() {
+            for channels in 1..=4 {
+                let frame = synth::noise(width, height * channels, 5, 16);
+                let frame = RGB48Frame::from_raw(
+                    frame.data[..width * height * channels].to_vec(),
+                    width,
+                    height,
+                    channels,
+                )
+                .unwrap();
+
+                let planar = frame.to_planar();
+                for (c, plane) in planar.chunks_exact(width * height).enumerate() {
+                    let expected: Vec<u16> = frame.plane(c).rows().flat_map(|r| r.iter()).collect();
+                    assert_eq!(plane, &expected[..], "{}x{}x{}", width, height, channels);
+                }
+                let interleaved =
+                    RGB48Frame::from_planar_buffer(&planar, width, height, channels).unwrap();
+                assert!(interleaved == frame);
+                let planes: Vec<&[u16]> = planar.chunks_exact(width * height).collect();
+                assert!(RGB48Frame::from_planar(&planes, width, height).unwrap() == frame);
+
+                let mut dest = vec![0; planar.len()];
+                frame.to_planar_into(&mut dest).unwrap();
+                assert_eq!(dest, planar);
+                let mut copy =
+                    RGB48Frame::from_raw(vec![0; planar.len()], width, height, channels).unwrap();
+                copy.copy_from_planar(&planar).unwrap();
+                assert!(copy == frame);
+            }
+        }
+
+        let mut frame = synth::flat(5, 3, 0);
+        assert_eq!(
+            frame.to_planar_into(&mut [0; 44]),
+            Err(FrameError::LengthMismatch {
+                expected: 45,
+                actual: 44
+            })
+        );
+        assert_eq!(
+            frame.copy_from_planar(&[0; 46]),
+            Err(FrameError::LengthMismatch {
+                expected: 45,
+                actual: 46
+            })
+        );
+        assert!(matches!(
+            RGB48Frame::from_planar_buffer(&[0; 45], 5, 3, 4),
+            Err(FrameError::LengthMismatch { .. })
+        ));
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_to_ycbcr() {
+        let frame = RGB48Frame::open("src/testdata/tears_of_steel_12130.tif")
+            .unwrap()
+            .crop(1024, 512, 64, 48)
+            .unwrap();
+        let mut rgba = frame.clone();
+        rgba.data = frame
+            .data
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 1234])
+            .collect();
+        for &matrix in [Matrix::Bt709, Matrix::Bt2020].iter() {
+            let ycbcr = frame.to_ycbcr(matrix).unwrap();
+            assert_eq!(
+                (ycbcr.data[0], ycbcr.data[1], ycbcr.data[2]),
+                matrix.rgb_to_ycbcr(frame.data[0], frame.data[1], frame.data[2])
+            );
+            let rgb = ycbcr.to_rgb(matrix).unwrap();
+            assert!(rgb
+                .data
+                .iter()
+                .zip(&frame.data)
+                .all(|(&a, &b)| (a as i32 - b as i32).abs() <= 1));
+
+            let ycbcr = rgba.to_ycbcr(matrix).unwrap();
+            assert!(ycbcr.data.iter().skip(3).step_by(4).all(|&a| a == 1234));
+            assert_eq!(&ycbcr.data[..3], &frame.to_ycbcr(matrix).unwrap().data[..3]);
+        }
+
+        // gray frames, such as gray TIFFs open as, have no color to convert
+        let gray = RGB48Frame::open("src/testdata/gray16.tif").unwrap();
+        for result in [gray.to_ycbcr(Matrix::Bt709), gray.to_rgb(Matrix::Bt709)] {
+            match result {
+                Err(FrameError::InvalidChannelCount(1)) => {}
+                other => panic!("unexpected result: {:?}", other.map(|_| ())),
+            }
+        }
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_raw_bytes() {
+        let frame = RGB48Frame::open("src/testdata/tears_of_steel_12130.tif")
+            .unwrap()
+            .crop(1024, 512, 64, 48)
+            .unwrap();
+        for &endianness in [Endianness::Little, Endianness::Big].iter() {
+            let bytes = frame.to_raw_bytes(endianness);
+            assert_eq!(bytes.len(), 64 * 48 * 3 * 2);
+            let decoded = RGB48Frame::from_raw_bytes(&bytes, 64, 48, 3, endianness).unwrap();
+            assert!(decoded == frame);
+
+            let path = std::env::temp_dir().join(format!(
+                "hvc-raw-{:?}-{}.raw",
+                endianness,
+                std::process::id()
+            ));
+            frame.write_raw_file(&path, endianness).unwrap();
+            let reloaded = RGB48Frame::from_raw_file(&path, 64, 48, 3, endianness);
+            let wrong_size = RGB48Frame::from_raw_file(&path, 64, 47, 3, endianness);
+            std::fs::remove_file(&path).unwrap();
+            assert!(reloaded.unwrap() == frame);
+            match wrong_size {
+                Err(FrameOpenError::InvalidFrame(FrameError::ByteLengthMismatch {
+                    expected: 18048,
+                    actual: 18432,
+                })) => {}
+                other => panic!("unexpected result: {:?}", other.map(|_| ())),
+            }
+        }
+
+        // 0x0102 and 0xfffe, stored big-endian
+        let bytes = [0x01, 0x02, 0xff, 0xfe];
+        let frame = RGB48Frame::from_raw_bytes(&bytes, 2, 1, 1, Endianness::Big).unwrap();
+        assert_eq!(frame.data, vec![0x0102, 0xfffe]);
+        let frame = RGB48Frame::from_raw_bytes(&bytes, 2, 1, 1, Endianness::Little).unwrap();
+        assert_eq!(frame.data, vec![0x0201, 0xfeff]);
+        assert_eq!(
+            frame.to_raw_bytes(Endianness::Big),
+            vec![0x02, 0x01, 0xfe, 0xff]
+        );
+
+        let err = RGB48Frame::from_raw_bytes(&bytes[..3], 2, 1, 1, Endianness::Big)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "expected 4 bytes, got 3");
+        let err = RGB48Frame::from_raw_bytes(&bytes, 2, 1, 5, Endianness::Big)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err, FrameError::InvalidChannelCount(5));
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_open_8_bit_tiff() {
+        // tears_of_steel_12130_crop_rgb24.tif is the 96x64 region at (1024, 512) of the 16-bit tiff,
+        // truncated to 8 bits
+        let frame = RGB48Frame::open("src/testdata/tears_of_steel_12130_crop_rgb24.tif").unwrap();
+        assert_eq!((frame.width, frame.height, frame.bit_depth), (96, 64, 8));
+        let original = RGB48Frame::open("src/testdata/tears_of_steel_12130.tif")
+            .unwrap()
+            .crop(1024, 512, 96, 64)
+            .unwrap();
+        for (&widened, &original) in frame.data.iter().zip(original.data.iter()) {
+            assert_eq!(widened, (original >> 8) * 257);
+        }
+
+        // the original depth survives encoding
+        let mut encoded = Vec::new();
+        frame
+            .encode_with_header::<codec::Codec, _>(&mut encoded, &Default::default())
+            .unwrap();
+        assert_eq!(RGB48Frame::read_header(&*encoded).unwrap().bit_depth, 8);
+        let decoded = RGB48Frame::decode_with_header::<codec::Codec, _>(&*encoded).unwrap();
+        assert!(decoded == frame);
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_open_formats() {
+        for &name in ["tears_of_steel_12130", "tears_of_steel_12209"].iter() {
+            let path = format!("src/testdata/{}.tif", name);
+            let frame = RGB48Frame::open(&path).unwrap();
+            assert!(frame == RGB48Frame::from_tiff(&path).unwrap());
+            assert_eq!((frame.width, frame.height), (4096, 1714));
+        }
+
+        let dir = std::env::temp_dir().join(format!("hvc-open-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // the magic wins over the extension
+        let misnamed = dir.join("frame.png");
+        std::fs::copy("src/testdata/tears_of_steel_12130.tif", &misnamed).unwrap();
+        assert!(
+            RGB48Frame::open(&misnamed).unwrap()
+                == RGB48Frame::from_tiff("src/testdata/tears_of_steel_12130.tif").unwrap()
+        );
+
+        for &(name, contents) in
+            [("notes.tif", &b"just some text\n"[..]), ("empty.tif", b"")].iter()
+        {
+            let path = dir.join(name);
+            std::fs::write(&path, contents).unwrap();
+            match RGB48Frame::open(&path) {
+                Err(FrameOpenError::UnknownFormat) => {}
+                Err(e) => panic!("unexpected error for {}: {:?}", name, e),
+                Ok(_) => panic!("opened {}", name),
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Returns at most 7 bytes from each read, like a slow network stream.
+    #[cfg(feature = "tiff")]
+    struct Trickle<R>(R);
+
+    #[cfg(feature = "tiff")]
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(7);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[cfg(feature = "tiff")]
+    impl<R: Seek> Seek for Trickle<R> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_open_from_memory() {
+        for &name in ["tears_of_steel_12130.tif", "gray16.tif", "two_pages.tif"].iter() {
+            let path = format!("src/testdata/{}", name);
+            let frame = RGB48Frame::from_tiff(&path).unwrap();
+            let bytes = std::fs::read(&path).unwrap();
+            assert!(RGB48Frame::from_bytes(&bytes).unwrap() == frame, "{}", name);
+            let reader = Trickle(io::Cursor::new(&bytes));
+            assert!(
+                RGB48Frame::from_tiff_reader(reader).unwrap() == frame,
+                "{}",
+                name
+            );
+        }
+        let planar = std::fs::read("src/testdata/tears_of_steel_12209_crop_planar.tif").unwrap();
+        assert!(
+            RGB48Frame::from_tiff_reader(Trickle(io::Cursor::new(&planar))).unwrap()
+                == RGB48Frame::open("src/testdata/tears_of_steel_12209_crop_planar.tif").unwrap()
+        );
+
+        #[cfg(feature = "png")]
+        {
+            let path = "src/testdata/gradient_rgb48.png";
+            let frame = RGB48Frame::from_png(path).unwrap();
+            let bytes = std::fs::read(path).unwrap();
+            assert!(RGB48Frame::from_bytes(&bytes).unwrap() == frame);
+            assert!(RGB48Frame::from_png_reader(Trickle(&bytes[..])).unwrap() == frame);
+        }
+
+        assert!(matches!(
+            RGB48Frame::from_bytes(b"just some text\n"),
+            Err(FrameOpenError::UnknownFormat)
+        ));
+        assert!(matches!(
+            RGB48Frame::from_bytes(&[]),
+            Err(FrameOpenError::UnknownFormat)
+        ));
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_open_png() {
+        let frame = RGB48Frame::open("src/testdata/gradient_rgb48.png").unwrap();
+        assert_eq!((frame.width, frame.height), (64, 48));
+        assert_eq!(&frame.data[..3], &[0, 0, 0]);
+        // the sample at (5, 7)
+        let i = (7 * 64 + 5) * 3;
+        assert_eq!(&frame.data[i..i + 3], &[5007, 7 * 1300 + 35, 5 * 7 * 37]);
+        assert!(frame == RGB48Frame::from_png("src/testdata/gradient_rgb48.png").unwrap());
+
+        // only RGB is supported
+        let mut png = std::fs::read("src/testdata/gradient_rgb48.png").unwrap();
+        // reinterpret the image as 192-pixel-wide grayscale, which has the same row size
+        png[16..20].copy_from_slice(&192u32.to_be_bytes());
+        png[25] = 0;
+        let crc = {
+            let mut crc = flate2::Crc::new();
+            crc.update(&png[12..29]);
+            crc.sum()
+        };
+        png[29..33].copy_from_slice(&crc.to_be_bytes());
+        match RGB48Frame::read_png(&*png) {
+            Err(FrameOpenError::UnsupportedPngColorType(png::ColorType::Gray)) => {}
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("loaded a grayscale png as RGB"),
+        }
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_png_matches_tiff() {
+        // tears_of_steel_12209_crop.png is the 96x64 region at (1800, 700) of the tiff
+        let tiff = RGB48Frame::open("src/testdata/tears_of_steel_12209.tif")
+            .unwrap()
+            .crop(1800, 700, 96, 64)
+            .unwrap();
+        let png = RGB48Frame::from_png("src/testdata/tears_of_steel_12209_crop.png").unwrap();
+        assert!(png == tiff);
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_open_png_8_bit() {
+        let sample = |x: usize, y: usize, c: usize| ((x * 13 + y * 7 + c * 85) % 256) as u8;
+        let mut encoded = Vec::new();
+        png::encode(&mut encoded, 20, 10, png::ColorType::RGB, 8, |y, row| {
+            for (i, b) in row.iter_mut().enumerate() {
+                *b = sample(i / 3, y, i % 3);
+            }
+        })
+        .unwrap();
+        let frame = RGB48Frame::read_png(&*encoded).unwrap();
+        assert_eq!((frame.width, frame.height, frame.bit_depth), (20, 10, 8));
+        for y in 0..10 {
+            for x in 0..20 {
+                for c in 0..3 {
+                    let expected = sample(x, y, c) as u16 * 257;
+                    assert_eq!(frame.data[(y * 20 + x) * 3 + c], expected);
+                }
+            }
+        }
+        assert!(frame.data.contains(&0xffff));
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_to_tiff() {
+        let frame = RGB48Frame::open("src/testdata/tears_of_steel_12130.tif").unwrap();
+        let path = std::env::temp_dir().join(format!("hvc-to-tiff-{}.tif", std::process::id()));
+        frame.to_tiff(&path).unwrap();
+        let reloaded = RGB48Frame::from_tiff(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(reloaded.unwrap() == frame);
+
+        // through the codec and back out via memory
+        let frame = frame.crop(1000, 600, 300, 200).unwrap();
+        let mut encoded = Vec::new();
+        frame
+            .encode_with_header::<codec::Codec, _>(&mut encoded, &Default::default())
+            .unwrap();
+        let decoded = RGB48Frame::decode_with_header::<codec::Codec, _>(&*encoded).unwrap();
+        let mut tiff = io::Cursor::new(Vec::new());
+        decoded.write_tiff(&mut tiff).unwrap();
+        tiff.set_position(0);
+        assert!(RGB48Frame::read_tiff(tiff).unwrap() == frame);
+
+        let gray = RGB48Frame {
+            data: vec![0; 4 * 4],
+            width: 4,
+            height: 4,
+            bit_depth: 16,
+        };
+        match gray.write_tiff(io::Cursor::new(Vec::new())) {
+            Err(FrameSaveError::UnsupportedPlaneCount(1)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_to_png() {
+        let frame = RGB48Frame::open("src/testdata/tears_of_steel_12209.tif")
+            .unwrap()
+            .crop(2000, 800, 320, 240)
+            .unwrap();
+        let path = std::env::temp_dir().join(format!("hvc-to-png-{}.png", std::process::id()));
+        frame.to_png(&path).unwrap();
+        let reloaded = RGB48Frame::from_png(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(reloaded.unwrap() == frame);
+
+        let mut encoded = Vec::new();
+        frame.write_png(&mut encoded).unwrap();
+        assert_eq!(encoded[..8], png::SIGNATURE);
+        assert_eq!(&encoded[8..16], b"\0\0\0\x0dIHDR");
+        assert_eq!(&encoded[16..20], &320u32.to_be_bytes());
+        assert_eq!(&encoded[20..24], &240u32.to_be_bytes());
+        // 16 bits, RGB, deflate, adaptive filtering, not interlaced
+        assert_eq!(&encoded[24..29], &[16, 2, 0, 0, 0]);
+        assert!(RGB48Frame::read_png(&*encoded).unwrap() == frame);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let frame = RGB48Frame {
+            data: (0..20 * 10 * 3).map(|i| ((i * 37) % 4096) as u16).collect(),
+            width: 20,
+            height: 10,
+            bit_depth: 16,
+        };
+        let mut encoded = Vec::new();
+        frame
+            .encode_with_header::<codec::Codec, _>(&mut encoded, &Default::default())
+            .unwrap();
+
+        // cut the stream off partway through the second plane
+        let mut first_plane = Vec::new();
+        codec::Codec::encode(&frame.plane(0), &mut first_plane).unwrap();
+        let truncated = &encoded[..15 + first_plane.len() + 10];
+        match RGB48Frame::decode_with_header::<codec::Codec, _>(truncated) {
+            Err(CodecError::TruncatedStream { plane: 1, row, col }) => {
+                assert!(row < 10 && col < 20)
+            }
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("decoded a truncated frame"),
+        }
+
+        let mut corrupt = encoded.clone();
+        corrupt[0] = b'X';
+        match RGB48Frame::decode_with_header::<codec::Codec, _>(&*corrupt) {
+            Err(CodecError::InvalidHeader(_)) => {}
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("decoded a frame with a bad header"),
+        }
+
+        let mut corrupt = encoded;
+        corrupt[4] = 3;
+        match RGB48Frame::decode_with_header::<codec::Codec, _>(&*corrupt) {
+            Err(CodecError::UnsupportedVersion(3)) => {}
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("decoded a frame with an unsupported version"),
+        }
+    }
+
+    #[test]
+    fn test_decode_into() {
+        let frames: Vec<RGB48Frame> = (0..2)
+            .map(|seed| RGB48Frame {
+                data: (0..20 * 10 * 3)
+                    .map(|i| ((i * 37 + seed * 1000) % 4096) as u16)
+                    .collect(),
+                width: 20,
+                height: 10,
+                bit_depth: 16,
+            })
+            .collect();
+        let mut buffer = RGB48Frame {
+            data: vec![0; 20 * 10 * 3],
+            width: 20,
+            height: 10,
+            bit_depth: 8,
+        };
+        let ptr = buffer.data.as_ptr();
+        for frame in frames.iter() {
+            let mut encoded = Vec::new();
+            frame.encode::<codec::Codec, _>(&mut encoded).unwrap();
+            RGB48Frame::decode_into::<codec::Codec, _>(&*encoded, &mut buffer).unwrap();
+            let fresh = RGB48Frame::decode::<codec::Codec, _>(&*encoded, 20, 10).unwrap();
+            assert!(buffer == fresh && buffer == *frame);
+
+            let mut encoded = Vec::new();
+            frame
+                .encode_with_header::<codec::Codec, _>(&mut encoded, &Default::default())
+                .unwrap();
+            buffer.data.iter_mut().for_each(|x| *x = 0);
+            RGB48Frame::decode_with_header_into::<codec::Codec, _>(&*encoded, &mut buffer).unwrap();
+            let fresh = RGB48Frame::decode_with_header::<codec::Codec, _>(&*encoded).unwrap();
+            assert!(buffer == fresh && buffer == *frame);
+        }
+        assert_eq!(buffer.data.as_ptr(), ptr);
+
+        // frames that don't match the stream are left alone
+        let mut encoded = Vec::new();
+        frames[0]
+            .encode_with_header::<codec::Codec, _>(&mut encoded, &Default::default())
+            .unwrap();
+        for &(width, height, channels) in [(10, 20, 3), (20, 10, 4), (20, 10, 1)].iter() {
+            let mut buffer = RGB48Frame {
+                data: vec![7; width * height * channels],
+                width,
+                height,
+                bit_depth: 16,
+            };
+            match RGB48Frame::decode_with_header_into::<codec::Codec, _>(&*encoded, &mut buffer) {
+                Err(CodecError::InvalidInput(_)) => {}
+                other => panic!("unexpected result: {:?}", other),
+            }
+            assert!(buffer.data.iter().all(|&x| x == 7));
+        }
+        let mut rgba = RGB48Frame {
+            data: vec![0; 20 * 10 * 4],
+            width: 20,
+            height: 10,
+            bit_depth: 16,
+        };
+        assert!(matches!(
+            RGB48Frame::decode_into::<codec::Codec, _>(&encoded[15..], &mut rgba),
+            Err(CodecError::InvalidInput(_))
+        ));
+    }
+
+    // A small xorshift generator for making up streams.
+    fn noise(len: usize, mut state: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_garbage() {
+        // arbitrary bytes either decode or fail cleanly
+        for seed in 1..200 {
+            let len = seed as usize * 7 % 1500;
+            let mut garbage = noise(len, seed);
+            if seed % 3 == 0 {
+                // mostly zero bits, for long unary codes
+                garbage.iter_mut().for_each(|b| *b &= 0x01);
+            }
+            if let Ok(frame) = RGB48Frame::decode::<codec::Codec, _>(&*garbage, 16, 12) {
+                assert_eq!(frame.data.len(), 16 * 12 * 3);
+            }
+
+            let mut with_header = Vec::new();
+            FrameHeader {
+                width: 16,
+                height: 12,
+                n_planes: 1 + seed as usize % 4,
+                bit_depth: 16,
+                sample_width: 16,
+                color_transform: ColorTransform::None,
+                tile_size: if seed % 2 == 0 { Some((5, 5)) } else { None },
+                quantization: None,
+                preview_factor: None,
+                band_height: if seed % 3 == 0 { Some(4) } else { None },
+                fixed_k: if seed % 5 == 0 {
+                    Some(vec![seed as u8 % 17; 1 + seed as usize % 4])
+                } else {
+                    None
+                },
+            }
+            .write(&mut with_header)
+            .unwrap();
+            with_header.extend_from_slice(&garbage);
+            let _ = RGB48Frame::decode_with_header::<codec::Codec, _>(&*with_header);
+        }
+    }
+
+    #[test]
+    fn test_decode_limits() {
+        // 1.5 MB of samples
+        let frame = RGB48Frame {
+            data: (0..512 * 512 * 3).map(|i| (i % 1000) as u16).collect(),
+            width: 512,
+            height: 512,
+            bit_depth: 16,
+        };
+        let mut encoded = Vec::new();
+        frame.encode::<codec::Codec, _>(&mut encoded).unwrap();
+        let mut with_header = Vec::new();
+        frame
+            .encode_with_header::<codec::Codec, _>(&mut with_header, &Default::default())
+            .unwrap();
+
+        let small = DecodeOptions {
+            limits: Limits {
+                max_decoded_bytes: 1 << 20,
+            },
+        };
+        let expected = "decoding needs 1572864 bytes, more than the limit of 1048576";
+        match RGB48Frame::decode_with_options::<codec::Codec, _>(&*encoded, 512, 512, &small) {
+            Err(e @ CodecError::LimitExceeded { .. }) => assert_eq!(e.to_string(), expected),
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("decoded a frame over the limit"),
+        }
+        match RGB48Frame::decode_with_header_and_options::<codec::Codec, _>(&*with_header, &small) {
+            Err(e @ CodecError::LimitExceeded { .. }) => assert_eq!(e.to_string(), expected),
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("decoded a frame over the limit"),
+        }
+
+        let large = DecodeOptions {
+            limits: Limits::unlimited(),
+        };
+        let decoded =
+            RGB48Frame::decode_with_options::<codec::Codec, _>(&*encoded, 512, 512, &large)
+                .unwrap();
+        assert!(decoded == frame);
+        let decoded =
+            RGB48Frame::decode_with_header_and_options::<codec::Codec, _>(&*with_header, &large)
+                .unwrap();
+        assert!(decoded == frame);
+
+        // a header claiming more than the default limit fails before anything is allocated
+        let mut header = Vec::new();
+        FrameHeader {
+            width: 65536,
+            height: 65536,
+            n_planes: 3,
+            bit_depth: 16,
+            sample_width: 16,
+            color_transform: ColorTransform::None,
+            tile_size: None,
+            quantization: None,
+            preview_factor: None,
+            band_height: None,
+            fixed_k: None,
+        }
+        .write(&mut header)
+        .unwrap();
+        let before = allocations();
+        let err = RGB48Frame::decode_with_header::<codec::Codec, _>(&*header)
+            .map(|_| ())
+            .unwrap_err();
+        assert!(matches!(err, CodecError::LimitExceeded { .. }), "{:?}", err);
+        assert!(allocations() - before <= 1);
+
+        // and so does one whose preview alone is over the limit
+        let mut header = Vec::new();
+        FrameHeader {
+            width: 1 << 31,
+            height: 1 << 28,
+            n_planes: 3,
+            bit_depth: 16,
+            sample_width: 16,
+            color_transform: ColorTransform::None,
+            tile_size: None,
+            quantization: None,
+            preview_factor: Some(2),
+            band_height: None,
+            fixed_k: None,
+        }
+        .write(&mut header)
+        .unwrap();
+        let before = allocations();
+        let err = RGB48Frame::decode_preview::<codec::Codec, _>(&*header)
+            .map(|_| ())
+            .unwrap_err();
+        assert!(matches!(err, CodecError::LimitExceeded { .. }), "{:?}", err);
+        assert!(allocations() - before <= 1);
+
+        let mut with_preview = Vec::new();
+        frame
+            .encode_with_header::<codec::Codec, _>(
+                &mut with_preview,
+                &EncodeOptions {
+                    preview_factor: Some(2),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let tiny = DecodeOptions {
+            limits: Limits {
+                max_decoded_bytes: 1 << 18,
+            },
+        };
+        let err = RGB48Frame::decode_preview_with_options::<codec::Codec, _>(&*with_preview, &tiny)
+            .map(|_| ())
+            .unwrap_err();
+        assert!(matches!(err, CodecError::LimitExceeded { .. }), "{:?}", err);
+        let preview =
+            RGB48Frame::decode_preview_with_options::<codec::Codec, _>(&*with_preview, &small)
+                .unwrap();
+        assert_eq!((preview.width, preview.height), (256, 256));
+    }
+
+    #[test]
+    fn test_decode_huge_dimensions() {
+        match RGB48Frame::decode::<codec::Codec, _>(&[0xff; 64][..], usize::MAX / 2, 4) {
+            Err(CodecError::InvalidInput(_)) => {}
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("decoded an impossibly large frame"),
+        }
+
+        match RGB48Frame::decode::<codec::Codec, _>(&[0xff; 64][..], usize::MAX / 2, 3) {
+            Err(CodecError::InvalidInput(_)) => {}
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("decoded an impossibly large frame"),
+        }
+
+        // an empty frame doesn't allocate anything for its width
+        let frame = RGB48Frame::decode::<codec::Codec, _>(&[][..], 1 << 40, 0).unwrap();
+        assert!(frame.data.is_empty());
+
+        // headers for empty or impossibly large frames are rejected
+        for &(width, height) in [(0, 12), (16, 0)].iter() {
+            let mut header = Vec::new();
+            FrameHeader {
+                width,
+                height,
+                n_planes: 3,
+                bit_depth: 16,
+                sample_width: 16,
+                color_transform: ColorTransform::None,
+                tile_size: None,
+                quantization: None,
+                preview_factor: None,
+                band_height: None,
+                fixed_k: None,
+            }
+            .write(&mut header)
+            .unwrap();
+            let err = RGB48Frame::decode_with_header::<codec::Codec, _>(&*header)
+                .map(|_| ())
+                .unwrap_err();
+            assert!(matches!(err, CodecError::InvalidHeader(_)), "{:?}", err);
+        }
+
+        let mut header = Vec::new();
+        FrameHeader {
+            width: u32::MAX as usize,
+            height: u32::MAX as usize,
+            n_planes: 4,
+            bit_depth: 16,
+            sample_width: 16,
+            color_transform: ColorTransform::None,
+            tile_size: Some((1, 1)),
+            quantization: None,
+            preview_factor: None,
+            band_height: None,
+            fixed_k: None,
+        }
+        .write(&mut header)
+        .unwrap();
+        if cfg!(target_pointer_width = "64") {
+            match RGB48Frame::decode_with_header::<codec::Codec, _>(&*header) {
+                Err(CodecError::InvalidHeader(_)) => {}
+                Err(e) => panic!("unexpected error: {:?}", e),
+                Ok(_) => panic!("decoded an impossibly large frame"),
+            }
+        }
+
+        let mut encoded = Vec::new();
+        RGB48Frame {
+            data: vec![0; 4 * 4 * 3],
+            width: 4,
+            height: 4,
+            bit_depth: 16,
+        }
+        .encode_with_header::<codec::Codec, _>(&mut encoded, &Default::default())
+        .unwrap();
+        let err = RGB48Frame::decode_region::<codec::Codec, _>(
+            io::Cursor::new(&encoded),
+            (2, 0, usize::MAX, 1),
+        )
+        .map(|_| ())
+        .unwrap_err();
+        assert!(matches!(err, CodecError::InvalidInput(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+        assert_eq!(
+            fnv1a_128(b"".iter().copied()),
+            0x6c62272e07bb014262b821756295c58d
+        );
+        assert_eq!(
+            fnv1a_128(b"a".iter().copied()),
+            0xd228cb696f1a8caf78912b704e4a8964
+        );
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_preview() {
+        // odd dimensions, so the blocks along the edges are partial
+        let frame = RGB48Frame::open("src/testdata/tears_of_steel_12130.tif")
+            .unwrap()
+            .crop(1024, 512, 517, 301)
+            .unwrap();
+        let mut flat = Vec::new();
+        frame
+            .encode_with_header::<codec::Codec, _>(&mut flat, &Default::default())
+            .unwrap();
+
+        for &factor in [4, 8].iter() {
+            let (width, height) = pyramid::base_size(frame.width, frame.height, factor);
+            let mut expected = RGB48Frame {
+                data: vec![0; width * height * 3],
+                width,
+                height,
+                bit_depth: 16,
+            };
+            for i in 0..3 {
+                expected
+                    .plane_mut(i)
+                    .copy_from(&pyramid::downsample(&frame.plane(i), factor))
+                    .unwrap();
+            }
+
+            for &color_transform in [ColorTransform::None, ColorTransform::YCoCgR].iter() {
+                let options = EncodeOptions {
+                    color_transform,
+                    preview_factor: Some(factor),
+                    ..Default::default()
+                };
+                let mut encoded = Vec::new();
+                frame
+                    .encode_with_header::<codec::Codec, _>(&mut encoded, &options)
+                    .unwrap();
+                let decoded = RGB48Frame::decode_with_header::<codec::Codec, _>(&*encoded).unwrap();
+                assert!(decoded == frame);
+                // the overhead of the base layer is small, even for a frame this small
+                assert!(
+                    encoded.len() < flat.len() * 110 / 100,
+                    "{} bytes hierarchically with a factor of {}, {} flat",
+                    encoded.len(),
+                    factor,
+                    flat.len()
+                );
+
+                let mut source = Counting::new(&encoded[..]);
+                let preview = RGB48Frame::decode_preview::<codec::Codec, _>(&mut source).unwrap();
+                assert!(
+                    source.count as usize <= encoded.len() * 4 / (factor * factor),
+                    "{} of {}",
+                    source.count,
+                    encoded.len()
+                );
+                assert_eq!((preview.width, preview.height), (width, height));
+                assert!(preview == expected);
+            }
+        }
+
+        // frames without a preview, and options that can't be combined with one
+        let err = RGB48Frame::decode_preview::<codec::Codec, _>(&*flat)
+            .map(|_| ())
+            .unwrap_err();
+        assert!(matches!(err, CodecError::InvalidInput(_)), "{:?}", err);
+        for options in [
+            EncodeOptions {
+                preview_factor: Some(4),
+                tile_size: Some((64, 64)),
+                ..Default::default()
+            },
+            EncodeOptions {
+                preview_factor: Some(4),
+                quantization: Some(vec![1, 2, 1]),
+                ..Default::default()
+            },
+            EncodeOptions {
+                preview_factor: Some(1),
+                ..Default::default()
+            },
+        ]
+        .iter()
+        {
+            let err = frame
+                .encode_with_header::<codec::Codec, _>(&mut Vec::new(), options)
+                .unwrap_err();
+            assert!(matches!(err, CodecError::InvalidInput(_)), "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let frame = synth::gradient(7, 5, synth::Direction::Horizontal);
+        // pinned, so that changes to the serialization are caught
+        assert_eq!(frame.content_hash(), 0xb4af3d25f9baead4);
+        assert_eq!(frame.content_hash_128(), 0x536257ac7d8100f2394c3e7b83e81b4c);
+
+        let mut serialized = Vec::new();
+        for &x in [7u64, 5, 3].iter() {
+            serialized.extend_from_slice(&x.to_le_bytes());
+        }
+        for x in &frame.data {
+            serialized.extend_from_slice(&x.to_le_bytes());
+        }
+        assert_eq!(frame.content_hash(), fnv1a(&serialized));
+
+        // stable across saving and loading, and encoding and decoding
+        #[cfg(feature = "tiff")]
+        {
+            let mut tiff = io::Cursor::new(Vec::new());
+            frame.write_tiff(&mut tiff).unwrap();
+            let loaded = RGB48Frame::from_bytes(tiff.get_ref()).unwrap();
+            assert_eq!(loaded.content_hash(), frame.content_hash());
+        }
+        let mut encoded = Vec::new();
+        let report = frame
+            .encode_verified::<codec::Codec, _>(&mut encoded)
+            .unwrap();
+        assert_eq!(report.content_hash, frame.content_hash());
+        let decoded = RGB48Frame::decode_with_header::<codec::Codec, _>(&*encoded).unwrap();
+        assert_eq!(decoded.content_hash_128(), frame.content_hash_128());
+
+        // any change to a sample or the dimensions changes it, but bit_depth doesn't
+        let mut changed = frame.clone();
+        changed.data[40] ^= 1;
+        assert_ne!(changed.content_hash(), frame.content_hash());
+        assert_ne!(changed.content_hash_128(), frame.content_hash_128());
+        let transposed = RGB48Frame {
+            width: 5,
+            height: 7,
+            ..frame.clone()
+        };
+        assert_ne!(transposed.content_hash(), frame.content_hash());
+        let narrow = RGB48Frame {
+            bit_depth: 10,
+            ..frame.clone()
+        };
+        assert_eq!(narrow.content_hash(), frame.content_hash());
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_encode_verified() {
+        let frame = RGB48Frame::open("src/testdata/tears_of_steel_12130.tif")
+            .unwrap()
+            .crop(512, 256, 256, 128)
+            .unwrap();
+        let mut encoded = Vec::new();
+        let report = frame
+            .encode_verified::<codec::Codec, _>(&mut encoded)
+            .unwrap();
+        assert_eq!(report.size, encoded.len());
+        assert_eq!(report.hash, fnv1a(&encoded));
+
+        let mut expected = Vec::new();
+        frame
+            .encode_with_header::<codec::Codec, _>(&mut expected, &Default::default())
+            .unwrap();
+        assert_eq!(encoded, expected);
+    }
+
+    // Decodes correctly except for flipping a bit of the sample at (5, 2).
+    struct BrokenCodec;
+
+    impl Codec for BrokenCodec {
+        fn encode<T: AsRef<[u16]>, W: Write>(plane: &Plane<T>, dest: W) -> Result<(), CodecError> {
+            codec::Codec::encode(plane, dest)
+        }
+
+        fn decode<T: AsMut<[u16]>, R: Read>(
+            source: R,
+            plane: &mut Plane<T>,
+        ) -> Result<(), CodecError> {
+            codec::Codec::decode(source, plane)?;
+            let i = 2 * plane.row_stride + 5 * plane.sample_stride;
+            plane.data.as_mut()[i] ^= 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_encode_verified_mismatch() {
+        let frame = RGB48Frame {
+            data: (0..20 * 10 * 3).map(|i| ((i * 37) % 4096) as u16).collect(),
+            width: 20,
+            height: 10,
+            bit_depth: 16,
+        };
+        let mut encoded = Vec::new();
+        match frame.encode_verified::<BrokenCodec, _>(&mut encoded) {
+            Err(CodecError::VerificationFailed {
+                plane: 0,
+                row: 2,
+                col: 5,
+            }) => {}
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(report) => panic!("verified a broken encode: {:?}", report),
+        }
+        assert!(encoded.is_empty());
     }
 }
@@ -0,0 +1,592 @@
+#[cfg(feature = "png")]
+use super::png;
+use super::{
+    color::Matrix,
+    dpx,
+    error::CodecError,
+    frame::{
+        self, open_image, raw_sample_count, Codec, EncodeOptions, FrameError, FrameOpenError,
+        FrameSaveError, ImageFormat, Plane, RGB48Frame,
+    },
+    pgm,
+};
+#[cfg(feature = "tiff")]
+use std::{convert::TryInto, io::Seek};
+use std::{
+    io::{self, Read, Write},
+    path::Path,
+};
+
+// What the channels of a Frame16 hold, which also determines how many there are.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChannelLayout {
+    Gray,
+    GrayAlpha,
+    Rgb,
+    Rgba,
+    // Y', Cb, and Cr as produced by Frame16::to_ycbcr, with chroma offset by 0x8000.
+    YCbCr,
+    YCbCrA,
+}
+
+impl ChannelLayout {
+    pub fn channels(self) -> usize {
+        match self {
+            Self::Gray => 1,
+            Self::GrayAlpha => 2,
+            Self::Rgb | Self::YCbCr => 3,
+            Self::Rgba | Self::YCbCrA => 4,
+        }
+    }
+
+    // Alpha, when there is any, is always the last channel.
+    pub fn has_alpha(self) -> bool {
+        match self {
+            Self::GrayAlpha | Self::Rgba | Self::YCbCrA => true,
+            Self::Gray | Self::Rgb | Self::YCbCr => false,
+        }
+    }
+
+    // The layout RGB48Frame implies for a number of channels: gray, gray with alpha, RGB, or RGBA.
+    pub fn from_channels(channels: usize) -> Option<Self> {
+        match channels {
+            1 => Some(Self::Gray),
+            2 => Some(Self::GrayAlpha),
+            3 => Some(Self::Rgb),
+            4 => Some(Self::Rgba),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "png")]
+    fn png_color_type(self) -> Option<png::ColorType> {
+        match self {
+            Self::Gray => Some(png::ColorType::Gray),
+            Self::GrayAlpha => Some(png::ColorType::GrayAlpha),
+            Self::Rgb => Some(png::ColorType::RGB),
+            Self::Rgba => Some(png::ColorType::RGBA),
+            Self::YCbCr | Self::YCbCrA => None,
+        }
+    }
+}
+
+// A frame of 1 to 4 interleaved 16-bit channels. Unlike RGB48Frame, which infers its number of
+// channels from the length of its data, the layout says how many channels there are and what they
+// mean, so that writers and conversions can do the right thing with them. It's encoded exactly like
+// an RGB48Frame with the same number of channels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame16 {
+    pub data: Vec<u16>,
+    pub width: usize,
+    pub height: usize,
+    pub layout: ChannelLayout,
+    // The bit depth of the samples the frame was loaded from, as for RGB48Frame.
+    pub bit_depth: u8,
+}
+
+impl Frame16 {
+    // Builds a frame from interleaved samples, checking that there are exactly width * height *
+    // layout.channels() of them.
+    pub fn from_raw(
+        data: Vec<u16>,
+        width: usize,
+        height: usize,
+        layout: ChannelLayout,
+    ) -> Result<Self, FrameError> {
+        let expected = raw_sample_count(width, height, layout.channels())?;
+        if data.len() != expected {
+            return Err(FrameError::LengthMismatch {
+                expected,
+                actual: data.len(),
+            });
+        }
+        Ok(Self {
+            data,
+            width,
+            height,
+            layout,
+            bit_depth: 16,
+        })
+    }
+
+    // Takes over an RGB48Frame's samples, with the layout its number of channels implies (see
+    // ChannelLayout::from_channels).
+    pub fn from_rgb48(frame: RGB48Frame) -> Result<Self, FrameError> {
+        let channels = frame.validate()?;
+        Ok(Self {
+            layout: ChannelLayout::from_channels(channels)
+                .ok_or(FrameError::InvalidChannelCount(channels))?,
+            data: frame.data,
+            width: frame.width,
+            height: frame.height,
+            bit_depth: frame.bit_depth,
+        })
+    }
+
+    // Loads a TIFF, PNG, DPX, or PGM file, telling which it is from its first few bytes. Grayscale
+    // and RGBA files keep their channels, and PNGs can be gray with alpha too.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, FrameOpenError> {
+        let frame = match open_image(path)? {
+            #[cfg(feature = "tiff")]
+            (f, ImageFormat::Tiff) => RGB48Frame::from_tiff_reader(f)?,
+            #[cfg(feature = "png")]
+            (f, ImageFormat::Png) => return Self::read_png(io::BufReader::new(f)),
+            (f, ImageFormat::Dpx) => dpx::read(io::BufReader::new(f))?,
+            (f, ImageFormat::Pgm) => pgm::read(io::BufReader::new(f))?,
+        };
+        Ok(Self::from_rgb48(frame)?)
+    }
+
+    #[cfg(feature = "png")]
+    pub fn from_png<P: AsRef<Path>>(path: P) -> Result<Self, FrameOpenError> {
+        Self::read_png(io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    // Loads an 8- or 16-bit gray, gray and alpha, RGB, or RGBA PNG. 8-bit samples are scaled by
+    // 257, as for RGB48Frame::from_png.
+    #[cfg(feature = "png")]
+    pub(crate) fn read_png<R: Read>(source: R) -> Result<Self, FrameOpenError> {
+        let image = png::decode(source)?;
+        let layout = match image.color_type {
+            png::ColorType::Gray => ChannelLayout::Gray,
+            png::ColorType::GrayAlpha => ChannelLayout::GrayAlpha,
+            png::ColorType::RGB => ChannelLayout::Rgb,
+            png::ColorType::RGBA => ChannelLayout::Rgba,
+            color_type => return Err(FrameOpenError::UnsupportedPngColorType(color_type)),
+        };
+        let data: Vec<u16> = match image.bit_depth {
+            16 => image
+                .data
+                .chunks_exact(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .collect(),
+            8 => image.data.iter().map(|&x| x as u16 * 257).collect(),
+            bit_depth => {
+                return Err(png::PngError::Unsupported(format!(
+                    "{}-bit {:?} images",
+                    bit_depth, image.color_type
+                ))
+                .into())
+            }
+        };
+        let mut ret = Self::from_raw(data, image.width, image.height, layout)?;
+        ret.bit_depth = image.bit_depth;
+        Ok(ret)
+    }
+
+    // Saves the frame as a TIFF or PNG, whichever the path's extension names.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), FrameSaveError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            #[cfg(feature = "png")]
+            Some("png") => self.to_png(path),
+            #[cfg(feature = "tiff")]
+            Some("tif") | Some("tiff") => self.to_tiff(path),
+            _ => Err(FrameSaveError::UnknownFormat(path.display().to_string())),
+        }
+    }
+
+    // Saves the frame as an uncompressed 16-bit gray, RGB, or RGBA TIFF.
+    #[cfg(feature = "tiff")]
+    pub fn to_tiff<P: AsRef<Path>>(&self, path: P) -> Result<(), FrameSaveError> {
+        let mut dest = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_tiff(&mut dest)?;
+        dest.flush()?;
+        Ok(())
+    }
+
+    #[cfg(feature = "tiff")]
+    pub fn write_tiff<W: Write + Seek>(&self, dest: W) -> Result<(), FrameSaveError> {
+        let (width, height) = match (self.width.try_into(), self.height.try_into()) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the frame is too large for a tiff",
+                )
+                .into())
+            }
+        };
+        use tiff::encoder::colortype;
+        let mut encoder = tiff::encoder::TiffEncoder::new(dest)?;
+        match self.layout {
+            ChannelLayout::Gray => {
+                encoder.write_image::<colortype::Gray16>(width, height, &self.data)?
+            }
+            ChannelLayout::Rgb => {
+                encoder.write_image::<colortype::RGB16>(width, height, &self.data)?
+            }
+            ChannelLayout::Rgba => {
+                encoder.write_image::<colortype::RGBA16>(width, height, &self.data)?
+            }
+            layout => return Err(FrameSaveError::UnsupportedLayout(layout)),
+        }
+        Ok(())
+    }
+
+    // Saves the frame as a 16-bit PNG of the matching color type, so RGBA frames keep their alpha.
+    // YCbCr frames have to be converted to RGB first.
+    #[cfg(feature = "png")]
+    pub fn to_png<P: AsRef<Path>>(&self, path: P) -> Result<(), FrameSaveError> {
+        let mut dest = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_png(&mut dest)?;
+        dest.flush()?;
+        Ok(())
+    }
+
+    #[cfg(feature = "png")]
+    pub fn write_png<W: Write>(&self, dest: W) -> Result<(), FrameSaveError> {
+        let color_type = self
+            .layout
+            .png_color_type()
+            .ok_or(FrameSaveError::UnsupportedLayout(self.layout))?;
+        let row_len = self.width * self.channels();
+        png::encode(
+            dest,
+            self.width,
+            self.height,
+            color_type,
+            16,
+            |row, dest| {
+                let samples = &self.data[row * row_len..(row + 1) * row_len];
+                for (bytes, sample) in dest.chunks_exact_mut(2).zip(samples) {
+                    bytes.copy_from_slice(&sample.to_be_bytes());
+                }
+            },
+        )?;
+        Ok(())
+    }
+
+    pub fn channels(&self) -> usize {
+        self.layout.channels()
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        self.layout.has_alpha()
+    }
+
+    // A view of the ith plane. Panics if there's no such plane.
+    pub fn plane(&self, i: usize) -> Plane<&[u16]> {
+        let channels = self.channels();
+        assert!(i < channels, "plane {} of a {}-plane frame", i, channels);
+        Plane {
+            data: &self.data[i..],
+            width: self.width,
+            height: self.height,
+            row_stride: channels * self.width,
+            sample_stride: channels,
+        }
+    }
+
+    pub fn plane_mut(&mut self, i: usize) -> Plane<&mut [u16]> {
+        let channels = self.channels();
+        assert!(i < channels, "plane {} of a {}-plane frame", i, channels);
+        Plane {
+            data: &mut self.data[i..],
+            width: self.width,
+            height: self.height,
+            row_stride: channels * self.width,
+            sample_stride: channels,
+        }
+    }
+
+    pub fn planes(&self) -> impl ExactSizeIterator<Item = Plane<&[u16]>> {
+        (0..self.channels()).map(move |i| self.plane(i))
+    }
+
+    pub fn encode<C: Codec, W: Write>(&self, mut dest: W) -> Result<(), CodecError> {
+        if Some(self.data.len()) != raw_sample_count(self.width, self.height, self.channels()).ok()
+        {
+            return Err(CodecError::InvalidInput(format!(
+                "{} samples don't fit a {}x{} {:?} frame",
+                self.data.len(),
+                self.width,
+                self.height,
+                self.layout
+            )));
+        }
+        for plane in self.planes() {
+            C::encode(&plane, &mut dest)?;
+        }
+        Ok(())
+    }
+
+    // Decodes a frame written by encode. Unlike RGB48Frame::decode, the layout says how many planes
+    // to expect.
+    pub fn decode<C: Codec, R: Read>(
+        mut source: R,
+        width: usize,
+        height: usize,
+        layout: ChannelLayout,
+    ) -> Result<Self, CodecError> {
+        let len = raw_sample_count(width, height, layout.channels())
+            .map_err(|e| CodecError::InvalidInput(e.to_string()))?;
+        frame::Limits::default().check(len)?;
+        let mut ret = Self::from_raw(vec![0; len], width, height, layout)
+            .map_err(|e| CodecError::InvalidInput(e.to_string()))?;
+        for i in 0..layout.channels() {
+            C::decode(&mut source, &mut ret.plane_mut(i)).map_err(|e| e.in_plane(i, 0, 0))?;
+        }
+        Ok(ret)
+    }
+
+    // Encodes the frame preceded by a header, the same way RGB48Frame::encode_with_header encodes
+    // a frame with as many channels. The header records the number of channels but not what they
+    // mean.
+    pub fn encode_with_header<C: Codec, W: Write>(
+        &self,
+        dest: W,
+        options: &EncodeOptions,
+    ) -> Result<(), CodecError> {
+        frame::encode_samples::<C, _>(
+            &self.data,
+            (self.width, self.height),
+            self.bit_depth,
+            dest,
+            options,
+            &mut |_| false,
+        )?;
+        Ok(())
+    }
+
+    // Decodes a frame written by encode_with_header, with the layout its number of channels
+    // implies. Frames that were YCbCr when encoded come back as RGB, and need their layout set.
+    pub fn decode_with_header<C: Codec, R: Read>(source: R) -> Result<Self, CodecError> {
+        let frame = RGB48Frame::decode_with_header::<C, _>(source)?;
+        Self::from_rgb48(frame).map_err(|e| CodecError::InvalidHeader(e.to_string()))
+    }
+
+    // Converts an RGB or RGBA frame to Y', Cb, and Cr, as RGB48Frame::to_ycbcr does, leaving alpha
+    // alone.
+    pub fn to_ycbcr(&self, matrix: Matrix) -> Result<Self, FrameError> {
+        let layout = match self.layout {
+            ChannelLayout::Rgb => ChannelLayout::YCbCr,
+            ChannelLayout::Rgba => ChannelLayout::YCbCrA,
+            layout => return Err(FrameError::UnsupportedLayout(layout)),
+        };
+        let mut ret = self.clone();
+        matrix.forward(&mut ret.data, self.channels());
+        ret.layout = layout;
+        Ok(ret)
+    }
+
+    // The inverse of to_ycbcr, for YCbCr frames.
+    pub fn to_rgb(&self, matrix: Matrix) -> Result<Self, FrameError> {
+        let layout = match self.layout {
+            ChannelLayout::YCbCr => ChannelLayout::Rgb,
+            ChannelLayout::YCbCrA => ChannelLayout::Rgba,
+            layout => return Err(FrameError::UnsupportedLayout(layout)),
+        };
+        let mut ret = self.clone();
+        matrix.inverse(&mut ret.data, self.channels());
+        ret.layout = layout;
+        Ok(ret)
+    }
+}
+
+impl From<Frame16> for RGB48Frame {
+    fn from(frame: Frame16) -> Self {
+        RGB48Frame {
+            data: frame.data,
+            width: frame.width,
+            height: frame.height,
+            bit_depth: frame.bit_depth,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{codec, synth},
+        *,
+    };
+
+    fn test_frame(width: usize, height: usize, layout: ChannelLayout) -> Frame16 {
+        let channels = layout.channels();
+        let noise = synth::noise(width, height, channels as u32, 16);
+        let zone_plate = synth::zone_plate(width, height);
+        // mix smooth and noisy channels, so that each plane codes differently
+        let data = (0..width * height * channels)
+            .map(|i| {
+                let (pixel, channel) = (i / channels, i % channels);
+                if channel % 2 == 0 {
+                    zone_plate.data[pixel * 3 + channel % 3]
+                } else {
+                    noise.data[pixel * 3 + channel % 3]
+                }
+            })
+            .collect();
+        Frame16::from_raw(data, width, height, layout).unwrap()
+    }
+
+    #[test]
+    fn test_from_raw() {
+        assert_eq!(
+            Frame16::from_raw(vec![0; 12], 2, 3, ChannelLayout::GrayAlpha)
+                .unwrap()
+                .planes()
+                .len(),
+            2
+        );
+        assert_eq!(
+            Frame16::from_raw(vec![0; 12], 2, 3, ChannelLayout::Rgb),
+            Err(FrameError::LengthMismatch {
+                expected: 18,
+                actual: 12
+            })
+        );
+        assert_eq!(
+            Frame16::from_rgb48(RGB48Frame::from_raw(vec![0; 12], 2, 3, 2).unwrap()),
+            Ok(Frame16::from_raw(vec![0; 12], 2, 3, ChannelLayout::GrayAlpha).unwrap())
+        );
+
+        let frame = test_frame(5, 4, ChannelLayout::Rgba);
+        let rgb48 = RGB48Frame::from(frame.clone());
+        assert!(rgb48.has_alpha());
+        assert_eq!(Frame16::from_rgb48(rgb48), Ok(frame));
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        for &layout in [
+            ChannelLayout::Gray,
+            ChannelLayout::GrayAlpha,
+            ChannelLayout::Rgb,
+            ChannelLayout::Rgba,
+        ]
+        .iter()
+        {
+            let frame = test_frame(37, 21, layout);
+
+            let mut encoded = Vec::new();
+            frame.encode::<codec::Codec, _>(&mut encoded).unwrap();
+            let decoded =
+                Frame16::decode::<codec::Codec, _>(encoded.as_slice(), 37, 21, layout).unwrap();
+            assert_eq!(decoded, frame);
+
+            for options in [
+                EncodeOptions::default(),
+                EncodeOptions {
+                    tile_size: Some((16, 8)),
+                    ..Default::default()
+                },
+            ]
+            .iter()
+            {
+                let mut encoded = Vec::new();
+                frame
+                    .encode_with_header::<codec::Codec, _>(&mut encoded, options)
+                    .unwrap();
+                let decoded = Frame16::decode_with_header::<codec::Codec, _>(encoded.as_slice());
+                assert_eq!(decoded.unwrap(), frame);
+
+                // the streams are the same as RGB48Frame's
+                let mut expected = Vec::new();
+                RGB48Frame::from(frame.clone())
+                    .encode_with_header::<codec::Codec, _>(&mut expected, options)
+                    .unwrap();
+                assert_eq!(encoded, expected);
+            }
+
+            let quantization = vec![4; layout.channels()];
+            let mut encoded = Vec::new();
+            frame
+                .encode_with_header::<codec::Codec, _>(
+                    &mut encoded,
+                    &EncodeOptions {
+                        quantization: Some(quantization),
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+            let decoded =
+                Frame16::decode_with_header::<codec::Codec, _>(encoded.as_slice()).unwrap();
+            assert_eq!(decoded.layout, layout);
+            assert!(decoded
+                .data
+                .iter()
+                .zip(&frame.data)
+                .all(|(&a, &b)| (a as i32 - b as i32).abs() <= 4));
+        }
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_png() {
+        for &layout in [
+            ChannelLayout::Gray,
+            ChannelLayout::GrayAlpha,
+            ChannelLayout::Rgb,
+            ChannelLayout::Rgba,
+        ]
+        .iter()
+        {
+            let frame = test_frame(9, 7, layout);
+            let mut encoded = Vec::new();
+            frame.write_png(&mut encoded).unwrap();
+            let decoded = Frame16::read_png(encoded.as_slice()).unwrap();
+            assert_eq!(decoded, frame);
+        }
+
+        let ycbcr = test_frame(9, 7, ChannelLayout::Rgb)
+            .to_ycbcr(Matrix::Bt709)
+            .unwrap();
+        assert!(matches!(
+            ycbcr.write_png(Vec::new()),
+            Err(FrameSaveError::UnsupportedLayout(ChannelLayout::YCbCr))
+        ));
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_tiff() {
+        for &layout in [ChannelLayout::Gray, ChannelLayout::Rgb, ChannelLayout::Rgba].iter() {
+            let frame = test_frame(9, 7, layout);
+            let mut encoded = io::Cursor::new(Vec::new());
+            frame.write_tiff(&mut encoded).unwrap();
+            encoded.set_position(0);
+            let decoded =
+                Frame16::from_rgb48(RGB48Frame::from_tiff_reader(encoded).unwrap()).unwrap();
+            assert_eq!(decoded, frame);
+        }
+        assert!(matches!(
+            test_frame(9, 7, ChannelLayout::GrayAlpha).write_tiff(io::Cursor::new(Vec::new())),
+            Err(FrameSaveError::UnsupportedLayout(ChannelLayout::GrayAlpha))
+        ));
+    }
+
+    #[test]
+    fn test_ycbcr() {
+        let frame = test_frame(9, 7, ChannelLayout::Rgba);
+        let ycbcr = frame.to_ycbcr(Matrix::Bt2020).unwrap();
+        assert_eq!(ycbcr.layout, ChannelLayout::YCbCrA);
+        assert_eq!(
+            ycbcr.data,
+            RGB48Frame::from(frame.clone())
+                .to_ycbcr(Matrix::Bt2020)
+                .unwrap()
+                .data
+        );
+        let rgb = ycbcr.to_rgb(Matrix::Bt2020).unwrap();
+        assert_eq!(rgb.layout, ChannelLayout::Rgba);
+        assert!(rgb
+            .data
+            .iter()
+            .zip(&frame.data)
+            .all(|(&a, &b)| (a as i32 - b as i32).abs() <= 1));
+
+        assert_eq!(
+            test_frame(9, 7, ChannelLayout::GrayAlpha).to_ycbcr(Matrix::Bt709),
+            Err(FrameError::UnsupportedLayout(ChannelLayout::GrayAlpha))
+        );
+        assert_eq!(
+            frame.to_rgb(Matrix::Bt709),
+            Err(FrameError::UnsupportedLayout(ChannelLayout::Rgba))
+        );
+    }
+}
@@ -0,0 +1,648 @@
+#[cfg(feature = "png")]
+use super::png;
+use super::{
+    error::CodecError,
+    frame::{
+        self, open_image, raw_sample_count, read_raw_file, samples_from_bytes, samples_to_bytes,
+        Codec, EncodeOptions, Endianness, FrameError, FrameOpenError, FrameSaveError, ImageFormat,
+        Plane, RGB48Frame,
+    },
+    frame16::{ChannelLayout, Frame16},
+    pgm,
+};
+#[cfg(feature = "tiff")]
+use std::{convert::TryInto, io::Seek};
+use std::{
+    io::{self, Read, Write},
+    path::Path,
+};
+
+// Luma weights for GrayFrame16::from_rgb, from BT.709 (HD) and BT.601 (SD) respectively.
+pub const REC709_LUMA: [f64; 3] = [0.2126, 0.7152, 0.0722];
+pub const REC601_LUMA: [f64; 3] = [0.299, 0.587, 0.114];
+
+// REC709_LUMA in 16-bit fixed point, for GrayFrame16::from_frame16. The weights are rounded to
+// the nearest 1/65536 and sum to exactly 65536, so equal R, G, and B samples give back the same
+// luma.
+const REC709_LUMA_FIXED: [u64; 3] = [13933, 46871, 4732];
+
+// A single-plane frame, for monochrome material like depth maps or film scans. It's encoded
+// exactly like an RGB48Frame with one channel, so either type can decode the other's streams.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GrayFrame16 {
+    pub data: Vec<u16>,
+    pub width: usize,
+    pub height: usize,
+    // The bit depth of the samples the frame was loaded from, as for RGB48Frame.
+    pub bit_depth: u8,
+}
+
+impl GrayFrame16 {
+    // Builds a frame from width * height samples.
+    pub fn from_raw(data: Vec<u16>, width: usize, height: usize) -> Result<Self, FrameError> {
+        let expected = raw_sample_count(width, height, 1)?;
+        if data.len() != expected {
+            return Err(FrameError::LengthMismatch {
+                expected,
+                actual: data.len(),
+            });
+        }
+        Ok(Self {
+            data,
+            width,
+            height,
+            bit_depth: 16,
+        })
+    }
+
+    // Builds a frame from headerless 16-bit samples, like RGB48Frame::from_raw_bytes with one
+    // channel.
+    pub fn from_raw_bytes(
+        bytes: &[u8],
+        width: usize,
+        height: usize,
+        endianness: Endianness,
+    ) -> Result<Self, FrameError> {
+        let expected = raw_sample_count(width, height, 1)?;
+        Ok(Self {
+            data: samples_from_bytes(bytes, expected, endianness)?,
+            width,
+            height,
+            bit_depth: 16,
+        })
+    }
+
+    pub fn from_raw_file<P: AsRef<Path>>(
+        path: P,
+        width: usize,
+        height: usize,
+        endianness: Endianness,
+    ) -> Result<Self, FrameOpenError> {
+        let expected = raw_sample_count(width, height, 1)?;
+        Ok(Self {
+            data: read_raw_file(path, expected, endianness)?,
+            width,
+            height,
+            bit_depth: 16,
+        })
+    }
+
+    pub fn to_raw_bytes(&self, endianness: Endianness) -> Vec<u8> {
+        samples_to_bytes(&self.data, endianness)
+    }
+
+    pub fn write_raw_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        endianness: Endianness,
+    ) -> Result<(), FrameSaveError> {
+        std::fs::write(path, self.to_raw_bytes(endianness))?;
+        Ok(())
+    }
+
+    // Loads a grayscale TIFF or PNG, or a PGM, telling which it is from its first few bytes. 8-bit
+    // samples are widened to 16 bits by multiplying them by 257.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, FrameOpenError> {
+        match open_image(path)? {
+            #[cfg(feature = "tiff")]
+            (f, ImageFormat::Tiff) => Self::read_tiff(f),
+            #[cfg(feature = "png")]
+            (f, ImageFormat::Png) => Self::read_png(io::BufReader::new(f)),
+            (_, ImageFormat::Dpx) => Err(FrameOpenError::UnsupportedDpx(
+                "only RGB frames can be loaded from dpx files".to_string(),
+            )),
+            (f, ImageFormat::Pgm) => {
+                let frame = pgm::read(io::BufReader::new(f))?;
+                Ok(Self {
+                    data: frame.data,
+                    width: frame.width,
+                    height: frame.height,
+                    bit_depth: frame.bit_depth,
+                })
+            }
+        }
+    }
+
+    // Like open, but also accepts color files, converting them to Rec. 709 luma with
+    // from_frame16. Gray files load exactly as they do with open, which remains the way to be sure
+    // nothing was converted.
+    pub fn open_luma<P: AsRef<Path>>(path: P) -> Result<Self, FrameOpenError> {
+        let path = path.as_ref();
+        match Self::open(path) {
+            #[cfg(feature = "tiff")]
+            Err(FrameOpenError::UnsupportedColorType(_)) => {}
+            Err(FrameOpenError::UnsupportedDpx(_)) => {}
+            #[cfg(feature = "png")]
+            Err(FrameOpenError::UnsupportedPngColorType(_)) => {}
+            result => return result,
+        }
+        Ok(Self::from_frame16(&Frame16::open(path)?))
+    }
+
+    #[cfg(feature = "tiff")]
+    pub fn from_tiff<P: AsRef<Path>>(path: P) -> Result<Self, FrameOpenError> {
+        Self::read_tiff(std::fs::File::open(path)?)
+    }
+
+    #[cfg(feature = "tiff")]
+    fn read_tiff<R: Read + Seek>(source: R) -> Result<Self, FrameOpenError> {
+        let mut dec =
+            tiff::decoder::Decoder::new(source)?.with_limits(tiff::decoder::Limits::unlimited());
+        let (width, height) = dec.dimensions()?;
+        let (data, bit_depth) = match dec.colortype()? {
+            tiff::ColorType::Gray(16) => match dec.read_image()? {
+                tiff::decoder::DecodingResult::U16(data) => (data, 16),
+                _ => return Err(FrameOpenError::UnsupportedSampleType),
+            },
+            tiff::ColorType::Gray(8) => match dec.read_image()? {
+                tiff::decoder::DecodingResult::U8(data) => {
+                    (data.into_iter().map(|x| x as u16 * 257).collect(), 8)
+                }
+                _ => return Err(FrameOpenError::UnsupportedSampleType),
+            },
+            color_type => return Err(FrameOpenError::UnsupportedColorType(color_type)),
+        };
+        Ok(Self {
+            data,
+            width: width as _,
+            height: height as _,
+            bit_depth,
+        })
+    }
+
+    #[cfg(feature = "png")]
+    pub fn from_png<P: AsRef<Path>>(path: P) -> Result<Self, FrameOpenError> {
+        Self::read_png(io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    #[cfg(feature = "png")]
+    fn read_png<R: Read>(source: R) -> Result<Self, FrameOpenError> {
+        let image = png::decode(source)?;
+        if image.color_type != png::ColorType::Gray {
+            return Err(FrameOpenError::UnsupportedPngColorType(image.color_type));
+        }
+        let data: Vec<u16> = match image.bit_depth {
+            16 => image
+                .data
+                .chunks_exact(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .collect(),
+            8 => image.data.iter().map(|&x| x as u16 * 257).collect(),
+            bit_depth => {
+                return Err(png::PngError::Unsupported(format!(
+                    "{}-bit grayscale images",
+                    bit_depth
+                ))
+                .into())
+            }
+        };
+        Ok(Self {
+            data,
+            width: image.width,
+            height: image.height,
+            bit_depth: image.bit_depth,
+        })
+    }
+
+    // Saves the frame as an uncompressed 16-bit grayscale TIFF.
+    #[cfg(feature = "tiff")]
+    pub fn to_tiff<P: AsRef<Path>>(&self, path: P) -> Result<(), FrameSaveError> {
+        let mut dest = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_tiff(&mut dest)?;
+        dest.flush()?;
+        Ok(())
+    }
+
+    #[cfg(feature = "tiff")]
+    pub fn write_tiff<W: Write + Seek>(&self, dest: W) -> Result<(), FrameSaveError> {
+        let (width, height) = match (self.width.try_into(), self.height.try_into()) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the frame is too large for a tiff",
+                )
+                .into())
+            }
+        };
+        tiff::encoder::TiffEncoder::new(dest)?
+            .write_image::<tiff::encoder::colortype::Gray16>(width, height, &self.data)?;
+        Ok(())
+    }
+
+    // Saves the frame as a 16-bit grayscale PNG.
+    #[cfg(feature = "png")]
+    pub fn to_png<P: AsRef<Path>>(&self, path: P) -> Result<(), FrameSaveError> {
+        let mut dest = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_png(&mut dest)?;
+        dest.flush()?;
+        Ok(())
+    }
+
+    #[cfg(feature = "png")]
+    pub fn write_png<W: Write>(&self, dest: W) -> Result<(), FrameSaveError> {
+        self.plane().write_png(dest)
+    }
+
+    // Saves the frame as a 16-bit binary PGM.
+    pub fn to_pgm<P: AsRef<Path>>(&self, path: P) -> Result<(), FrameSaveError> {
+        let mut dest = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_pgm(&mut dest)?;
+        dest.flush()?;
+        Ok(())
+    }
+
+    pub fn write_pgm<W: Write>(&self, dest: W) -> Result<(), FrameSaveError> {
+        self.plane().write_pgm(dest)
+    }
+
+    // Computes each pixel's luma as the weighted sum of its R, G, and B samples, rounded and
+    // clamped to the 16-bit range. Alpha is ignored, and single-channel frames are copied as-is.
+    pub fn from_rgb(frame: &RGB48Frame, weights: [f64; 3]) -> Result<Self, FrameError> {
+        let channels = frame.channels();
+        let data = match channels {
+            1 => frame.data.clone(),
+            3 | 4 => frame
+                .data
+                .chunks_exact(channels)
+                .map(|pixel| {
+                    let luma = weights[0] * pixel[0] as f64
+                        + weights[1] * pixel[1] as f64
+                        + weights[2] * pixel[2] as f64;
+                    luma.round().clamp(0.0, 65535.0) as u16
+                })
+                .collect(),
+            _ => return Err(FrameError::InvalidChannelCount(channels)),
+        };
+        Ok(Self {
+            data,
+            width: frame.width,
+            height: frame.height,
+            bit_depth: frame.bit_depth,
+        })
+    }
+
+    // Takes a frame's luma: for RGB, the Rec. 709 weighted sum of each pixel's samples in 16-bit
+    // fixed point, rounded half up, which is exact to within one code value of from_rgb with
+    // REC709_LUMA and needs no floating point. Gray and Y'CbCr frames already have a luma channel,
+    // which is copied. Alpha is ignored.
+    pub fn from_frame16(frame: &Frame16) -> Self {
+        let channels = frame.channels();
+        let data = match frame.layout {
+            ChannelLayout::Rgb | ChannelLayout::Rgba => frame
+                .data
+                .chunks_exact(channels)
+                .map(|pixel| {
+                    let sum: u64 = REC709_LUMA_FIXED
+                        .iter()
+                        .zip(pixel)
+                        .map(|(&w, &x)| w * x as u64)
+                        .sum();
+                    ((sum + 0x8000) >> 16) as u16
+                })
+                .collect(),
+            ChannelLayout::Gray
+            | ChannelLayout::GrayAlpha
+            | ChannelLayout::YCbCr
+            | ChannelLayout::YCbCrA => frame.data.iter().step_by(channels).copied().collect(),
+        };
+        Self {
+            data,
+            width: frame.width,
+            height: frame.height,
+            bit_depth: frame.bit_depth,
+        }
+    }
+
+    // Converts the frame to RGB by copying each sample into all three channels.
+    pub fn to_rgb48(&self) -> RGB48Frame {
+        RGB48Frame {
+            data: self.data.iter().flat_map(|&x| [x, x, x]).collect(),
+            width: self.width,
+            height: self.height,
+            bit_depth: self.bit_depth,
+        }
+    }
+
+    pub fn plane(&self) -> Plane<&[u16]> {
+        Plane {
+            data: &self.data,
+            width: self.width,
+            height: self.height,
+            sample_stride: 1,
+            row_stride: self.width,
+        }
+    }
+
+    pub fn plane_mut(&mut self) -> Plane<&mut [u16]> {
+        Plane {
+            data: &mut self.data,
+            width: self.width,
+            height: self.height,
+            sample_stride: 1,
+            row_stride: self.width,
+        }
+    }
+
+    // Turns the frame into its plane, without copying, for use with Codec or other Plane functions.
+    pub fn into_plane(self) -> Plane<Vec<u16>> {
+        let (width, height) = (self.width, self.height);
+        Plane {
+            data: self.data,
+            width,
+            height,
+            sample_stride: 1,
+            row_stride: width,
+        }
+    }
+
+    // For symmetry with RGB48Frame::planes. There's always exactly one.
+    pub fn planes(&self) -> impl ExactSizeIterator<Item = Plane<&[u16]>> {
+        std::iter::once(self.plane())
+    }
+
+    pub fn encode<C: Codec, W: Write>(&self, dest: W) -> Result<(), CodecError> {
+        C::encode(&self.plane(), dest)
+    }
+
+    // Decodes a frame written by encode. Frames larger than the default Limits are rejected.
+    pub fn decode<C: Codec, R: Read>(
+        source: R,
+        width: usize,
+        height: usize,
+    ) -> Result<Self, CodecError> {
+        let len = raw_sample_count(width, height, 1)
+            .map_err(|e| CodecError::InvalidInput(e.to_string()))?;
+        frame::Limits::default().check(len)?;
+        let mut ret = Self::from_raw(vec![0; len], width, height)
+            .map_err(|e| CodecError::InvalidInput(e.to_string()))?;
+        C::decode(source, &mut ret.plane_mut())?;
+        Ok(ret)
+    }
+
+    // Encodes the frame preceded by a header, the same way RGB48Frame::encode_with_header encodes
+    // a single-channel frame.
+    pub fn encode_with_header<C: Codec, W: Write>(
+        &self,
+        dest: W,
+        options: &EncodeOptions,
+    ) -> Result<(), CodecError> {
+        frame::encode_samples::<C, _>(
+            &self.data,
+            (self.width, self.height),
+            self.bit_depth,
+            dest,
+            options,
+            &mut |_| false,
+        )?;
+        Ok(())
+    }
+
+    pub fn decode_with_header<C: Codec, R: Read>(mut source: R) -> Result<Self, CodecError> {
+        let header = RGB48Frame::read_header(&mut source)?;
+        if header.n_planes != 1 {
+            return Err(CodecError::InvalidHeader(format!(
+                "{} planes don't fit in a GrayFrame16",
+                header.n_planes
+            )));
+        }
+        let frame = RGB48Frame::decode_after_header::<C, _>(
+            &header,
+            source,
+            &Default::default(),
+            &mut |_| false,
+        )?;
+        Ok(Self {
+            data: frame.data,
+            width: frame.width,
+            height: frame.height,
+            bit_depth: frame.bit_depth,
+        })
+    }
+}
+
+// The tests load their frames from TIFFs.
+#[cfg(all(test, feature = "tiff"))]
+mod tests {
+    use super::{super::codec, *};
+
+    fn test_frame() -> GrayFrame16 {
+        GrayFrame16::open("src/testdata/gray16.tif").unwrap()
+    }
+
+    #[test]
+    fn test_gray_roundtrip() {
+        let frame = test_frame();
+        assert_eq!((frame.width, frame.height, frame.bit_depth), (72, 40, 16));
+        assert_eq!(frame.planes().len(), 1);
+
+        for options in [
+            EncodeOptions::default(),
+            EncodeOptions {
+                tile_size: Some((32, 16)),
+                quantization: Some(vec![4]),
+                ..Default::default()
+            },
+        ]
+        .iter()
+        {
+            let mut encoded = Vec::new();
+            frame
+                .encode_with_header::<codec::Codec, _>(&mut encoded, options)
+                .unwrap();
+
+            // the stream is the same as for a single-channel RGB48Frame, and each type can
+            // decode it
+            let rgb = RGB48Frame::open("src/testdata/gray16.tif").unwrap();
+            assert_eq!(rgb.channels(), 1);
+            let mut rgb_encoded = Vec::new();
+            rgb.encode_with_header::<codec::Codec, _>(&mut rgb_encoded, options)
+                .unwrap();
+            assert_eq!(encoded, rgb_encoded);
+
+            let decoded = GrayFrame16::decode_with_header::<codec::Codec, _>(&*encoded).unwrap();
+            let rgb_decoded = RGB48Frame::decode_with_header::<codec::Codec, _>(&*encoded).unwrap();
+            assert_eq!(decoded.data, rgb_decoded.data);
+            if options.quantization.is_none() {
+                assert_eq!(decoded, frame);
+            }
+        }
+
+        let mut encoded = Vec::new();
+        frame.encode::<codec::Codec, _>(&mut encoded).unwrap();
+        let decoded = GrayFrame16::decode::<codec::Codec, _>(&*encoded, 72, 40).unwrap();
+        assert_eq!(decoded, frame);
+
+        // dimensions that can't be counted, or are over the limit
+        let err = GrayFrame16::decode::<codec::Codec, _>(&*encoded, usize::MAX / 2, 4).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidInput(_)), "{:?}", err);
+        let err = GrayFrame16::decode::<codec::Codec, _>(&*encoded, 65536, 65536).unwrap_err();
+        assert!(matches!(err, CodecError::LimitExceeded { .. }), "{:?}", err);
+
+        // multi-plane streams aren't gray
+        let mut encoded = Vec::new();
+        frame
+            .to_rgb48()
+            .encode_with_header::<codec::Codec, _>(&mut encoded, &Default::default())
+            .unwrap();
+        let err = GrayFrame16::decode_with_header::<codec::Codec, _>(&*encoded).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidHeader(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_gray_conversions() {
+        let frame = test_frame();
+        let rgb = frame.to_rgb48();
+        assert_eq!(rgb.channels(), 3);
+        assert_eq!(rgb.data[..3], [frame.data[0]; 3]);
+        assert_eq!(
+            rgb.data[rgb.data.len() - 3..],
+            [*frame.data.last().unwrap(); 3]
+        );
+        assert_eq!(GrayFrame16::from_rgb(&rgb, REC709_LUMA).unwrap(), frame);
+        assert_eq!(GrayFrame16::from_rgb(&rgb, REC601_LUMA).unwrap(), frame);
+
+        let rgb =
+            RGB48Frame::from_raw(vec![1000, 2000, 3000, 65535, 65535, 65535], 2, 1, 3).unwrap();
+        let gray = GrayFrame16::from_rgb(&rgb, REC709_LUMA).unwrap();
+        assert_eq!(gray.data, vec![1860, 65535]);
+        let gray = GrayFrame16::from_rgb(&rgb, [1.0, 0.0, 0.0]).unwrap();
+        assert_eq!(gray.data, vec![1000, 65535]);
+        let gray = GrayFrame16::from_rgb(&rgb, [1.0, 1.0, 1.0]).unwrap();
+        assert_eq!(gray.data, vec![6000, 65535]);
+
+        // the fixed-point conversion gives back gray as-is, and pure red is its weight
+        let rgb = Frame16::from_rgb48(frame.to_rgb48()).unwrap();
+        assert_eq!(GrayFrame16::from_frame16(&rgb), frame);
+        let red = Frame16::from_raw(
+            vec![65535, 0, 0, 1000, 2000, 3000],
+            2,
+            1,
+            ChannelLayout::Rgb,
+        )
+        .unwrap();
+        assert_eq!(GrayFrame16::from_frame16(&red).data, vec![13933, 1860]);
+        let gray_alpha =
+            Frame16::from_raw(vec![7, 65535, 9, 0], 2, 1, ChannelLayout::GrayAlpha).unwrap();
+        assert_eq!(GrayFrame16::from_frame16(&gray_alpha).data, vec![7, 9]);
+
+        let two_channels = RGB48Frame::from_raw(vec![0; 4], 2, 1, 2).unwrap();
+        assert_eq!(
+            GrayFrame16::from_rgb(&two_channels, REC709_LUMA).unwrap_err(),
+            FrameError::InvalidChannelCount(2)
+        );
+    }
+
+    #[test]
+    fn test_gray_files() {
+        let frame = test_frame();
+        let mut tiff = io::Cursor::new(Vec::new());
+        frame.write_tiff(&mut tiff).unwrap();
+        tiff.set_position(0);
+        assert_eq!(GrayFrame16::read_tiff(tiff).unwrap(), frame);
+
+        #[cfg(feature = "png")]
+        {
+            let mut png = Vec::new();
+            frame.write_png(&mut png).unwrap();
+            assert_eq!(GrayFrame16::read_png(&*png).unwrap(), frame);
+        }
+
+        for &endianness in [Endianness::Little, Endianness::Big].iter() {
+            let bytes = frame.to_raw_bytes(endianness);
+            assert_eq!(
+                GrayFrame16::from_raw_bytes(&bytes, 72, 40, endianness).unwrap(),
+                frame
+            );
+        }
+
+        // 16-bit PNGs load as-is, and 8-bit ones are widened
+        #[cfg(feature = "png")]
+        {
+            let samples = [0u16, 1, 255, 256, 4095, 32768, 65534, 65535];
+            let mut png = Vec::new();
+            png::encode(&mut png, 4, 2, png::ColorType::Gray, 16, |row, dest| {
+                for (bytes, sample) in dest.chunks_exact_mut(2).zip(&samples[row * 4..]) {
+                    bytes.copy_from_slice(&sample.to_be_bytes());
+                }
+            })
+            .unwrap();
+            let loaded = GrayFrame16::read_png(&*png).unwrap();
+            assert_eq!((loaded.width, loaded.height, loaded.bit_depth), (4, 2, 16));
+            assert_eq!(loaded.data, samples);
+
+            // and the plane goes straight through the codec
+            let plane = loaded.into_plane();
+            let mut encoded = Vec::new();
+            codec::Codec::encode(&plane, &mut encoded).unwrap();
+            let mut decoded = GrayFrame16::from_raw(vec![0; 8], 4, 2)
+                .unwrap()
+                .into_plane();
+            codec::Codec::decode(&*encoded, &mut decoded).unwrap();
+            assert_eq!(decoded.data, samples);
+
+            let mut png = Vec::new();
+            png::encode(&mut png, 2, 1, png::ColorType::Gray, 8, |_, dest| {
+                dest.copy_from_slice(&[1, 255])
+            })
+            .unwrap();
+            let loaded = GrayFrame16::read_png(&*png).unwrap();
+            assert_eq!((loaded.data, loaded.bit_depth), (vec![257, 65535], 8));
+        }
+
+        // PGMs and PNGs written from planes load back the same, even when the plane's samples
+        // are interleaved with others
+        let path = std::env::temp_dir().join(format!("hvc-gray-pgm-{}.pgm", std::process::id()));
+        frame.to_pgm(&path).unwrap();
+        let loaded = GrayFrame16::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), frame);
+        let rgb = RGB48Frame::from_raw(
+            frame.data.iter().flat_map(|&x| [x, !x, x / 2]).collect(),
+            72,
+            40,
+            3,
+        )
+        .unwrap();
+        let green = rgb.plane(1);
+        let mut pgm = Vec::new();
+        green.write_pgm(&mut pgm).unwrap();
+        let expected: Vec<u16> = frame.data.iter().map(|&x| !x).collect();
+        assert_eq!(RGB48Frame::from_bytes(&pgm).unwrap().data, expected);
+        #[cfg(feature = "png")]
+        {
+            let mut png = Vec::new();
+            green.write_png(&mut png).unwrap();
+            assert_eq!(GrayFrame16::read_png(&*png).unwrap().data, expected);
+        }
+
+        // color files aren't gray
+        match GrayFrame16::open("src/testdata/two_pages.tif") {
+            Err(FrameOpenError::UnsupportedColorType(tiff::ColorType::RGB(16))) => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+
+        // but they can be converted to luma, and gray content loads the same either way
+        assert_eq!(
+            GrayFrame16::open_luma("src/testdata/gray16.tif").unwrap(),
+            frame
+        );
+        let path = std::env::temp_dir().join(format!("hvc-gray-luma-{}.tif", std::process::id()));
+        frame.to_rgb48().to_tiff(&path).unwrap();
+        let loaded = GrayFrame16::open_luma(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), frame);
+        let luma = GrayFrame16::open_luma("src/testdata/two_pages.tif").unwrap();
+        let rgb = Frame16::open("src/testdata/two_pages.tif").unwrap();
+        assert_eq!((luma.width, luma.height), (rgb.width, rgb.height));
+        assert_eq!(luma, GrayFrame16::from_frame16(&rgb));
+
+        assert_eq!(
+            GrayFrame16::from_raw(vec![0; 5], 2, 3).unwrap_err(),
+            FrameError::LengthMismatch {
+                expected: 6,
+                actual: 5
+            }
+        );
+    }
+}
@@ -0,0 +1,577 @@
+use super::{
+    codec::MAX_FIXED_K,
+    color::ColorTransform,
+    error::{CodecError, Result},
+    frame::PlaneCoding,
+};
+use std::io::{Read, Write};
+
+pub const MAGIC: [u8; 4] = *b"HVCF";
+pub const VERSION: u8 = 1;
+// Streams that use any of the extensions below are written as this version instead, which adds an
+// extensions byte after the bit depth. Everything else is still written as version 1, so it stays
+// readable by decoders that predate the extensions.
+pub const EXTENDED_VERSION: u8 = 2;
+
+// The header written by RGB48Frame::encode_with_header, which makes the stream self-describing:
+//
+//   magic         4 bytes  "HVCF"
+//   version       1 byte
+//   width         4 bytes  big-endian
+//   height        4 bytes  big-endian
+//   flags         1 byte   bits 7-6: number of planes - 1
+//                          bit 5: quantized
+//                          bit 4: 32-bit samples
+//                          bit 3: hierarchical
+//                          bit 2: tiled
+//                          bits 1-0: color transform
+//   bit depth     1 byte   significant bits per sample, at most 16 unless samples are 32-bit
+//   extensions    1 byte   version 2 only, bit 1: fixed k
+//                                         bit 0: banded
+//
+// If the tiled flag is set, the tile width and height follow, 4 bytes each, big-endian. Then if the
+// quantized flag is set, each plane's quantization step follows, 2 bytes each, big-endian. Then if
+// the hierarchical flag is set, the preview factor follows, 1 byte. Then if the banded flag is set,
+// the band height follows, 4 bytes, big-endian. Then if the fixed k flag is set, each plane's k
+// follows, 1 byte each.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameHeader {
+    pub width: usize,
+    pub height: usize,
+    pub n_planes: usize,
+    pub bit_depth: u8,
+    // The width of the samples in bits, either 16 or 32.
+    pub sample_width: u8,
+    pub color_transform: ColorTransform,
+    pub tile_size: Option<(usize, usize)>,
+    // The quantization step for each plane. None is equivalent to a step of 1 (lossless) for every
+    // plane.
+    pub quantization: Option<Vec<u16>>,
+    // If given, the planes are coded hierarchically, with a base layer downsampled by this factor
+    // (see the pyramid module).
+    pub preview_factor: Option<usize>,
+    // If given, the planes are interleaved in bands of this many rows (see the band module).
+    pub band_height: Option<usize>,
+    // The Rice parameter every sample of each plane is coded with. None means each sample's is
+    // adapted to its neighbors (see codec::KMode).
+    pub fixed_k: Option<Vec<u8>>,
+}
+
+fn invalid_header<S: Into<String>>(message: S) -> CodecError {
+    CodecError::InvalidHeader(message.into())
+}
+
+impl FrameHeader {
+    // The number of bytes that write produces.
+    pub fn encoded_len(&self) -> usize {
+        15 + self.tile_size.map_or(0, |_| 8)
+            + self.quantization.as_ref().map_or(0, |q| 2 * q.len())
+            + self.preview_factor.map_or(0, |_| 1)
+            + if self.is_extended() { 1 } else { 0 }
+            + self.band_height.map_or(0, |_| 4)
+            + self.fixed_k.as_ref().map_or(0, |k| k.len())
+    }
+
+    // Whether the header needs EXTENDED_VERSION to describe it.
+    fn is_extended(&self) -> bool {
+        self.band_height.is_some() || self.fixed_k.is_some()
+    }
+
+    // How the given plane is coded.
+    pub fn plane_coding(&self, plane: usize) -> PlaneCoding {
+        PlaneCoding {
+            q: self.quantization.as_ref().map_or(1, |q| q[plane]),
+            k: self.fixed_k.as_ref().map(|k| k[plane]),
+        }
+    }
+
+    pub fn write<W: Write>(&self, mut dest: W) -> Result<()> {
+        if !(1..=4).contains(&self.n_planes) {
+            return Err(CodecError::InvalidInput(format!(
+                "unsupported number of planes: {}",
+                self.n_planes
+            )));
+        }
+        if (self.sample_width != 16 && self.sample_width != 32)
+            || !(1..=self.sample_width).contains(&self.bit_depth)
+        {
+            return Err(CodecError::InvalidInput(format!(
+                "invalid bit depth for {}-bit samples: {}",
+                self.sample_width, self.bit_depth
+            )));
+        }
+        if self.width > u32::MAX as usize || self.height > u32::MAX as usize {
+            return Err(CodecError::InvalidInput(
+                "frame dimensions are too large".to_string(),
+            ));
+        }
+        if let Some((tile_width, tile_height)) = self.tile_size {
+            if tile_width == 0
+                || tile_height == 0
+                || tile_width > u32::MAX as usize
+                || tile_height > u32::MAX as usize
+            {
+                return Err(CodecError::InvalidInput(format!(
+                    "invalid tile size: {}x{}",
+                    tile_width, tile_height
+                )));
+            }
+        }
+
+        if let Some(quantization) = &self.quantization {
+            if quantization.len() != self.n_planes || quantization.contains(&0) {
+                return Err(CodecError::InvalidInput(format!(
+                    "invalid quantization for {} planes: {:?}",
+                    self.n_planes, quantization
+                )));
+            }
+        }
+
+        if let Some(factor) = self.preview_factor {
+            if !(2..=255).contains(&factor) {
+                return Err(CodecError::InvalidInput(format!(
+                    "invalid preview factor: {}",
+                    factor
+                )));
+            }
+        }
+
+        if let Some(band_height) = self.band_height {
+            if band_height == 0 || band_height > u32::MAX as usize {
+                return Err(CodecError::InvalidInput(format!(
+                    "invalid band height: {}",
+                    band_height
+                )));
+            }
+        }
+
+        if let Some(fixed_k) = &self.fixed_k {
+            if fixed_k.len() != self.n_planes || fixed_k.iter().any(|&k| k > MAX_FIXED_K) {
+                return Err(CodecError::InvalidInput(format!(
+                    "invalid fixed k for {} planes: {:?}",
+                    self.n_planes, fixed_k
+                )));
+            }
+        }
+
+        dest.write_all(&MAGIC)?;
+        dest.write_all(&[if self.is_extended() {
+            EXTENDED_VERSION
+        } else {
+            VERSION
+        }])?;
+        dest.write_all(&(self.width as u32).to_be_bytes())?;
+        dest.write_all(&(self.height as u32).to_be_bytes())?;
+        let tiled = if self.tile_size.is_some() { 0x04 } else { 0 };
+        let quantized = if self.quantization.is_some() { 0x20 } else { 0 };
+        let wide = if self.sample_width == 32 { 0x10 } else { 0 };
+        let hierarchical = if self.preview_factor.is_some() {
+            0x08
+        } else {
+            0
+        };
+        dest.write_all(&[
+            ((self.n_planes as u8 - 1) << 6)
+                | quantized
+                | wide
+                | hierarchical
+                | tiled
+                | self.color_transform as u8,
+            self.bit_depth,
+        ])?;
+        if self.is_extended() {
+            let fixed_k = if self.fixed_k.is_some() { 0x02 } else { 0 };
+            let banded = if self.band_height.is_some() { 0x01 } else { 0 };
+            dest.write_all(&[fixed_k | banded])?;
+        }
+        if let Some((tile_width, tile_height)) = self.tile_size {
+            dest.write_all(&(tile_width as u32).to_be_bytes())?;
+            dest.write_all(&(tile_height as u32).to_be_bytes())?;
+        }
+        if let Some(quantization) = &self.quantization {
+            for q in quantization {
+                dest.write_all(&q.to_be_bytes())?;
+            }
+        }
+        if let Some(factor) = self.preview_factor {
+            dest.write_all(&[factor as u8])?;
+        }
+        if let Some(band_height) = self.band_height {
+            dest.write_all(&(band_height as u32).to_be_bytes())?;
+        }
+        if let Some(fixed_k) = &self.fixed_k {
+            dest.write_all(fixed_k)?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut source: R) -> Result<Self> {
+        let mut buf = [0; 15];
+        source.read_exact(&mut buf)?;
+
+        if buf[..4] != MAGIC {
+            return Err(invalid_header("not a frame header"));
+        }
+        if buf[4] != VERSION && buf[4] != EXTENDED_VERSION {
+            return Err(CodecError::UnsupportedVersion(buf[4]));
+        }
+        let width = u32::from_be_bytes([buf[5], buf[6], buf[7], buf[8]]) as usize;
+        let height = u32::from_be_bytes([buf[9], buf[10], buf[11], buf[12]]) as usize;
+        let flags = buf[13];
+        let color_transform = ColorTransform::from_u8(flags & 0x03)
+            .ok_or_else(|| invalid_header(format!("invalid color transform: {}", flags & 0x03)))?;
+        let sample_width = if flags & 0x10 != 0 { 32 } else { 16 };
+        let bit_depth = buf[14];
+        if !(1..=sample_width).contains(&bit_depth) {
+            return Err(invalid_header(format!("invalid bit depth: {}", bit_depth)));
+        }
+        let extensions = if buf[4] == EXTENDED_VERSION {
+            let mut extensions = [0];
+            source.read_exact(&mut extensions)?;
+            if extensions[0] & !0x03 != 0 {
+                return Err(invalid_header(format!(
+                    "unknown extensions: {:#04x}",
+                    extensions[0]
+                )));
+            }
+            extensions[0]
+        } else {
+            0
+        };
+
+        let tile_size = if flags & 0x04 != 0 {
+            let mut buf = [0; 8];
+            source.read_exact(&mut buf)?;
+            let tile_width = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+            let tile_height = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize;
+            if tile_width == 0 || tile_height == 0 {
+                return Err(invalid_header(format!(
+                    "invalid tile size: {}x{}",
+                    tile_width, tile_height
+                )));
+            }
+            Some((tile_width, tile_height))
+        } else {
+            None
+        };
+
+        let n_planes = (flags >> 6) as usize + 1;
+        let quantization = if flags & 0x20 != 0 {
+            let mut quantization = Vec::with_capacity(n_planes);
+            for i in 0..n_planes {
+                let mut buf = [0; 2];
+                source.read_exact(&mut buf)?;
+                let q = u16::from_be_bytes(buf);
+                if q == 0 {
+                    return Err(invalid_header(format!(
+                        "invalid quantization step for plane {}: 0",
+                        i
+                    )));
+                }
+                quantization.push(q);
+            }
+            Some(quantization)
+        } else {
+            None
+        };
+
+        let preview_factor = if flags & 0x08 != 0 {
+            let mut factor = [0];
+            source.read_exact(&mut factor)?;
+            if factor[0] < 2 {
+                return Err(invalid_header(format!(
+                    "invalid preview factor: {}",
+                    factor[0]
+                )));
+            }
+            Some(factor[0] as usize)
+        } else {
+            None
+        };
+
+        let band_height = if extensions & 0x01 != 0 {
+            let mut buf = [0; 4];
+            source.read_exact(&mut buf)?;
+            let band_height = u32::from_be_bytes(buf) as usize;
+            if band_height == 0 {
+                return Err(invalid_header("invalid band height: 0"));
+            }
+            Some(band_height)
+        } else {
+            None
+        };
+
+        let fixed_k = if extensions & 0x02 != 0 {
+            let mut fixed_k = vec![0; n_planes];
+            source.read_exact(&mut fixed_k)?;
+            if let Some(&k) = fixed_k.iter().find(|&&k| k > MAX_FIXED_K) {
+                return Err(invalid_header(format!("invalid fixed k: {}", k)));
+            }
+            Some(fixed_k)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            width,
+            height,
+            n_planes,
+            bit_depth,
+            sample_width,
+            color_transform,
+            tile_size,
+            quantization,
+            preview_factor,
+            band_height,
+            fixed_k,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_roundtrip() {
+        let header = FrameHeader {
+            width: 4096,
+            height: 1714,
+            n_planes: 3,
+            bit_depth: 16,
+            sample_width: 16,
+            color_transform: ColorTransform::YCoCgR,
+            tile_size: None,
+            quantization: None,
+            preview_factor: None,
+            band_height: None,
+            fixed_k: None,
+        };
+        let mut buf = Vec::new();
+        header.write(&mut buf).unwrap();
+        assert_eq!(buf.len(), 15);
+        assert_eq!(header.encoded_len(), 15);
+        assert_eq!(FrameHeader::read(&*buf).unwrap(), header);
+
+        let header = FrameHeader {
+            tile_size: Some((256, 128)),
+            ..header
+        };
+        let mut buf = Vec::new();
+        header.write(&mut buf).unwrap();
+        assert_eq!(buf.len(), 23);
+        assert_eq!(header.encoded_len(), 23);
+        assert_eq!(FrameHeader::read(&*buf).unwrap(), header);
+
+        let header = FrameHeader {
+            quantization: Some(vec![4, 16, 16]),
+            ..header
+        };
+        let mut buf = Vec::new();
+        header.write(&mut buf).unwrap();
+        assert_eq!(buf.len(), 29);
+        assert_eq!(header.encoded_len(), 29);
+        assert_eq!(FrameHeader::read(&*buf).unwrap(), header);
+
+        // a zero step is rejected in both directions
+        buf[28] = 0;
+        buf[27] = 0;
+        let err = FrameHeader::read(&*buf).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidHeader(_)), "{:?}", err);
+        let header = FrameHeader {
+            quantization: Some(vec![4, 0, 16]),
+            ..header
+        };
+        let err = header.write(&mut Vec::new()).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidInput(_)), "{:?}", err);
+
+        let header = FrameHeader {
+            tile_size: None,
+            quantization: None,
+            preview_factor: Some(8),
+            ..header
+        };
+        let mut buf = Vec::new();
+        header.write(&mut buf).unwrap();
+        assert_eq!(buf.len(), 16);
+        assert_eq!(header.encoded_len(), 16);
+        assert_eq!(buf[13] & 0x08, 0x08);
+        assert_eq!(FrameHeader::read(&*buf).unwrap(), header);
+
+        // factors below 2 are rejected in both directions, and so are factors above 255 since they
+        // don't fit in a byte
+        buf[15] = 1;
+        let err = FrameHeader::read(&*buf).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidHeader(_)), "{:?}", err);
+        for &factor in [0, 1, 256].iter() {
+            let header = FrameHeader {
+                preview_factor: Some(factor),
+                ..header.clone()
+            };
+            let err = header.write(&mut Vec::new()).unwrap_err();
+            assert!(matches!(err, CodecError::InvalidInput(_)), "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn test_header_sample_width() {
+        let header = FrameHeader {
+            width: 64,
+            height: 48,
+            n_planes: 1,
+            bit_depth: 20,
+            sample_width: 32,
+            color_transform: ColorTransform::None,
+            tile_size: None,
+            quantization: None,
+            preview_factor: None,
+            band_height: None,
+            fixed_k: None,
+        };
+        let mut buf = Vec::new();
+        header.write(&mut buf).unwrap();
+        assert_eq!(buf[13], 0x10);
+        assert_eq!(FrameHeader::read(&*buf).unwrap(), header);
+
+        // more than 16 bits need 32-bit samples
+        buf[13] = 0;
+        let err = FrameHeader::read(&*buf).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidHeader(_)), "{:?}", err);
+        for &(bit_depth, sample_width) in [(20, 16), (33, 32), (8, 24)].iter() {
+            let header = FrameHeader {
+                bit_depth,
+                sample_width,
+                ..header.clone()
+            };
+            let err = header.write(&mut Vec::new()).unwrap_err();
+            assert!(matches!(err, CodecError::InvalidInput(_)), "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let header = FrameHeader {
+            width: 1,
+            height: 1,
+            n_planes: 4,
+            bit_depth: 16,
+            sample_width: 16,
+            color_transform: ColorTransform::None,
+            tile_size: None,
+            quantization: None,
+            preview_factor: None,
+            band_height: None,
+            fixed_k: None,
+        };
+        let mut buf = Vec::new();
+        header.write(&mut buf).unwrap();
+
+        for &(i, b) in [(0, b'X'), (13, 0xc3), (14, 17)].iter() {
+            let mut buf = buf.clone();
+            buf[i] = b;
+            let err = FrameHeader::read(&*buf).unwrap_err();
+            assert!(
+                matches!(err, CodecError::InvalidHeader(_)),
+                "i = {}, b = {}, err = {:?}",
+                i,
+                b,
+                err
+            );
+        }
+
+        buf[4] = 0;
+        let err = FrameHeader::read(&*buf).unwrap_err();
+        assert!(
+            matches!(err, CodecError::UnsupportedVersion(0)),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_header_extensions() {
+        let header = FrameHeader {
+            width: 640,
+            height: 480,
+            n_planes: 3,
+            bit_depth: 12,
+            sample_width: 16,
+            color_transform: ColorTransform::YCoCgR,
+            tile_size: None,
+            quantization: Some(vec![1, 2, 2]),
+            preview_factor: None,
+            band_height: Some(16),
+            fixed_k: None,
+        };
+        let mut buf = Vec::new();
+        header.write(&mut buf).unwrap();
+        assert_eq!(buf.len(), 26);
+        assert_eq!(header.encoded_len(), 26);
+        assert_eq!(buf[4], EXTENDED_VERSION);
+        assert_eq!(buf[15], 0x01);
+        assert_eq!(buf[22..], [0, 0, 0, 16]);
+        assert_eq!(FrameHeader::read(&*buf).unwrap(), header);
+
+        // headers without extensions are still version 1
+        let plain = FrameHeader {
+            band_height: None,
+            ..header.clone()
+        };
+        let mut plain_buf = Vec::new();
+        plain.write(&mut plain_buf).unwrap();
+        assert_eq!(plain_buf[4], VERSION);
+        assert_eq!(plain_buf.len(), plain.encoded_len());
+
+        // empty bands are rejected in both directions, and so are extensions this version doesn't
+        // know about
+        let mut zero = buf.clone();
+        zero[25] = 0;
+        let err = FrameHeader::read(&*zero).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidHeader(_)), "{:?}", err);
+        let err = FrameHeader {
+            band_height: Some(0),
+            ..header.clone()
+        }
+        .write(&mut Vec::new())
+        .unwrap_err();
+        assert!(matches!(err, CodecError::InvalidInput(_)), "{:?}", err);
+        let mut unknown = buf.clone();
+        unknown[15] |= 0x80;
+        let err = FrameHeader::read(&*unknown).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidHeader(_)), "{:?}", err);
+
+        // each plane's fixed k follows the band height
+        let header = FrameHeader {
+            fixed_k: Some(vec![0, 5, MAX_FIXED_K]),
+            ..header
+        };
+        let mut fixed = Vec::new();
+        header.write(&mut fixed).unwrap();
+        assert_eq!(fixed.len(), 29);
+        assert_eq!(header.encoded_len(), 29);
+        assert_eq!(fixed[15], 0x03);
+        assert_eq!(fixed[26..], [0, 5, MAX_FIXED_K]);
+        assert_eq!(FrameHeader::read(&*fixed).unwrap(), header);
+        assert_eq!(header.plane_coding(1), PlaneCoding { q: 2, k: Some(5) });
+        fixed[28] = MAX_FIXED_K + 1;
+        let err = FrameHeader::read(&*fixed).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidHeader(_)), "{:?}", err);
+        for fixed_k in [vec![1, 2], vec![1, 2, MAX_FIXED_K + 1]] {
+            let err = FrameHeader {
+                fixed_k: Some(fixed_k),
+                ..header.clone()
+            }
+            .write(&mut Vec::new())
+            .unwrap_err();
+            assert!(matches!(err, CodecError::InvalidInput(_)), "{:?}", err);
+        }
+
+        buf[4] = EXTENDED_VERSION + 1;
+        let err = FrameHeader::read(&*buf).unwrap_err();
+        assert!(
+            matches!(err, CodecError::UnsupportedVersion(3)),
+            "{:?}",
+            err
+        );
+    }
+}
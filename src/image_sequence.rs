@@ -0,0 +1,288 @@
+use super::frame::{FrameOpenError, RGB48Frame};
+use std::{
+    io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ImageSequenceError {
+    #[error(transparent)]
+    IO(#[from] io::Error),
+    // The pattern doesn't have exactly one %d or %0Nd in its file name.
+    #[error("invalid pattern: {0}")]
+    InvalidPattern(String),
+    #[error("no numbered files found for {}", .0.display())]
+    NoFrames(PathBuf),
+    #[error("failed to load {}: {error}", .path.display())]
+    Frame {
+        path: PathBuf,
+        #[source]
+        error: FrameOpenError,
+    },
+}
+
+// What numbered files' names look like: a prefix, a number, and a suffix.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Template {
+    prefix: String,
+    suffix: String,
+    // For %0Nd patterns, the number of digits numbers are padded to.
+    width: Option<usize>,
+}
+
+impl Template {
+    // Parses a printf-style file name like frame_%06d.tif.
+    fn parse(name: &str) -> Option<Self> {
+        let start = name.find('%')?;
+        let rest = &name[start + 1..];
+        let end = rest.find('d')?;
+        let spec = &rest[..end];
+        let width = match spec {
+            "" => None,
+            spec if spec.starts_with('0') && spec.bytes().all(|b| b.is_ascii_digit()) => {
+                Some(spec.parse().ok()?)
+            }
+            _ => return None,
+        };
+        let suffix = &rest[end + 1..];
+        if suffix.contains('%') {
+            return None;
+        }
+        Some(Self {
+            prefix: name[..start].to_string(),
+            suffix: suffix.to_string(),
+            width,
+        })
+    }
+
+    // The template of a file name, taking its last run of digits as the number, and the number.
+    fn of(name: &str) -> Option<(Self, u64)> {
+        let end = name.rfind(|c: char| c.is_ascii_digit())? + 1;
+        let start = name[..end]
+            .rfind(|c: char| !c.is_ascii_digit())
+            .map_or(0, |i| i + 1);
+        let number = name[start..end].parse().ok()?;
+        Some((
+            Self {
+                prefix: name[..start].to_string(),
+                suffix: name[end..].to_string(),
+                width: None,
+            },
+            number,
+        ))
+    }
+
+    // Returns the number in name, if it fits the template. Numbers wider than a padded template's
+    // width are accepted as long as they aren't padded themselves.
+    fn number(&self, name: &str) -> Option<u64> {
+        let digits = name
+            .strip_prefix(self.prefix.as_str())?
+            .strip_suffix(self.suffix.as_str())?;
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        if let Some(width) = self.width {
+            if digits.len() < width || (digits.len() > width && digits.starts_with('0')) {
+                return None;
+            }
+        }
+        digits.parse().ok()
+    }
+}
+
+// The numbered image files making up a sequence, like frame_000001.tif through frame_012345.tif,
+// in numeric order. Frames are loaded one at a time as they're iterated over.
+pub struct ImageSequence {
+    // Frame numbers and paths, sorted by number.
+    frames: Vec<(u64, PathBuf)>,
+}
+
+impl ImageSequence {
+    // Finds the files of a sequence, given either a printf-style pattern like
+    // shots/frame_%06d.tif or a directory. In a directory, every file name's last run of digits is
+    // taken as its number, and the largest set of files whose names differ only there is the
+    // sequence. Other files are ignored either way.
+    pub fn open<P: AsRef<Path>>(pattern_or_dir: P) -> Result<Self, ImageSequenceError> {
+        let path = pattern_or_dir.as_ref();
+        let mut frames = if path.is_dir() {
+            Self::scan_dir(path)?
+        } else {
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| ImageSequenceError::InvalidPattern(path.display().to_string()))?;
+            let template = Template::parse(name)
+                .ok_or_else(|| ImageSequenceError::InvalidPattern(name.to_string()))?;
+            let dir = match path.parent() {
+                Some(dir) if dir != Path::new("") => dir,
+                _ => Path::new("."),
+            };
+            let mut frames = Vec::new();
+            for (name, path) in Self::files(dir)? {
+                if let Some(number) = template.number(&name) {
+                    frames.push((number, path));
+                }
+            }
+            frames
+        };
+        if frames.is_empty() {
+            return Err(ImageSequenceError::NoFrames(path.to_path_buf()));
+        }
+        frames.sort();
+        Ok(Self { frames })
+    }
+
+    // The names and paths of the files in dir.
+    fn files(dir: &Path) -> Result<Vec<(String, PathBuf)>, ImageSequenceError> {
+        let mut ret = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            if let Ok(name) = entry.file_name().into_string() {
+                ret.push((name, entry.path()));
+            }
+        }
+        Ok(ret)
+    }
+
+    fn scan_dir(dir: &Path) -> Result<Vec<(u64, PathBuf)>, ImageSequenceError> {
+        let mut groups: Vec<(Template, Vec<(u64, PathBuf)>)> = Vec::new();
+        for (name, path) in Self::files(dir)? {
+            let (template, number) = match Template::of(&name) {
+                Some(found) => found,
+                None => continue,
+            };
+            match groups.iter_mut().find(|(t, _)| *t == template) {
+                Some((_, frames)) => frames.push((number, path)),
+                None => groups.push((template, vec![(number, path)])),
+            }
+        }
+        // the largest group, breaking ties by template so that the result doesn't depend on the
+        // order the directory is listed in
+        groups.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(&b.0)));
+        Ok(groups
+            .into_iter()
+            .next()
+            .map_or_else(Vec::new, |(_, frames)| frames))
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // The path of the ith frame in order, which isn't necessarily frame number i.
+    pub fn path(&self, i: usize) -> &Path {
+        &self.frames[i].1
+    }
+
+    // The ith frame's number, as it appears in its file name.
+    pub fn number(&self, i: usize) -> u64 {
+        self.frames[i].0
+    }
+
+    pub fn paths(&self) -> impl ExactSizeIterator<Item = &Path> {
+        self.frames.iter().map(|(_, path)| path.as_path())
+    }
+
+    // The ranges of frame numbers missing between the first frame and the last.
+    pub fn gaps(&self) -> Vec<Range<u64>> {
+        self.frames
+            .windows(2)
+            .filter(|w| w[1].0 > w[0].0 + 1)
+            .map(|w| w[0].0 + 1..w[1].0)
+            .collect()
+    }
+
+    // Loads the frames in order, each only when it's reached (see RGB48Frame::open).
+    pub fn frames(
+        &self,
+    ) -> impl ExactSizeIterator<Item = Result<RGB48Frame, ImageSequenceError>> + '_ {
+        self.paths().map(|path| {
+            RGB48Frame::open(path).map_err(|error| ImageSequenceError::Frame {
+                path: path.to_path_buf(),
+                error,
+            })
+        })
+    }
+}
+
+// The tests write their sequences as TIFFs.
+#[cfg(all(test, feature = "tiff"))]
+mod tests {
+    use super::{super::synth, *};
+
+    #[test]
+    fn test_image_sequence() {
+        let dir = std::env::temp_dir().join(format!("hvc-sequence-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // unpadded, out of order in a lexicographic listing, and missing 4 through 9 and 12
+        let numbers = [1, 2, 3, 10, 11, 13];
+        for &n in numbers.iter() {
+            synth::flat(3, 2, n as u16 * 1000)
+                .to_tiff(dir.join(format!("frame_{}.tif", n)))
+                .unwrap();
+        }
+        std::fs::write(dir.join("notes.txt"), "not a frame").unwrap();
+        std::fs::write(dir.join("take2_1.tif"), "not part of the sequence").unwrap();
+        std::fs::create_dir_all(dir.join("frame_99.tif")).unwrap();
+
+        for sequence in [
+            ImageSequence::open(&dir).unwrap(),
+            ImageSequence::open(dir.join("frame_%d.tif")).unwrap(),
+        ]
+        .iter()
+        {
+            assert_eq!(sequence.len(), numbers.len());
+            let names: Vec<_> = sequence
+                .paths()
+                .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+                .collect();
+            assert_eq!(
+                names,
+                [
+                    "frame_1.tif",
+                    "frame_2.tif",
+                    "frame_3.tif",
+                    "frame_10.tif",
+                    "frame_11.tif",
+                    "frame_13.tif"
+                ]
+            );
+            assert_eq!(sequence.number(3), 10);
+            assert_eq!(sequence.gaps(), vec![4..10, 12..13]);
+            for (frame, &n) in sequence.frames().zip(numbers.iter()) {
+                assert!(frame.unwrap() == synth::flat(3, 2, n as u16 * 1000));
+            }
+        }
+
+        // padded patterns only match numbers padded the same way
+        std::fs::write(dir.join("frame_007.tif"), "not a tiff").unwrap();
+        let padded = ImageSequence::open(dir.join("frame_%03d.tif")).unwrap();
+        assert_eq!(padded.len(), 1);
+        match padded.frames().next().unwrap() {
+            Err(ImageSequenceError::Frame { path, .. }) => {
+                assert_eq!(path, dir.join("frame_007.tif"))
+            }
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("loaded a text file"),
+        }
+
+        assert!(matches!(
+            ImageSequence::open(dir.join("frame_%x.tif")),
+            Err(ImageSequenceError::InvalidPattern(_))
+        ));
+        assert!(matches!(
+            ImageSequence::open(dir.join("shot_%d.tif")),
+            Err(ImageSequenceError::NoFrames(_))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
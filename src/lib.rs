@@ -1,3 +1,126 @@
+pub mod aligned;
+pub mod band;
+pub mod batch;
+pub mod bayer;
+pub mod bench;
 pub mod bitstream;
 pub mod codec;
+pub mod color;
+pub mod container;
+pub mod dither;
+mod dpx;
+pub mod dyn_codec;
+pub mod error;
+#[cfg(feature = "exr")]
+pub mod exr;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod frame;
+pub mod frame16;
+pub mod gray;
+pub mod header;
+pub mod image_sequence;
+pub mod metadata;
+pub mod metrics;
+pub mod motion;
+mod pgm;
+pub mod pipeline;
+#[cfg(feature = "tiff")]
+mod planar_tiff;
+#[cfg(feature = "png")]
+pub mod png;
+pub mod prelude;
+pub mod progress;
+pub mod pyramid;
+pub mod rate;
+pub mod rawvideo;
+pub mod recover;
+pub mod report;
+#[cfg(feature = "deflate")]
+pub mod residual;
+pub mod resize;
+pub mod sequence;
+pub mod store;
+pub mod subsampled;
+pub mod synth;
+pub mod tile;
+pub mod y4m;
+
+use codec::Codec;
+use error::{CodecError, Error};
+use frame::{DecodeOptions, EncodeOptions, RGB48Frame};
+use frame16::Frame16;
+use std::{fs, path::Path, time::Instant};
+
+// What encode_file reports about the frame it encoded.
+pub type EncodeReport = report::Report;
+
+/// Encodes the image at `input` (a TIFF, PNG, or any other format RGB48Frame::open reads) with the
+/// standard codec, and writes it to `output` with its header, so that decode_file needs nothing
+/// else to read it back.
+///
+/// ```
+/// use hello_video_codec::prelude::*;
+///
+/// let dir = std::env::temp_dir();
+/// let encoded = dir.join("doctest_encode_file.hvc");
+/// let report = encode_file("src/testdata/gray16.tif", &encoded, &EncodeOptions::default())?;
+/// assert_eq!(report.planes, 1);
+/// assert_eq!(report.encoded_size as u64, std::fs::metadata(&encoded)?.len());
+///
+/// decode_file(&encoded, dir.join("doctest_encode_file.tif"), &DecodeOptions::default())?;
+/// # Ok::<(), hello_video_codec::error::Error>(())
+/// ```
+pub fn encode_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    options: &EncodeOptions,
+) -> Result<EncodeReport, Error> {
+    let input = input.as_ref();
+    let frame = RGB48Frame::open(input)?;
+    let start = Instant::now();
+    let encoded = encode_frame(&frame, options)?;
+    let wall_time = start.elapsed().as_secs_f64();
+    fs::write(output, &encoded)?;
+
+    let mut report = EncodeReport::new(0, &frame, options);
+    report.input = Some(input.display().to_string());
+    report.encoded_size = encoded.len();
+    report.wall_time = wall_time;
+    Ok(report)
+}
+
+/// Decodes a frame written by encode_file and saves it to `output` as a TIFF or PNG, whichever its
+/// extension names. Gray and RGBA frames are saved as such.
+pub fn decode_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    options: &DecodeOptions,
+) -> Result<(), Error> {
+    let encoded = fs::read(input)?;
+    let frame = RGB48Frame::decode_with_header_and_options::<Codec, _>(&*encoded, options)?;
+    Frame16::from_rgb48(frame)?.save(output)?;
+    Ok(())
+}
+
+/// Encodes a frame in memory with the standard codec, header included, as encode_file would write
+/// it.
+///
+/// ```
+/// use hello_video_codec::{prelude::*, synth};
+///
+/// let frame = synth::zone_plate(64, 48);
+/// let encoded = encode_frame(&frame, &EncodeOptions::default())?;
+/// assert!(decode_frame(&encoded)? == frame);
+/// # Ok::<(), hello_video_codec::error::CodecError>(())
+/// ```
+pub fn encode_frame(frame: &RGB48Frame, options: &EncodeOptions) -> Result<Vec<u8>, CodecError> {
+    let mut encoded = Vec::new();
+    frame.encode_with_header::<Codec, _>(&mut encoded, options)?;
+    Ok(encoded)
+}
+
+/// Decodes a frame written by encode_frame or encode_file.
+pub fn decode_frame(encoded: &[u8]) -> Result<RGB48Frame, CodecError> {
+    RGB48Frame::decode_with_header::<Codec, _>(encoded)
+}
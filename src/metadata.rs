@@ -0,0 +1,291 @@
+use super::error::{CodecError, Result};
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+};
+
+// Metadata sections larger than this are treated as corrupt rather than allocated for.
+const MAX_SECTION_LEN: u32 = 1 << 20;
+
+// Record keys. Records with other keys are kept as they are (see SequenceMetadata).
+const COLOR_KEY: u8 = 1;
+const TAG_KEY: u8 = 2;
+
+// The standard a sequence's colorimetry follows, for each of its matrix coefficients, primaries, and
+// transfer characteristics.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorStandard {
+    #[default]
+    Unspecified,
+    Bt709,
+    Bt2020,
+}
+
+impl ColorStandard {
+    fn from_u8(x: u8) -> Option<Self> {
+        match x {
+            0 => Some(Self::Unspecified),
+            1 => Some(Self::Bt709),
+            2 => Some(Self::Bt2020),
+            _ => None,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::Unspecified => 0,
+            Self::Bt709 => 1,
+            Self::Bt2020 => 2,
+        }
+    }
+}
+
+// Whether samples use their whole range, or leave headroom and footroom as broadcast video does
+// (16-235 at 8 bits, scaled up for deeper samples).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorRange {
+    #[default]
+    Full,
+    Limited,
+}
+
+impl ColorRange {
+    fn from_u8(x: u8) -> Option<Self> {
+        match x {
+            0 => Some(Self::Full),
+            1 => Some(Self::Limited),
+            _ => None,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::Full => 0,
+            Self::Limited => 1,
+        }
+    }
+}
+
+// What a sequence needs to be shown correctly downstream, plus free-form tags like camera serial
+// numbers.
+//
+// In a container, the frame rate is part of the fixed header, and everything else is in a metadata
+// section after it:
+//
+//   length         4 bytes  big-endian, the length of the records that follow
+//   records        each a 1-byte key, a 4-byte big-endian length, and that many bytes of value
+//
+// The records are:
+//
+//   1  color       matrix, primaries, and transfer (ColorStandard) and range (ColorRange), 1 byte
+//                  each
+//   2  tag         a 2-byte big-endian key length, the key, and the value, both UTF-8
+//
+// Records with other keys, or that this version doesn't understand, are kept as they are and
+// written back after the rest, so rewriting a newer file's metadata doesn't lose anything.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SequenceMetadata {
+    // As a numerator and denominator, e.g. (24000, 1001).
+    pub frame_rate: (u32, u32),
+    pub matrix: ColorStandard,
+    pub primaries: ColorStandard,
+    pub transfer: ColorStandard,
+    pub range: ColorRange,
+    pub tags: BTreeMap<String, String>,
+    unknown: Vec<(u8, Vec<u8>)>,
+}
+
+impl SequenceMetadata {
+    pub fn new(frame_rate: (u32, u32)) -> Self {
+        Self {
+            frame_rate,
+            matrix: ColorStandard::Unspecified,
+            primaries: ColorStandard::Unspecified,
+            transfer: ColorStandard::Unspecified,
+            range: ColorRange::Full,
+            tags: BTreeMap::new(),
+            unknown: Vec::new(),
+        }
+    }
+
+    // Sets the matrix, primaries, and transfer characteristics all at once.
+    pub fn with_color(self, standard: ColorStandard) -> Self {
+        Self {
+            matrix: standard,
+            primaries: standard,
+            transfer: standard,
+            ..self
+        }
+    }
+
+    pub fn with_range(self, range: ColorRange) -> Self {
+        Self { range, ..self }
+    }
+
+    pub fn with_tag<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.tags.insert(key.into(), value.into());
+        self
+    }
+
+    // Writes the metadata section, which doesn't include the frame rate.
+    pub(crate) fn write<W: Write>(&self, mut dest: W) -> Result<()> {
+        let mut records = Vec::new();
+        let mut push = |key: u8, value: &[u8]| {
+            records.push(key);
+            records.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            records.extend_from_slice(value);
+        };
+        push(
+            COLOR_KEY,
+            &[
+                self.matrix.to_u8(),
+                self.primaries.to_u8(),
+                self.transfer.to_u8(),
+                self.range.to_u8(),
+            ],
+        );
+        for (key, value) in &self.tags {
+            if key.len() > u16::MAX as usize {
+                return Err(CodecError::InvalidInput(format!(
+                    "a {}-byte tag key is too long",
+                    key.len()
+                )));
+            }
+            let mut record = (key.len() as u16).to_be_bytes().to_vec();
+            record.extend_from_slice(key.as_bytes());
+            record.extend_from_slice(value.as_bytes());
+            push(TAG_KEY, &record);
+        }
+        for (key, value) in &self.unknown {
+            push(*key, value);
+        }
+        if records.len() > MAX_SECTION_LEN as usize {
+            return Err(CodecError::InvalidInput(
+                "the metadata is too large".to_string(),
+            ));
+        }
+        dest.write_all(&(records.len() as u32).to_be_bytes())?;
+        dest.write_all(&records)?;
+        Ok(())
+    }
+
+    // Reads a metadata section written by write, returning it and its length in bytes.
+    pub(crate) fn read<R: Read>(mut source: R, frame_rate: (u32, u32)) -> Result<(Self, u64)> {
+        let invalid = |message: &str| CodecError::InvalidHeader(message.to_string());
+        let mut buf = [0; 4];
+        source.read_exact(&mut buf)?;
+        let len = u32::from_be_bytes(buf);
+        if len > MAX_SECTION_LEN {
+            return Err(invalid("the metadata section is too large"));
+        }
+        let mut records = vec![0; len as usize];
+        source.read_exact(&mut records)?;
+
+        let mut ret = Self::new(frame_rate);
+        let mut rest = &records[..];
+        while !rest.is_empty() {
+            if rest.len() < 5 {
+                return Err(invalid("truncated metadata record"));
+            }
+            let key = rest[0];
+            let len = u32::from_be_bytes([rest[1], rest[2], rest[3], rest[4]]) as usize;
+            if rest.len() - 5 < len {
+                return Err(invalid("truncated metadata record"));
+            }
+            let value = &rest[5..5 + len];
+            rest = &rest[5 + len..];
+            if !ret.parse_record(key, value) {
+                ret.unknown.push((key, value.to_vec()));
+            }
+        }
+        Ok((ret, 4 + len as u64))
+    }
+
+    // Applies a record, returning false if it isn't one this version understands.
+    fn parse_record(&mut self, key: u8, value: &[u8]) -> bool {
+        match (key, value) {
+            (COLOR_KEY, &[matrix, primaries, transfer, range]) => {
+                match (
+                    ColorStandard::from_u8(matrix),
+                    ColorStandard::from_u8(primaries),
+                    ColorStandard::from_u8(transfer),
+                    ColorRange::from_u8(range),
+                ) {
+                    (Some(matrix), Some(primaries), Some(transfer), Some(range)) => {
+                        self.matrix = matrix;
+                        self.primaries = primaries;
+                        self.transfer = transfer;
+                        self.range = range;
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            (TAG_KEY, &[a, b, ref rest @ ..]) => {
+                let key_len = u16::from_be_bytes([a, b]) as usize;
+                if rest.len() < key_len {
+                    return false;
+                }
+                let (key, value) = rest.split_at(key_len);
+                match (std::str::from_utf8(key), std::str::from_utf8(value)) {
+                    (Ok(key), Ok(value)) => {
+                        self.tags.insert(key.to_string(), value.to_string());
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<(u32, u32)> for SequenceMetadata {
+    fn from(frame_rate: (u32, u32)) -> Self {
+        Self::new(frame_rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_roundtrip() {
+        let metadata = SequenceMetadata::new((24000, 1001))
+            .with_color(ColorStandard::Bt2020)
+            .with_range(ColorRange::Limited)
+            .with_tag("camera serial", "A001-1234")
+            .with_tag("lens", "50mm \u{2248} f/1.4");
+        let mut encoded = Vec::new();
+        metadata.write(&mut encoded).unwrap();
+        let (decoded, len) = SequenceMetadata::read(encoded.as_slice(), (24000, 1001)).unwrap();
+        assert_eq!(decoded, metadata);
+        assert_eq!(len, encoded.len() as u64);
+
+        // a record with an unknown key, and a color record with an unknown standard, are kept
+        let mut injected = encoded[4..].to_vec();
+        injected.extend_from_slice(&[200, 0, 0, 0, 3, 1, 2, 3]);
+        injected.extend_from_slice(&[COLOR_KEY, 0, 0, 0, 4, 9, 9, 9, 0]);
+        let mut section = (injected.len() as u32).to_be_bytes().to_vec();
+        section.extend_from_slice(&injected);
+        let (decoded, _) = SequenceMetadata::read(section.as_slice(), (24000, 1001)).unwrap();
+        assert_eq!(decoded.matrix, ColorStandard::Bt2020);
+        assert_eq!(decoded.tags, metadata.tags);
+        let mut rewritten = Vec::new();
+        decoded.write(&mut rewritten).unwrap();
+        assert_eq!(rewritten, section);
+
+        // truncated records are errors
+        let mut truncated = section.clone();
+        truncated[3] -= 1;
+        assert!(matches!(
+            SequenceMetadata::read(&truncated[..truncated.len() - 1], (1, 1)),
+            Err(CodecError::InvalidHeader(_))
+        ));
+        assert!(matches!(
+            SequenceMetadata::read(&[0xff, 0xff, 0xff, 0xff][..], (1, 1)),
+            Err(CodecError::InvalidHeader(_))
+        ));
+    }
+}
@@ -0,0 +1,1042 @@
+use super::{
+    frame::{FrameError, Plane, RGB48Frame},
+    gray::GrayFrame16,
+    resize::Filter,
+    tile::{BlockPos, TileLayout},
+};
+use std::{collections::VecDeque, fmt};
+
+// The peak signal-to-noise ratio in decibels for 16-bit samples with the given mean squared error.
+// Identical samples have an infinite PSNR.
+pub fn psnr(mse: f64) -> f64 {
+    if mse == 0.0 {
+        return f64::INFINITY;
+    }
+    10.0 * (65535.0 * 65535.0 / mse).log10()
+}
+
+// The PSNR of each of a frame's planes, plus one for the frame as a whole.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FramePsnr {
+    pub planes: Vec<f64>,
+    // Computed from the mean squared error over all of the planes, not from the mean of their
+    // PSNRs, so a single identical plane doesn't make it infinite.
+    pub combined: f64,
+}
+
+// How many differing samples RGB48Frame::diff lists individually.
+pub const DEFAULT_DIFF_LIMIT: usize = 16;
+
+// A sample that differs between two frames.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SampleDiff {
+    pub col: usize,
+    pub row: usize,
+    pub plane: usize,
+    pub expected: u16,
+    pub actual: u16,
+}
+
+impl SampleDiff {
+    // The actual value minus the expected one.
+    pub fn delta(&self) -> i32 {
+        self.actual as i32 - self.expected as i32
+    }
+
+    pub fn abs_error(&self) -> u16 {
+        self.delta().unsigned_abs() as u16
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlaneDiff {
+    pub differing: usize,
+    pub max_abs_error: u16,
+}
+
+// The differences between two frames, as returned by RGB48Frame::diff.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiffReport {
+    // The number of samples that differ, over all planes.
+    pub differing: usize,
+    pub max_abs_error: u16,
+    pub planes: Vec<PlaneDiff>,
+    // The first differing samples, in the order they're stored.
+    pub first: Vec<SampleDiff>,
+}
+
+impl DiffReport {
+    // True if the frames are identical.
+    pub fn is_empty(&self) -> bool {
+        self.differing == 0
+    }
+}
+
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no differences");
+        }
+        writeln!(
+            f,
+            "{} samples differ, max error {}",
+            self.differing, self.max_abs_error
+        )?;
+        for (i, plane) in self.planes.iter().enumerate() {
+            writeln!(
+                f,
+                "  plane {}: {} differ, max error {}",
+                i, plane.differing, plane.max_abs_error
+            )?;
+        }
+        for diff in self.first.iter() {
+            writeln!(
+                f,
+                "  ({}, {}) plane {}: expected {}, got {} ({:+})",
+                diff.col,
+                diff.row,
+                diff.plane,
+                diff.expected,
+                diff.actual,
+                diff.delta()
+            )?;
+        }
+        if self.differing > self.first.len() {
+            writeln!(f, "  and {} more", self.differing - self.first.len())?;
+        }
+        Ok(())
+    }
+}
+
+// The samples that differ between two frames with the same dimensions and number of channels, in
+// the order they're stored.
+fn differences<'a>(
+    expected: &'a RGB48Frame,
+    actual: &'a RGB48Frame,
+) -> impl Iterator<Item = SampleDiff> + 'a {
+    let (width, n_planes) = (expected.width, expected.n_planes());
+    expected
+        .data
+        .iter()
+        .zip(actual.data.iter())
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .map(move |(i, (&expected, &actual))| {
+            let pixel = i / n_planes;
+            SampleDiff {
+                col: pixel % width,
+                row: pixel / width,
+                plane: i % n_planes,
+                expected,
+                actual,
+            }
+        })
+}
+
+fn check_dimensions(expected: (usize, usize), actual: (usize, usize)) -> Result<(), FrameError> {
+    if expected != actual {
+        return Err(FrameError::DimensionMismatch { expected, actual });
+    }
+    Ok(())
+}
+
+fn check_comparable(a: &RGB48Frame, b: &RGB48Frame) -> Result<(), FrameError> {
+    check_dimensions((a.width, a.height), (b.width, b.height))?;
+    if a.n_planes() != b.n_planes() {
+        return Err(FrameError::ChannelMismatch {
+            expected: a.n_planes(),
+            actual: b.n_planes(),
+        });
+    }
+    Ok(())
+}
+
+// The PSNR of each block of a plane, as returned by Plane::psnr_map, for finding where quality was
+// lost. Blocks are numbered in raster order, as in layout.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PsnrMap {
+    pub layout: TileLayout,
+    // Each block's mean squared error.
+    pub mse: Vec<f64>,
+}
+
+impl PsnrMap {
+    pub fn len(&self) -> usize {
+        self.mse.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mse.is_empty()
+    }
+
+    // Block i's PSNR, which is infinite if the block is identical in both planes.
+    pub fn psnr(&self, i: usize) -> f64 {
+        psnr(self.mse[i])
+    }
+
+    // The lowest PSNR of any block, or infinity if there are none or they're all identical.
+    pub fn min(&self) -> f64 {
+        psnr(self.mse.iter().copied().fold(0.0, f64::max))
+    }
+
+    // The block with the lowest PSNR, the first in raster order if several share it, or None if
+    // there are no blocks.
+    pub fn worst(&self) -> Option<BlockPos> {
+        let mut worst: Option<(usize, f64)> = None;
+        for (i, &mse) in self.mse.iter().enumerate() {
+            if worst.is_none_or(|(_, max)| mse > max) {
+                worst = Some((i, mse));
+            }
+        }
+        worst.map(|(i, _)| self.layout.block(i).0)
+    }
+
+    // The PSNR that p percent of blocks are at or below, by the nearest-rank method, so 0 gives
+    // the lowest and 100 the highest. Infinity if there are no blocks. Panics unless p is from 0 to
+    // 100.
+    pub fn percentile(&self, p: f64) -> f64 {
+        assert!((0.0..=100.0).contains(&p), "invalid percentile {}", p);
+        let mut psnrs: Vec<f64> = self.mse.iter().map(|&mse| psnr(mse)).collect();
+        if psnrs.is_empty() {
+            return f64::INFINITY;
+        }
+        psnrs.sort_by(|a, b| a.total_cmp(b));
+        let rank = (p / 100.0 * psnrs.len() as f64).ceil() as usize;
+        psnrs[rank.max(1) - 1]
+    }
+
+    // An image the size of the plane, with each block filled with its root mean squared error
+    // mapped to brightness as for Plane::diff_heatmap. The worst blocks are white, and identical
+    // ones black.
+    pub fn heatmap(&self, scale: HeatmapScale) -> GrayFrame16 {
+        let (width, height) = (self.layout.width, self.layout.height);
+        let mut errors = vec![0; width * height];
+        for (i, &mse) in self.mse.iter().enumerate() {
+            let (pos, (w, h)) = self.layout.block(i);
+            // rounded up, so that blocks with any error at all aren't black
+            let rmse = mse.sqrt().ceil() as u16;
+            for row in errors[pos.y * width..].chunks_mut(width).take(h) {
+                row[pos.x..pos.x + w].fill(rmse);
+            }
+        }
+        heatmap(errors, width, height, scale)
+    }
+}
+
+// How Plane::diff_heatmap maps absolute differences to brightness. Either way, no difference is
+// black and the largest difference in the image is white.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HeatmapScale {
+    // Proportional to the difference.
+    Linear,
+    // Proportional to the logarithm of one more than the difference, which makes differences of a
+    // few code values visible next to much larger ones.
+    Log,
+}
+
+// Maps absolute differences to 8-bit brightness, widened to 16 bits by multiplying by 257 like
+// other 8-bit samples.
+fn heatmap(errors: Vec<u16>, width: usize, height: usize, scale: HeatmapScale) -> GrayFrame16 {
+    let max = errors.iter().copied().max().unwrap_or(0);
+    let map = |x: u16| -> u16 {
+        if x == 0 {
+            return 0;
+        }
+        let level = match scale {
+            HeatmapScale::Linear => (x as u32 * 255 + max as u32 / 2) / max as u32,
+            HeatmapScale::Log => (255.0 * (x as f64).ln_1p() / (max as f64).ln_1p()).round() as u32,
+        };
+        level as u16 * 257
+    };
+    GrayFrame16 {
+        data: errors.into_iter().map(map).collect(),
+        width,
+        height,
+        bit_depth: 8,
+    }
+}
+
+// SSIM's stabilizing constants, (0.01 * 65535)^2 and (0.03 * 65535)^2. As with PSNR, the dynamic
+// range is always 16 bits.
+const SSIM_C1: f64 = 655.35 * 655.35;
+const SSIM_C2: f64 = 1966.05 * 1966.05;
+
+// The weights of MS-SSIM's scales, finest first, from Wang, Simoncelli, and Bovik's "Multi-scale
+// structural similarity for image quality assessment".
+pub const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+// The window Plane::ms_ssim uses at every scale.
+pub const MS_SSIM_WINDOW: usize = 8;
+
+// Sums of a, b, a^2, b^2, and ab over part of a window.
+type Moments = [u64; 5];
+
+fn add_moments(a: &mut Moments, b: &Moments) {
+    a.iter_mut().zip(b.iter()).for_each(|(a, b)| *a += b);
+}
+
+fn sub_moments(a: &mut Moments, b: &Moments) {
+    a.iter_mut().zip(b.iter()).for_each(|(a, b)| *a -= b);
+}
+
+// The range of the window of the given size around i, clipped to 0..len.
+fn window_range(i: usize, window: usize, len: usize) -> (usize, usize) {
+    let start = i.saturating_sub(window / 2);
+    let end = (i + window - window / 2).min(len);
+    (start, end)
+}
+
+impl<T: AsRef<[u16]>> Plane<T> {
+    // The mean squared error between two planes of the same dimensions.
+    pub fn mse<U: AsRef<[u16]>>(&self, other: &Plane<U>) -> Result<f64, FrameError> {
+        check_dimensions((self.width, self.height), (other.width, other.height))?;
+        let mut sum = 0.0;
+        for (a, b) in self.rows().zip(other.rows()) {
+            // the squared error of a row fits easily in a u64, so only rows are summed as floats
+            let row: u64 = a
+                .iter()
+                .zip(b.iter())
+                .map(|(a, b)| {
+                    let d = (a as i64 - b as i64).unsigned_abs();
+                    d * d
+                })
+                .sum();
+            sum += row as f64;
+        }
+        Ok(sum / (self.width * self.height).max(1) as f64)
+    }
+
+    // The PSNR between two planes of the same dimensions. The peak is always 65535: samples of
+    // narrower bit depths are widened to the full 16-bit range when they're loaded.
+    pub fn psnr<U: AsRef<[u16]>>(&self, other: &Plane<U>) -> Result<f64, FrameError> {
+        self.mse(other).map(psnr)
+    }
+
+    // An image of the absolute difference between each pair of samples of two planes with the same
+    // dimensions, for seeing where they differ. It's 8-bit, scaled to the largest difference, and
+    // can be saved with GrayFrame16::to_png or to_pgm.
+    pub fn diff_heatmap<U: AsRef<[u16]>>(
+        &self,
+        other: &Plane<U>,
+        scale: HeatmapScale,
+    ) -> Result<GrayFrame16, FrameError> {
+        check_dimensions((self.width, self.height), (other.width, other.height))?;
+        let mut errors = Vec::with_capacity(self.width * self.height);
+        for (a, b) in self.rows().zip(other.rows()) {
+            errors.extend(a.iter().zip(b.iter()).map(|(a, b)| a.abs_diff(b)));
+        }
+        Ok(heatmap(errors, self.width, self.height, scale))
+    }
+
+    // The PSNR of each block_width×block_height block of two planes of the same dimensions, where
+    // blocks along the right and bottom edges are only as large as what's left of the planes.
+    // Panics if either block dimension is 0.
+    pub fn psnr_map<U: AsRef<[u16]>>(
+        &self,
+        other: &Plane<U>,
+        block_width: usize,
+        block_height: usize,
+    ) -> Result<PsnrMap, FrameError> {
+        check_dimensions((self.width, self.height), (other.width, other.height))?;
+        let mse = self
+            .blocks(block_width, block_height)
+            .zip(other.blocks(block_width, block_height))
+            .map(|((_, a), (_, b))| a.mse(&b))
+            .collect::<Result<_, _>>()?;
+        Ok(PsnrMap {
+            layout: TileLayout::new(self.width, self.height, block_width, block_height),
+            mse,
+        })
+    }
+
+    // The structural similarity index between two planes of the same dimensions, from -1 to 1,
+    // where 1 means they're identical. Statistics are computed over a window x window box around
+    // each sample, and the result is the mean over all samples. Near the plane's edges, the box
+    // shrinks to the part of it that lies within the plane.
+    //
+    // The box sums are computed separably and exactly, in integers, so the cost per sample doesn't
+    // depend on the window size. Panics if window is 0.
+    pub fn ssim<U: AsRef<[u16]>>(
+        &self,
+        other: &Plane<U>,
+        window: usize,
+    ) -> Result<f64, FrameError> {
+        self.ssim_terms(other, window).map(|(ssim, _)| ssim)
+    }
+
+    // The multi-scale structural similarity index between two planes of the same dimensions, from
+    // 0 to 1, where 1 means they're identical. The planes are compared at up to 5 scales, each
+    // half the size of the last (with the box filter of Plane::resize), and the mean contrast and
+    // structure terms of SSIM at each scale but the coarsest are combined with the full SSIM at the
+    // coarsest, weighted by MS_SSIM_WEIGHTS. Each scale uses an MS_SSIM_WINDOW window, so planes
+    // too small for all 5 scales get as many as are at least that large in both directions, with
+    // the weights of the scales they do have rescaled to add up to 1.
+    //
+    // Terms are clamped to 0 before they're combined, since negative ones have no real powers.
+    pub fn ms_ssim<U: AsRef<[u16]>>(&self, other: &Plane<U>) -> Result<f64, FrameError> {
+        check_dimensions((self.width, self.height), (other.width, other.height))?;
+        let mut n_scales = 1;
+        while n_scales < MS_SSIM_WEIGHTS.len()
+            && (self.width.min(self.height) >> n_scales) >= MS_SSIM_WINDOW
+        {
+            n_scales += 1;
+        }
+        let weights = &MS_SSIM_WEIGHTS[..n_scales];
+        let weight_sum: f64 = weights.iter().sum();
+
+        let (mut a, mut b) = (self.to_owned(), other.to_owned());
+        let mut ret = 1.0;
+        for (scale, &weight) in weights.iter().enumerate() {
+            let (ssim, cs) = a.ssim_terms(&b, MS_SSIM_WINDOW)?;
+            let term = if scale + 1 == n_scales { ssim } else { cs };
+            ret *= term.max(0.0).powf(weight / weight_sum);
+            if scale + 1 < n_scales {
+                let (width, height) = (a.width / 2, a.height / 2);
+                a = a.resize(width, height, Filter::Box);
+                b = b.resize(width, height, Filter::Box);
+            }
+        }
+        Ok(ret)
+    }
+
+    // The mean SSIM, and the mean of its contrast and structure terms alone, which is SSIM without
+    // the luminance term.
+    fn ssim_terms<U: AsRef<[u16]>>(
+        &self,
+        other: &Plane<U>,
+        window: usize,
+    ) -> Result<(f64, f64), FrameError> {
+        assert!(window > 0, "ssim needs a window of at least 1 sample");
+        check_dimensions((self.width, self.height), (other.width, other.height))?;
+        let (width, height) = (self.width, self.height);
+        if width == 0 || height == 0 {
+            return Ok((1.0, 1.0));
+        }
+
+        // the horizontal box sums of a row
+        let mut prefix = vec![[0u64; 5]; width + 1];
+        let mut row_sums = |row: usize| {
+            let a = self.view(0, row, width, 1);
+            let b = other.view(0, row, width, 1);
+            let (a, b) = (a.rows().next().unwrap(), b.rows().next().unwrap());
+            for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+                let (a, b) = (a as u64, b as u64);
+                let mut sums = prefix[i];
+                add_moments(&mut sums, &[a, b, a * a, b * b, a * b]);
+                prefix[i + 1] = sums;
+            }
+            (0..width)
+                .map(|col| {
+                    let (start, end) = window_range(col, window, width);
+                    let mut sums = prefix[end];
+                    sub_moments(&mut sums, &prefix[start]);
+                    sums
+                })
+                .collect::<Vec<Moments>>()
+        };
+
+        // the vertical sums of the rows currently in the window, and the rows themselves
+        let mut column_sums = vec![[0u64; 5]; width];
+        let mut rows = VecDeque::with_capacity(window);
+        let mut next_row = 0;
+        let (mut total, mut cs_total) = (0.0, 0.0);
+        for row in 0..height {
+            let (start, end) = window_range(row, window, height);
+            while next_row < end {
+                let sums = row_sums(next_row);
+                column_sums
+                    .iter_mut()
+                    .zip(sums.iter())
+                    .for_each(|(a, b)| add_moments(a, b));
+                rows.push_back(sums);
+                next_row += 1;
+            }
+            while next_row - rows.len() < start {
+                let sums = rows.pop_front().unwrap();
+                column_sums
+                    .iter_mut()
+                    .zip(sums.iter())
+                    .for_each(|(a, b)| sub_moments(a, b));
+            }
+
+            let window_rows = (end - start) as i128;
+            for (col, sums) in column_sums.iter().enumerate() {
+                let (col_start, col_end) = window_range(col, window, width);
+                let n = window_rows * (col_end - col_start) as i128;
+                let [a, b, aa, bb, ab] = sums.map(|x| x as i128);
+                // the variances and covariance are differences of exact integers, so they don't
+                // suffer from cancellation
+                let n2 = (n * n) as f64;
+                let (mean_a, mean_b) = (a as f64 / n as f64, b as f64 / n as f64);
+                let var_a = (n * aa - a * a) as f64 / n2;
+                let var_b = (n * bb - b * b) as f64 / n2;
+                let cov = (n * ab - a * b) as f64 / n2;
+                total += (2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * cov + SSIM_C2)
+                    / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2));
+                cs_total += (2.0 * cov + SSIM_C2) / (var_a + var_b + SSIM_C2);
+            }
+        }
+        let n = (width * height) as f64;
+        Ok((total / n, cs_total / n))
+    }
+}
+
+impl RGB48Frame {
+    // Compares the frame with another of the same dimensions and number of channels, typically its
+    // decoded counterpart after lossy encoding.
+    pub fn psnr(&self, other: &RGB48Frame) -> Result<FramePsnr, FrameError> {
+        check_comparable(self, other)?;
+        let mse = self
+            .planes()
+            .zip(other.planes())
+            .map(|(a, b)| a.mse(&b))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(FramePsnr {
+            planes: mse.iter().map(|&mse| psnr(mse)).collect(),
+            combined: psnr(mse.iter().sum::<f64>() / mse.len() as f64),
+        })
+    }
+
+    // Lists the differences between the frame and another of the same dimensions and number of
+    // channels, treating this frame's samples as the expected ones. At most DEFAULT_DIFF_LIMIT
+    // differences are listed individually, but all of them are counted.
+    pub fn diff(&self, other: &RGB48Frame) -> Result<DiffReport, FrameError> {
+        self.diff_with_limit(other, DEFAULT_DIFF_LIMIT)
+    }
+
+    // Like diff, but lists up to limit differences individually.
+    pub fn diff_with_limit(
+        &self,
+        other: &RGB48Frame,
+        limit: usize,
+    ) -> Result<DiffReport, FrameError> {
+        check_comparable(self, other)?;
+        let mut report = DiffReport {
+            differing: 0,
+            max_abs_error: 0,
+            planes: vec![PlaneDiff::default(); self.n_planes()],
+            first: Vec::new(),
+        };
+        for diff in differences(self, other) {
+            let error = diff.abs_error();
+            report.differing += 1;
+            report.max_abs_error = report.max_abs_error.max(error);
+            let plane_diff = &mut report.planes[diff.plane];
+            plane_diff.differing += 1;
+            plane_diff.max_abs_error = plane_diff.max_abs_error.max(error);
+            if report.first.len() < limit {
+                report.first.push(diff);
+            }
+        }
+        Ok(report)
+    }
+
+    // True if the frames have the same dimensions and number of channels, and no sample differs by
+    // more than max_abs_error. Stops at the first sample that does.
+    pub fn approx_eq(&self, other: &RGB48Frame, max_abs_error: u16) -> bool {
+        check_comparable(self, other).is_ok()
+            && differences(self, other).all(|diff| diff.abs_error() <= max_abs_error)
+    }
+
+    // Like approx_eq, but panics with the first sample that differs by too much, or with the way
+    // the frames' shapes differ. Meant for tests, treating this frame's samples as the expected
+    // ones.
+    #[track_caller]
+    pub fn assert_approx_eq(&self, other: &RGB48Frame, max_abs_error: u16) {
+        if let Err(e) = check_comparable(self, other) {
+            panic!("frames differ: {}", e);
+        }
+        if let Some(diff) = differences(self, other).find(|diff| diff.abs_error() > max_abs_error) {
+            panic!(
+                "frames differ at ({}, {}) plane {}: expected {}, got {}, an error of {} when at \
+                 most {} is allowed",
+                diff.col,
+                diff.row,
+                diff.plane,
+                diff.expected,
+                diff.actual,
+                diff.abs_error(),
+                max_abs_error
+            );
+        }
+    }
+
+    // Like Plane::diff_heatmap, taking each pixel's largest difference over all of its channels.
+    pub fn diff_heatmap(
+        &self,
+        other: &RGB48Frame,
+        scale: HeatmapScale,
+    ) -> Result<GrayFrame16, FrameError> {
+        check_comparable(self, other)?;
+        let n_planes = self.n_planes();
+        let errors = self
+            .data
+            .chunks_exact(n_planes)
+            .zip(other.data.chunks_exact(n_planes))
+            .map(|(a, b)| {
+                a.iter()
+                    .zip(b)
+                    .map(|(a, b)| a.abs_diff(*b))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        Ok(heatmap(errors, self.width, self.height, scale))
+    }
+
+    // The mean of Plane::ssim over the frame's planes.
+    pub fn ssim(&self, other: &RGB48Frame, window: usize) -> Result<f64, FrameError> {
+        check_comparable(self, other)?;
+        let mut total = 0.0;
+        for (a, b) in self.planes().zip(other.planes()) {
+            total += a.ssim(&b, window)?;
+        }
+        Ok(total / self.n_planes() as f64)
+    }
+
+    // The mean of Plane::ms_ssim over the frame's planes.
+    pub fn ms_ssim(&self, other: &RGB48Frame) -> Result<f64, FrameError> {
+        check_comparable(self, other)?;
+        let mut total = 0.0;
+        for (a, b) in self.planes().zip(other.planes()) {
+            total += a.ms_ssim(&b)?;
+        }
+        Ok(total / self.n_planes() as f64)
+    }
+}
+
+impl GrayFrame16 {
+    // The PSNR between the frame and another of the same dimensions, with a peak of 65535 like
+    // Plane::psnr.
+    pub fn psnr(&self, other: &GrayFrame16) -> Result<f64, FrameError> {
+        self.plane().psnr(&other.plane())
+    }
+
+    pub fn ssim(&self, other: &GrayFrame16, window: usize) -> Result<f64, FrameError> {
+        self.plane().ssim(&other.plane(), window)
+    }
+
+    pub fn ms_ssim(&self, other: &GrayFrame16) -> Result<f64, FrameError> {
+        self.plane().ms_ssim(&other.plane())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_psnr() {
+        assert_eq!(psnr(0.0), f64::INFINITY);
+        assert!((psnr(65535.0 * 65535.0)).abs() < 1e-9);
+        assert!((psnr(1.0) - 96.3296).abs() < 1e-3);
+    }
+
+    fn test_frame(width: usize, height: usize) -> RGB48Frame {
+        let data = (0..width * height * 3)
+            .map(|i| (i * 7919 % 60000) as u16 + 1000)
+            .collect();
+        RGB48Frame::from_raw(data, width, height, 3).unwrap()
+    }
+
+    #[test]
+    fn test_frame_psnr() {
+        let frame = test_frame(40, 30);
+        let identical = frame.psnr(&frame.clone()).unwrap();
+        assert_eq!(identical.planes, vec![f64::INFINITY; 3]);
+        assert_eq!(identical.combined, f64::INFINITY);
+
+        // offsetting red by 100 and blue by 10 gives mean squared errors of 10000, 0, and 100
+        let mut offset = frame.clone();
+        for pixel in offset.data.chunks_exact_mut(3) {
+            pixel[0] += 100;
+            pixel[2] -= 10;
+        }
+        let result = frame.psnr(&offset).unwrap();
+        let expected = |mse: f64| 20.0 * 65535f64.log10() - 10.0 * mse.log10();
+        assert!((result.planes[0] - expected(10000.0)).abs() < 1e-9);
+        assert_eq!(result.planes[1], f64::INFINITY);
+        assert!((result.planes[2] - expected(100.0)).abs() < 1e-9);
+        assert!((result.combined - expected(10100.0 / 3.0)).abs() < 1e-9);
+
+        // a single sample off by 3 out of 8 gives a mean squared error of 9 / 8
+        let plane = frame.plane(1).view(3, 5, 4, 2).to_owned();
+        let mut changed = plane.to_owned();
+        changed.data[6] += 3;
+        assert!((plane.psnr(&changed).unwrap() - 95.8179).abs() < 1e-4);
+
+        // planes work the same way, whatever their strides
+        let red = frame.plane(0);
+        assert_eq!(red.psnr(&offset.plane(0)).unwrap(), result.planes[0]);
+        assert_eq!(
+            red.to_owned().psnr(&offset.plane(0)).unwrap(),
+            result.planes[0]
+        );
+    }
+
+    #[test]
+    fn test_psnr_mismatch() {
+        let frame = test_frame(40, 30);
+        assert_eq!(
+            frame.psnr(&test_frame(30, 40)),
+            Err(FrameError::DimensionMismatch {
+                expected: (40, 30),
+                actual: (30, 40)
+            })
+        );
+        let rgba = RGB48Frame::from_raw(vec![0; 40 * 30 * 4], 40, 30, 4).unwrap();
+        assert_eq!(
+            frame.psnr(&rgba),
+            Err(FrameError::ChannelMismatch {
+                expected: 3,
+                actual: 4
+            })
+        );
+        assert!(matches!(
+            frame.plane(0).psnr(&frame.plane(1).view(0, 0, 40, 29)),
+            Err(FrameError::DimensionMismatch { .. })
+        ));
+    }
+
+    // A smooth pattern with some detail, plus noise of the given amplitude.
+    fn noisy_plane(width: usize, height: usize, noise: u32, seed: u32) -> Plane<Vec<u16>> {
+        let mut state = seed;
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let n = if noise > 0 {
+                    ((state >> 8) % noise) as f64 - noise as f64 / 2.0
+                } else {
+                    0.0
+                };
+                let (fx, fy) = (x as f64, y as f64);
+                let value = 30000.0 + 15000.0 * (fx / 9.0).sin() * (fy / 13.0).cos() + n;
+                data.push(value.clamp(0.0, 65535.0) as u16);
+            }
+        }
+        Plane {
+            data,
+            width,
+            height,
+            sample_stride: 1,
+            row_stride: width,
+        }
+    }
+
+    const SLIGHT_SSIM: f64 = 0.9719517199435447;
+    const SLIGHT_MS_SSIM: f64 = 0.9975460302918429;
+
+    #[test]
+    fn test_ssim() {
+        let clean = noisy_plane(96, 64, 0, 1);
+        for &window in [1, 7, 8, 200].iter() {
+            assert_eq!(clean.ssim(&clean, window).unwrap(), 1.0);
+        }
+
+        let slightly = noisy_plane(96, 64, 2000, 2);
+        let heavily = noisy_plane(96, 64, 60000, 3);
+        let slight = clean.ssim(&slightly, 8).unwrap();
+        let heavy = clean.ssim(&heavily, 8).unwrap();
+        assert!(slight > 0.9, "{}", slight);
+        assert!(heavy < 0.3, "{}", heavy);
+        assert_eq!(heavily.ssim(&clean, 8).unwrap(), heavy);
+
+        // pins the formula, including its handling of the edges
+        assert!((slight - SLIGHT_SSIM).abs() < 1e-12, "{}", slight);
+
+        // strided planes give the same results as contiguous ones
+        let mut interleaved = Vec::new();
+        for (&a, &b) in clean.data.iter().zip(slightly.data.iter()) {
+            interleaved.extend_from_slice(&[a, b, 0]);
+        }
+        let frame = RGB48Frame::from_raw(interleaved, 96, 64, 3).unwrap();
+        assert_eq!(frame.plane(0).ssim(&frame.plane(1), 8).unwrap(), slight);
+        assert_eq!(frame.ssim(&frame, 8).unwrap(), 1.0);
+
+        assert!(matches!(
+            clean.ssim(&clean.view(0, 0, 95, 64), 8),
+            Err(FrameError::DimensionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_ms_ssim() {
+        let clean = noisy_plane(96, 64, 0, 1);
+        assert_eq!(clean.ms_ssim(&clean).unwrap(), 1.0);
+
+        // more noise scores lower, as with SSIM, but noise that averages away at coarser scales
+        // costs less than it does in SSIM
+        let slightly = noisy_plane(96, 64, 2000, 2);
+        let heavily = noisy_plane(96, 64, 60000, 3);
+        let slight = clean.ms_ssim(&slightly).unwrap();
+        let heavy = clean.ms_ssim(&heavily).unwrap();
+        assert!(heavy < slight && slight < 1.0, "{} {}", heavy, slight);
+        assert!(slight > clean.ssim(&slightly, MS_SSIM_WINDOW).unwrap());
+        assert_eq!(slightly.ms_ssim(&clean).unwrap(), slight);
+
+        // pins the combination of scales
+        assert!((slight - SLIGHT_MS_SSIM).abs() < 1e-12, "{}", slight);
+
+        // 32x32 only has room for 3 scales of 8x8 windows, and tiny planes for 1
+        let small = clean.view(0, 0, 32, 32);
+        let small_ms_ssim = small.ms_ssim(&slightly.view(0, 0, 32, 32)).unwrap();
+        assert!(small_ms_ssim > 0.0 && small_ms_ssim < 1.0);
+        let tiny = clean.view(0, 0, 3, 1);
+        assert_eq!(
+            tiny.ms_ssim(&slightly.view(0, 0, 3, 1)).unwrap(),
+            tiny.ssim(&slightly.view(0, 0, 3, 1), MS_SSIM_WINDOW)
+                .unwrap()
+        );
+
+        assert!(matches!(
+            clean.ms_ssim(&clean.view(0, 0, 95, 64)),
+            Err(FrameError::DimensionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_psnr_map() {
+        // 40x30 in 16x16 blocks, so the right column and bottom row of blocks are partial
+        let frame = test_frame(40, 30);
+        let plane = frame.plane(0);
+        let map = plane.psnr_map(&plane, 16, 16).unwrap();
+        assert_eq!(map.len(), 3 * 2);
+        assert_eq!(map.min(), f64::INFINITY);
+        assert_eq!(map.percentile(0.0), f64::INFINITY);
+        assert!(map
+            .heatmap(HeatmapScale::Linear)
+            .data
+            .iter()
+            .all(|&x| x == 0));
+
+        // alternately add and subtract 30 throughout the 8x14 block at the bottom right, for a mean
+        // squared error of 900 there and nowhere else
+        let mut damaged = plane.to_owned();
+        damaged
+            .view_mut(32, 16, 8, 14)
+            .map_samples(|x| if x % 2 == 0 { x + 30 } else { x - 30 });
+        let map = plane.psnr_map(&damaged, 16, 16).unwrap();
+        assert_eq!(map.min(), psnr(900.0));
+        assert_eq!(
+            map.worst(),
+            Some(BlockPos {
+                col: 2,
+                row: 1,
+                x: 32,
+                y: 16
+            })
+        );
+        for i in 0..5 {
+            assert_eq!(map.psnr(i), f64::INFINITY);
+        }
+        assert_eq!(map.percentile(0.0), psnr(900.0));
+        assert_eq!(map.percentile(100.0 / 6.0), psnr(900.0));
+        assert_eq!(map.percentile(50.0), f64::INFINITY);
+
+        let heatmap = map.heatmap(HeatmapScale::Linear);
+        assert_eq!((heatmap.width, heatmap.height), (40, 30));
+        for (i, &x) in heatmap.data.iter().enumerate() {
+            let inside = i % 40 >= 32 && i / 40 >= 16;
+            assert_eq!(x, if inside { 65535 } else { 0 }, "{}", i);
+        }
+
+        assert!(matches!(
+            plane.psnr_map(&plane.view(0, 0, 40, 29), 16, 16),
+            Err(FrameError::DimensionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_ssim_large() {
+        // the cost per sample doesn't grow with the window, so large windows over large planes are
+        // still quick, and they average away more of the noise
+        let (a, b) = (
+            noisy_plane(1024, 428, 0, 1),
+            noisy_plane(1024, 428, 4000, 2),
+        );
+        let small = a.ssim(&b, 3).unwrap();
+        let large = a.ssim(&b, 64).unwrap();
+        assert!(small < large && large < 1.0, "{} {}", small, large);
+    }
+
+    #[test]
+    fn test_diff() {
+        let frame = test_frame(40, 30);
+        let report = frame.diff(&frame.clone()).unwrap();
+        assert!(report.is_empty());
+        assert_eq!(report.max_abs_error, 0);
+        assert_eq!(report.planes, vec![PlaneDiff::default(); 3]);
+        assert!(report.first.is_empty());
+        assert_eq!(report.to_string(), "no differences");
+
+        let mut changed = frame.clone();
+        changed.data[(7 * 40 + 3) * 3 + 2] += 5;
+        changed.data[(20 * 40 + 39) * 3] -= 300;
+        let report = frame.diff(&changed).unwrap();
+        assert_eq!(report.differing, 2);
+        assert_eq!(report.max_abs_error, 300);
+        assert_eq!(
+            report.planes,
+            vec![
+                PlaneDiff {
+                    differing: 1,
+                    max_abs_error: 300
+                },
+                PlaneDiff::default(),
+                PlaneDiff {
+                    differing: 1,
+                    max_abs_error: 5
+                },
+            ]
+        );
+        let expected = |i: usize| frame.data[i];
+        assert_eq!(
+            report.first,
+            vec![
+                SampleDiff {
+                    col: 3,
+                    row: 7,
+                    plane: 2,
+                    expected: expected((7 * 40 + 3) * 3 + 2),
+                    actual: expected((7 * 40 + 3) * 3 + 2) + 5,
+                },
+                SampleDiff {
+                    col: 39,
+                    row: 20,
+                    plane: 0,
+                    expected: expected((20 * 40 + 39) * 3),
+                    actual: expected((20 * 40 + 39) * 3) - 300,
+                },
+            ]
+        );
+        assert_eq!(report.first[0].delta(), 5);
+        assert_eq!(report.first[1].delta(), -300);
+        assert_eq!(
+            report.to_string(),
+            format!(
+                "2 samples differ, max error 300\n  plane 0: 1 differ, max error 300\n  plane 1: 0 \
+                 differ, max error 0\n  plane 2: 1 differ, max error 5\n  (3, 7) plane 2: expected \
+                 {}, got {} (+5)\n  (39, 20) plane 0: expected {}, got {} (-300)\n",
+                report.first[0].expected,
+                report.first[0].actual,
+                report.first[1].expected,
+                report.first[1].actual
+            )
+        );
+
+        // only the first few are listed, but all are counted
+        let mut shifted = frame.clone();
+        shifted.data.iter_mut().for_each(|x| *x += 1);
+        let report = frame.diff_with_limit(&shifted, 1).unwrap();
+        assert_eq!(report.differing, 40 * 30 * 3);
+        assert_eq!(report.first.len(), 1);
+        assert!(report.to_string().ends_with("  and 3599 more\n"));
+
+        assert!(matches!(
+            frame.diff(&test_frame(30, 40)),
+            Err(FrameError::DimensionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_approx_eq() {
+        let frame = test_frame(40, 30);
+        assert!(frame.approx_eq(&frame, 0));
+        frame.assert_approx_eq(&frame, 0);
+
+        let mut changed = frame.clone();
+        changed.data[(5 * 40 + 9) * 3 + 1] += 3;
+        changed.data[(6 * 40 + 2) * 3] -= 2;
+        assert!(frame.approx_eq(&changed, 3));
+        frame.assert_approx_eq(&changed, 3);
+        assert!(!frame.approx_eq(&changed, 2));
+        assert!(!frame.approx_eq(&changed, 0));
+
+        let message = std::panic::catch_unwind(|| frame.assert_approx_eq(&changed, 2))
+            .unwrap_err()
+            .downcast::<String>()
+            .unwrap();
+        assert_eq!(
+            *message,
+            format!(
+                "frames differ at (9, 5) plane 1: expected {}, got {}, an error of 3 when at most \
+                 2 is allowed",
+                frame.data[(5 * 40 + 9) * 3 + 1],
+                changed.data[(5 * 40 + 9) * 3 + 1]
+            )
+        );
+
+        // frames of different shapes are never equal
+        assert!(!frame.approx_eq(&test_frame(30, 40), u16::MAX));
+        let message =
+            std::panic::catch_unwind(|| frame.assert_approx_eq(&test_frame(30, 40), u16::MAX))
+                .unwrap_err()
+                .downcast::<String>()
+                .unwrap();
+        assert_eq!(*message, "frames differ: expected a 40x30 plane, got 30x40");
+    }
+    #[test]
+    fn test_gray_metrics() {
+        let frame = test_frame(40, 30);
+        let gray = GrayFrame16::from_raw(frame.plane(0).to_owned().data, 40, 30).unwrap();
+        let mut offset = gray.clone();
+        offset.data.iter_mut().for_each(|x| *x += 100);
+        assert_eq!(gray.psnr(&gray).unwrap(), f64::INFINITY);
+        assert_eq!(
+            gray.psnr(&offset).unwrap(),
+            frame.plane(0).psnr(&offset.plane()).unwrap()
+        );
+        assert!((gray.psnr(&offset).unwrap() - psnr(10000.0)).abs() < 1e-9);
+        assert_eq!(gray.ssim(&gray, 8).unwrap(), 1.0);
+        assert!(gray.ssim(&offset, 8).unwrap() < 1.0);
+
+        let small = GrayFrame16::from_raw(vec![0; 6], 3, 2).unwrap();
+        assert!(gray.psnr(&small).is_err());
+    }
+    #[test]
+    fn test_diff_heatmap() {
+        let frame = test_frame(7, 5);
+        for &scale in [HeatmapScale::Linear, HeatmapScale::Log].iter() {
+            let heatmap = frame.diff_heatmap(&frame, scale).unwrap();
+            assert_eq!(
+                (heatmap.width, heatmap.height, heatmap.bit_depth),
+                (7, 5, 8)
+            );
+            assert!(heatmap.data.iter().all(|&x| x == 0));
+        }
+
+        // one differing pixel is the brightest
+        let mut changed = frame.clone();
+        changed.data[(2 * 7 + 4) * 3 + 1] += 3;
+        for &scale in [HeatmapScale::Linear, HeatmapScale::Log].iter() {
+            let heatmap = frame.diff_heatmap(&changed, scale).unwrap();
+            for (i, &x) in heatmap.data.iter().enumerate() {
+                assert_eq!(x, if i == 2 * 7 + 4 { 65535 } else { 0 });
+            }
+        }
+
+        // smaller differences are darker, and much less so on a log scale
+        changed.data[1] += 100;
+        changed.data[2] -= 400;
+        let (a, b) = (frame.plane(2), changed.plane(2));
+        let heatmap = a.diff_heatmap(&b, HeatmapScale::Linear).unwrap();
+        assert_eq!(heatmap.data[..2], [255 * 257, 0]);
+        let heatmap = frame.diff_heatmap(&changed, HeatmapScale::Linear).unwrap();
+        assert_eq!(heatmap.data[2 * 7 + 4], 2 * 257);
+        let green = frame
+            .plane(1)
+            .diff_heatmap(&changed.plane(1), HeatmapScale::Linear);
+        assert_eq!(green.unwrap().data[0], 255 * 257);
+        let heatmap = frame.diff_heatmap(&changed, HeatmapScale::Log).unwrap();
+        assert_eq!(heatmap.data[0], 255 * 257);
+        assert_eq!(heatmap.data[2 * 7 + 4], 59 * 257);
+
+        assert!(matches!(
+            frame
+                .plane(0)
+                .diff_heatmap(&changed.plane(0).view(0, 0, 7, 4), HeatmapScale::Linear),
+            Err(FrameError::DimensionMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,296 @@
+use super::{
+    bitstream::{Bitstream, BitstreamWriter},
+    codec::{decode_value, encode_value},
+    error::{CodecError, Result},
+    frame::RGB48Frame,
+    sequence::{fold_delta, unfold_delta},
+};
+use std::io::{self, Read, Write};
+
+// Motion compensation works on square blocks of this many samples. Blocks along the right and
+// bottom edges are smaller if the frame dimensions aren't multiples of it.
+pub const BLOCK_SIZE: usize = 16;
+
+// Motion vectors are searched for within this many samples in each direction.
+pub const SEARCH_RANGE: i32 = 8;
+
+// The Rice parameter used to code motion vector components, which are usually small.
+const MV_K: u32 = 1;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MotionVector {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Block {
+    // The block is coded without reference to the previous frame.
+    Intra,
+    // The block is predicted by the block of the previous frame at this offset from its position.
+    Inter(MotionVector),
+}
+
+// One Block per BLOCK_SIZE×BLOCK_SIZE block of a frame, in raster order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MotionField {
+    pub blocks_wide: usize,
+    pub blocks_high: usize,
+    pub blocks: Vec<Block>,
+}
+
+fn block_rect(bx: usize, by: usize, width: usize, height: usize) -> (usize, usize, usize, usize) {
+    let (x, y) = (bx * BLOCK_SIZE, by * BLOCK_SIZE);
+    (x, y, BLOCK_SIZE.min(width - x), BLOCK_SIZE.min(height - y))
+}
+
+// Returns the index of the first sample of the reference for (x, y). References outside of the
+// frame are clamped to its edges, so that blocks along the edges can follow motion that reveals new
+// content.
+fn reference_index(x: usize, y: usize, mv: MotionVector, width: usize, height: usize) -> usize {
+    let rx = (x as i32 + mv.x).clamp(0, width as i32 - 1) as usize;
+    let ry = (y as i32 + mv.y).clamp(0, height as i32 - 1) as usize;
+    ry * width + rx
+}
+
+impl MotionField {
+    fn new(width: usize, height: usize) -> Self {
+        let blocks_wide = width.div_ceil(BLOCK_SIZE);
+        let blocks_high = height.div_ceil(BLOCK_SIZE);
+        Self {
+            blocks_wide,
+            blocks_high,
+            blocks: Vec::with_capacity(blocks_wide * blocks_high),
+        }
+    }
+
+    // Motion vectors are coded as differences from the vector of the block to the left, or if
+    // that isn't an inter block, the one above.
+    fn predicted_vector(&self, index: usize) -> MotionVector {
+        let left = if !index.is_multiple_of(self.blocks_wide) {
+            Some(self.blocks[index - 1])
+        } else {
+            None
+        };
+        let top = if index >= self.blocks_wide {
+            Some(self.blocks[index - self.blocks_wide])
+        } else {
+            None
+        };
+        match (left, top) {
+            (Some(Block::Inter(mv)), _) | (_, Some(Block::Inter(mv))) => mv,
+            _ => MotionVector::default(),
+        }
+    }
+
+    // Searches the previous frame for the best integer-sample match for each block of the frame.
+    // Blocks for which no candidate beats a rough estimate of the block's intra cost are escaped
+    // to intra coding.
+    pub fn estimate(frame: &RGB48Frame, previous: &RGB48Frame) -> Self {
+        let (width, height) = (frame.width, frame.height);
+        let channels = frame.channels();
+        let mut field = Self::new(width, height);
+
+        for by in 0..field.blocks_high {
+            for bx in 0..field.blocks_wide {
+                let (x0, y0, w, h) = block_rect(bx, by, width, height);
+
+                // the cost of coding each sample against its left (or top) neighbor
+                let mut intra_cost = 0;
+                for y in y0..y0 + h {
+                    for x in x0..x0 + w {
+                        let neighbor = if x > x0 {
+                            (y * width + x - 1) * channels
+                        } else if y > y0 {
+                            ((y - 1) * width + x) * channels
+                        } else {
+                            continue;
+                        };
+                        let i = (y * width + x) * channels;
+                        for c in 0..channels {
+                            intra_cost += (frame.data[i + c] as i32
+                                - frame.data[neighbor + c] as i32)
+                                .unsigned_abs() as u64;
+                        }
+                    }
+                }
+
+                let mut best: Option<(u64, MotionVector)> = None;
+                // (0, 0) goes first so that it wins ties
+                let candidates = std::iter::once((0, 0)).chain(
+                    (-SEARCH_RANGE..=SEARCH_RANGE)
+                        .flat_map(|my| (-SEARCH_RANGE..=SEARCH_RANGE).map(move |mx| (mx, my))),
+                );
+                for (mx, my) in candidates {
+                    let mv = MotionVector { x: mx, y: my };
+                    let limit = best.map(|(sad, _)| sad).unwrap_or(u64::MAX);
+                    let mut sad = 0;
+                    for y in y0..y0 + h {
+                        for x in x0..x0 + w {
+                            let i = (y * width + x) * channels;
+                            let r = reference_index(x, y, mv, width, height) * channels;
+                            for c in 0..channels {
+                                sad += (frame.data[i + c] as i32 - previous.data[r + c] as i32)
+                                    .unsigned_abs() as u64;
+                            }
+                        }
+                        if sad >= limit {
+                            break;
+                        }
+                    }
+                    if sad < limit {
+                        best = Some((sad, mv));
+                    }
+                }
+
+                field.blocks.push(match best {
+                    Some((sad, mv)) if sad <= intra_cost => Block::Inter(mv),
+                    _ => Block::Intra,
+                });
+            }
+        }
+
+        field
+    }
+
+    pub fn write<W: Write>(&self, dest: W) -> io::Result<()> {
+        let mut bitstream = BitstreamWriter::new(dest);
+        let mut field = Self {
+            blocks_wide: self.blocks_wide,
+            blocks_high: self.blocks_high,
+            blocks: Vec::with_capacity(self.blocks.len()),
+        };
+        for &block in &self.blocks {
+            match block {
+                Block::Intra => bitstream.write_bits(1, 1)?,
+                Block::Inter(mv) => {
+                    let prediction = field.predicted_vector(field.blocks.len());
+                    bitstream.write_bits(0, 1)?;
+                    encode_value(MV_K, mv.x - prediction.x, &mut bitstream)?;
+                    encode_value(MV_K, mv.y - prediction.y, &mut bitstream)?;
+                }
+            }
+            field.blocks.push(block);
+        }
+        bitstream.flush()
+    }
+
+    // Reads a motion field for a frame of the given dimensions, verifying that every motion vector
+    // is within the search range.
+    pub fn read<R: Read>(source: R, width: usize, height: usize) -> Result<Self> {
+        let mut bitstream = Bitstream::new(source);
+        let mut field = Self::new(width, height);
+        for _ in 0..field.blocks_wide * field.blocks_high {
+            let block = if bitstream.read_bits(1)? == 1 {
+                Block::Intra
+            } else {
+                let prediction = field.predicted_vector(field.blocks.len());
+                let mv = MotionVector {
+                    x: prediction
+                        .x
+                        .wrapping_add(decode_value(MV_K, &mut bitstream)?),
+                    y: prediction
+                        .y
+                        .wrapping_add(decode_value(MV_K, &mut bitstream)?),
+                };
+                if mv.x.abs() > SEARCH_RANGE || mv.y.abs() > SEARCH_RANGE {
+                    return Err(CodecError::InvalidData(
+                        "motion vector is outside of the search range".to_string(),
+                    ));
+                }
+                Block::Inter(mv)
+            };
+            field.blocks.push(block);
+        }
+        Ok(field)
+    }
+
+    // Calls f(frame index, reference index) for every sample of the frame, where the reference
+    // index is the motion-compensated sample of the previous frame, or None for intra blocks.
+    fn for_each_sample<F: FnMut(usize, Option<usize>)>(
+        &self,
+        width: usize,
+        height: usize,
+        channels: usize,
+        mut f: F,
+    ) {
+        for by in 0..self.blocks_high {
+            for bx in 0..self.blocks_wide {
+                let (x0, y0, w, h) = block_rect(bx, by, width, height);
+                let mv = match self.blocks[by * self.blocks_wide + bx] {
+                    Block::Intra => None,
+                    Block::Inter(mv) => Some(mv),
+                };
+                for y in y0..y0 + h {
+                    for x in x0..x0 + w {
+                        let i = (y * width + x) * channels;
+                        let r = mv.map(|mv| reference_index(x, y, mv, width, height) * channels);
+                        for c in 0..channels {
+                            f(i + c, r.map(|r| r + c));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Produces the frame that gets handed to the codec: inter blocks hold their folded difference
+    // from the motion-compensated reference, and intra blocks hold their samples unchanged.
+    pub fn residual_frame(&self, frame: &RGB48Frame, previous: &RGB48Frame) -> RGB48Frame {
+        let mut residual = frame.clone();
+        self.for_each_sample(frame.width, frame.height, frame.channels(), |i, r| {
+            if let Some(r) = r {
+                residual.data[i] = fold_delta(frame.data[i], previous.data[r]);
+            }
+        });
+        residual
+    }
+
+    // The inverse of residual_frame, reconstructing the frame in place.
+    pub fn reconstruct(&self, residual: &mut RGB48Frame, previous: &RGB48Frame) {
+        let (width, height, channels) = (residual.width, residual.height, residual.channels());
+        let data = &mut residual.data;
+        self.for_each_sample(width, height, channels, |i, r| {
+            if let Some(r) = r {
+                data[i] = unfold_delta(data[i], previous.data[r]);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_motion_field_roundtrip() {
+        let field = MotionField {
+            blocks_wide: 3,
+            blocks_high: 2,
+            blocks: vec![
+                Block::Inter(MotionVector { x: 3, y: 2 }),
+                Block::Inter(MotionVector { x: 3, y: 2 }),
+                Block::Intra,
+                Block::Intra,
+                Block::Inter(MotionVector { x: -8, y: 0 }),
+                Block::Inter(MotionVector { x: 0, y: 0 }),
+            ],
+        };
+        let mut encoded = Vec::new();
+        field.write(&mut encoded).unwrap();
+        assert_eq!(MotionField::read(&*encoded, 40, 32).unwrap(), field);
+    }
+
+    #[test]
+    fn test_motion_vector_out_of_range() {
+        let field = MotionField {
+            blocks_wide: 1,
+            blocks_high: 1,
+            blocks: vec![Block::Inter(MotionVector { x: 9, y: 0 })],
+        };
+        let mut encoded = Vec::new();
+        field.write(&mut encoded).unwrap();
+        let err = MotionField::read(&*encoded, 16, 16).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidData(_)), "{:?}", err);
+    }
+}
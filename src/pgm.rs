@@ -0,0 +1,177 @@
+use super::frame::{raw_sample_count, FrameOpenError, Plane, RGB48Frame};
+use std::io::{self, Read, Write};
+
+// Reads and writes binary PGM (P5) files: a short text header giving the dimensions and the
+// largest sample value, maxval, then the samples, one byte each if maxval is under 256 and two
+// big-endian bytes each otherwise.
+//
+// Samples are scaled from 0..=maxval to the full 16-bit range, rounding to nearest, so 8-bit files
+// are widened by 257 as for other formats. The frame's bit_depth is the number of bits maxval
+// needs. Frames are always written with a maxval of 65535.
+
+pub(crate) const MAGIC: [u8; 2] = *b"P5";
+
+fn invalid<S: Into<String>>(message: S) -> FrameOpenError {
+    FrameOpenError::InvalidPgm(message.into())
+}
+
+fn read_byte<R: Read>(source: &mut R) -> Result<u8, FrameOpenError> {
+    let mut b = [0];
+    source.read_exact(&mut b)?;
+    Ok(b[0])
+}
+
+// Reads a header field: a decimal number after any whitespace and comments. The single whitespace
+// byte that ends the number is consumed too.
+fn read_field<R: Read>(source: &mut R, name: &str) -> Result<u32, FrameOpenError> {
+    let mut b = read_byte(source)?;
+    loop {
+        match b {
+            b'#' => {
+                while b != b'\n' && b != b'\r' {
+                    b = read_byte(source)?;
+                }
+            }
+            _ if b.is_ascii_whitespace() => b = read_byte(source)?,
+            _ => break,
+        }
+    }
+    let mut value: u32 = 0;
+    if !b.is_ascii_digit() {
+        return Err(invalid(format!("expected the {}", name)));
+    }
+    while b.is_ascii_digit() {
+        value = value
+            .checked_mul(10)
+            .and_then(|value| value.checked_add((b - b'0') as u32))
+            .ok_or_else(|| invalid(format!("the {} is too large", name)))?;
+        b = read_byte(source)?;
+    }
+    if !b.is_ascii_whitespace() {
+        return Err(invalid(format!(
+            "the {} isn't followed by whitespace",
+            name
+        )));
+    }
+    Ok(value)
+}
+
+pub(crate) fn read<R: Read>(mut source: R) -> Result<RGB48Frame, FrameOpenError> {
+    let mut magic = [0; 2];
+    source.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid("not a binary pgm file"));
+    }
+    let width = read_field(&mut source, "width")? as usize;
+    let height = read_field(&mut source, "height")? as usize;
+    let maxval = read_field(&mut source, "maximum value")?;
+    if width == 0 || height == 0 {
+        return Err(invalid(format!("the image is {}x{}", width, height)));
+    }
+    if maxval == 0 || maxval > 65535 {
+        return Err(invalid(format!("invalid maximum value {}", maxval)));
+    }
+
+    let n = raw_sample_count(width, height, 1)?;
+    let wide = maxval > 255;
+    let len = if wide { n * 2 } else { n };
+    // the samples are read as they arrive rather than into a buffer of the size the header claims,
+    // which could be far more than the file holds
+    let mut bytes = Vec::new();
+    source.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the pgm file ended before its samples",
+        )
+        .into());
+    }
+    let maxval = maxval as u64;
+    let scale = |x: u16| -> Result<u16, FrameOpenError> {
+        match x as u64 {
+            x if x > maxval => Err(invalid(format!("sample {} exceeds the maximum value", x))),
+            x => Ok(((x * 65535 + maxval / 2) / maxval) as u16),
+        }
+    };
+    let data = if wide {
+        bytes
+            .chunks_exact(2)
+            .map(|b| scale(u16::from_be_bytes([b[0], b[1]])))
+            .collect::<Result<_, _>>()?
+    } else {
+        bytes
+            .iter()
+            .map(|&b| scale(b as u16))
+            .collect::<Result<_, _>>()?
+    };
+    Ok(RGB48Frame {
+        data,
+        width,
+        height,
+        bit_depth: (64 - maxval.leading_zeros()) as u8,
+    })
+}
+
+pub(crate) fn write<T: AsRef<[u16]>, W: Write>(plane: &Plane<T>, mut dest: W) -> io::Result<()> {
+    write!(dest, "P5\n{} {}\n65535\n", plane.width, plane.height)?;
+    let mut bytes = vec![0; plane.width * 2];
+    for row in plane.rows() {
+        for (bytes, x) in bytes.chunks_exact_mut(2).zip(row.iter()) {
+            bytes.copy_from_slice(&x.to_be_bytes());
+        }
+        dest.write_all(&bytes)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pgm() {
+        let frame = read(&b"P5 # a comment\n3\t1\n255\n\x00\x01\xff"[..]).unwrap();
+        assert_eq!((frame.width, frame.height, frame.bit_depth), (3, 1, 8));
+        assert_eq!(frame.data, vec![0, 257, 65535]);
+
+        // 10-bit samples are scaled, not shifted
+        let frame = read(&b"P5\n2 1\n1023\n\x00\x01\x03\xff"[..]).unwrap();
+        assert_eq!(frame.bit_depth, 10);
+        assert_eq!(frame.data, vec![64, 65535]);
+
+        // every other sample of a 2x2 region, so rows aren't contiguous
+        let data: Vec<u16> = (0..12).map(|i| i * 5000).collect();
+        let plane = Plane::with_padding(&data, 3, 2, 2, 6).unwrap();
+        let mut pgm = Vec::new();
+        write(&plane, &mut pgm).unwrap();
+        assert!(pgm.starts_with(b"P5\n3 2\n65535\n"));
+        let frame = read(&*pgm).unwrap();
+        assert_eq!((frame.width, frame.height, frame.bit_depth), (3, 2, 16));
+        assert_eq!(frame.data, plane.to_owned().data);
+
+        for bad in [
+            &b"P6\n1 1\n255\n\x00"[..],
+            b"P5\n0 1\n255\n",
+            b"P5\n1 1\n65536\n\x00\x00",
+            b"P5\n1 1\n100\n\x65",
+            b"P5\n1 x\n255\n\x00",
+        ]
+        .iter()
+        {
+            match read(*bad) {
+                Err(FrameOpenError::InvalidPgm(_)) => {}
+                other => panic!("unexpected result: {:?}", other.map(|_| ())),
+            }
+        }
+        match read(&b"P5\n2 1\n255\n\x00"[..]) {
+            Err(FrameOpenError::IO(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+
+        // a header claiming 8 GB of samples, with none after it
+        match read(&b"P5 65535 65535 65535\n\x00\x00"[..]) {
+            Err(FrameOpenError::IO(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use super::error::{CodecError, Result};
+use std::{
+    io::{self, Write},
+    sync::mpsc::{sync_channel, Receiver, SyncSender},
+    thread,
+};
+
+// The size of the buffers handed to the writer thread by RGB48Frame::encode_pipelined.
+pub const BUFFER_SIZE: usize = 1 << 20;
+
+// The writer side of a pipeline. Bytes are collected into a buffer, and once it's full it's sent to
+// the writer thread and the buffer the thread finished with last is taken back. With two buffers,
+// one is being filled while the other is being written.
+struct Pipe {
+    buf: Vec<u8>,
+    buffer_size: usize,
+    full: SyncSender<Vec<u8>>,
+    empty: Receiver<Vec<u8>>,
+}
+
+fn writer_stopped() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "the writer thread stopped")
+}
+
+impl Pipe {
+    fn send(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let next = self.empty.recv().map_err(|_| writer_stopped())?;
+        let full = std::mem::replace(&mut self.buf, next);
+        self.full.send(full).map_err(|_| writer_stopped())
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.buffer_size - self.buf.len());
+        self.buf.extend_from_slice(&buf[..n]);
+        if self.buf.len() == self.buffer_size {
+            self.send()?;
+        }
+        Ok(n)
+    }
+
+    // Hands off what's been written so far without waiting for it to reach the destination. The
+    // destination itself is flushed once everything has been written.
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+// Calls f with a writer whose output is written to dest by another thread, in chunks of
+// buffer_size bytes, so that producing the output overlaps with writing it. The bytes reaching
+// dest are exactly those f writes. If writing to dest fails, f's writes start failing too, and the
+// error from dest is returned rather than whatever f made of it.
+pub fn pipelined<W, T, F>(mut dest: W, buffer_size: usize, f: F) -> Result<T>
+where
+    W: Write + Send,
+    F: FnOnce(&mut dyn Write) -> Result<T>,
+{
+    assert!(buffer_size > 0, "the buffer size must be nonzero");
+    thread::scope(|scope| {
+        let (full_sender, full_receiver) = sync_channel::<Vec<u8>>(1);
+        let (empty_sender, empty_receiver) = sync_channel(1);
+        empty_sender
+            .send(Vec::with_capacity(buffer_size))
+            .expect("the receiver is still alive");
+
+        let writer = scope.spawn(move || -> io::Result<()> {
+            for mut buf in full_receiver {
+                dest.write_all(&buf)?;
+                buf.clear();
+                // the pipe is gone once everything has been sent
+                let _ = empty_sender.send(buf);
+            }
+            dest.flush()
+        });
+
+        let mut pipe = Pipe {
+            buf: Vec::with_capacity(buffer_size),
+            buffer_size,
+            full: full_sender,
+            empty: empty_receiver,
+        };
+        let result = f(&mut pipe).and_then(|ret| {
+            pipe.send()?;
+            Ok(ret)
+        });
+        // closing the channel lets the writer thread finish
+        drop(pipe);
+
+        match writer.join() {
+            Ok(Ok(())) => result,
+            Ok(Err(e)) => Err(CodecError::Io(e)),
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{
+            codec::Codec,
+            frame::{EncodeOptions, RGB48Frame},
+            synth,
+        },
+        *,
+    };
+    use std::time::{Duration, Instant};
+
+    // Sleeps for a while on every write, like a slow disk or network.
+    struct SlowWriter {
+        data: Vec<u8>,
+        delay: Duration,
+        first_write: Option<Instant>,
+    }
+
+    impl Write for SlowWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.first_write.get_or_insert_with(Instant::now);
+            thread::sleep(self.delay);
+            self.data.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Fails once more than `limit` bytes have been written.
+    struct FailingWriter {
+        written: usize,
+        limit: usize,
+    }
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.written + buf.len() > self.limit {
+                return Err(io::Error::other("disk full"));
+            }
+            self.written += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn test_frame(width: usize, height: usize) -> RGB48Frame {
+        synth::zone_plate(width, height)
+    }
+
+    #[test]
+    fn test_pipelined_output() {
+        let frame = test_frame(128, 96);
+        for options in [
+            EncodeOptions::default(),
+            EncodeOptions {
+                tile_size: Some((100, 100)),
+                quantization: Some(vec![1, 4, 16]),
+                ..Default::default()
+            },
+        ]
+        .iter()
+        {
+            let mut expected = Vec::new();
+            frame
+                .encode_with_header::<Codec, _>(&mut expected, options)
+                .unwrap();
+
+            for &buffer_size in [1, 1000, BUFFER_SIZE].iter() {
+                let mut encoded = Vec::new();
+                pipelined(&mut encoded, buffer_size, |dest| {
+                    frame.encode_with_header::<Codec, _>(dest, options)
+                })
+                .unwrap();
+                assert!(encoded == expected, "buffer size {}", buffer_size);
+            }
+
+            let mut encoded = Vec::new();
+            frame
+                .encode_pipelined::<Codec, _>(&mut encoded, options)
+                .unwrap();
+            assert!(encoded == expected);
+        }
+    }
+
+    #[test]
+    fn test_pipelined_writer_error() {
+        let frame = test_frame(256, 256);
+        let mut dest = FailingWriter {
+            written: 0,
+            limit: 50_000,
+        };
+        let err = pipelined(&mut dest, 16 * 1024, |dest| {
+            frame.encode_with_header::<Codec, _>(dest, &Default::default())
+        })
+        .unwrap_err();
+        match err {
+            CodecError::Io(e) => assert_eq!(e.to_string(), "disk full"),
+            e => panic!("unexpected error: {:?}", e),
+        }
+        assert!(dest.written <= 50_000);
+    }
+
+    #[test]
+    fn test_pipelined_overlap() {
+        let frame = test_frame(512, 384);
+        let buffer_size = 64 * 1024;
+
+        let start = Instant::now();
+        let mut expected = Vec::new();
+        frame
+            .encode_with_header::<Codec, _>(&mut expected, &Default::default())
+            .unwrap();
+        let compute_time = start.elapsed();
+
+        // make writing take about as long as encoding
+        let n_writes = expected.len().div_ceil(buffer_size) as u32;
+        assert!(n_writes > 2);
+        let mut dest = SlowWriter {
+            data: Vec::new(),
+            delay: compute_time / n_writes,
+            first_write: None,
+        };
+
+        // comparing elapsed times is unreliable when other tests are competing for the cpu, so
+        // check that writing started before encoding finished instead
+        let mut encoded_at = None;
+        pipelined(&mut dest, buffer_size, |dest| {
+            let result = frame.encode_with_header::<Codec, _>(dest, &Default::default());
+            encoded_at = Some(Instant::now());
+            result
+        })
+        .unwrap();
+
+        assert!(dest.data == expected);
+        assert!(dest.first_write.unwrap() < encoded_at.unwrap());
+    }
+}
@@ -0,0 +1,261 @@
+use super::frame::{FrameOpenError, RGB48Frame};
+use std::{
+    collections::HashMap,
+    io::{Read, Seek, SeekFrom},
+};
+use tiff::{
+    tags::{CompressionMethod, PlanarConfiguration, Tag},
+    ColorType, TiffError, TiffFormatError, TiffUnsupportedError,
+};
+
+// The tiff crate only reads chunky TIFFs, where each pixel's samples are stored together, and
+// rejects files with a PlanarConfiguration of 2, which store every R sample, then every G, then
+// every B. This reads planar files with uncompressed 8- or 16-bit RGB strips, which is what most
+// tools write when asked for planar output.
+
+// The raw contents of an IFD entry: its type, count, and the 4 bytes holding either the value or
+// the offset it's stored at.
+#[derive(Clone, Copy)]
+struct Entry {
+    field_type: u16,
+    count: u32,
+    value: [u8; 4],
+}
+
+struct Reader<R> {
+    source: R,
+    big_endian: bool,
+}
+
+impl<R: Read + Seek> Reader<R> {
+    fn read_u16(&mut self) -> Result<u16, TiffError> {
+        let mut buf = [0; 2];
+        self.source.read_exact(&mut buf)?;
+        Ok(self.u16_from_bytes(buf))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, TiffError> {
+        let mut buf = [0; 4];
+        self.source.read_exact(&mut buf)?;
+        Ok(self.u32_from_bytes(buf))
+    }
+
+    fn u16_from_bytes(&self, buf: [u8; 2]) -> u16 {
+        if self.big_endian {
+            u16::from_be_bytes(buf)
+        } else {
+            u16::from_le_bytes(buf)
+        }
+    }
+
+    fn u32_from_bytes(&self, buf: [u8; 4]) -> u32 {
+        if self.big_endian {
+            u32::from_be_bytes(buf)
+        } else {
+            u32::from_le_bytes(buf)
+        }
+    }
+
+    // Reads the values of a SHORT or LONG entry.
+    fn values(&mut self, tag: Tag, entry: Entry) -> Result<Vec<u32>, TiffError> {
+        let size = match entry.field_type {
+            3 => 2,
+            4 => 4,
+            _ => return Err(TiffFormatError::InvalidTagValueType(tag).into()),
+        };
+        let count = entry.count as usize;
+        let bytes = if size * count <= 4 {
+            entry.value[..size * count].to_vec()
+        } else {
+            let offset = self.u32_from_bytes(entry.value);
+            self.source.seek(SeekFrom::Start(offset as u64))?;
+            let mut bytes = Vec::new();
+            (&mut self.source)
+                .take((size * count) as u64)
+                .read_to_end(&mut bytes)?;
+            if bytes.len() != size * count {
+                return Err(TiffFormatError::InconsistentSizesEncountered.into());
+            }
+            bytes
+        };
+        Ok(bytes
+            .chunks_exact(size)
+            .map(|b| match size {
+                2 => self.u16_from_bytes([b[0], b[1]]) as u32,
+                _ => self.u32_from_bytes([b[0], b[1], b[2], b[3]]),
+            })
+            .collect())
+    }
+}
+
+struct Ifd<R> {
+    reader: Reader<R>,
+    entries: HashMap<u16, Entry>,
+}
+
+impl<R: Read + Seek> Ifd<R> {
+    fn find(&mut self, tag: Tag) -> Result<Option<Vec<u32>>, TiffError> {
+        match self.entries.get(&tag.to_u16()) {
+            Some(&entry) => Ok(Some(self.reader.values(tag, entry)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn require(&mut self, tag: Tag) -> Result<Vec<u32>, TiffError> {
+        self.find(tag)?.ok_or(TiffError::FormatError(
+            TiffFormatError::RequiredTagNotFound(tag),
+        ))
+    }
+
+    fn require_one(&mut self, tag: Tag) -> Result<u32, TiffError> {
+        self.find_one(tag)?.ok_or(TiffError::FormatError(
+            TiffFormatError::RequiredTagNotFound(tag),
+        ))
+    }
+
+    fn find_one(&mut self, tag: Tag) -> Result<Option<u32>, TiffError> {
+        match self.find(tag)? {
+            Some(values) if values.len() == 1 => Ok(Some(values[0])),
+            Some(_) => Err(TiffFormatError::InvalidTagValueType(tag).into()),
+            None => Ok(None),
+        }
+    }
+}
+
+// Reads the first image of source if it's planar. If it isn't, or source isn't a classic TIFF at
+// all, Ok(None) is returned and it's left to the tiff crate.
+pub(crate) fn read<R: Read + Seek>(source: R) -> Result<Option<RGB48Frame>, FrameOpenError> {
+    let mut reader = Reader {
+        source,
+        big_endian: false,
+    };
+    reader.source.seek(SeekFrom::Start(0))?;
+    let mut magic = [0; 4];
+    reader.source.read_exact(&mut magic)?;
+    reader.big_endian = match magic {
+        [b'I', b'I', 42, 0] => false,
+        [b'M', b'M', 0, 42] => true,
+        _ => return Ok(None),
+    };
+    let ifd_offset = reader.read_u32()?;
+    reader.source.seek(SeekFrom::Start(ifd_offset as u64))?;
+    let mut entries = HashMap::new();
+    for _ in 0..reader.read_u16()? {
+        let tag = reader.read_u16()?;
+        let field_type = reader.read_u16()?;
+        let count = reader.read_u32()?;
+        let mut value = [0; 4];
+        reader.source.read_exact(&mut value)?;
+        entries.insert(
+            tag,
+            Entry {
+                field_type,
+                count,
+                value,
+            },
+        );
+    }
+    let mut ifd = Ifd { reader, entries };
+
+    if ifd.find_one(Tag::PlanarConfiguration)? != Some(PlanarConfiguration::Planar.to_u16() as u32)
+    {
+        return Ok(None);
+    }
+
+    let width = ifd.require_one(Tag::ImageWidth)? as usize;
+    let height = ifd.require_one(Tag::ImageLength)? as usize;
+    let bits = ifd.require(Tag::BitsPerSample)?;
+    let samples = ifd.find_one(Tag::SamplesPerPixel)?.unwrap_or(1);
+    let photometric = ifd.require_one(Tag::PhotometricInterpretation)?;
+    if samples != 3 || photometric != 2 || bits.len() != 3 || bits.iter().any(|&b| b != bits[0]) {
+        return Err(
+            TiffError::from(TiffUnsupportedError::UnsupportedPlanarConfig(Some(
+                PlanarConfiguration::Planar,
+            )))
+            .into(),
+        );
+    }
+    let bit_depth = bits[0] as u8;
+    if bit_depth != 8 && bit_depth != 16 {
+        return Err(FrameOpenError::UnsupportedColorType(ColorType::RGB(
+            bit_depth,
+        )));
+    }
+    if ifd.find_one(Tag::SampleFormat)?.unwrap_or(1) != 1 {
+        return Err(FrameOpenError::UnsupportedSampleType);
+    }
+    if ifd.find_one(Tag::Predictor)?.unwrap_or(1) != 1 {
+        return Err(
+            TiffError::from(TiffUnsupportedError::HorizontalPredictor(ColorType::RGB(
+                bit_depth,
+            )))
+            .into(),
+        );
+    }
+    match ifd.find_one(Tag::Compression)?.unwrap_or(1) as u16 {
+        1 => {}
+        compression => {
+            return Err(
+                TiffError::from(match CompressionMethod::from_u16(compression) {
+                    Some(method) => TiffUnsupportedError::UnsupportedCompressionMethod(method),
+                    None => TiffUnsupportedError::UnknownCompressionMethod,
+                })
+                .into(),
+            )
+        }
+    }
+
+    let rows_per_strip =
+        (ifd.find_one(Tag::RowsPerStrip)?.unwrap_or(u32::MAX) as usize).min(height);
+    let offsets = ifd.require(Tag::StripOffsets)?;
+    let byte_counts = ifd.require(Tag::StripByteCounts)?;
+    let len = width.checked_mul(height).and_then(|n| n.checked_mul(3));
+    if width == 0 || height == 0 || rows_per_strip == 0 || len.is_none() {
+        return Err(TiffError::from(TiffFormatError::InvalidDimensions(
+            width as u32,
+            height as u32,
+        ))
+        .into());
+    }
+    let strips_per_plane = height.div_ceil(rows_per_strip);
+    if offsets.len() != 3 * strips_per_plane || byte_counts.len() != offsets.len() {
+        return Err(TiffError::from(TiffFormatError::InconsistentSizesEncountered).into());
+    }
+
+    let bytes_per_sample = bit_depth as usize / 8;
+    let mut data = vec![0; width * height * 3];
+    let mut strip = Vec::new();
+    for (i, (&offset, &byte_count)) in offsets.iter().zip(byte_counts.iter()).enumerate() {
+        let (plane, first_row) = (i / strips_per_plane, i % strips_per_plane * rows_per_strip);
+        let rows = rows_per_strip.min(height - first_row);
+        let len = rows * width * bytes_per_sample;
+        if (byte_count as usize) < len {
+            return Err(TiffError::from(TiffFormatError::InconsistentSizesEncountered).into());
+        }
+        strip.resize(len, 0);
+        ifd.reader.source.seek(SeekFrom::Start(offset as u64))?;
+        ifd.reader.source.read_exact(&mut strip)?;
+
+        let dest = data[first_row * width * 3..(first_row + rows) * width * 3]
+            .iter_mut()
+            .skip(plane)
+            .step_by(3);
+        if bit_depth == 16 {
+            for (sample, b) in dest.zip(strip.chunks_exact(2)) {
+                *sample = ifd.reader.u16_from_bytes([b[0], b[1]]);
+            }
+        } else {
+            // widened the same way as chunky 8-bit files
+            for (sample, &b) in dest.zip(strip.iter()) {
+                *sample = b as u16 * 257;
+            }
+        }
+    }
+
+    Ok(Some(RGB48Frame {
+        data,
+        width,
+        height,
+        bit_depth,
+    }))
+}
@@ -0,0 +1,447 @@
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression, Crc};
+use std::io::{self, Read, Write};
+use thiserror::Error;
+
+// Just enough of PNG to load and save frames. Decoding handles non-interlaced images of any color
+// type and bit depth, with ancillary chunks ignored. Samples are left as the big-endian bytes PNG
+// stores them as.
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+#[derive(Error, Debug)]
+pub enum PngError {
+    #[error(transparent)]
+    IO(#[from] io::Error),
+    #[error("not a png file")]
+    InvalidSignature,
+    #[error("invalid png: {0}")]
+    InvalidData(String),
+    #[error("unsupported png: {0}")]
+    Unsupported(String),
+}
+
+fn invalid_data<S: Into<String>>(message: S) -> PngError {
+    PngError::InvalidData(message.into())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorType {
+    Gray = 0,
+    RGB = 2,
+    Indexed = 3,
+    GrayAlpha = 4,
+    RGBA = 6,
+}
+
+impl ColorType {
+    fn from_u8(x: u8) -> Option<Self> {
+        match x {
+            0 => Some(Self::Gray),
+            2 => Some(Self::RGB),
+            3 => Some(Self::Indexed),
+            4 => Some(Self::GrayAlpha),
+            6 => Some(Self::RGBA),
+            _ => None,
+        }
+    }
+
+    pub fn channels(self) -> usize {
+        match self {
+            Self::Gray | Self::Indexed => 1,
+            Self::GrayAlpha => 2,
+            Self::RGB => 3,
+            Self::RGBA => 4,
+        }
+    }
+}
+
+// A decoded image. data holds the unfiltered scanlines back to back, each row_bytes() long.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    pub fn row_bytes(&self) -> usize {
+        row_bytes(self.width, self.color_type, self.bit_depth)
+    }
+}
+
+fn row_bytes(width: usize, color_type: ColorType, bit_depth: u8) -> usize {
+    (width * color_type.channels() * bit_depth as usize).div_ceil(8)
+}
+
+fn read_u32<R: Read>(mut source: R) -> Result<u32, PngError> {
+    let mut buf = [0; 4];
+    source.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+// Reads the next chunk, verifying its CRC, and returns its type and data.
+fn read_chunk<R: Read>(mut source: R, data: &mut Vec<u8>) -> Result<[u8; 4], PngError> {
+    let len = read_u32(&mut source)?;
+    if len > i32::MAX as u32 {
+        return Err(invalid_data("chunk is too long"));
+    }
+    let mut chunk_type = [0; 4];
+    source.read_exact(&mut chunk_type)?;
+    data.clear();
+    (&mut source).take(len as u64).read_to_end(data)?;
+    if data.len() != len as usize {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated png chunk").into());
+    }
+    let mut crc = Crc::new();
+    crc.update(&chunk_type);
+    crc.update(data);
+    if read_u32(&mut source)? != crc.sum() {
+        return Err(invalid_data(format!(
+            "bad crc for {} chunk",
+            String::from_utf8_lossy(&chunk_type)
+        )));
+    }
+    Ok(chunk_type)
+}
+
+fn write_chunk<W: Write>(mut dest: W, chunk_type: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut crc = Crc::new();
+    crc.update(chunk_type);
+    crc.update(data);
+    dest.write_all(&(data.len() as u32).to_be_bytes())?;
+    dest.write_all(chunk_type)?;
+    dest.write_all(data)?;
+    dest.write_all(&crc.sum().to_be_bytes())
+}
+
+// The most image data written in a single IDAT chunk.
+const IDAT_SIZE: usize = 64 * 1024;
+
+// Splits what's written to it into IDAT chunks.
+struct IdatWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> IdatWriter<W> {
+    fn finish(mut self) -> io::Result<W> {
+        if !self.buf.is_empty() {
+            write_chunk(&mut self.inner, b"IDAT", &self.buf)?;
+        }
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for IdatWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(IDAT_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..n]);
+        if self.buf.len() == IDAT_SIZE {
+            write_chunk(&mut self.inner, b"IDAT", &self.buf)?;
+            self.buf.clear();
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Reverses the per-row filters. filtered holds each row preceded by its filter type, and
+// the unfiltered rows are written to dest.
+fn unfilter(
+    filtered: &[u8],
+    row_bytes: usize,
+    bpp: usize,
+    dest: &mut Vec<u8>,
+) -> Result<(), PngError> {
+    let mut prev = vec![0; row_bytes];
+    for row in filtered.chunks_exact(row_bytes + 1) {
+        let (filter, row) = (row[0], &row[1..]);
+        let start = dest.len();
+        dest.extend_from_slice(row);
+        let cur = &mut dest[start..];
+        for i in 0..row_bytes {
+            let a = if i >= bpp { cur[i - bpp] } else { 0 };
+            let (b, c) = (prev[i], if i >= bpp { prev[i - bpp] } else { 0 });
+            cur[i] = cur[i].wrapping_add(match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(invalid_data(format!("invalid filter type: {}", filter))),
+            });
+        }
+        prev.copy_from_slice(cur);
+    }
+    Ok(())
+}
+
+pub fn decode<R: Read>(mut source: R) -> Result<Image, PngError> {
+    let mut signature = [0; 8];
+    source.read_exact(&mut signature)?;
+    if signature != SIGNATURE {
+        return Err(PngError::InvalidSignature);
+    }
+
+    let mut chunk = Vec::new();
+    if &read_chunk(&mut source, &mut chunk)? != b"IHDR" || chunk.len() != 13 {
+        return Err(invalid_data("missing IHDR chunk"));
+    }
+    let width = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as usize;
+    let height = u32::from_be_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as usize;
+    let bit_depth = chunk[8];
+    let color_type = ColorType::from_u8(chunk[9])
+        .ok_or_else(|| invalid_data(format!("invalid color type: {}", chunk[9])))?;
+    let valid_bit_depth = match color_type {
+        ColorType::Gray => [1, 2, 4, 8, 16].contains(&bit_depth),
+        ColorType::Indexed => [1, 2, 4, 8].contains(&bit_depth),
+        _ => [8, 16].contains(&bit_depth),
+    };
+    if width == 0 || height == 0 || !valid_bit_depth || chunk[10] != 0 || chunk[11] != 0 {
+        return Err(invalid_data("invalid IHDR chunk"));
+    }
+    if chunk[12] != 0 {
+        return Err(PngError::Unsupported("interlacing".to_string()));
+    }
+
+    let mut compressed = Vec::new();
+    loop {
+        let chunk_type = read_chunk(&mut source, &mut chunk)?;
+        match &chunk_type {
+            b"IDAT" => compressed.extend_from_slice(&chunk),
+            b"IEND" => break,
+            b"PLTE" => {}
+            _ if chunk_type[0].is_ascii_uppercase() => {
+                return Err(PngError::Unsupported(format!(
+                    "critical chunk {}",
+                    String::from_utf8_lossy(&chunk_type)
+                )))
+            }
+            _ => {}
+        }
+    }
+
+    let row_bytes = row_bytes(width, color_type, bit_depth);
+    let filtered_len = (row_bytes + 1)
+        .checked_mul(height)
+        .ok_or_else(|| invalid_data("image is too large"))?;
+    // never inflate more than the image can hold
+    let mut filtered = Vec::new();
+    ZlibDecoder::new(&*compressed)
+        .take(filtered_len as u64 + 1)
+        .read_to_end(&mut filtered)
+        .map_err(|e| invalid_data(format!("bad image data: {}", e)))?;
+    if filtered.len() != filtered_len {
+        return Err(invalid_data("image data is the wrong size"));
+    }
+
+    let bpp = (color_type.channels() * bit_depth as usize).div_ceil(8);
+    let mut data = Vec::with_capacity(row_bytes * height);
+    unfilter(&filtered, row_bytes, bpp, &mut data)?;
+    Ok(Image {
+        width,
+        height,
+        bit_depth,
+        color_type,
+        data,
+    })
+}
+
+// Encodes a non-interlaced image, calling fill_row for each row in turn to get its raw bytes.
+// Rows are filtered with the Paeth filter and compressed as they arrive, so the whole image never
+// has to be in memory at once.
+pub fn encode<W: Write, F: FnMut(usize, &mut [u8])>(
+    mut dest: W,
+    width: usize,
+    height: usize,
+    color_type: ColorType,
+    bit_depth: u8,
+    mut fill_row: F,
+) -> Result<(), PngError> {
+    if width == 0
+        || height == 0
+        || width > i32::MAX as usize
+        || height > i32::MAX as usize
+        || !(color_type == ColorType::Gray || color_type == ColorType::Indexed || bit_depth >= 8)
+        || ![1, 2, 4, 8, 16].contains(&bit_depth)
+    {
+        return Err(invalid_data(format!(
+            "can't encode a {}x{} {}-bit {:?} image",
+            width, height, bit_depth, color_type
+        )));
+    }
+
+    dest.write_all(&SIGNATURE)?;
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[bit_depth, color_type as u8, 0, 0, 0]);
+    write_chunk(&mut dest, b"IHDR", &ihdr)?;
+
+    let row_bytes = row_bytes(width, color_type, bit_depth);
+    let bpp = (color_type.channels() * bit_depth as usize).div_ceil(8);
+    let mut prev = vec![0; row_bytes];
+    let mut cur = vec![0; row_bytes];
+    let mut filtered = vec![0; row_bytes + 1];
+    filtered[0] = 4;
+    let mut encoder = ZlibEncoder::new(
+        IdatWriter {
+            inner: &mut dest,
+            buf: Vec::with_capacity(IDAT_SIZE),
+        },
+        Compression::default(),
+    );
+    for row in 0..height {
+        fill_row(row, &mut cur);
+        for i in 0..row_bytes {
+            let a = if i >= bpp { cur[i - bpp] } else { 0 };
+            let c = if i >= bpp { prev[i - bpp] } else { 0 };
+            filtered[i + 1] = cur[i].wrapping_sub(paeth(a, prev[i], c));
+        }
+        encoder.write_all(&filtered)?;
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    encoder.finish()?.finish()?;
+
+    write_chunk(&mut dest, b"IEND", &[])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The test image's samples, which cover all five filter types (row y uses filter y % 5).
+    fn expected_sample(x: usize, y: usize, channel: usize) -> u16 {
+        match channel {
+            0 => x * 1000 + y,
+            1 => y * 1300 + x * 7,
+            _ => x * y * 37,
+        }
+        .rem_euclid(65536) as u16
+    }
+
+    #[test]
+    fn test_png_decode() {
+        let image = decode(&*std::fs::read("src/testdata/gradient_rgb48.png").unwrap()).unwrap();
+        assert_eq!((image.width, image.height), (64, 48));
+        assert_eq!((image.color_type, image.bit_depth), (ColorType::RGB, 16));
+        assert_eq!(image.row_bytes(), 64 * 6);
+        assert_eq!(image.data.len(), 64 * 48 * 6);
+        for y in 0..48 {
+            for x in 0..64 {
+                for channel in 0..3 {
+                    let i = y * image.row_bytes() + (x * 3 + channel) * 2;
+                    assert_eq!(
+                        u16::from_be_bytes([image.data[i], image.data[i + 1]]),
+                        expected_sample(x, y, channel),
+                        "({}, {}) channel {}",
+                        x,
+                        y,
+                        channel
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_png_errors() {
+        let png = std::fs::read("src/testdata/gradient_rgb48.png").unwrap();
+
+        let mut corrupt = png.clone();
+        corrupt[0] = 0;
+        assert!(matches!(decode(&*corrupt), Err(PngError::InvalidSignature)));
+
+        // the IHDR chunk's data starts at 16, so this breaks its crc
+        let mut corrupt = png.clone();
+        corrupt[20] ^= 1;
+        assert!(matches!(decode(&*corrupt), Err(PngError::InvalidData(_))));
+
+        for &len in [4, 20, 100, png.len() - 13].iter() {
+            match decode(&png[..len]) {
+                Err(PngError::IO(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+                Err(e) => panic!("unexpected error for {} bytes: {:?}", len, e),
+                Ok(_) => panic!("decoded {} bytes", len),
+            }
+        }
+    }
+
+    #[test]
+    fn test_png_encode() {
+        let png = std::fs::read("src/testdata/gradient_rgb48.png").unwrap();
+        let image = decode(&*png).unwrap();
+
+        let mut encoded = Vec::new();
+        encode(&mut encoded, 64, 48, ColorType::RGB, 16, |row, dest| {
+            dest.copy_from_slice(&image.data[row * 384..(row + 1) * 384])
+        })
+        .unwrap();
+        // the header is the same as the original's
+        assert_eq!(encoded[..33], png[..33]);
+        let decoded = decode(&*encoded).unwrap();
+        assert_eq!(decoded.data, image.data);
+
+        // sub-byte depths and large images, which span several IDAT chunks
+        for &(width, height, color_type, bit_depth) in [
+            (13, 5, ColorType::Gray, 1),
+            (7, 3, ColorType::Gray, 4),
+            (300, 200, ColorType::RGBA, 16),
+        ]
+        .iter()
+        {
+            let row_bytes = row_bytes(width, color_type, bit_depth);
+            let data: Vec<u8> = (0..row_bytes * height)
+                .map(|i| (i * 7919 % 251) as u8)
+                .collect();
+            let mut encoded = Vec::new();
+            encode(
+                &mut encoded,
+                width,
+                height,
+                color_type,
+                bit_depth,
+                |row, dest| dest.copy_from_slice(&data[row * row_bytes..(row + 1) * row_bytes]),
+            )
+            .unwrap();
+            let decoded = decode(&*encoded).unwrap();
+            assert_eq!(
+                (
+                    decoded.width,
+                    decoded.height,
+                    decoded.color_type,
+                    decoded.bit_depth
+                ),
+                (width, height, color_type, bit_depth)
+            );
+            // padding bits at the end of each row aren't meaningful, so compare whole rows only
+            // where there's no padding
+            if (width * color_type.channels() * bit_depth as usize).is_multiple_of(8) {
+                assert_eq!(decoded.data, data);
+            }
+        }
+
+        let err = encode(Vec::new(), 4, 4, ColorType::RGB, 4, |_, _| {}).unwrap_err();
+        assert!(matches!(err, PngError::InvalidData(_)), "{:?}", err);
+    }
+}
@@ -0,0 +1,17 @@
+// The types and functions most programs need, for glob importing:
+//
+//     use hello_video_codec::prelude::*;
+//
+// Codec here is the standard codec, which encode_frame and friends use. The trait codecs implement
+// is frame::Codec, which is only needed to write generic code or a codec of one's own.
+pub use super::{
+    codec::Codec,
+    container::{VideoReader, VideoWriter},
+    decode_file, decode_frame, encode_file, encode_frame,
+    error::{CodecError, Error},
+    frame::{DecodeOptions, EncodeOptions, RGB48Frame},
+    frame16::Frame16,
+    gray::GrayFrame16,
+    metadata::SequenceMetadata,
+    EncodeReport,
+};
@@ -0,0 +1,182 @@
+use std::io::{self, Read, Write};
+
+// Reported to progress callbacks as encoding or decoding proceeds. Callbacks return true to cancel
+// the operation, which then stops and returns CodecError::Cancelled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    // The index of the frame within its container or sequence, or 0 for a lone frame.
+    pub frame: usize,
+    // The index of the plane that was just finished.
+    pub plane: usize,
+    // Rows finished and in total, counted across all of the frame's planes.
+    pub rows_done: usize,
+    pub rows_total: usize,
+    // The number of bytes written or read so far for the frame.
+    pub bytes: u64,
+}
+
+// Counts the bytes passing through a reader or writer.
+pub(crate) struct Counting<T> {
+    pub inner: T,
+    pub count: u64,
+}
+
+impl<T> Counting<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        codec::Codec,
+        container::{VideoReader, VideoWriter},
+        error::CodecError,
+        frame::{self, RGB48Frame},
+        header::FrameHeader,
+    };
+    use std::{cell::RefCell, io::Cursor, rc::Rc};
+
+    fn test_frame() -> RGB48Frame {
+        RGB48Frame {
+            data: (0..40 * 30 * 3).map(|i| ((i * 37) % 4096) as u16).collect(),
+            width: 40,
+            height: 30,
+            bit_depth: 16,
+        }
+    }
+
+    #[test]
+    fn test_progress_counting() {
+        let frame = test_frame();
+        let mut calls = Vec::new();
+        let mut encoded = Vec::new();
+        frame
+            .encode_with_progress::<Codec, _, _>(&mut encoded, &Default::default(), |p| {
+                calls.push(*p);
+                false
+            })
+            .unwrap();
+        assert_eq!(calls.len(), 3);
+        for (i, p) in calls.iter().enumerate() {
+            assert_eq!((p.frame, p.plane), (0, i));
+            assert_eq!((p.rows_done, p.rows_total), ((i + 1) * 30, 90));
+        }
+        assert_eq!(calls[2].bytes, encoded.len() as u64);
+
+        let mut decode_calls = Vec::new();
+        let decoded = RGB48Frame::decode_with_progress::<Codec, _, _>(&*encoded, |p| {
+            decode_calls.push(*p);
+            false
+        })
+        .unwrap();
+        assert!(decoded == frame);
+        assert_eq!(decode_calls, calls);
+    }
+
+    #[test]
+    fn test_progress_cancel() {
+        let frame = test_frame();
+        let mut encoded = Vec::new();
+        let err = frame
+            .encode_with_progress::<Codec, _, _>(&mut encoded, &Default::default(), |p| {
+                p.plane == 0
+            })
+            .unwrap_err();
+        assert!(matches!(err, CodecError::Cancelled), "{:?}", err);
+
+        // only the header and the first plane were written
+        let mut expected = Vec::new();
+        FrameHeader::read(&*encoded)
+            .unwrap()
+            .write(&mut expected)
+            .unwrap();
+        <Codec as frame::Codec>::encode(&frame.plane(0), &mut expected).unwrap();
+        assert_eq!(encoded, expected);
+
+        let mut encoded = Vec::new();
+        frame
+            .encode_with_header::<Codec, _>(&mut encoded, &Default::default())
+            .unwrap();
+        let mut calls = 0;
+        let err = RGB48Frame::decode_with_progress::<Codec, _, _>(&*encoded, |_| {
+            calls += 1;
+            true
+        })
+        .map(|_| ())
+        .unwrap_err();
+        assert!(matches!(err, CodecError::Cancelled), "{:?}", err);
+        assert_eq!(calls, 1);
+
+        // other errors aren't cancellations
+        let err = RGB48Frame::decode_with_progress::<Codec, _, _>(&encoded[..20], |_| false)
+            .map(|_| ())
+            .unwrap_err();
+        assert!(
+            matches!(err, CodecError::TruncatedStream { plane: 0, .. }),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_container_progress() {
+        let frame = test_frame();
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut writer = VideoWriter::<Codec, _>::new(Cursor::new(Vec::new()), 40, 30, 3, (24, 1))
+            .unwrap()
+            .with_progress({
+                let calls = calls.clone();
+                move |p| {
+                    calls.borrow_mut().push(*p);
+                    p.frame == 2 && p.plane == 1
+                }
+            });
+        for _ in 0..2 {
+            writer.write_frame(&frame).unwrap();
+        }
+        let err = writer.write_frame(&frame).unwrap_err();
+        assert!(matches!(err, CodecError::Cancelled), "{:?}", err);
+        assert_eq!(calls.borrow().len(), 8);
+        let encoded = writer.finalize().unwrap().into_inner();
+
+        // the cancelled frame isn't in the container
+        let calls = Rc::new(RefCell::new(0));
+        let mut reader = VideoReader::<Codec, _>::open(Cursor::new(encoded))
+            .unwrap()
+            .with_progress({
+                let calls = calls.clone();
+                move |_| {
+                    *calls.borrow_mut() += 1;
+                    false
+                }
+            });
+        assert_eq!(reader.frame_count(), 2);
+        assert!(!reader.index_rebuilt());
+        for decoded in reader.frames() {
+            assert!(decoded.unwrap() == frame);
+        }
+        assert_eq!(*calls.borrow(), 6);
+    }
+}
@@ -0,0 +1,187 @@
+use super::frame::Plane;
+
+// Hierarchical coding splits each plane into two layers: a base layer, box-downsampled by some
+// factor in each direction, and a residual layer at full resolution holding each sample's
+// difference from the base layer upsampled back to full resolution. The base layers are coded
+// first, so a decoder can stop after them to get a preview, and the residuals make the full decode
+// lossless.
+//
+// Residuals are offset by 0x8000 and wrapped to 16 bits. The base is made of averages of nearby
+// samples, so residuals of natural images stay far from wrapping, and the wrapping is exactly
+// undone either way.
+
+// The dimensions of the base layer of a width×height plane.
+pub fn base_size(width: usize, height: usize, factor: usize) -> (usize, usize) {
+    (width.div_ceil(factor), height.div_ceil(factor))
+}
+
+// Returns the base layer of a plane: the rounded mean of each factor×factor block of samples. Blocks
+// along the right and bottom edges may be smaller, and are averaged over the samples they have.
+pub fn downsample<T: AsRef<[u16]>>(plane: &Plane<T>, factor: usize) -> Plane<Vec<u16>> {
+    let (width, height) = base_size(plane.width, plane.height, factor);
+    let mut ret = Plane {
+        data: vec![0; width * height],
+        width,
+        height,
+        sample_stride: 1,
+        row_stride: width,
+    };
+    downsample_into(plane, factor, &mut ret);
+    ret
+}
+
+// Like downsample, but writes the base layer to dest, which must be the right size for it.
+pub fn downsample_into<T: AsRef<[u16]>, U: AsMut<[u16]>>(
+    plane: &Plane<T>,
+    factor: usize,
+    dest: &mut Plane<U>,
+) {
+    assert!(factor > 0, "the downsampling factor must be nonzero");
+    let (width, height) = base_size(plane.width, plane.height, factor);
+    assert!(
+        (dest.width, dest.height) == (width, height),
+        "the base layer of a {}x{} plane is {}x{}, not {}x{}",
+        plane.width,
+        plane.height,
+        width,
+        height,
+        dest.width,
+        dest.height
+    );
+    let (sample_stride, row_stride) = (dest.sample_stride, dest.row_stride);
+    let data = dest.data.as_mut();
+    let mut sums = vec![0u64; width];
+    let mut rows = plane.rows();
+    for y in 0..height {
+        sums.iter_mut().for_each(|sum| *sum = 0);
+        let block_height = factor.min(plane.height - y * factor);
+        for row in rows.by_ref().take(block_height) {
+            for (col, x) in row.iter().enumerate() {
+                sums[col / factor] += x as u64;
+            }
+        }
+        for (x, &sum) in sums.iter().enumerate() {
+            let n = (block_height * factor.min(plane.width - x * factor)) as u64;
+            data[y * row_stride + x * sample_stride] = ((sum + n / 2) / n) as u16;
+        }
+    }
+}
+
+// Interpolates row y of a width-sample-wide plane from its base layer, bilinearly between the
+// centers of the base layer's blocks. Interpolating rather than repeating each base sample across
+// its block keeps the base layer's edges out of the residuals, where they'd cost bits.
+fn predict_row<U: AsRef<[u16]>>(base: &Plane<U>, y: usize, factor: usize, dest: &mut [u16]) {
+    // positions relative to the first block's center, in units of 1 / (2 * factor) of a block, and
+    // the pair of blocks to interpolate between along with the weight of the second
+    let span = 2 * factor as i64;
+    let locate = |x: usize, n: usize| {
+        let pos = (2 * x as i64 + 1 - factor as i64).max(0);
+        let i = (pos / span) as usize;
+        if i + 1 >= n {
+            (n - 1, n - 1, 0)
+        } else {
+            (i, i + 1, (pos % span) as u64)
+        }
+    };
+    let span = span as u64;
+    let (y0, y1, wy) = locate(y, base.height);
+    for (x, dest) in dest.iter_mut().enumerate() {
+        let (x0, x1, wx) = locate(x, base.width);
+        let row =
+            |y: usize| base.sample(x0, y) as u64 * (span - wx) + base.sample(x1, y) as u64 * wx;
+        let sum = row(y0) * (span - wy) + row(y1) * wy;
+        *dest = ((sum + span * span / 2) / (span * span)) as u16;
+    }
+}
+
+// Returns the residual layer of a plane, given its base layer.
+pub fn residuals<T: AsRef<[u16]>, U: AsRef<[u16]>>(
+    plane: &Plane<T>,
+    base: &Plane<U>,
+    factor: usize,
+) -> Plane<Vec<u16>> {
+    let mut data = vec![0; plane.width * plane.height];
+    if plane.width > 0 {
+        for (y, (row, dest)) in plane
+            .rows()
+            .zip(data.chunks_exact_mut(plane.width))
+            .enumerate()
+        {
+            predict_row(base, y, factor, dest);
+            for (dest, sample) in dest.iter_mut().zip(row.iter()) {
+                *dest = sample.wrapping_sub(*dest).wrapping_add(0x8000);
+            }
+        }
+    }
+    Plane {
+        data,
+        width: plane.width,
+        height: plane.height,
+        sample_stride: 1,
+        row_stride: plane.width,
+    }
+}
+
+// Turns a decoded residual layer back into the plane it came from, in place.
+pub fn restore<T: AsMut<[u16]>, U: AsRef<[u16]>>(
+    plane: &mut Plane<T>,
+    base: &Plane<U>,
+    factor: usize,
+) {
+    let (width, height) = (plane.width, plane.height);
+    let (sample_stride, row_stride) = (plane.sample_stride, plane.row_stride);
+    let data = plane.data.as_mut();
+    let mut predicted = vec![0; width];
+    for y in 0..height {
+        predict_row(base, y, factor, &mut predicted);
+        for (x, &predicted) in predicted.iter().enumerate() {
+            let sample = &mut data[y * row_stride + x * sample_stride];
+            *sample = sample.wrapping_sub(0x8000).wrapping_add(predicted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pyramid() {
+        // 5x3, so the blocks on the right and bottom are partial
+        #[rustfmt::skip]
+        let samples = vec![
+            0, 2, 10, 20, 65535,
+            4, 6, 30, 40, 65535,
+            1, 2, 3, 4, 0,
+        ];
+        let plane = Plane {
+            data: samples.clone(),
+            width: 5,
+            height: 3,
+            sample_stride: 1,
+            row_stride: 5,
+        };
+        let base = downsample(&plane, 2);
+        assert_eq!((base.width, base.height), base_size(5, 3, 2));
+        assert_eq!(base.data, vec![3, 25, 65535, 2, 4, 0]);
+
+        let residuals = residuals(&plane, &base, 2);
+        assert_eq!(residuals.sample(0, 0), 0x8000 - 3);
+        // (4, 1) is predicted from the blocks at (1, 0) and (2, 0), weighted 1:3, and (1, 1) and
+        // (2, 1), weighted the same, with the first row weighted 3:1 against the second
+        let predicted = ((25 + 65535 * 3) * 3 + 4 + 8) / 16;
+        assert_eq!(residuals.sample(4, 1) as u32, 65535 - predicted + 0x8000);
+        let mut restored = residuals.to_owned();
+        restore(&mut restored, &base, 2);
+        assert_eq!(restored.data, samples);
+
+        // a factor larger than the plane gives a single sample, and samples far from it wrap
+        // around but still come back exactly
+        let base = downsample(&plane, 8);
+        assert_eq!(base.data, vec![8746]);
+        let mut restored = super::residuals(&plane, &base, 8);
+        assert_eq!(restored.sample(4, 0), (65535u32 - 8746 + 0x8000) as u16);
+        restore(&mut restored, &base, 8);
+        assert_eq!(restored.data, samples);
+    }
+}
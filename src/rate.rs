@@ -0,0 +1,273 @@
+use super::{
+    error::CodecError,
+    frame::{Codec, EncodeOptions, RGB48Frame},
+};
+use std::io::{self, Write};
+use thiserror::Error;
+
+// The height of the strips of rows that estimate_size samples.
+const STRIP_HEIGHT: usize = 32;
+
+// A writer that only counts what's written to it.
+struct ByteCounter(usize);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Estimates the size of frame.encode_with_header(options) by encoding one strip of rows out of
+// every `stride` and scaling the result up, which takes about 1/stride as long as a real encode. A
+// stride of 1 encodes the whole frame, giving the exact size. Each strip is coded as if it were a
+// frame of its own, so the estimate runs slightly high.
+pub fn estimate_size<C: Codec>(
+    frame: &RGB48Frame,
+    options: &EncodeOptions,
+    stride: usize,
+) -> Result<usize, CodecError> {
+    assert!(stride > 0, "the stride must be nonzero");
+    if stride == 1 {
+        let mut counter = ByteCounter(0);
+        frame.encode_with_header::<C, _>(&mut counter, options)?;
+        return Ok(counter.0);
+    }
+
+    let options = EncodeOptions {
+        tile_size: None,
+        ..options.clone()
+    };
+    let (mut sampled_bytes, mut sampled_rows) = (0, 0);
+    for y in (0..frame.height).step_by(STRIP_HEIGHT * stride) {
+        let strip = frame.crop_rect((0, y, frame.width, STRIP_HEIGHT.min(frame.height - y)));
+        let mut counter = ByteCounter(0);
+        strip.encode_with_header::<C, _>(&mut counter, &options)?;
+        sampled_bytes += counter.0;
+        sampled_rows += strip.height;
+    }
+    Ok((sampled_bytes as f64 * frame.height as f64 / sampled_rows as f64).round() as usize)
+}
+
+#[derive(Error, Debug)]
+pub enum RateControlError {
+    #[error(transparent)]
+    Codec(#[from] CodecError),
+    #[error(
+        "even the maximum quantization step of {quantization} takes {size} bytes, more than the \
+         target of {target_size}"
+    )]
+    TargetTooSmall {
+        quantization: u16,
+        size: usize,
+        target_size: usize,
+    },
+}
+
+// The outcome of RateControl::encode.
+#[derive(Clone, Debug)]
+pub struct RateControlResult {
+    // The quantization step used for every plane.
+    pub quantization: u16,
+    // The number of bytes written.
+    pub size: usize,
+    // The mean squared error of each plane, as returned by RGB48Frame::encode_with_distortion.
+    pub mse: Vec<f64>,
+    // The number of estimation passes used to choose the step.
+    pub passes: usize,
+}
+
+// Encodes frames to fit within a byte budget by searching for the smallest quantization step whose
+// estimated size fits, then encoding for real. If the real encode turns out to be too large after
+// all, the step is increased until it fits.
+#[derive(Clone, Debug)]
+pub struct RateControl {
+    pub target_size: usize,
+    // The search stops early once an estimate is within this many bytes under the target.
+    pub tolerance: usize,
+    // The maximum number of estimates made while searching.
+    pub max_passes: usize,
+    pub max_quantization: u16,
+    // Passed to estimate_size.
+    pub estimate_stride: usize,
+}
+
+impl RateControl {
+    pub fn new(target_size: usize) -> Self {
+        Self {
+            target_size,
+            tolerance: target_size / 50,
+            max_passes: 12,
+            max_quantization: 4096,
+            estimate_stride: 8,
+        }
+    }
+
+    // Encodes the frame with the given options, overriding their quantization. If even
+    // max_quantization doesn't fit, nothing is written and TargetTooSmall is returned.
+    pub fn encode<C: Codec, W: Write>(
+        &self,
+        frame: &RGB48Frame,
+        options: &EncodeOptions,
+        mut dest: W,
+    ) -> Result<RateControlResult, RateControlError> {
+        assert!(
+            self.max_quantization > 0,
+            "max_quantization must be nonzero"
+        );
+        let n_planes = frame.channels();
+        let options_for = |q: u16| EncodeOptions {
+            quantization: Some(vec![q; n_planes]),
+            ..options.clone()
+        };
+        let mut passes = 0;
+        let mut fits = |q: u16| -> Result<(bool, usize), CodecError> {
+            passes += 1;
+            let size = estimate_size::<C>(frame, &options_for(q), self.estimate_stride)?;
+            Ok((
+                size <= self.target_size,
+                self.target_size.saturating_sub(size),
+            ))
+        };
+
+        // Search for the smallest step that fits. The sizes are only roughly monotonic in the
+        // step, so this settles for the invariant that lo doesn't fit and hi does.
+        let mut q = if fits(1)?.0 {
+            1
+        } else {
+            let mut lo = 1;
+            let mut hi = self.max_quantization;
+            let mut remaining_passes = self.max_passes.saturating_sub(1);
+            if hi > 1 && remaining_passes > 0 && fits(hi)?.0 {
+                remaining_passes -= 1;
+                while hi - lo > 1 && remaining_passes > 0 {
+                    remaining_passes -= 1;
+                    // the size falls roughly with log(q), so bisect geometrically
+                    let mid = ((lo as f64 * hi as f64).sqrt() as u16).clamp(lo + 1, hi - 1);
+                    let (mid_fits, slack) = fits(mid)?;
+                    if mid_fits {
+                        hi = mid;
+                        if slack <= self.tolerance {
+                            break;
+                        }
+                    } else {
+                        lo = mid;
+                    }
+                }
+            }
+            hi
+        };
+
+        loop {
+            let mut encoded = Vec::new();
+            let mse = frame.encode_with_distortion::<C, _>(&mut encoded, &options_for(q))?;
+            if encoded.len() <= self.target_size {
+                dest.write_all(&encoded).map_err(CodecError::from)?;
+                return Ok(RateControlResult {
+                    quantization: q,
+                    size: encoded.len(),
+                    mse,
+                    passes,
+                });
+            }
+            if q == self.max_quantization {
+                return Err(RateControlError::TargetTooSmall {
+                    quantization: q,
+                    size: encoded.len(),
+                    target_size: self.target_size,
+                });
+            }
+            q = q.saturating_add(q / 8 + 1).min(self.max_quantization);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "tiff")]
+    use super::super::metrics::psnr;
+    use super::{super::codec::Codec, *};
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_estimate_size() {
+        let frame = RGB48Frame::open("src/testdata/tears_of_steel_12130.tif")
+            .unwrap()
+            .crop(0, 0, 1024, 512)
+            .unwrap();
+        let options = EncodeOptions {
+            quantization: Some(vec![16; 3]),
+            ..Default::default()
+        };
+        let mut encoded = Vec::new();
+        frame
+            .encode_with_header::<Codec, _>(&mut encoded, &options)
+            .unwrap();
+
+        assert_eq!(
+            estimate_size::<Codec>(&frame, &options, 1).unwrap(),
+            encoded.len()
+        );
+        let estimate = estimate_size::<Codec>(&frame, &options, 4).unwrap();
+        assert!(
+            (estimate as f64 / encoded.len() as f64 - 1.0).abs() < 0.1,
+            "estimated {} bytes, actually {}",
+            estimate,
+            encoded.len()
+        );
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_rate_control() {
+        let frame = RGB48Frame::open("src/testdata/tears_of_steel_12209.tif").unwrap();
+
+        let mut results = Vec::new();
+        for &target_size in [10_000_000, 20_000_000].iter() {
+            let mut encoded = Vec::new();
+            let result = RateControl::new(target_size)
+                .encode::<Codec, _>(&frame, &Default::default(), &mut encoded)
+                .unwrap();
+            assert_eq!(result.size, encoded.len());
+            assert!(
+                result.size <= target_size && result.size > target_size * 8 / 10,
+                "{:?}",
+                result
+            );
+            assert!(result.passes <= 12);
+            results.push(result);
+        }
+        let psnr_10mb = psnr(results[0].mse.iter().sum::<f64>() / 3.0);
+        let psnr_20mb = psnr(results[1].mse.iter().sum::<f64>() / 3.0);
+        assert!(psnr_20mb > psnr_10mb, "{} vs {}", psnr_20mb, psnr_10mb);
+    }
+
+    #[test]
+    fn test_rate_control_target_too_small() {
+        let frame = RGB48Frame {
+            data: (0..64 * 64 * 3)
+                .map(|i| (i * 7919 % 65536) as u16)
+                .collect(),
+            width: 64,
+            height: 64,
+            bit_depth: 16,
+        };
+        let mut encoded = Vec::new();
+        match RateControl::new(100).encode::<Codec, _>(&frame, &Default::default(), &mut encoded) {
+            Err(RateControlError::TargetTooSmall {
+                quantization,
+                size,
+                target_size,
+            }) => {
+                assert_eq!((quantization, target_size), (4096, 100));
+                assert!(size > 100);
+            }
+            r => panic!("unexpected result: {:?}", r.map(|r| r.size)),
+        }
+        assert!(encoded.is_empty());
+    }
+}
@@ -0,0 +1,392 @@
+use super::frame::{raw_sample_count, Endianness, FrameError, RGB48Frame};
+use std::io::{self, Read, Write};
+use thiserror::Error;
+
+// Reads and writes headerless streams of fixed-size frames of interleaved 16-bit samples, which is
+// what ffmpeg produces and consumes with `-f rawvideo` and one of the pixel formats below. Nothing
+// in the stream says where frames start, so the dimensions and pixel format have to be known
+// ahead of time, and every frame is width * height * channels * 2 bytes.
+//
+// Frames are read and written one at a time, so a stream of any length can be piped through with
+// a single frame in memory.
+
+#[derive(Error, Debug)]
+pub enum RawVideoError {
+    #[error(transparent)]
+    IO(#[from] io::Error),
+    // The stream ended partway through a frame, after actual of its expected bytes.
+    #[error("the stream ends partway through a frame ({actual} of {expected} bytes)")]
+    TruncatedFrame { expected: usize, actual: usize },
+    #[error("invalid frame: {0}")]
+    InvalidFrame(String),
+    #[error(transparent)]
+    InvalidDimensions(#[from] FrameError),
+}
+
+// The pixel formats that can be read and written, named as they are in ffmpeg.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelFormat {
+    Gray16Le,
+    Gray16Be,
+    Rgb48Le,
+    Rgb48Be,
+    Rgba64Le,
+    Rgba64Be,
+}
+
+impl PixelFormat {
+    const ALL: [PixelFormat; 6] = [
+        Self::Gray16Le,
+        Self::Gray16Be,
+        Self::Rgb48Le,
+        Self::Rgb48Be,
+        Self::Rgba64Le,
+        Self::Rgba64Be,
+    ];
+
+    // Looks up a format by its ffmpeg name, like rgb48le.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .cloned()
+            .find(|format| format.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gray16Le => "gray16le",
+            Self::Gray16Be => "gray16be",
+            Self::Rgb48Le => "rgb48le",
+            Self::Rgb48Be => "rgb48be",
+            Self::Rgba64Le => "rgba64le",
+            Self::Rgba64Be => "rgba64be",
+        }
+    }
+
+    // The little-endian format with the given number of channels, if there is one.
+    pub fn for_channels(channels: usize) -> Option<Self> {
+        match channels {
+            1 => Some(Self::Gray16Le),
+            3 => Some(Self::Rgb48Le),
+            4 => Some(Self::Rgba64Le),
+            _ => None,
+        }
+    }
+
+    pub fn channels(self) -> usize {
+        match self {
+            Self::Gray16Le | Self::Gray16Be => 1,
+            Self::Rgb48Le | Self::Rgb48Be => 3,
+            Self::Rgba64Le | Self::Rgba64Be => 4,
+        }
+    }
+
+    pub fn endianness(self) -> Endianness {
+        match self {
+            Self::Gray16Le | Self::Rgb48Le | Self::Rgba64Le => Endianness::Little,
+            Self::Gray16Be | Self::Rgb48Be | Self::Rgba64Be => Endianness::Big,
+        }
+    }
+}
+
+// What RawVideoReader does when the stream ends partway through a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PartialFrame {
+    // Return RawVideoError::TruncatedFrame.
+    Error,
+    // Drop the partial frame and end the stream there, as if it had ended cleanly.
+    Discard,
+}
+
+// The size of each frame of a stream in bytes.
+fn frame_size(width: usize, height: usize, format: PixelFormat) -> Result<usize, FrameError> {
+    let samples = raw_sample_count(width, height, format.channels())?;
+    samples.checked_mul(2).ok_or(FrameError::TooLarge {
+        width,
+        height,
+        channels: format.channels(),
+    })
+}
+
+// Reads the frames of a raw video stream. As an iterator, it stops after the last frame, or after
+// yielding the first error.
+pub struct RawVideoReader<R> {
+    source: R,
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+    partial_frame: PartialFrame,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<R: Read> RawVideoReader<R> {
+    // Frames read from source are width×height, in the given format. A partial final frame is an
+    // error unless with_partial_frame says otherwise. source is read in whole frames, so it
+    // doesn't need to be buffered.
+    pub fn new(
+        source: R,
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+    ) -> Result<Self, RawVideoError> {
+        Ok(Self {
+            buf: vec![0; frame_size(width, height, format)?],
+            source,
+            width,
+            height,
+            format,
+            partial_frame: PartialFrame::Error,
+            done: false,
+        })
+    }
+
+    pub fn with_partial_frame(self, partial_frame: PartialFrame) -> Self {
+        Self {
+            partial_frame,
+            ..self
+        }
+    }
+
+    pub fn frame_size(&self) -> usize {
+        self.buf.len()
+    }
+
+    // Reads the next frame, or returns None at the end of the stream. Frames have a bit_depth of
+    // 16.
+    pub fn read_frame(&mut self) -> Result<Option<RGB48Frame>, RawVideoError> {
+        let mut filled = 0;
+        while filled < self.buf.len() {
+            match self.source.read(&mut self.buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if filled == 0 {
+            return Ok(None);
+        }
+        if filled < self.buf.len() {
+            return match self.partial_frame {
+                PartialFrame::Error => Err(RawVideoError::TruncatedFrame {
+                    expected: self.buf.len(),
+                    actual: filled,
+                }),
+                PartialFrame::Discard => Ok(None),
+            };
+        }
+        Ok(Some(RGB48Frame::from_raw_bytes(
+            &self.buf,
+            self.width,
+            self.height,
+            self.format.channels(),
+            self.format.endianness(),
+        )?))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+}
+
+impl<R: Read> Iterator for RawVideoReader<R> {
+    type Item = Result<RGB48Frame, RawVideoError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let ret = self.read_frame().transpose();
+        if !matches!(ret, Some(Ok(_))) {
+            self.done = true;
+        }
+        ret
+    }
+}
+
+// Writes frames to a raw video stream.
+pub struct RawVideoWriter<W> {
+    dest: W,
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> RawVideoWriter<W> {
+    // Frames written to dest must be width×height, with as many channels as the format has. dest
+    // is written a whole frame at a time, so it doesn't need to be buffered.
+    pub fn new(
+        dest: W,
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+    ) -> Result<Self, RawVideoError> {
+        Ok(Self {
+            buf: Vec::with_capacity(frame_size(width, height, format)?),
+            dest,
+            width,
+            height,
+            format,
+        })
+    }
+
+    // Writes a frame. If dest is a pipe whose reader has gone away, this fails with an IO error of
+    // kind BrokenPipe, which callers streaming to another process usually treat as a request to
+    // stop rather than as a failure.
+    pub fn write_frame(&mut self, frame: &RGB48Frame) -> Result<(), RawVideoError> {
+        let channels = self.format.channels();
+        if (frame.width, frame.height, frame.channels()) != (self.width, self.height, channels) {
+            return Err(RawVideoError::InvalidFrame(format!(
+                "expected a {}x{} frame with {} channels, got a {}x{} frame with {}",
+                self.width,
+                self.height,
+                channels,
+                frame.width,
+                frame.height,
+                frame.channels()
+            )));
+        }
+        let to_bytes = match self.format.endianness() {
+            Endianness::Little => u16::to_le_bytes,
+            Endianness::Big => u16::to_be_bytes,
+        };
+        self.buf.clear();
+        self.buf
+            .extend(frame.data.iter().flat_map(|&x| to_bytes(x)));
+        self.dest.write_all(&self.buf)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), RawVideoError> {
+        self.dest.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.dest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::synth, *};
+
+    // Frames concatenated the way ffmpeg would pipe them.
+    fn stream(frames: &[RGB48Frame], format: PixelFormat) -> Vec<u8> {
+        let mut writer =
+            RawVideoWriter::new(Vec::new(), frames[0].width, frames[0].height, format).unwrap();
+        for frame in frames {
+            writer.write_frame(frame).unwrap();
+        }
+        writer.into_inner()
+    }
+
+    // Returns at most 5 bytes from each read, like a pipe that's being filled slowly.
+    struct Trickle<R>(R);
+
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(5);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn test_rawvideo() {
+        let frames: Vec<RGB48Frame> = (0..3).map(|seed| synth::noise(7, 5, seed, 16)).collect();
+        let le = stream(&frames, PixelFormat::Rgb48Le);
+        assert_eq!(le.len(), 3 * 7 * 5 * 3 * 2);
+        assert_eq!(le[..2], frames[0].data[0].to_le_bytes());
+        let be = stream(&frames, PixelFormat::Rgb48Be);
+        assert_eq!(be[..2], frames[0].data[0].to_be_bytes());
+
+        for &(bytes, format) in [(&le, PixelFormat::Rgb48Le), (&be, PixelFormat::Rgb48Be)].iter() {
+            let reader = RawVideoReader::new(Trickle(&bytes[..]), 7, 5, format).unwrap();
+            assert_eq!(reader.frame_size(), 7 * 5 * 6);
+            let read: Vec<RGB48Frame> = reader.collect::<Result<_, _>>().unwrap();
+            assert_eq!(read.len(), 3);
+            for (read, frame) in read.iter().zip(&frames) {
+                assert!(read.data == frame.data);
+                assert_eq!((read.width, read.height, read.bit_depth), (7, 5, 16));
+            }
+        }
+
+        let gray = synth::noise_gray(4, 4, 1, 16);
+        let gray = RGB48Frame::from_raw(gray.data, 4, 4, 1).unwrap();
+        let bytes = stream(&[gray.clone(), gray.clone()], PixelFormat::Gray16Le);
+        let mut reader = RawVideoReader::new(&bytes[..], 4, 4, PixelFormat::Gray16Le).unwrap();
+        assert!(reader.read_frame().unwrap().unwrap().data == gray.data);
+        assert!(reader.read_frame().unwrap().is_some());
+        assert!(reader.read_frame().unwrap().is_none());
+
+        // an empty stream has no frames
+        let mut reader = RawVideoReader::new(&[][..], 4, 4, PixelFormat::Gray16Le).unwrap();
+        assert!(reader.read_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_rawvideo_partial_frame() {
+        let frames: Vec<RGB48Frame> = (0..2).map(|seed| synth::noise(3, 3, seed, 16)).collect();
+        let mut bytes = stream(&frames, PixelFormat::Rgb48Le);
+        bytes.truncate(bytes.len() - 7);
+
+        let mut reader = RawVideoReader::new(&bytes[..], 3, 3, PixelFormat::Rgb48Le).unwrap();
+        assert!(reader.read_frame().unwrap().is_some());
+        match reader.read_frame() {
+            Err(RawVideoError::TruncatedFrame { expected, actual }) => {
+                assert_eq!((expected, actual), (54, 47))
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+        let results: Vec<_> = RawVideoReader::new(&bytes[..], 3, 3, PixelFormat::Rgb48Le)
+            .unwrap()
+            .collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[1].is_err());
+
+        let reader = RawVideoReader::new(&bytes[..], 3, 3, PixelFormat::Rgb48Le)
+            .unwrap()
+            .with_partial_frame(PartialFrame::Discard);
+        assert_eq!(reader.map(Result::unwrap).count(), 1);
+    }
+
+    #[test]
+    fn test_rawvideo_errors() {
+        for &(width, height) in [(0, 4), (4, 0), (usize::MAX, 2)].iter() {
+            assert!(RawVideoReader::new(&[][..], width, height, PixelFormat::Rgb48Le).is_err());
+            assert!(RawVideoWriter::new(Vec::new(), width, height, PixelFormat::Rgb48Le).is_err());
+        }
+
+        let mut writer = RawVideoWriter::new(Vec::new(), 4, 4, PixelFormat::Rgba64Be).unwrap();
+        for frame in [synth::noise(4, 4, 1, 16), synth::noise(5, 4, 1, 16)].iter() {
+            match writer.write_frame(frame) {
+                Err(RawVideoError::InvalidFrame(_)) => {}
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+        assert!(writer.into_inner().is_empty());
+
+        // a reader that has gone away
+        struct Closed;
+        impl Write for Closed {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut writer = RawVideoWriter::new(Closed, 4, 4, PixelFormat::Rgb48Le).unwrap();
+        match writer.write_frame(&synth::noise(4, 4, 1, 16)) {
+            Err(RawVideoError::IO(e)) => assert_eq!(e.kind(), io::ErrorKind::BrokenPipe),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        for format in PixelFormat::ALL.iter() {
+            assert_eq!(PixelFormat::from_name(format.name()), Some(*format));
+        }
+        assert_eq!(PixelFormat::from_name("yuv420p"), None);
+    }
+}
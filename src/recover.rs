@@ -0,0 +1,509 @@
+use super::{
+    band,
+    error::CodecError,
+    frame::{Codec, Limits, RGB48Frame},
+    header::FrameHeader,
+    pyramid,
+    tile::{self, TileLayout},
+};
+use std::io::Read;
+
+// The value damaged regions are filled with when there's no row above them to copy: mid-gray, or
+// no chroma in a color-transformed plane.
+const NEUTRAL: u16 = 0x8000;
+
+// A region of a plane that couldn't be decoded and was filled in instead.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DamagedRegion {
+    pub plane: usize,
+    // The region as (x, y, w, h).
+    pub rect: (usize, usize, usize, usize),
+    // Why it couldn't be decoded. Regions that come after the damage in the stream, and couldn't be
+    // found once it was lost, give the error that lost them.
+    pub reason: String,
+}
+
+// What RGB48Frame::decode_lossy_recover had to make up. Everything not listed was decoded as
+// usual.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecoveryReport {
+    pub damaged: Vec<DamagedRegion>,
+}
+
+impl RecoveryReport {
+    // True if the whole frame was decoded.
+    pub fn is_intact(&self) -> bool {
+        self.damaged.is_empty()
+    }
+
+    // The damaged regions of the given plane.
+    pub fn damaged_in(&self, plane: usize) -> impl Iterator<Item = &DamagedRegion> {
+        self.damaged
+            .iter()
+            .filter(move |region| region.plane == plane)
+    }
+}
+
+impl RGB48Frame {
+    // Decodes a frame written by encode_with_header as far as the stream allows, filling in what
+    // can't be decoded rather than failing. A damaged region is filled with the row above it,
+    // repeated down, or with mid-gray if it starts at the top of the plane.
+    //
+    // How much survives depends on how the frame was coded. The planes of an untiled frame follow
+    // one another with nothing to say where each starts, so damage to one loses the rows from the
+    // damage down and every plane after it. A tiled frame's planes each start with a table of their
+    // tiles' lengths, so damage is confined to the tiles it hits, and full-width tiles make that a
+    // range of rows. Each band of a banded frame has its length in front, so damage is confined to
+    // the bands it hits, unless it's to a length. Hierarchically coded frames are recovered a plane
+    // at a time.
+    //
+    // The report is in terms of the planes as coded, so with a color transform, a damaged region
+    // of any plane affects every channel of the decoded frame there. Damage that still decodes to
+    // something plausible, and takes exactly as many bytes as it should, can't be detected. An
+    // error is only returned if the header can't be read, since then there's nothing to recover.
+    pub fn decode_lossy_recover<C: Codec, R: Read>(
+        mut source: R,
+    ) -> Result<(Self, RecoveryReport), CodecError> {
+        let header = Self::read_header(&mut source)?;
+        let n_samples = header.width * header.height * header.n_planes;
+        Limits::default().check(n_samples)?;
+        let mut frame = Self {
+            data: vec![0; n_samples],
+            width: header.width,
+            height: header.height,
+            bit_depth: header.bit_depth,
+        };
+        let mut report = RecoveryReport::default();
+        let mut recovery = Recovery {
+            header: &header,
+            frame: &mut frame,
+            report: &mut report,
+        };
+
+        match (header.preview_factor, header.band_height) {
+            (Some(factor), _) => recovery.hierarchical::<C, _>(&mut source, factor),
+            (None, Some(band_height)) => recovery.banded::<C, _>(&mut source, band_height),
+            (None, None) => {
+                for i in 0..header.n_planes {
+                    let result = match header.tile_size {
+                        None => recovery.untiled::<C, _>(&mut source, i),
+                        Some((tile_width, tile_height)) => {
+                            let layout = TileLayout::new(
+                                header.width,
+                                header.height,
+                                tile_width,
+                                tile_height,
+                            );
+                            recovery.tiled::<C, _>(&mut source, i, &layout)
+                        }
+                    };
+                    // the stream is lost from here on
+                    if let Err(reason) = result {
+                        recovery.lose_planes(i + 1, &reason);
+                        break;
+                    }
+                }
+            }
+        }
+        header
+            .color_transform
+            .inverse(&mut frame.data, header.n_planes);
+        Ok((frame, report))
+    }
+}
+
+struct Recovery<'a> {
+    header: &'a FrameHeader,
+    frame: &'a mut RGB48Frame,
+    report: &'a mut RecoveryReport,
+}
+
+impl Recovery<'_> {
+    // Fills in a region of a plane and records it as damaged.
+    fn fill(&mut self, plane: usize, rect: (usize, usize, usize, usize), reason: &str) {
+        let (x, y, w, h) = rect;
+        if w == 0 || h == 0 {
+            return;
+        }
+        let plane_data = self.frame.plane_mut(plane);
+        for row in y..y + h {
+            for col in x..x + w {
+                let value = if y > 0 {
+                    plane_data.sample(col, y - 1)
+                } else {
+                    NEUTRAL
+                };
+                let i = row * plane_data.row_stride + col * plane_data.sample_stride;
+                plane_data.data[i] = value;
+            }
+        }
+        self.report.damaged.push(DamagedRegion {
+            plane,
+            rect,
+            reason: reason.to_string(),
+        });
+    }
+
+    // Fills in every plane from first on, which can't be found in the stream.
+    fn lose_planes(&mut self, first: usize, reason: &str) {
+        let (width, height) = (self.header.width, self.header.height);
+        for plane in first..self.header.n_planes {
+            self.fill(plane, (0, 0, width, height), reason);
+        }
+    }
+
+    // Decodes an untiled plane. The rows before a truncation are kept, and anything else that goes
+    // wrong loses the whole plane. Either way, the error is returned, since the next plane's start
+    // is lost with it.
+    fn untiled<C: Codec, R: Read>(&mut self, source: R, plane: usize) -> Result<(), String> {
+        let coding = self.header.plane_coding(plane);
+        let result = coding.decode::<C, _, _>(source, &mut self.frame.plane_mut(plane));
+        let (width, height) = (self.header.width, self.header.height);
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let e = e.in_plane(plane, 0, 0);
+                let first_lost = match e {
+                    CodecError::TruncatedStream { row, .. } => row.min(height),
+                    _ => 0,
+                };
+                let reason = e.to_string();
+                self.fill(plane, (0, first_lost, width, height - first_lost), &reason);
+                Err(reason)
+            }
+        }
+    }
+
+    // Decodes a tiled plane a tile at a time, filling in the tiles that fail. Only losing the tile
+    // table or running out of data loses the rest of the stream.
+    fn tiled<C: Codec, R: Read>(
+        &mut self,
+        mut source: R,
+        plane: usize,
+        layout: &TileLayout,
+    ) -> Result<(), String> {
+        let coding = self.header.plane_coding(plane);
+        let table = match tile::read_tile_table(&mut source, layout) {
+            Ok(table) => table,
+            Err(e) => {
+                let reason = format!("plane {}'s tile table is unreadable: {}", plane, e);
+                self.fill(plane, (0, 0, layout.width, layout.height), &reason);
+                return Err(reason);
+            }
+        };
+        let mut payload = Vec::new();
+        for (i, &len) in table.iter().enumerate() {
+            let rect = layout.tile_rect(i);
+            payload.clear();
+            (&mut source)
+                .take(len as u64)
+                .read_to_end(&mut payload)
+                .ok();
+            if payload.len() != len {
+                let reason = format!("tile {} of plane {} is truncated", i, plane);
+                for lost in i..table.len() {
+                    self.fill(plane, layout.tile_rect(lost), &reason);
+                }
+                return Err(reason);
+            }
+            let (x, y, w, h) = rect;
+            let result = tile::decode_tile::<C, _>(
+                &payload,
+                &mut self.frame.plane_mut(plane).view_mut(x, y, w, h),
+                coding,
+                i,
+            );
+            if let Err(e) = result {
+                let reason = e.in_plane(plane, x, y).to_string();
+                self.fill(plane, rect, &reason);
+            }
+        }
+        Ok(())
+    }
+
+    // Decodes a banded frame a band at a time, filling in the bands that fail. Losing a band's
+    // length or running out of data loses the rest of the frame.
+    fn banded<C: Codec, R: Read>(&mut self, mut source: R, band_height: usize) {
+        let (width, height) = (self.header.width, self.header.height);
+        let layout = band::layout(width, height, band_height);
+        let mut payload = Vec::new();
+        for i in 0..layout.len() {
+            let rect = layout.tile_rect(i);
+            let (x, y, w, h) = rect;
+            for plane in 0..self.header.n_planes {
+                if let Err(e) = band::read_band(&mut source, &mut payload) {
+                    let reason = e.in_plane(plane, 0, y).to_string();
+                    for lost in 0..self.header.n_planes {
+                        let first_lost = if lost < plane { y + h } else { y };
+                        self.fill(lost, (0, first_lost, width, height - first_lost), &reason);
+                    }
+                    return;
+                }
+                let coding = self.header.plane_coding(plane);
+                let result = band::decode_band::<C, _>(
+                    &payload,
+                    &mut self.frame.plane_mut(plane).view_mut(x, y, w, h),
+                    coding,
+                );
+                if let Err(e) = result {
+                    let reason = e.in_plane(plane, x, y).to_string();
+                    self.fill(plane, rect, &reason);
+                }
+            }
+        }
+    }
+
+    // Decodes a hierarchically coded frame: the base layers, which every plane needs, then each
+    // plane's residuals.
+    fn hierarchical<C: Codec, R: Read>(&mut self, mut source: R, factor: usize) {
+        let base =
+            match RGB48Frame::decode_base::<C, _>(self.header, &mut source, &Limits::default()) {
+                Ok(base) => base,
+                Err(e) => return self.lose_planes(0, &format!("the base layer is lost: {}", e)),
+            };
+        for plane in 0..self.header.n_planes {
+            let mut data = self.frame.plane_mut(plane);
+            match C::decode(&mut source, &mut data) {
+                Ok(()) => pyramid::restore(&mut data, &base.plane(plane), factor),
+                Err(e) => {
+                    let reason = e.in_plane(plane, 0, 0).to_string();
+                    let (width, height) = (self.header.width, self.header.height);
+                    self.fill(plane, (0, 0, width, height), &reason);
+                    return self.lose_planes(plane + 1, &reason);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{
+            codec::{Codec, KMode},
+            frame::{self, EncodeOptions},
+            synth,
+        },
+        *,
+    };
+    use std::convert::TryInto;
+
+    fn encode(frame: &RGB48Frame, options: &EncodeOptions) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        frame
+            .encode_with_header::<Codec, _>(&mut encoded, options)
+            .unwrap();
+        encoded
+    }
+
+    fn plane_eq(a: &RGB48Frame, b: &RGB48Frame, plane: usize) -> bool {
+        a.plane(plane)
+            .rows()
+            .zip(b.plane(plane).rows())
+            .all(|(a, b)| a.iter().eq(b.iter()))
+    }
+
+    #[test]
+    fn test_recover_intact() {
+        let frame = synth::zone_plate(40, 24);
+        for options in [
+            EncodeOptions::default(),
+            EncodeOptions {
+                tile_size: Some((16, 8)),
+                ..Default::default()
+            },
+            EncodeOptions {
+                preview_factor: Some(4),
+                ..Default::default()
+            },
+            EncodeOptions {
+                band_height: Some(5),
+                ..Default::default()
+            },
+            EncodeOptions {
+                k_mode: KMode::PerPlaneAuto,
+                ..Default::default()
+            },
+        ]
+        .iter()
+        {
+            let (decoded, report) =
+                RGB48Frame::decode_lossy_recover::<Codec, _>(&*encode(&frame, options)).unwrap();
+            assert!(report.is_intact(), "{:?}", report);
+            assert!(decoded == frame);
+        }
+    }
+
+    #[test]
+    fn test_recover_tiles() {
+        // full-width tiles, so each covers a range of rows
+        let (width, height) = (40, 24);
+        let frame = synth::noise(width, height, 3, 12);
+        let options = EncodeOptions {
+            tile_size: Some((width, 8)),
+            ..Default::default()
+        };
+        let encoded = encode(&frame, &options);
+        let header = FrameHeader::read(&*encoded).unwrap();
+        let layout = TileLayout::new(width, height, width, 8);
+
+        // find the middle tile of the middle plane by reading the tile tables
+        let mut offset = header.encoded_len();
+        let mut tiles = Vec::new();
+        for _ in 0..3 {
+            let table = tile::read_tile_table(&encoded[offset..], &layout).unwrap();
+            offset += 4 * table.len();
+            let mut starts = Vec::new();
+            for len in table {
+                starts.push((offset, len));
+                offset += len;
+            }
+            tiles.push(starts);
+        }
+        assert_eq!(offset, encoded.len());
+        let (start, len) = tiles[1][1];
+        let mut damaged = encoded.clone();
+        damaged[start..start + len].iter_mut().for_each(|b| *b = 0);
+
+        // the normal decode fails
+        assert!(RGB48Frame::decode_with_header::<Codec, _>(&*damaged).is_err());
+
+        let (decoded, report) = RGB48Frame::decode_lossy_recover::<Codec, _>(&*damaged).unwrap();
+        assert!(plane_eq(&decoded, &frame, 0));
+        assert!(plane_eq(&decoded, &frame, 2));
+        assert_eq!(report.damaged.len(), 1, "{:?}", report);
+        assert_eq!(
+            (report.damaged[0].plane, report.damaged[0].rect),
+            (1, (0, 8, width, 8))
+        );
+        assert_eq!(report.damaged_in(0).count(), 0);
+        // only the damaged rows are made up, from the last row above them
+        let plane = decoded.plane(1);
+        for row in 0..height {
+            for col in 0..width {
+                let expected = match row {
+                    8..=15 => frame.plane(1).sample(col, 7),
+                    _ => frame.plane(1).sample(col, row),
+                };
+                assert_eq!(plane.sample(col, row), expected, "({}, {})", col, row);
+            }
+        }
+
+        // running out of data loses the tiles that weren't there
+        let (decoded, report) =
+            RGB48Frame::decode_lossy_recover::<Codec, _>(&encoded[..start + 1]).unwrap();
+        assert!(plane_eq(&decoded, &frame, 0));
+        let lost = report
+            .damaged
+            .iter()
+            .map(|region| (region.plane, region.rect.1))
+            .collect::<Vec<_>>();
+        assert_eq!(lost, vec![(1, 8), (1, 16), (2, 0)]);
+    }
+
+    #[test]
+    fn test_recover_bands() {
+        let (width, height) = (40, 24);
+        let frame = synth::noise(width, height, 7, 12);
+        let options = EncodeOptions {
+            band_height: Some(8),
+            ..Default::default()
+        };
+        let encoded = encode(&frame, &options);
+
+        // find each band's payload by following the lengths
+        let mut offset = FrameHeader::read(&*encoded).unwrap().encoded_len();
+        let mut bands = Vec::new();
+        for _ in 0..3 * 3 {
+            let len = u32::from_be_bytes(encoded[offset..offset + 4].try_into().unwrap()) as usize;
+            bands.push((offset + 4, len));
+            offset += 4 + len;
+        }
+        assert_eq!(offset, encoded.len());
+
+        // damage to the middle band of the middle plane only loses that band
+        let (start, len) = bands[3 + 1];
+        let mut damaged = encoded.clone();
+        damaged[start..start + len].iter_mut().for_each(|b| *b = 0);
+        assert!(RGB48Frame::decode_with_header::<Codec, _>(&*damaged).is_err());
+        let (decoded, report) = RGB48Frame::decode_lossy_recover::<Codec, _>(&*damaged).unwrap();
+        assert!(plane_eq(&decoded, &frame, 0));
+        assert!(plane_eq(&decoded, &frame, 2));
+        let damaged_rects = report
+            .damaged
+            .iter()
+            .map(|region| (region.plane, region.rect))
+            .collect::<Vec<_>>();
+        assert_eq!(damaged_rects, vec![(1, (0, 8, width, 8))]);
+        let (original, recovered) = (frame.plane(1), decoded.plane(1));
+        for (y, (a, b)) in original.rows().zip(recovered.rows()).enumerate() {
+            assert!((8..16).contains(&y) || a.iter().eq(b.iter()), "row {}", y);
+        }
+
+        // running out of data in the last band's first plane loses that band of every plane
+        let (start, len) = bands[2 * 3];
+        let (decoded, report) =
+            RGB48Frame::decode_lossy_recover::<Codec, _>(&encoded[..start + len / 2]).unwrap();
+        let lost = report
+            .damaged
+            .iter()
+            .map(|region| (region.plane, region.rect))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lost,
+            (0..3)
+                .map(|plane| (plane, (0, 16, width, 8)))
+                .collect::<Vec<_>>()
+        );
+        assert!(decoded.crop_rect((0, 0, width, 16)) == frame.crop_rect((0, 0, width, 16)));
+    }
+
+    #[test]
+    fn test_recover_untiled() {
+        // without tiles, a truncated plane keeps the rows before the truncation, and the planes
+        // after it are lost
+        let (width, height) = (32, 16);
+        let frame = synth::zone_plate(width, height);
+        let encoded = encode(&frame, &EncodeOptions::default());
+        let header_len = FrameHeader::read(&*encoded).unwrap().encoded_len();
+        let mut plane_0 = Vec::new();
+        <Codec as frame::Codec>::encode(&frame.plane(0), &mut plane_0).unwrap();
+        let mut plane_1 = Vec::new();
+        <Codec as frame::Codec>::encode(&frame.plane(1), &mut plane_1).unwrap();
+        let end = header_len + plane_0.len() + plane_1.len() * 3 / 4;
+
+        let (decoded, report) =
+            RGB48Frame::decode_lossy_recover::<Codec, _>(&encoded[..end]).unwrap();
+        assert!(plane_eq(&decoded, &frame, 0));
+        let damaged = report.damaged_in(1).collect::<Vec<_>>();
+        assert_eq!(damaged.len(), 1);
+        let (_, first_lost, _, lost_rows) = damaged[0].rect;
+        assert!(
+            first_lost > 0 && first_lost + lost_rows == height,
+            "{:?}",
+            damaged
+        );
+        for row in 0..first_lost {
+            assert!(decoded.plane(1).rows().nth(row).unwrap().iter().eq(frame
+                .plane(1)
+                .rows()
+                .nth(row)
+                .unwrap()
+                .iter()));
+        }
+        assert_eq!(
+            report
+                .damaged_in(2)
+                .map(|region| region.rect)
+                .collect::<Vec<_>>(),
+            vec![(0, 0, width, height)]
+        );
+        assert!(decoded
+            .plane(2)
+            .rows()
+            .all(|row| row.iter().all(|x| x == NEUTRAL)));
+
+        // a header that can't be read leaves nothing to recover
+        assert!(RGB48Frame::decode_lossy_recover::<Codec, _>(&encoded[..4]).is_err());
+    }
+}
@@ -0,0 +1,878 @@
+use super::{
+    codec::KMode,
+    color::ColorTransform,
+    frame::{EncodeOptions, RGB48Frame, VerifyReport},
+    metrics::{DiffReport, FramePsnr, PlaneDiff, SampleDiff},
+};
+use std::{
+    convert::TryInto,
+    fmt,
+    io::{self, BufRead, Write},
+    str::Chars,
+};
+use thiserror::Error;
+
+// Reports are written as JSON, one record per line, for tools that aggregate results over many
+// frames. The field names of the records are the names of the structs' fields, so renaming a field
+// changes what those tools see.
+
+#[derive(Error, Debug)]
+pub enum JsonError {
+    #[error(transparent)]
+    IO(#[from] io::Error),
+    // The text isn't JSON. The position is a byte offset into the line or string being parsed.
+    #[error("invalid JSON at byte {0}")]
+    Syntax(usize),
+    // The JSON is valid, but the named field is missing or has the wrong type.
+    #[error("missing or invalid field: {0}")]
+    InvalidField(String),
+}
+
+// A JSON value. Numbers that are whole and fit in a u64 are kept as integers, so that hashes and
+// sizes survive being written and read back exactly. Objects keep their fields in order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Integer(u64),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    // Parses a single JSON value, which may be surrounded by whitespace.
+    pub fn parse(text: &str) -> Result<Self, JsonError> {
+        let mut parser = Parser {
+            chars: text.chars(),
+            len: text.len(),
+        };
+        let ret = parser.value()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => Ok(ret),
+            Some(_) => Err(parser.error()),
+        }
+    }
+
+    // The value of an object's field, or None if it has no such field or isn't an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Self::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    // Reads an object's field. A missing field is read as null, so it's only an error if the field
+    // isn't optional.
+    pub fn field<T: FromJson>(&self, key: &str) -> Result<T, JsonError> {
+        T::from_json(self.get(key).unwrap_or(&Self::Null))
+            .map_err(|_| JsonError::InvalidField(key.to_string()))
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+// Formats the value as compact JSON, with no whitespace. Numbers that aren't finite, which JSON
+// can't represent, are written as null.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Null => write!(f, "null"),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Integer(n) => write!(f, "{}", n),
+            Self::Number(x) if x.is_finite() => write!(f, "{}", x),
+            Self::Number(_) => write!(f, "null"),
+            Self::String(s) => write_string(f, s),
+            Self::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Self::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+// A recursive descent parser over the characters of a string.
+struct Parser<'a> {
+    chars: Chars<'a>,
+    len: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self) -> JsonError {
+        JsonError::Syntax(self.len - self.chars.as_str().len())
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.clone().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), JsonError> {
+        match self.chars.as_str().strip_prefix(expected) {
+            Some(rest) => {
+                self.chars = rest.chars();
+                Ok(())
+            }
+            None => Err(self.error()),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.skip_whitespace();
+        match self.peek().ok_or_else(|| self.error())? {
+            'n' => self.expect("null").map(|_| Json::Null),
+            't' => self.expect("true").map(|_| Json::Bool(true)),
+            'f' => self.expect("false").map(|_| Json::Bool(false)),
+            '"' => self.string().map(Json::String),
+            '[' => {
+                self.chars.next();
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.chars.next();
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.chars.next() {
+                        Some(',') => continue,
+                        Some(']') => return Ok(Json::Array(values)),
+                        _ => return Err(self.error()),
+                    }
+                }
+            }
+            '{' => {
+                self.chars.next();
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.chars.next();
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some('"') {
+                        return Err(self.error());
+                    }
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    self.expect(":")?;
+                    fields.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.chars.next() {
+                        Some(',') => continue,
+                        Some('}') => return Ok(Json::Object(fields)),
+                        _ => return Err(self.error()),
+                    }
+                }
+            }
+            '-' | '0'..='9' => self.number(),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let rest = self.chars.as_str();
+        let len = rest
+            .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+            .unwrap_or(rest.len());
+        let text = &rest[..len];
+        let ret = match text.parse::<u64>() {
+            Ok(n) => Json::Integer(n),
+            Err(_) => Json::Number(text.parse().map_err(|_| self.error())?),
+        };
+        self.chars = rest[len..].chars();
+        Ok(ret)
+    }
+
+    fn hex_escape(&mut self) -> Result<u32, JsonError> {
+        let rest = self.chars.as_str();
+        let code = rest
+            .get(..4)
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| self.error())?;
+        self.chars = rest[4..].chars();
+        Ok(code)
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect("\"")?;
+        let mut ret = String::new();
+        loop {
+            match self.chars.next().ok_or_else(|| self.error())? {
+                '"' => return Ok(ret),
+                '\\' => {
+                    let c = match self.chars.next().ok_or_else(|| self.error())? {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let mut code = self.hex_escape()?;
+                            // characters outside the basic multilingual plane are escaped as
+                            // surrogate pairs
+                            if (0xd800..0xdc00).contains(&code) {
+                                self.expect("\\u")?;
+                                let low = self.hex_escape()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(self.error());
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            char::from_u32(code).ok_or_else(|| self.error())?
+                        }
+                        _ => return Err(self.error()),
+                    };
+                    ret.push(c);
+                }
+                c if (c as u32) < 0x20 => return Err(self.error()),
+                c => ret.push(c),
+            }
+        }
+    }
+}
+
+// Types that can be written as JSON.
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+// Types that can be read back from the JSON their ToJson implementation writes.
+pub trait FromJson: Sized {
+    fn from_json(json: &Json) -> Result<Self, JsonError>;
+}
+
+fn invalid(what: &str) -> JsonError {
+    JsonError::InvalidField(what.to_string())
+}
+
+impl ToJson for u64 {
+    fn to_json(&self) -> Json {
+        Json::Integer(*self)
+    }
+}
+
+impl FromJson for u64 {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json {
+            Json::Integer(n) => Ok(*n),
+            _ => Err(invalid("expected an unsigned integer")),
+        }
+    }
+}
+
+impl ToJson for usize {
+    fn to_json(&self) -> Json {
+        Json::Integer(*self as u64)
+    }
+}
+
+impl FromJson for usize {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        u64::from_json(json)?
+            .try_into()
+            .map_err(|_| invalid("integer out of range"))
+    }
+}
+
+impl ToJson for u16 {
+    fn to_json(&self) -> Json {
+        Json::Integer(*self as u64)
+    }
+}
+
+impl FromJson for u16 {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        u64::from_json(json)?
+            .try_into()
+            .map_err(|_| invalid("integer out of range"))
+    }
+}
+
+impl ToJson for u8 {
+    fn to_json(&self) -> Json {
+        Json::Integer(*self as u64)
+    }
+}
+
+impl FromJson for u8 {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        u64::from_json(json)?
+            .try_into()
+            .map_err(|_| invalid("integer out of range"))
+    }
+}
+
+impl ToJson for bool {
+    fn to_json(&self) -> Json {
+        Json::Bool(*self)
+    }
+}
+
+impl FromJson for bool {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json {
+            Json::Bool(b) => Ok(*b),
+            _ => Err(invalid("expected a boolean")),
+        }
+    }
+}
+
+impl ToJson for f64 {
+    fn to_json(&self) -> Json {
+        Json::Number(*self)
+    }
+}
+
+// Null is read as infinity, since that's the only value written as null that a report holds: the
+// PSNR of identical samples.
+impl FromJson for f64 {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json {
+            Json::Integer(n) => Ok(*n as f64),
+            Json::Number(x) => Ok(*x),
+            Json::Null => Ok(f64::INFINITY),
+            _ => Err(invalid("expected a number")),
+        }
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> Json {
+        Json::String(self.clone())
+    }
+}
+
+impl FromJson for String {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json {
+            Json::String(s) => Ok(s.clone()),
+            _ => Err(invalid("expected a string")),
+        }
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Json {
+        self.as_ref().map_or(Json::Null, ToJson::to_json)
+    }
+}
+
+impl<T: FromJson> FromJson for Option<T> {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json {
+            Json::Null => Ok(None),
+            json => T::from_json(json).map(Some),
+        }
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> Json {
+        Json::Array(self.iter().map(ToJson::to_json).collect())
+    }
+}
+
+impl<T: FromJson> FromJson for Vec<T> {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json {
+            Json::Array(values) => values.iter().map(T::from_json).collect(),
+            _ => Err(invalid("expected an array")),
+        }
+    }
+}
+
+// Pairs, like tile sizes, are written as two-element arrays.
+impl<A: ToJson, B: ToJson> ToJson for (A, B) {
+    fn to_json(&self) -> Json {
+        Json::Array(vec![self.0.to_json(), self.1.to_json()])
+    }
+}
+
+impl<A: FromJson, B: FromJson> FromJson for (A, B) {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json {
+            Json::Array(values) if values.len() == 2 => {
+                Ok((A::from_json(&values[0])?, B::from_json(&values[1])?))
+            }
+            _ => Err(invalid("expected a pair")),
+        }
+    }
+}
+
+pub(crate) fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+impl ToJson for ColorTransform {
+    fn to_json(&self) -> Json {
+        Json::String(format!("{:?}", self))
+    }
+}
+
+impl FromJson for ColorTransform {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json {
+            Json::String(s) if s == "None" => Ok(Self::None),
+            Json::String(s) if s == "YCoCgR" => Ok(Self::YCoCgR),
+            _ => Err(invalid("expected a color transform")),
+        }
+    }
+}
+
+// A fixed k is written as the number, and the other modes by name. Records from before there was a
+// choice have no k mode, which means KMode::Adaptive.
+impl ToJson for KMode {
+    fn to_json(&self) -> Json {
+        match self {
+            Self::Fixed(k) => k.to_json(),
+            _ => Json::String(format!("{:?}", self)),
+        }
+    }
+}
+
+impl FromJson for KMode {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json {
+            Json::Null => Ok(Self::Adaptive),
+            Json::String(s) if s == "Adaptive" => Ok(Self::Adaptive),
+            Json::String(s) if s == "PerPlaneAuto" => Ok(Self::PerPlaneAuto),
+            _ => Ok(Self::Fixed(
+                u8::from_json(json).map_err(|_| invalid("expected a k mode"))?,
+            )),
+        }
+    }
+}
+
+impl ToJson for EncodeOptions {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("color_transform", self.color_transform.to_json()),
+            ("tile_size", self.tile_size.to_json()),
+            ("quantization", self.quantization.to_json()),
+            ("preview_factor", self.preview_factor.to_json()),
+            ("band_height", self.band_height.to_json()),
+            ("k_mode", self.k_mode.to_json()),
+        ])
+    }
+}
+
+impl FromJson for EncodeOptions {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        Ok(Self {
+            color_transform: json.field("color_transform")?,
+            tile_size: json.field("tile_size")?,
+            quantization: json.field("quantization")?,
+            preview_factor: json.field("preview_factor")?,
+            band_height: json.field("band_height")?,
+            k_mode: json.field("k_mode")?,
+        })
+    }
+}
+
+impl ToJson for FramePsnr {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("planes", self.planes.to_json()),
+            ("combined", self.combined.to_json()),
+        ])
+    }
+}
+
+impl FromJson for FramePsnr {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        Ok(Self {
+            planes: json.field("planes")?,
+            combined: json.field("combined")?,
+        })
+    }
+}
+
+impl ToJson for SampleDiff {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("col", self.col.to_json()),
+            ("row", self.row.to_json()),
+            ("plane", self.plane.to_json()),
+            ("expected", self.expected.to_json()),
+            ("actual", self.actual.to_json()),
+        ])
+    }
+}
+
+impl FromJson for SampleDiff {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        Ok(Self {
+            col: json.field("col")?,
+            row: json.field("row")?,
+            plane: json.field("plane")?,
+            expected: json.field("expected")?,
+            actual: json.field("actual")?,
+        })
+    }
+}
+
+impl ToJson for PlaneDiff {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("differing", self.differing.to_json()),
+            ("max_abs_error", self.max_abs_error.to_json()),
+        ])
+    }
+}
+
+impl FromJson for PlaneDiff {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        Ok(Self {
+            differing: json.field("differing")?,
+            max_abs_error: json.field("max_abs_error")?,
+        })
+    }
+}
+
+impl ToJson for DiffReport {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("differing", self.differing.to_json()),
+            ("max_abs_error", self.max_abs_error.to_json()),
+            ("planes", self.planes.to_json()),
+            ("first", self.first.to_json()),
+        ])
+    }
+}
+
+impl FromJson for DiffReport {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        Ok(Self {
+            differing: json.field("differing")?,
+            max_abs_error: json.field("max_abs_error")?,
+            planes: json.field("planes")?,
+            first: json.field("first")?,
+        })
+    }
+}
+
+impl ToJson for VerifyReport {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("size", self.size.to_json()),
+            ("hash", self.hash.to_json()),
+            ("content_hash", self.content_hash.to_json()),
+        ])
+    }
+}
+
+impl FromJson for VerifyReport {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        Ok(Self {
+            size: json.field("size")?,
+            hash: json.field("hash")?,
+            content_hash: json.field("content_hash")?,
+        })
+    }
+}
+
+// What encoding one frame produced, and what it took.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    // The frame's index within its container or sequence, or 0 for a lone frame.
+    pub frame: usize,
+    // The file the frame was loaded from, if any.
+    pub input: Option<String>,
+    pub width: usize,
+    pub height: usize,
+    pub planes: usize,
+    pub bit_depth: u8,
+    // The size of the frame's samples at 16 bits each.
+    pub uncompressed_size: usize,
+    // The size of the frame as encoded by RGB48Frame::encode_with_header.
+    pub encoded_size: usize,
+    // The time encoding took, in seconds.
+    pub wall_time: f64,
+    pub options: EncodeOptions,
+    // The frame's quality as decoded, for lossy encodes.
+    pub psnr: Option<FramePsnr>,
+    // The outcome of encoding with RGB48Frame::encode_verified.
+    pub verify: Option<VerifyReport>,
+}
+
+impl Report {
+    // A report on a frame encoded with the given options, with the frame's dimensions filled in and
+    // the rest left to the caller.
+    pub fn new(frame: usize, image: &RGB48Frame, options: &EncodeOptions) -> Self {
+        Self {
+            frame,
+            input: None,
+            width: image.width,
+            height: image.height,
+            planes: image.n_planes(),
+            bit_depth: image.bit_depth,
+            uncompressed_size: image.data.len() * 2,
+            encoded_size: 0,
+            wall_time: 0.0,
+            options: options.clone(),
+            psnr: None,
+            verify: None,
+        }
+    }
+}
+
+impl ToJson for Report {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("frame", self.frame.to_json()),
+            ("input", self.input.to_json()),
+            ("width", self.width.to_json()),
+            ("height", self.height.to_json()),
+            ("planes", self.planes.to_json()),
+            ("bit_depth", self.bit_depth.to_json()),
+            ("uncompressed_size", self.uncompressed_size.to_json()),
+            ("encoded_size", self.encoded_size.to_json()),
+            ("wall_time", self.wall_time.to_json()),
+            ("options", self.options.to_json()),
+            ("psnr", self.psnr.to_json()),
+            ("verify", self.verify.to_json()),
+        ])
+    }
+}
+
+impl FromJson for Report {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        Ok(Self {
+            frame: json.field("frame")?,
+            input: json.field("input")?,
+            width: json.field("width")?,
+            height: json.field("height")?,
+            planes: json.field("planes")?,
+            bit_depth: json.field("bit_depth")?,
+            uncompressed_size: json.field("uncompressed_size")?,
+            encoded_size: json.field("encoded_size")?,
+            wall_time: json.field("wall_time")?,
+            options: json.field("options")?,
+            psnr: json.field("psnr")?,
+            verify: json.field("verify")?,
+        })
+    }
+}
+
+// Writes records as newline-delimited JSON, one object per line.
+pub struct RecordWriter<W: Write> {
+    dest: W,
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(dest: W) -> Self {
+        Self { dest }
+    }
+
+    pub fn write_record<T: ToJson + ?Sized>(&mut self, record: &T) -> io::Result<()> {
+        writeln!(self.dest, "{}", record.to_json())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.dest.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.dest
+    }
+}
+
+// Reads records written by RecordWriter, skipping blank lines.
+pub fn read_records<T: FromJson, R: BufRead>(
+    source: R,
+) -> impl Iterator<Item = Result<T, JsonError>> {
+    source
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| T::from_json(&Json::parse(&line?)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::synth, *};
+
+    #[test]
+    fn test_json() {
+        let json = Json::parse(
+            r#" {"a": [1, -2, 0.5, 1e3, 18446744073709551615], "b": {"c": null, "d": true},
+                "e": "tab\there \"quoted\" \u00e9\ud83d\ude00", "f": [], "g": {}} "#,
+        )
+        .unwrap();
+        assert_eq!(
+            json.get("a"),
+            Some(&Json::Array(vec![
+                Json::Integer(1),
+                Json::Number(-2.0),
+                Json::Number(0.5),
+                Json::Number(1000.0),
+                Json::Integer(u64::MAX),
+            ]))
+        );
+        assert_eq!(
+            json.get("e"),
+            Some(&Json::String("tab\there \"quoted\" é😀".to_string()))
+        );
+        assert_eq!(json.get("b").and_then(|b| b.get("c")), Some(&Json::Null));
+        // what's written parses back to the same thing, though whole numbers become integers
+        let written = json.to_string();
+        assert_eq!(Json::parse(&written).unwrap().to_string(), written);
+        assert_eq!(Json::Number(1000.0).to_string(), "1000");
+        assert_eq!(
+            Json::Array(vec![
+                Json::Number(f64::INFINITY),
+                Json::String("\u{1}".into())
+            ])
+            .to_string(),
+            r#"[null,"\u0001"]"#
+        );
+
+        for bad in [
+            "",
+            "[1,]",
+            "{\"a\" 1}",
+            "\"unterminated",
+            "tru",
+            "[1] 2",
+            "\"\\ud800\"",
+        ] {
+            assert!(
+                matches!(Json::parse(bad), Err(JsonError::Syntax(_))),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_report_golden() {
+        // the field names are what other tools read, so they mustn't change by accident
+        let frame = synth::noise(4, 2, 1, 10);
+        let mut report = Report::new(3, &frame, &EncodeOptions::default());
+        report.input = Some("frames/0003.tif".to_string());
+        report.encoded_size = 40;
+        report.wall_time = 0.25;
+        report.psnr = Some(FramePsnr {
+            planes: vec![f64::INFINITY, 48.5, 50.0],
+            combined: 49.125,
+        });
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.write_record(&report).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            concat!(
+                r#"{"frame":3,"input":"frames/0003.tif","width":4,"height":2,"planes":3,"#,
+                r#""bit_depth":10,"uncompressed_size":48,"encoded_size":40,"wall_time":0.25,"#,
+                r#""options":{"color_transform":"None","tile_size":null,"quantization":null,"#,
+                r#""preview_factor":null,"band_height":null,"k_mode":"Adaptive"},"#,
+                r#""psnr":{"planes":[null,48.5,50],"combined":49.125},"#,
+                r#""verify":null}"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_report_roundtrip() {
+        let frame = synth::noise(16, 8, 2, 12);
+        let options = EncodeOptions {
+            color_transform: ColorTransform::YCoCgR,
+            tile_size: Some((8, 4)),
+            quantization: Some(vec![1, 2, 3]),
+            ..Default::default()
+        };
+        let mut first = Report::new(0, &frame, &options);
+        first.input = Some("a \"quoted\"\nname".to_string());
+        first.encoded_size = 123;
+        first.wall_time = 0.1 + 0.2;
+        first.psnr = Some(FramePsnr {
+            planes: vec![61.25, f64::INFINITY, 1.0 / 3.0],
+            combined: 70.0,
+        });
+        first.verify = Some(VerifyReport {
+            size: 123,
+            hash: u64::MAX - 1,
+            content_hash: frame.content_hash(),
+        });
+        let mut second = Report::new(1, &frame, &EncodeOptions::default());
+        second.wall_time = 1e-9;
+
+        let mut writer = RecordWriter::new(Vec::new());
+        writer.write_record(&first).unwrap();
+        writer.write_record(&second).unwrap();
+        let written = writer.into_inner();
+        let read = read_records::<Report, _>(&*written)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(read, vec![first, second]);
+
+        // and the other reports
+        let mut other = frame.clone();
+        other.data[5] ^= 0x40;
+        other.data[20] ^= 0x1;
+        let diff = frame.diff(&other).unwrap();
+        let json = Json::parse(&diff.to_json().to_string()).unwrap();
+        assert_eq!(DiffReport::from_json(&json).unwrap(), diff);
+        assert_eq!(json.get("first").map(|first| first.to_string()).unwrap(), {
+            let (a, b) = (&diff.first[0], &diff.first[1]);
+            format!(
+                r#"[{{"col":{},"row":{},"plane":2,"expected":{},"actual":{}}},{{"col":{},"row":{},"plane":2,"expected":{},"actual":{}}}]"#,
+                a.col, a.row, a.expected, a.actual, b.col, b.row, b.expected, b.actual
+            )
+        });
+
+        let err = read_records::<Report, _>(&b"{\"frame\":1}\n"[..])
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(err, JsonError::InvalidField(field) if field == "width"));
+    }
+}
@@ -0,0 +1,196 @@
+use super::{
+    codec::{fixed_prediction, unzigzag, zigzag},
+    error::{CodecError, Result},
+    frame::{self, Plane},
+};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::io::{Error, ErrorKind, Read, Write};
+
+// Computes the same prediction residuals as codec::Codec, but instead of Rice coding them, packs
+// them as zigzagged varints and hands the whole plane to a general-purpose compressor. This does
+// poorly on natural imagery but wins big on flat or synthetic content (UI captures, plots), where
+// long runs of identical residuals are exactly what LZ77 is good at.
+//
+// Each plane is written as a big-endian u64 byte count followed by the deflate stream, so that
+// decoding never reads past the end of the plane. LEVEL is the deflate compression level (0-9).
+pub struct DeflateResidualCodec<const LEVEL: u32 = 6>;
+
+fn write_varint(mut x: u32, dest: &mut Vec<u8>) {
+    while x >= 0x80 {
+        dest.push((x as u8) | 0x80);
+        x >>= 7;
+    }
+    dest.push(x as u8);
+}
+
+fn read_varint<I: Iterator<Item = u8>>(source: &mut I) -> Result<u32> {
+    let mut x = 0u32;
+    for shift in (0..35).step_by(7) {
+        let b = source.next().ok_or_else(|| {
+            Error::new(ErrorKind::UnexpectedEof, "unexpected end of residual data")
+        })?;
+        x |= ((b & 0x7f) as u32) << shift;
+        if b & 0x80 == 0 {
+            return Ok(x);
+        }
+    }
+    Err(CodecError::InvalidData(
+        "residual varint is too long".to_string(),
+    ))
+}
+
+impl<const LEVEL: u32> frame::Codec for DeflateResidualCodec<LEVEL> {
+    fn encode<T: AsRef<[u16]>, W: Write>(plane: &Plane<T>, mut dest: W) -> Result<()> {
+        let data = plane.data.as_ref();
+        let mut residuals = Vec::with_capacity(plane.width * plane.height);
+
+        let mut b = 0;
+        for row in 0..plane.height {
+            let mut a = 0;
+            let mut c = 0;
+            for col in 0..plane.width {
+                let x = data[row * plane.row_stride + col * plane.sample_stride];
+                let d = if row > 0 && col + 1 < plane.width {
+                    data[(row - 1) * plane.row_stride + (col + 1) * plane.sample_stride]
+                } else {
+                    0
+                };
+
+                let prediction = fixed_prediction(a, b, c);
+                write_varint(zigzag(x as i32 - prediction), &mut residuals);
+
+                c = b;
+                b = d;
+                a = x;
+            }
+            b = data[row * plane.row_stride];
+        }
+
+        let mut compressed = DeflateEncoder::new(Vec::new(), Compression::new(LEVEL));
+        compressed.write_all(&residuals)?;
+        let compressed = compressed.finish()?;
+        dest.write_all(&(compressed.len() as u64).to_be_bytes())?;
+        Ok(dest.write_all(&compressed)?)
+    }
+
+    fn decode<T: AsMut<[u16]>, R: Read>(mut source: R, plane: &mut Plane<T>) -> Result<()> {
+        let mut len = [0; 8];
+        source.read_exact(&mut len)?;
+        let len = u64::from_be_bytes(len);
+
+        // a residual varint never exceeds 3 bytes, which bounds how much we're willing to inflate
+        let max_len = 3 * plane.width * plane.height;
+        let mut residuals = Vec::new();
+        DeflateDecoder::new(source.take(len))
+            .take(max_len as u64)
+            .read_to_end(&mut residuals)?;
+        let mut residuals = residuals.into_iter();
+
+        let data = plane.data.as_mut();
+
+        let mut b = 0;
+        for row in 0..plane.height {
+            let mut a = 0;
+            let mut c = 0;
+            for col in 0..plane.width {
+                let d = if row > 0 && col + 1 < plane.width {
+                    data[(row - 1) * plane.row_stride + (col + 1) * plane.sample_stride]
+                } else {
+                    0
+                };
+
+                let prediction = fixed_prediction(a, b, c);
+                let prediction_residual =
+                    unzigzag(read_varint(&mut residuals).map_err(|e| e.in_plane(0, col, row))?);
+
+                let x = prediction.wrapping_add(prediction_residual) as u16;
+                data[row * plane.row_stride + col * plane.sample_stride] = x;
+
+                c = b;
+                b = d;
+                a = x;
+            }
+            b = data[row * plane.row_stride];
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{codec::Codec, frame::RGB48Frame},
+        *,
+    };
+
+    fn gradient_frame(width: usize, height: usize) -> RGB48Frame {
+        let mut data = Vec::with_capacity(width * height * 3);
+        for row in 0..height {
+            for col in 0..width {
+                data.push((col * 16) as u16);
+                data.push((row * 16) as u16);
+                data.push(((col + row) * 8) as u16);
+            }
+        }
+        RGB48Frame {
+            data,
+            width,
+            height,
+            bit_depth: 16,
+        }
+    }
+
+    #[test]
+    fn test_varint() {
+        for &x in [0, 1, 127, 128, 16383, 16384, 131070, u32::MAX].iter() {
+            let mut buf = Vec::new();
+            write_varint(x, &mut buf);
+            assert_eq!(read_varint(&mut buf.into_iter()).unwrap(), x);
+        }
+    }
+
+    #[test]
+    fn test_synthetic_beats_rice() {
+        for frame in [
+            RGB48Frame {
+                data: vec![0x1234; 512 * 256 * 3],
+                width: 512,
+                height: 256,
+                bit_depth: 16,
+            },
+            gradient_frame(512, 256),
+        ]
+        .iter()
+        {
+            let mut rice = Vec::new();
+            frame.encode::<Codec, _>(&mut rice).unwrap();
+
+            let mut deflate = Vec::new();
+            frame
+                .encode::<DeflateResidualCodec, _>(&mut deflate)
+                .unwrap();
+            assert!(deflate.len() * 10 < rice.len());
+
+            let decoded =
+                RGB48Frame::decode::<DeflateResidualCodec, _>(&*deflate, frame.width, frame.height)
+                    .unwrap();
+            assert!(*frame == decoded);
+        }
+    }
+
+    #[test]
+    fn test_codec_12130() {
+        let frame = RGB48Frame::open("src/testdata/tears_of_steel_12130.tif").unwrap();
+
+        let mut encoded = Vec::new();
+        frame
+            .encode::<DeflateResidualCodec<1>, _>(&mut encoded)
+            .unwrap();
+
+        let decoded =
+            RGB48Frame::decode::<DeflateResidualCodec<1>, _>(&*encoded, frame.width, frame.height)
+                .unwrap();
+        assert!(frame == decoded);
+    }
+}
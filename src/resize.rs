@@ -0,0 +1,256 @@
+use super::{
+    frame::{Plane, RGB48Frame},
+    gray::GrayFrame16,
+    pyramid,
+};
+
+// Resampling filters for Plane::resize. Both work in integers, so results are exactly reproducible:
+// each output sample is a weighted sum of source samples with integer weights, divided by the sum
+// of the weights and rounded half up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    // Averages the source samples each output sample's area covers, weighted by how much of each
+    // it covers. Upscaling with it repeats samples, blending them only where an output sample
+    // straddles two source samples.
+    Box,
+    // Interpolates between the source samples nearest each output sample's center. Sample centers
+    // are aligned the way they are for the box filter, and samples past the edges are clamped to
+    // the edge samples.
+    Bilinear,
+}
+
+// For each output position along an axis, the source positions it draws from and their weights.
+// Every position's weights add up to the same total.
+struct Taps {
+    taps: Vec<Vec<(usize, u64)>>,
+    total: u64,
+}
+
+impl Taps {
+    // Positions are measured in units of 1 / dst of a source sample, so that each source sample is
+    // dst units wide and each output sample src units wide.
+    fn boxed(src: usize, dst: usize) -> Self {
+        let (src64, dst64) = (src as u64, dst as u64);
+        let taps = (0..dst64)
+            .map(|x| {
+                let (lo, hi) = (x * src64, (x + 1) * src64);
+                (lo / dst64..=(hi - 1) / dst64)
+                    .map(|i| {
+                        let overlap = hi.min((i + 1) * dst64) - lo.max(i * dst64);
+                        (i as usize, overlap)
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { taps, total: src64 }
+    }
+
+    // Positions are measured in units of 1 / (2 * dst) of a source sample, relative to the first
+    // source sample's center.
+    fn bilinear(src: usize, dst: usize) -> Self {
+        let (src64, dst64) = (src as u64, dst as u64);
+        let span = 2 * dst64;
+        let taps = (0..dst64)
+            .map(|x| {
+                let pos = ((2 * x + 1) * src64).saturating_sub(dst64);
+                let i = (pos / span) as usize;
+                if i + 1 >= src {
+                    vec![(src - 1, span)]
+                } else {
+                    let weight = pos % span;
+                    vec![(i, span - weight), (i + 1, weight)]
+                }
+            })
+            .collect();
+        Self { taps, total: span }
+    }
+
+    fn new(filter: Filter, src: usize, dst: usize) -> Self {
+        match filter {
+            Filter::Box => Self::boxed(src, dst),
+            Filter::Bilinear => Self::bilinear(src, dst),
+        }
+    }
+}
+
+impl<T: AsRef<[u16]>> Plane<T> {
+    // Resamples the plane to width×height. Shrinking by a whole factor in both directions always
+    // uses the box filter, which for such factors averages whole blocks of samples, and is done
+    // with pyramid::downsample when the factors are the same. Panics if the plane is empty and the
+    // result isn't.
+    pub fn resize(&self, width: usize, height: usize, filter: Filter) -> Plane<Vec<u16>> {
+        let mut ret = Plane {
+            data: vec![0; width * height],
+            width,
+            height,
+            sample_stride: 1,
+            row_stride: width,
+        };
+        if width == 0 || height == 0 {
+            return ret;
+        }
+        assert!(
+            self.width > 0 && self.height > 0,
+            "can't resize an empty plane to {}x{}",
+            width,
+            height
+        );
+
+        let whole_factors = self.width.is_multiple_of(width) && self.height.is_multiple_of(height);
+        if whole_factors && self.width / width == self.height / height {
+            pyramid::downsample_into(self, self.width / width, &mut ret);
+            return ret;
+        }
+        let filter = if whole_factors { Filter::Box } else { filter };
+
+        // filter the rows horizontally into unnormalized sums, then those vertically
+        let horizontal = Taps::new(filter, self.width, width);
+        let vertical = Taps::new(filter, self.height, height);
+        let mut sums = Vec::with_capacity(width * self.height);
+        for row in self.rows() {
+            sums.extend(horizontal.taps.iter().map(|taps| {
+                taps.iter()
+                    .map(|&(i, weight)| row.sample(i) as u64 * weight)
+                    .sum::<u64>()
+            }));
+        }
+        let total = horizontal.total * vertical.total;
+        for (taps, dest) in vertical.taps.iter().zip(ret.data.chunks_exact_mut(width)) {
+            for (x, dest) in dest.iter_mut().enumerate() {
+                let sum: u64 = taps
+                    .iter()
+                    .map(|&(j, weight)| sums[j * width + x] * weight)
+                    .sum();
+                *dest = ((sum + total / 2) / total) as u16;
+            }
+        }
+        ret
+    }
+}
+
+impl RGB48Frame {
+    // Resamples each of the frame's planes with Plane::resize.
+    pub fn resize(&self, width: usize, height: usize, filter: Filter) -> RGB48Frame {
+        let n_planes = self.n_planes();
+        let mut ret = RGB48Frame {
+            data: vec![0; width * height * n_planes],
+            width,
+            height,
+            bit_depth: self.bit_depth,
+        };
+        for i in 0..n_planes {
+            let plane = self.plane(i).resize(width, height, filter);
+            if width > 0 && height > 0 {
+                ret.plane_mut(i)
+                    .copy_from(&plane)
+                    .expect("the resized plane has the frame's dimensions");
+            }
+        }
+        ret
+    }
+}
+
+impl GrayFrame16 {
+    pub fn resize(&self, width: usize, height: usize, filter: Filter) -> GrayFrame16 {
+        GrayFrame16 {
+            data: self.plane().resize(width, height, filter).data,
+            width,
+            height,
+            bit_depth: self.bit_depth,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::synth, *};
+
+    fn plane(data: Vec<u16>, width: usize, height: usize) -> Plane<Vec<u16>> {
+        Plane {
+            data,
+            width,
+            height,
+            sample_stride: 1,
+            row_stride: width,
+        }
+    }
+
+    #[test]
+    fn test_resize() {
+        // every 2x2 block of a checkerboard has two of each value
+        let checkerboard = synth::checkerboard_gray(6, 4, 1, 0, 65535);
+        let checkerboard = checkerboard.plane();
+        for &filter in [Filter::Box, Filter::Bilinear].iter() {
+            let half = checkerboard.resize(3, 2, filter);
+            assert_eq!(half.data, vec![32768; 6]);
+        }
+        // shrinking only horizontally averages 3x1 blocks, and a box that straddles source samples
+        // weighs them by how much of each it covers
+        let squashed = checkerboard.resize(2, 4, Filter::Box);
+        assert_eq!(squashed.data[..2], [21845, 43690]);
+        let stretched = checkerboard.view(0, 0, 3, 1).resize(2, 1, Filter::Box);
+        // 0 and 65535 weighted 2:1, then 65535 and 0 weighted 1:2
+        assert_eq!(stretched.data, vec![21845, 21845]);
+
+        // whole-factor shrinking matches the pyramid's base layer
+        let frame = synth::noise(24, 12, 1, 16);
+        let red = frame.plane(0);
+        assert_eq!(
+            red.resize(6, 3, Filter::Bilinear).data,
+            pyramid::downsample(&red, 4).data
+        );
+        // and unequal factors give plain block averages too
+        let block = red.view(0, 0, 3, 2).to_owned();
+        let sum: u64 = block.data.iter().map(|&x| x as u64).sum();
+        assert_eq!(
+            red.resize(8, 6, Filter::Bilinear).sample(0, 0) as u64,
+            (sum + 3) / 6
+        );
+
+        // scaling a constant image up and back down changes nothing
+        let flat = plane(vec![12345; 5 * 3], 5, 3);
+        for &filter in [Filter::Box, Filter::Bilinear].iter() {
+            let up = flat.resize(13, 7, filter);
+            assert!(up.data.iter().all(|&x| x == 12345));
+            assert_eq!(up.resize(5, 3, filter).data, flat.data);
+        }
+
+        // bilinear upscaling interpolates between sample centers and clamps at the edges
+        let ramp = plane(vec![0, 1000], 2, 1);
+        assert_eq!(
+            ramp.resize(4, 1, Filter::Bilinear).data,
+            vec![0, 250, 750, 1000]
+        );
+        assert_eq!(ramp.resize(4, 1, Filter::Box).data, vec![0, 0, 1000, 1000]);
+
+        // dimensions of 1, in either direction, and interleaved planes
+        let (green, blue) = (frame.plane(1), frame.plane(2));
+        let row = green.view(0, 5, 24, 1);
+        assert_eq!(row.resize(1, 1, Filter::Bilinear).height, 1);
+        assert_eq!(row.resize(7, 5, Filter::Bilinear).data.len(), 35);
+        let column = blue.view(3, 0, 1, 12);
+        assert_eq!(column.resize(1, 4, Filter::Box).data.len(), 4);
+        assert_eq!(column.resize(3, 1, Filter::Bilinear).data.len(), 3);
+        assert_eq!(flat.resize(0, 3, Filter::Box).data, vec![]);
+
+        let gray = synth::noise_gray(9, 9, 2, 12);
+        let resized = gray.resize(4, 5, Filter::Box);
+        assert_eq!(
+            (resized.width, resized.height, resized.bit_depth),
+            (4, 5, 12)
+        );
+        assert_eq!(resized.data, gray.plane().resize(4, 5, Filter::Box).data);
+
+        let resized = frame.resize(10, 7, Filter::Bilinear);
+        assert_eq!(
+            (resized.width, resized.height, resized.channels()),
+            (10, 7, 3)
+        );
+        for i in 0..3 {
+            assert_eq!(
+                resized.plane(i).to_owned().data,
+                frame.plane(i).resize(10, 7, Filter::Bilinear).data
+            );
+        }
+    }
+}
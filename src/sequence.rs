@@ -0,0 +1,449 @@
+use super::{
+    error::{CodecError, Result},
+    frame::{Codec, RGB48Frame},
+    motion::MotionField,
+};
+use std::{
+    io::{Read, Write},
+    marker::PhantomData,
+};
+
+// Each frame of a sequence is preceded by a byte indicating how it was coded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameType {
+    // The frame is coded on its own, exactly like RGB48Frame::encode.
+    Intra = 0,
+    // The frame is coded as its difference from the previous frame. Each difference is wrapped to
+    // 16 bits and zigzagged (0, -1, 1, -2, ... become 0, 1, 2, 3, ...), so that small changes in
+    // either direction become small samples, and the resulting planes are fed to the codec like
+    // any other frame. The codec's prediction context starts at zero, so keeping the unchanged
+    // case at zero rather than mid-range is what makes this cheap.
+    Inter = 1,
+    // Like Inter, but the difference is taken against a motion-compensated version of the previous
+    // frame. The frame data is preceded by its motion field (see motion::MotionField), and blocks
+    // that the motion search escaped to intra coding hold their samples unchanged.
+    MotionCompensated = 2,
+}
+
+impl FrameType {
+    fn from_u8(b: u8) -> Result<Self> {
+        match b {
+            0 => Ok(Self::Intra),
+            1 => Ok(Self::Inter),
+            2 => Ok(Self::MotionCompensated),
+            _ => Err(CodecError::InvalidData(format!(
+                "invalid frame type: {}",
+                b
+            ))),
+        }
+    }
+}
+
+// Maps x to its difference from the reference sample p, as described for FrameType::Inter.
+pub(crate) fn fold_delta(x: u16, p: u16) -> u16 {
+    let delta = x.wrapping_sub(p) as i16;
+    ((delta << 1) ^ (delta >> 15)) as u16
+}
+
+pub(crate) fn unfold_delta(folded: u16, p: u16) -> u16 {
+    p.wrapping_add((folded >> 1) ^ (folded & 1).wrapping_neg())
+}
+
+fn temporal_delta(frame: &RGB48Frame, previous: &RGB48Frame) -> RGB48Frame {
+    RGB48Frame {
+        data: frame
+            .data
+            .iter()
+            .zip(previous.data.iter())
+            .map(|(&x, &p)| fold_delta(x, p))
+            .collect(),
+        width: frame.width,
+        height: frame.height,
+        bit_depth: frame.bit_depth,
+    }
+}
+
+fn undo_temporal_delta(delta: &mut RGB48Frame, previous: &RGB48Frame) {
+    for (x, &p) in delta.data.iter_mut().zip(previous.data.iter()) {
+        *x = unfold_delta(*x, p);
+    }
+}
+
+// Encodes a sequence of frames, using inter coding when it beats intra coding. The first frame and
+// scene cuts end up intra coded. The sequence has no header, and the decoder is given the
+// dimensions once (see SequenceDecoder::new), so every frame must have 3 channels and the same
+// dimensions as the first.
+pub struct SequenceEncoder<C: Codec> {
+    previous: Option<RGB48Frame>,
+    motion_compensation: bool,
+    _codec: PhantomData<C>,
+}
+
+impl<C: Codec> Default for SequenceEncoder<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Codec> SequenceEncoder<C> {
+    pub fn new() -> Self {
+        Self {
+            previous: None,
+            motion_compensation: false,
+            _codec: PhantomData,
+        }
+    }
+
+    // Enables motion-compensated inter coding. The motion search is a naive full search, so this
+    // is considerably slower than the other modes.
+    pub fn with_motion_compensation(mut self, enabled: bool) -> Self {
+        self.motion_compensation = enabled;
+        self
+    }
+
+    // Encodes the next frame of the sequence, returning the type it was coded as. Both modes are
+    // tried and the smaller one is kept, so inter frames cost roughly twice as much to encode.
+    // Frames that the decoder couldn't decode are rejected with CodecError::InvalidInput, and
+    // nothing is written for them.
+    pub fn encode_frame<W: Write>(&mut self, frame: &RGB48Frame, mut dest: W) -> Result<FrameType> {
+        let n_planes = frame
+            .validate()
+            .map_err(|e| CodecError::InvalidInput(e.to_string()))?;
+        if n_planes != 3 {
+            return Err(CodecError::InvalidInput(format!(
+                "a sequence's frames need 3 channels, not {}",
+                n_planes
+            )));
+        }
+        if let Some(previous) = &self.previous {
+            if (frame.width, frame.height) != (previous.width, previous.height) {
+                return Err(CodecError::InvalidInput(format!(
+                    "the frame is {}x{}, but the sequence is {}x{}",
+                    frame.width, frame.height, previous.width, previous.height
+                )));
+            }
+        }
+
+        let mut intra = Vec::new();
+        frame.encode::<C, _>(&mut intra)?;
+        let mut best = (FrameType::Intra, intra);
+
+        if let Some(previous) = &self.previous {
+            let mut inter = Vec::new();
+            temporal_delta(frame, previous).encode::<C, _>(&mut inter)?;
+            if inter.len() < best.1.len() {
+                best = (FrameType::Inter, inter);
+            }
+
+            if self.motion_compensation {
+                let field = MotionField::estimate(frame, previous);
+                let mut mc = Vec::new();
+                field.write(&mut mc)?;
+                field
+                    .residual_frame(frame, previous)
+                    .encode::<C, _>(&mut mc)?;
+                if mc.len() < best.1.len() {
+                    best = (FrameType::MotionCompensated, mc);
+                }
+            }
+        }
+
+        dest.write_all(&[best.0 as u8])?;
+        dest.write_all(&best.1)?;
+        // the codec is lossless, so the decoder's reference will be identical to the input
+        self.previous = Some(frame.clone());
+        Ok(best.0)
+    }
+}
+
+// Decodes a sequence of frames produced by SequenceEncoder.
+pub struct SequenceDecoder<C: Codec> {
+    previous: Option<RGB48Frame>,
+    width: usize,
+    height: usize,
+    _codec: PhantomData<C>,
+}
+
+impl<C: Codec> SequenceDecoder<C> {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            previous: None,
+            width,
+            height,
+            _codec: PhantomData,
+        }
+    }
+
+    fn previous(&self) -> Result<&RGB48Frame> {
+        self.previous.as_ref().ok_or_else(|| {
+            CodecError::InvalidData("inter frame without a previous frame".to_string())
+        })
+    }
+
+    pub fn decode_frame<R: Read>(&mut self, mut source: R) -> Result<RGB48Frame> {
+        let mut frame_type = [0];
+        source.read_exact(&mut frame_type)?;
+
+        let frame = match FrameType::from_u8(frame_type[0])? {
+            FrameType::Intra => RGB48Frame::decode::<C, _>(&mut source, self.width, self.height)?,
+            FrameType::Inter => {
+                let previous = self.previous()?;
+                let mut frame = RGB48Frame::decode::<C, _>(&mut source, self.width, self.height)?;
+                undo_temporal_delta(&mut frame, previous);
+                frame
+            }
+            FrameType::MotionCompensated => {
+                let previous = self.previous()?;
+                let field = MotionField::read(&mut source, self.width, self.height)?;
+                let mut frame = RGB48Frame::decode::<C, _>(&mut source, self.width, self.height)?;
+                field.reconstruct(&mut frame, previous);
+                frame
+            }
+        };
+
+        self.previous = Some(frame.clone());
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{
+            codec::Codec,
+            motion::{Block, MotionVector},
+        },
+        *,
+    };
+
+    #[test]
+    fn test_synthetic_sequence() {
+        let (width, height) = (64, 48);
+        let mut state = 1u32;
+        let mut frames = vec![RGB48Frame {
+            data: (0..width * height * 3)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    state as u16 & 0x0fff
+                })
+                .collect(),
+            width,
+            height,
+            bit_depth: 16,
+        }];
+        // each subsequent frame brightens slightly, with a little noise
+        for n in 1..4 {
+            let mut frame = frames[n - 1].clone();
+            for (i, x) in frame.data.iter_mut().enumerate() {
+                *x = x.wrapping_add(3 + (i * 7 % 5) as u16);
+            }
+            frames.push(frame);
+        }
+
+        let mut encoder = SequenceEncoder::<Codec>::new();
+        let mut encoded = Vec::new();
+        let mut frame_types = Vec::new();
+        for frame in &frames {
+            frame_types.push(encoder.encode_frame(frame, &mut encoded).unwrap());
+        }
+        assert_eq!(
+            frame_types,
+            vec![
+                FrameType::Intra,
+                FrameType::Inter,
+                FrameType::Inter,
+                FrameType::Inter
+            ]
+        );
+
+        let mut intra_len = 0;
+        for frame in &frames {
+            let mut intra = Vec::new();
+            frame.encode::<Codec, _>(&mut intra).unwrap();
+            intra_len += 1 + intra.len();
+        }
+        assert!(encoded.len() < intra_len);
+
+        let mut decoder = SequenceDecoder::<Codec>::new(width, height);
+        let mut source = &*encoded;
+        for frame in &frames {
+            assert!(decoder.decode_frame(&mut source).unwrap() == *frame);
+        }
+        assert!(source.is_empty());
+    }
+
+    fn noise_frame(width: usize, height: usize, mut state: u32) -> RGB48Frame {
+        RGB48Frame {
+            data: (0..width * height * 3)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    state as u16 & 0x0fff
+                })
+                .collect(),
+            width,
+            height,
+            bit_depth: 16,
+        }
+    }
+
+    #[test]
+    fn test_motion_compensated_shift() {
+        let (width, height) = (128, 96);
+        let first = noise_frame(width, height, 1);
+
+        // the second frame is the first shifted right by 3 and up by 2, with new content revealed
+        // along the left and bottom edges
+        let mut second = noise_frame(width, height, 2);
+        for y in 0..height - 2 {
+            for x in 3..width {
+                for c in 0..3 {
+                    second.data[(y * width + x) * 3 + c] =
+                        first.data[((y + 2) * width + x - 3) * 3 + c];
+                }
+            }
+        }
+
+        let mut encoder = SequenceEncoder::<Codec>::new().with_motion_compensation(true);
+        let mut encoded = Vec::new();
+        encoder.encode_frame(&first, &mut encoded).unwrap();
+        let first_len = encoded.len();
+        assert_eq!(
+            encoder.encode_frame(&second, &mut encoded).unwrap(),
+            FrameType::MotionCompensated
+        );
+        // only the revealed edges should cost anything
+        assert!((encoded.len() - first_len) * 4 < first_len);
+
+        let field = MotionField::estimate(&second, &first);
+        for by in 0..field.blocks_high - 1 {
+            for bx in 1..field.blocks_wide {
+                assert_eq!(
+                    field.blocks[by * field.blocks_wide + bx],
+                    Block::Inter(MotionVector { x: -3, y: 2 })
+                );
+            }
+        }
+
+        let mut decoder = SequenceDecoder::<Codec>::new(width, height);
+        let mut source = &*encoded;
+        assert!(decoder.decode_frame(&mut source).unwrap() == first);
+        assert!(decoder.decode_frame(&mut source).unwrap() == second);
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_motion_compensated_tears_of_steel() {
+        // a crop keeps the motion search fast enough for a debug build
+        let crop = |frame: RGB48Frame| {
+            let (width, height) = (200, 120);
+            let mut data = Vec::with_capacity(width * height * 3);
+            for y in 800..800 + height {
+                let i = (y * frame.width + 2000) * 3;
+                data.extend_from_slice(&frame.data[i..i + width * 3]);
+            }
+            RGB48Frame {
+                data,
+                width,
+                height,
+                bit_depth: 16,
+            }
+        };
+        let first = crop(RGB48Frame::open("src/testdata/tears_of_steel_12130.tif").unwrap());
+        let second = crop(RGB48Frame::open("src/testdata/tears_of_steel_12209.tif").unwrap());
+
+        let field = MotionField::estimate(&second, &first);
+        let mut encoded = Vec::new();
+        field.write(&mut encoded).unwrap();
+        field
+            .residual_frame(&second, &first)
+            .encode::<Codec, _>(&mut encoded)
+            .unwrap();
+
+        let mut source = &*encoded;
+        let decoded_field = MotionField::read(&mut source, second.width, second.height).unwrap();
+        assert_eq!(decoded_field, field);
+        let mut decoded =
+            RGB48Frame::decode::<Codec, _>(&mut source, second.width, second.height).unwrap();
+        decoded_field.reconstruct(&mut decoded, &first);
+        assert!(decoded == second);
+    }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn test_tears_of_steel_sequence() {
+        let frames = [
+            RGB48Frame::open("src/testdata/tears_of_steel_12130.tif").unwrap(),
+            RGB48Frame::open("src/testdata/tears_of_steel_12209.tif").unwrap(),
+        ];
+
+        let mut encoder = SequenceEncoder::<Codec>::new();
+        let mut encoded = Vec::new();
+        for frame in &frames {
+            // these frames are from different shots, so the second one is a scene cut
+            assert_eq!(
+                encoder.encode_frame(frame, &mut encoded).unwrap(),
+                FrameType::Intra
+            );
+        }
+        assert_eq!(encoded.len(), 2 + 25526583 + 28270586);
+
+        let mut decoder = SequenceDecoder::<Codec>::new(frames[0].width, frames[0].height);
+        let mut source = &*encoded;
+        for frame in &frames {
+            assert!(decoder.decode_frame(&mut source).unwrap() == *frame);
+        }
+    }
+
+    #[test]
+    fn test_inter_without_previous() {
+        let frame = RGB48Frame {
+            data: vec![0; 4 * 4 * 3],
+            width: 4,
+            height: 4,
+            bit_depth: 16,
+        };
+        let mut encoded = vec![FrameType::Inter as u8];
+        frame.encode::<Codec, _>(&mut encoded).unwrap();
+
+        match SequenceDecoder::<Codec>::new(4, 4).decode_frame(&*encoded) {
+            Err(e) => assert!(matches!(e, CodecError::InvalidData(_)), "{:?}", e),
+            Ok(_) => panic!("decoded an inter frame without a previous frame"),
+        }
+    }
+
+    #[test]
+    fn test_sequence_frame_mismatch() {
+        // frames the decoder couldn't decode are rejected, and the sequence around them still
+        // decodes
+        let frames = [noise_frame(16, 8, 1), noise_frame(16, 8, 2)];
+        let gray = RGB48Frame {
+            data: vec![0; 16 * 8],
+            width: 16,
+            height: 8,
+            bit_depth: 16,
+        };
+        let mut encoder = SequenceEncoder::<Codec>::new();
+        let mut encoded = Vec::new();
+        let err = encoder.encode_frame(&gray, &mut encoded).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidInput(_)), "{:?}", err);
+        encoder.encode_frame(&frames[0], &mut encoded).unwrap();
+        for bad in [noise_frame(8, 16, 3), noise_frame(16, 9, 3), gray] {
+            let len = encoded.len();
+            let err = encoder.encode_frame(&bad, &mut encoded).unwrap_err();
+            assert!(matches!(err, CodecError::InvalidInput(_)), "{:?}", err);
+            assert_eq!(encoded.len(), len);
+        }
+        encoder.encode_frame(&frames[1], &mut encoded).unwrap();
+
+        let mut decoder = SequenceDecoder::<Codec>::new(16, 8);
+        let mut source = &*encoded;
+        for frame in &frames {
+            assert!(decoder.decode_frame(&mut source).unwrap() == *frame);
+        }
+        assert!(source.is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+use super::{
+    error::{CodecError, Result},
+    frame::{self, Plane},
+};
+use std::io::{ErrorKind, Read, Write};
+
+// Writes samples as they are, 16-bit big-endian in raster order, without compressing them. It's the
+// baseline other codecs are measured against (see the bench module), and costs next to nothing to
+// encode or decode.
+pub struct StoreCodec;
+
+impl frame::Codec for StoreCodec {
+    fn encode<T: AsRef<[u16]>, W: Write>(plane: &Plane<T>, mut dest: W) -> Result<()> {
+        let mut buf = Vec::with_capacity(plane.width * 2);
+        for row in plane.rows() {
+            buf.clear();
+            for col in 0..plane.width {
+                buf.extend_from_slice(&row.sample(col).to_be_bytes());
+            }
+            dest.write_all(&buf)?;
+        }
+        Ok(())
+    }
+
+    fn decode<T: AsMut<[u16]>, R: Read>(mut source: R, plane: &mut Plane<T>) -> Result<()> {
+        let mut buf = vec![0; plane.width * 2];
+        for row in 0..plane.height {
+            source.read_exact(&mut buf).map_err(|e| match e.kind() {
+                ErrorKind::UnexpectedEof => CodecError::TruncatedStream {
+                    plane: 0,
+                    row,
+                    col: 0,
+                },
+                _ => e.into(),
+            })?;
+            let data = plane.data.as_mut();
+            for (col, sample) in buf.chunks_exact(2).enumerate() {
+                data[row * plane.row_stride + col * plane.sample_stride] =
+                    u16::from_be_bytes([sample[0], sample[1]]);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{frame::RGB48Frame, synth},
+        *,
+    };
+
+    #[test]
+    fn test_store_codec() {
+        let frame = synth::noise(7, 5, 1, 16);
+        let mut encoded = Vec::new();
+        frame.encode::<StoreCodec, _>(&mut encoded).unwrap();
+        assert_eq!(encoded.len(), frame.data.len() * 2);
+        assert_eq!(encoded[..2], frame.data[0].to_be_bytes());
+        let decoded = RGB48Frame::decode::<StoreCodec, _>(&*encoded, 7, 5).unwrap();
+        assert!(decoded == frame);
+
+        let err = RGB48Frame::decode::<StoreCodec, _>(&encoded[..encoded.len() - 1], 7, 5)
+            .map(|_| ())
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                CodecError::TruncatedStream {
+                    plane: 2,
+                    row: 4,
+                    ..
+                }
+            ),
+            "{:?}",
+            err
+        );
+    }
+}
@@ -0,0 +1,395 @@
+use super::{
+    color::{rgb_to_ycbcr_709, ycbcr_709_to_rgb},
+    error::{CodecError, Result},
+    frame::{Codec, DecodeOptions, Plane, RGB48Frame},
+};
+use std::io::{Read, Write};
+
+// Chroma subsampling schemes, named after the usual J:a:b notation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Subsampling {
+    S444 = 0,
+    // Chroma planes have half the width of the luma plane.
+    S422 = 1,
+    // Chroma planes have half the width and half the height of the luma plane.
+    S420 = 2,
+}
+
+impl Subsampling {
+    pub fn from_u8(b: u8) -> Option<Self> {
+        match b {
+            0 => Some(Self::S444),
+            1 => Some(Self::S422),
+            2 => Some(Self::S420),
+            _ => None,
+        }
+    }
+
+    // Returns the horizontal and vertical chroma subsampling factors.
+    pub fn factors(self) -> (usize, usize) {
+        match self {
+            Self::S444 => (1, 1),
+            Self::S422 => (2, 1),
+            Self::S420 => (2, 2),
+        }
+    }
+
+    // Returns the dimensions of plane i of a frame with the given luma dimensions. Chroma
+    // dimensions are rounded up, so odd-sized frames keep their last column and row.
+    pub fn plane_dimensions(self, i: usize, width: usize, height: usize) -> (usize, usize) {
+        if i == 0 {
+            (width, height)
+        } else {
+            let (sx, sy) = self.factors();
+            (width.div_ceil(sx), height.div_ceil(sy))
+        }
+    }
+}
+
+// A Y, Cb, Cr frame whose chroma planes may be subsampled. Unlike RGB48Frame, the planes are
+// stored separately, each tightly packed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubsampledFrame {
+    pub data: [Vec<u16>; 3],
+    pub width: usize,
+    pub height: usize,
+    pub subsampling: Subsampling,
+}
+
+fn invalid_input<S: Into<String>>(message: S) -> CodecError {
+    CodecError::InvalidInput(message.into())
+}
+
+impl SubsampledFrame {
+    // Creates a frame, verifying that each plane has the length its dimensions call for.
+    pub fn new(
+        data: [Vec<u16>; 3],
+        width: usize,
+        height: usize,
+        subsampling: Subsampling,
+    ) -> Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(invalid_input("frame dimensions must be nonzero"));
+        }
+        for (i, plane) in data.iter().enumerate() {
+            let (w, h) = subsampling.plane_dimensions(i, width, height);
+            if plane.len() != w * h {
+                return Err(invalid_input(format!(
+                    "plane {} should be {}x{} ({} samples), but has {} samples",
+                    i,
+                    w,
+                    h,
+                    w * h,
+                    plane.len()
+                )));
+            }
+        }
+        Ok(Self {
+            data,
+            width,
+            height,
+            subsampling,
+        })
+    }
+
+    pub fn plane_dimensions(&self, i: usize) -> (usize, usize) {
+        self.subsampling
+            .plane_dimensions(i, self.width, self.height)
+    }
+
+    pub fn planes(&self) -> Vec<Plane<&[u16]>> {
+        self.data
+            .iter()
+            .enumerate()
+            .map(|(i, data)| {
+                let (width, height) = self.plane_dimensions(i);
+                Plane {
+                    data: &data[..],
+                    width,
+                    height,
+                    row_stride: width,
+                    sample_stride: 1,
+                }
+            })
+            .collect()
+    }
+
+    // Writes the frame's subsampling, luma dimensions, and each plane's dimensions, followed by
+    // the planes themselves:
+    //
+    //   subsampling    1 byte
+    //   width          4 bytes  big-endian
+    //   height         4 bytes  big-endian
+    //   plane widths and heights, 4 bytes each, big-endian
+    pub fn encode<C: Codec, W: Write>(&self, mut dest: W) -> Result<()> {
+        if self.width > u32::MAX as usize || self.height > u32::MAX as usize {
+            return Err(invalid_input("frame dimensions are too large"));
+        }
+        dest.write_all(&[self.subsampling as u8])?;
+        dest.write_all(&(self.width as u32).to_be_bytes())?;
+        dest.write_all(&(self.height as u32).to_be_bytes())?;
+        for i in 0..3 {
+            let (width, height) = self.plane_dimensions(i);
+            dest.write_all(&(width as u32).to_be_bytes())?;
+            dest.write_all(&(height as u32).to_be_bytes())?;
+        }
+        for plane in self.planes() {
+            C::encode(&plane, &mut dest)?;
+        }
+        Ok(())
+    }
+
+    // Decodes a frame written by encode. Frames larger than the default Limits are rejected before
+    // anything is allocated for them.
+    pub fn decode<C: Codec, R: Read>(source: R) -> Result<Self> {
+        Self::decode_with_options::<C, _>(source, &Default::default())
+    }
+
+    pub fn decode_with_options<C: Codec, R: Read>(
+        mut source: R,
+        options: &DecodeOptions,
+    ) -> Result<Self> {
+        let read_u32 = |source: &mut R| -> Result<usize> {
+            let mut buf = [0; 4];
+            source.read_exact(&mut buf)?;
+            Ok(u32::from_be_bytes(buf) as usize)
+        };
+
+        let mut subsampling = [0];
+        source.read_exact(&mut subsampling)?;
+        let subsampling = Subsampling::from_u8(subsampling[0]).ok_or_else(|| {
+            CodecError::InvalidHeader(format!("invalid subsampling: {}", subsampling[0]))
+        })?;
+        let width = read_u32(&mut source)?;
+        let height = read_u32(&mut source)?;
+        for i in 0..3 {
+            let dimensions = (read_u32(&mut source)?, read_u32(&mut source)?);
+            let expected = subsampling.plane_dimensions(i, width, height);
+            if dimensions != expected {
+                return Err(CodecError::InvalidHeader(format!(
+                    "plane {} is {}x{}, but {:?} {}x{} calls for {}x{}",
+                    i,
+                    dimensions.0,
+                    dimensions.1,
+                    subsampling,
+                    width,
+                    height,
+                    expected.0,
+                    expected.1
+                )));
+            }
+        }
+
+        let len = (0..3)
+            .try_fold(0usize, |len, i| {
+                let (width, height) = subsampling.plane_dimensions(i, width, height);
+                width.checked_mul(height)?.checked_add(len)
+            })
+            .filter(|&len| len <= isize::MAX as usize / 2)
+            .ok_or_else(|| {
+                CodecError::InvalidHeader(format!("a {}x{} frame is too large", width, height))
+            })?;
+        options.limits.check(len)?;
+
+        let mut data = [Vec::new(), Vec::new(), Vec::new()];
+        for (i, data) in data.iter_mut().enumerate() {
+            let (width, height) = subsampling.plane_dimensions(i, width, height);
+            *data = vec![0; width * height];
+            C::decode(
+                &mut source,
+                &mut Plane {
+                    data: &mut data[..],
+                    width,
+                    height,
+                    row_stride: width,
+                    sample_stride: 1,
+                },
+            )
+            .map_err(|e| e.in_plane(i, 0, 0))?;
+        }
+        Self::new(data, width, height, subsampling).map_err(|e| match e {
+            CodecError::InvalidInput(message) => CodecError::InvalidHeader(message),
+            e => e,
+        })
+    }
+
+    // Converts the frame back to RGB using BT.709 coefficients, upsampling chroma by replicating
+    // the nearest sample. Like the conversion in the other direction, this is lossy.
+    pub fn to_rgb48(&self) -> RGB48Frame {
+        let (sx, sy) = self.subsampling.factors();
+        let (chroma_width, _) = self.plane_dimensions(1);
+        let mut data = Vec::with_capacity(self.width * self.height * 3);
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let chroma = (row / sy) * chroma_width + col / sx;
+                let (r, g, b) = ycbcr_709_to_rgb(
+                    self.data[0][row * self.width + col],
+                    self.data[1][chroma],
+                    self.data[2][chroma],
+                );
+                data.extend_from_slice(&[r, g, b]);
+            }
+        }
+        RGB48Frame {
+            data,
+            width: self.width,
+            height: self.height,
+            bit_depth: 16,
+        }
+    }
+}
+
+impl RGB48Frame {
+    // Converts the frame to Y, Cb, Cr using BT.709 coefficients over the full 16-bit range, then
+    // subsamples the chroma planes by averaging each block of samples (a box filter). Both steps
+    // lose information, so converting back with SubsampledFrame::to_rgb48 only approximates the
+    // original frame.
+    pub fn to_subsampled_ycbcr(&self, subsampling: Subsampling) -> SubsampledFrame {
+        let mut ycbcr = [
+            Vec::with_capacity(self.width * self.height),
+            Vec::with_capacity(self.width * self.height),
+            Vec::with_capacity(self.width * self.height),
+        ];
+        for rgb in self.data.chunks_exact(3) {
+            let (y, cb, cr) = rgb_to_ycbcr_709(rgb[0], rgb[1], rgb[2]);
+            ycbcr[0].push(y);
+            ycbcr[1].push(cb);
+            ycbcr[2].push(cr);
+        }
+
+        let (sx, sy) = subsampling.factors();
+        let (chroma_width, chroma_height) =
+            subsampling.plane_dimensions(1, self.width, self.height);
+        let [y, cb, cr] = ycbcr;
+        let downsample = |full: Vec<u16>| -> Vec<u16> {
+            if (sx, sy) == (1, 1) {
+                return full;
+            }
+            let mut ret = Vec::with_capacity(chroma_width * chroma_height);
+            for row in 0..chroma_height {
+                for col in 0..chroma_width {
+                    let (mut sum, mut n) = (0, 0);
+                    for y in row * sy..((row + 1) * sy).min(self.height) {
+                        for x in col * sx..((col + 1) * sx).min(self.width) {
+                            sum += full[y * self.width + x] as u32;
+                            n += 1;
+                        }
+                    }
+                    ret.push(((sum + n / 2) / n) as u16);
+                }
+            }
+            ret
+        };
+
+        SubsampledFrame {
+            data: [y, downsample(cb), downsample(cr)],
+            width: self.width,
+            height: self.height,
+            subsampling,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::codec::Codec, *};
+
+    fn test_frame(width: usize, height: usize) -> RGB48Frame {
+        let mut data = Vec::with_capacity(width * height * 3);
+        for row in 0..height {
+            for col in 0..width {
+                data.extend_from_slice(&[
+                    (col * 1000) as u16,
+                    (row * 2000) as u16,
+                    ((col * row * 37) % 65536) as u16,
+                ]);
+            }
+        }
+        RGB48Frame {
+            data,
+            width,
+            height,
+            bit_depth: 16,
+        }
+    }
+
+    #[test]
+    fn test_subsampled_roundtrip() {
+        for &subsampling in [Subsampling::S444, Subsampling::S422, Subsampling::S420].iter() {
+            for &(width, height) in [(1, 1), (16, 8), (17, 9), (31, 2)].iter() {
+                let frame = test_frame(width, height).to_subsampled_ycbcr(subsampling);
+                for (i, plane) in frame.planes().iter().enumerate() {
+                    assert_eq!(
+                        (plane.width, plane.height),
+                        subsampling.plane_dimensions(i, width, height)
+                    );
+                }
+
+                let mut encoded = Vec::new();
+                frame.encode::<Codec, _>(&mut encoded).unwrap();
+                let decoded = SubsampledFrame::decode::<Codec, _>(&*encoded).unwrap();
+                assert_eq!(decoded, frame);
+            }
+        }
+    }
+
+    #[test]
+    fn test_subsampled_dimensions() {
+        assert_eq!(Subsampling::S420.plane_dimensions(1, 17, 9), (9, 5));
+        assert_eq!(Subsampling::S422.plane_dimensions(2, 17, 9), (9, 9));
+        assert_eq!(Subsampling::S420.plane_dimensions(0, 17, 9), (17, 9));
+    }
+
+    #[test]
+    fn test_subsampled_dimension_mismatch() {
+        let err = SubsampledFrame::new(
+            [vec![0; 16], vec![0; 16], vec![0; 4]],
+            4,
+            4,
+            Subsampling::S420,
+        )
+        .unwrap_err();
+        assert!(matches!(err, CodecError::InvalidInput(_)), "{:?}", err);
+
+        let frame = test_frame(8, 8).to_subsampled_ycbcr(Subsampling::S420);
+        let mut encoded = Vec::new();
+        frame.encode::<Codec, _>(&mut encoded).unwrap();
+        // claim that the first chroma plane is 5 samples wide instead of 4
+        encoded[1 + 8 + 8 + 3] = 5;
+        let err = SubsampledFrame::decode::<Codec, _>(&*encoded).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidHeader(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_subsampled_huge_dimensions() {
+        // headers claiming frames that can't be counted, or are over the limit, with nothing after
+        // them
+        let header = |width: u32, height: u32| {
+            let mut header = vec![Subsampling::S444 as u8];
+            for _ in 0..4 {
+                header.extend_from_slice(&width.to_be_bytes());
+                header.extend_from_slice(&height.to_be_bytes());
+            }
+            header
+        };
+        let err = SubsampledFrame::decode::<Codec, _>(&*header(u32::MAX, u32::MAX)).unwrap_err();
+        assert!(matches!(err, CodecError::InvalidHeader(_)), "{:?}", err);
+        let err = SubsampledFrame::decode::<Codec, _>(&*header(65536, 65536)).unwrap_err();
+        assert!(matches!(err, CodecError::LimitExceeded { .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn test_subsampled_rgb_roundtrip() {
+        // a flat frame survives subsampling, so only the matrix rounding remains
+        let frame = RGB48Frame {
+            data: [40000, 20000, 10000].repeat(6 * 4),
+            width: 6,
+            height: 4,
+            bit_depth: 16,
+        };
+        let rgb = frame.to_subsampled_ycbcr(Subsampling::S420).to_rgb48();
+        for (&a, &b) in frame.data.iter().zip(rgb.data.iter()) {
+            assert!((a as i32 - b as i32).abs() <= 2, "{} vs {}", a, b);
+        }
+    }
+}
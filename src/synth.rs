@@ -0,0 +1,361 @@
+use super::{frame::RGB48Frame, gray::GrayFrame16};
+
+// Synthetic frames with known properties, for tests and benchmarks that would otherwise have to
+// load and crop large files. Everything is computed with integer arithmetic, so the same arguments
+// produce the same samples on every platform.
+//
+// Each generator comes in an RGB and a gray version. The RGB versions give their channels
+// different content where that makes sense, so that bugs that mix channels up don't go unnoticed.
+// All of them panic if either dimension is 0.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    // Dark on the left, bright on the right.
+    Horizontal,
+    // Dark at the top, bright at the bottom.
+    Vertical,
+    // Dark at the top left, bright at the bottom right.
+    Diagonal,
+}
+
+// A xorshift32 generator, which is all the randomness test images need.
+struct Rng(u32);
+
+impl Rng {
+    fn new(seed: u32) -> Self {
+        // xorshift gets stuck at 0
+        Self(seed ^ 0x9e37_79b9)
+    }
+
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+}
+
+fn generate<F: FnMut(usize, usize) -> [u16; 3]>(
+    width: usize,
+    height: usize,
+    mut f: F,
+) -> RGB48Frame {
+    assert!(
+        width > 0 && height > 0,
+        "a {}x{} frame is empty",
+        width,
+        height
+    );
+    let mut data = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            data.extend_from_slice(&f(x, y));
+        }
+    }
+    RGB48Frame {
+        data,
+        width,
+        height,
+        bit_depth: 16,
+    }
+}
+
+fn generate_gray<F: FnMut(usize, usize) -> u16>(
+    width: usize,
+    height: usize,
+    mut f: F,
+) -> GrayFrame16 {
+    assert!(
+        width > 0 && height > 0,
+        "a {}x{} frame is empty",
+        width,
+        height
+    );
+    let mut data = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            data.push(f(x, y));
+        }
+    }
+    GrayFrame16 {
+        data,
+        width,
+        height,
+        bit_depth: 16,
+    }
+}
+
+// Scales position out of 0..=max onto 0..=65535, rounding to the nearest.
+fn ramp(position: usize, max: usize) -> u16 {
+    if max == 0 {
+        return 0;
+    }
+    ((position as u64 * 65535 + max as u64 / 2) / max as u64) as u16
+}
+
+fn gradient_sample(
+    width: usize,
+    height: usize,
+    direction: Direction,
+) -> impl Fn(usize, usize) -> u16 {
+    move |x, y| match direction {
+        Direction::Horizontal => ramp(x, width - 1),
+        Direction::Vertical => ramp(y, height - 1),
+        Direction::Diagonal => ramp(x + y, width + height - 2),
+    }
+}
+
+// A linear ramp across the whole 16-bit range. Red follows the ramp, green runs the other way, and
+// blue follows it over half the range.
+pub fn gradient(width: usize, height: usize, direction: Direction) -> RGB48Frame {
+    let sample = gradient_sample(width, height, direction);
+    generate(width, height, |x, y| {
+        let t = sample(x, y);
+        [t, 65535 - t, t / 2 + 16384]
+    })
+}
+
+pub fn gradient_gray(width: usize, height: usize, direction: Direction) -> GrayFrame16 {
+    generate_gray(width, height, gradient_sample(width, height, direction))
+}
+
+// Widens a sample of the given bit depth to 16 bits, rounding to the nearest, so that 8-bit
+// samples are multiplied by 257 like 8-bit files are when they're loaded.
+fn widen(x: u32, bit_depth: u8) -> u16 {
+    let max = (1u64 << bit_depth) - 1;
+    ((x as u64 * 65535 + max / 2) / max) as u16
+}
+
+fn noise_sample(seed: u32, bit_depth: u8) -> impl FnMut() -> u16 {
+    assert!(
+        (1..=16).contains(&bit_depth),
+        "{}-bit samples aren't supported",
+        bit_depth
+    );
+    let mut rng = Rng::new(seed);
+    move || widen(rng.next() >> (32 - bit_depth), bit_depth)
+}
+
+// Uniformly distributed samples of the given bit depth, widened to 16 bits. The frame's bit_depth
+// is set accordingly. Noise is the worst case for compression.
+pub fn noise(width: usize, height: usize, seed: u32, bit_depth: u8) -> RGB48Frame {
+    let mut sample = noise_sample(seed, bit_depth);
+    let mut ret = generate(width, height, |_, _| [sample(), sample(), sample()]);
+    ret.bit_depth = bit_depth;
+    ret
+}
+
+pub fn noise_gray(width: usize, height: usize, seed: u32, bit_depth: u8) -> GrayFrame16 {
+    let mut sample = noise_sample(seed, bit_depth);
+    let mut ret = generate_gray(width, height, |_, _| sample());
+    ret.bit_depth = bit_depth;
+    ret
+}
+
+// Every sample of every channel set to value.
+pub fn flat(width: usize, height: usize, value: u16) -> RGB48Frame {
+    generate(width, height, |_, _| [value; 3])
+}
+
+pub fn flat_gray(width: usize, height: usize, value: u16) -> GrayFrame16 {
+    generate_gray(width, height, |_, _| value)
+}
+
+fn checkerboard_sample(period: usize, low: u16, high: u16) -> impl Fn(usize, usize) -> u16 {
+    assert!(
+        period > 0,
+        "a checkerboard needs squares of at least 1 sample"
+    );
+    move |x, y| {
+        if (x / period + y / period).is_multiple_of(2) {
+            low
+        } else {
+            high
+        }
+    }
+}
+
+// Squares of period x period samples alternating between low and high, starting with low at the
+// top left. Blue is inverted, so it has high where the others have low.
+pub fn checkerboard(width: usize, height: usize, period: usize, low: u16, high: u16) -> RGB48Frame {
+    let sample = checkerboard_sample(period, low, high);
+    generate(width, height, |x, y| {
+        let v = sample(x, y);
+        [v, v, low + high - v]
+    })
+}
+
+pub fn checkerboard_gray(
+    width: usize,
+    height: usize,
+    period: usize,
+    low: u16,
+    high: u16,
+) -> GrayFrame16 {
+    generate_gray(width, height, checkerboard_sample(period, low, high))
+}
+
+// An approximation of the cosine of a phase given in 1024ths of a cycle, scaled to -32767..=32767.
+// Each half cycle is a parabola, which is within 6% of a true cosine and needs no floating point.
+fn cosine(phase: u64) -> i32 {
+    let t = (phase + 256) % 1024;
+    let half = (t % 512) as i64;
+    let v = (4 * half * (512 - half) * 32767 / (512 * 512)) as i32;
+    if t < 512 {
+        v
+    } else {
+        -v
+    }
+}
+
+fn zone_plate_sample(width: usize, height: usize) -> impl Fn(usize, usize) -> u16 {
+    // with coordinates doubled so the center falls on an integer, the phase at radius r is
+    // r^2 / 2n cycles for n = max(width, height), which reaches the Nyquist frequency at n / 2
+    let n = width.max(height) as u64;
+    move |x, y| {
+        let dx = (2 * x as i64 - (width as i64 - 1)).unsigned_abs();
+        let dy = (2 * y as i64 - (height as i64 - 1)).unsigned_abs();
+        let phase = (dx * dx + dy * dy) * 128 / n;
+        (32768 + cosine(phase)) as u16
+    }
+}
+
+// Concentric rings around the center whose frequency rises with distance, reaching one cycle per
+// two samples at the middle of each edge of a square frame. Zone plates exercise every frequency
+// and orientation at once, which makes filtering and prediction artifacts easy to spot. All three
+// channels are the same.
+pub fn zone_plate(width: usize, height: usize) -> RGB48Frame {
+    let sample = zone_plate_sample(width, height);
+    generate(width, height, |x, y| [sample(x, y); 3])
+}
+
+pub fn zone_plate_gray(width: usize, height: usize) -> GrayFrame16 {
+    generate_gray(width, height, zone_plate_sample(width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::codec, *};
+
+    // A 64-bit FNV-1a hash of the samples, for pinning their values.
+    fn hash(data: &[u16]) -> u64 {
+        data.iter()
+            .flat_map(|x| x.to_le_bytes())
+            .fold(0xcbf29ce484222325, |hash, b| {
+                (hash ^ b as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+
+    #[test]
+    fn test_gradient() {
+        let frame = gradient(256, 3, Direction::Horizontal);
+        assert_eq!(&frame.data[..3], &[0, 65535, 16384]);
+        assert_eq!(&frame.data[255 * 3..256 * 3], &[65535, 0, 49151]);
+        assert_eq!(frame.data[3], 257);
+        let gray = gradient_gray(256, 3, Direction::Horizontal);
+        assert!(gray
+            .data
+            .iter()
+            .zip(frame.data.iter().step_by(3))
+            .all(|(a, b)| a == b));
+
+        let vertical = gradient_gray(3, 256, Direction::Vertical);
+        assert_eq!(vertical.data[255 * 3], 65535);
+        let diagonal = gradient_gray(10, 20, Direction::Diagonal);
+        assert_eq!((diagonal.data[0], diagonal.data[199]), (0, 65535));
+        assert_eq!(diagonal.data[1], diagonal.data[10]);
+
+        // a single column or row is flat
+        assert!(gradient(1, 5, Direction::Horizontal)
+            .data
+            .chunks(3)
+            .all(|p| p[0] == 0));
+    }
+
+    #[test]
+    fn test_noise() {
+        let frame = noise(64, 32, 7, 16);
+        assert_eq!(frame.bit_depth, 16);
+        assert_eq!(hash(&frame.data), NOISE_HASH);
+        assert!(frame.data == noise(64, 32, 7, 16).data);
+        assert!(frame.data != noise(64, 32, 8, 16).data);
+
+        // narrower samples are widened exactly
+        let frame = noise(64, 32, 7, 10);
+        assert_eq!(frame.bit_depth, 10);
+        for &x in frame.data.iter() {
+            assert_eq!(widen((x >> 6) as u32, 10), x);
+        }
+        let frame = noise_gray(64, 32, 7, 8);
+        assert!(frame.data.iter().all(|&x| x % 257 == 0));
+        let mean = frame.data.iter().map(|&x| x as u64).sum::<u64>() / frame.data.len() as u64;
+        assert!((30000..35000).contains(&mean), "{}", mean);
+    }
+
+    const NOISE_HASH: u64 = 10230858007113751982;
+
+    #[test]
+    fn test_flat_and_checkerboard() {
+        assert!(flat(5, 4, 1234).data == vec![1234; 60]);
+        assert!(flat_gray(5, 4, 1234).data == vec![1234; 20]);
+
+        let frame = checkerboard(6, 4, 2, 100, 900);
+        let red: Vec<u16> = frame.data.iter().step_by(3).cloned().collect();
+        #[rustfmt::skip]
+        assert_eq!(red, vec![
+            100, 100, 900, 900, 100, 100,
+            100, 100, 900, 900, 100, 100,
+            900, 900, 100, 100, 900, 900,
+            900, 900, 100, 100, 900, 900,
+        ]);
+        assert_eq!(&frame.data[..3], &[100, 100, 900]);
+        assert!(checkerboard_gray(6, 4, 2, 100, 900).data == red);
+    }
+
+    #[test]
+    fn test_zone_plate() {
+        assert_eq!(cosine(0), 32767);
+        assert_eq!(cosine(512), -32767);
+        assert_eq!((cosine(256), cosine(768)), (0, 0));
+
+        let frame = zone_plate_gray(65, 65);
+        assert_eq!(frame.data[32 * 65 + 32], 65535);
+        // symmetric about the center
+        for y in 0..65 {
+            for x in 0..65 {
+                let v = frame.data[y * 65 + x];
+                assert_eq!(v, frame.data[y * 65 + 64 - x]);
+                assert_eq!(v, frame.data[(64 - y) * 65 + x]);
+                assert_eq!(v, frame.data[x * 65 + y]);
+            }
+        }
+        let rgb = zone_plate(65, 65);
+        assert!(rgb
+            .data
+            .chunks(3)
+            .zip(frame.data.iter())
+            .all(|(p, &v)| p == [v; 3]));
+    }
+
+    #[test]
+    fn test_compressed_sizes() {
+        // pins the output of the generators and of the codec on them
+        let size = |frame: &RGB48Frame| {
+            let mut encoded = Vec::new();
+            frame.encode::<codec::Codec, _>(&mut encoded).unwrap();
+            encoded.len()
+        };
+        assert_eq!(size(&flat(128, 64, 40000)), FLAT_SIZE);
+        assert_eq!(size(&gradient(128, 64, Direction::Diagonal)), GRADIENT_SIZE);
+        assert_eq!(size(&zone_plate(128, 64)), ZONE_PLATE_SIZE);
+        // noise doesn't compress below its bit depth
+        let noise_size = size(&noise(128, 64, 1, 10));
+        assert_eq!(noise_size, NOISE_SIZE);
+        assert!(noise_size > 128 * 64 * 3 * 10 / 8);
+    }
+
+    const FLAT_SIZE: usize = 34401;
+    const GRADIENT_SIZE: usize = 53701;
+    const ZONE_PLATE_SIZE: usize = 60045;
+    const NOISE_SIZE: usize = 87715;
+}
@@ -0,0 +1,556 @@
+use super::{
+    error::{CodecError, Result},
+    frame::{Codec, Plane, PlaneCoding},
+};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+// Describes how a plane is partitioned into tiles. Tiles are tile_width×tile_height samples, except
+// along the right and bottom edges where they may be smaller, and are numbered in raster order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileLayout {
+    pub width: usize,
+    pub height: usize,
+    pub tile_width: usize,
+    pub tile_height: usize,
+}
+
+impl TileLayout {
+    pub fn new(width: usize, height: usize, tile_width: usize, tile_height: usize) -> Self {
+        assert!(tile_width > 0 && tile_height > 0, "tiles must be nonempty");
+        Self {
+            width,
+            height,
+            tile_width,
+            tile_height,
+        }
+    }
+
+    pub fn tiles_wide(&self) -> usize {
+        self.width.div_ceil(self.tile_width)
+    }
+
+    pub fn tiles_high(&self) -> usize {
+        self.height.div_ceil(self.tile_height)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles_wide() * self.tiles_high()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Returns whether tile i overlaps the (x, y, w, h) region.
+    pub fn intersects(&self, i: usize, rect: (usize, usize, usize, usize)) -> bool {
+        let (x, y, w, h) = self.tile_rect(i);
+        x < rect.0 + rect.2 && rect.0 < x + w && y < rect.1 + rect.3 && rect.1 < y + h
+    }
+
+    // Returns the (x, y, w, h) region covered by tile i.
+    pub fn tile_rect(&self, i: usize) -> (usize, usize, usize, usize) {
+        let x = (i % self.tiles_wide()) * self.tile_width;
+        let y = (i / self.tiles_wide()) * self.tile_height;
+        (
+            x,
+            y,
+            self.tile_width.min(self.width - x),
+            self.tile_height.min(self.height - y),
+        )
+    }
+}
+
+// Where a block returned by Plane::blocks is: its column and row among the blocks, and the
+// position of its top-left sample in the plane.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockPos {
+    pub col: usize,
+    pub row: usize,
+    pub x: usize,
+    pub y: usize,
+}
+
+impl TileLayout {
+    // Where tile i is as a block, and its width and height.
+    pub(crate) fn block(&self, i: usize) -> (BlockPos, (usize, usize)) {
+        let (x, y, w, h) = self.tile_rect(i);
+        let pos = BlockPos {
+            col: i % self.tiles_wide(),
+            row: i / self.tiles_wide(),
+            x,
+            y,
+        };
+        (pos, (w, h))
+    }
+}
+
+impl<T: AsRef<[u16]>> Plane<T> {
+    // Views of the plane's block_width×block_height blocks, in raster order. Blocks along the right
+    // and bottom edges are only as large as what's left of the plane. Panics if either block
+    // dimension is 0.
+    pub fn blocks(
+        &self,
+        block_width: usize,
+        block_height: usize,
+    ) -> impl ExactSizeIterator<Item = (BlockPos, Plane<&[u16]>)> + '_ {
+        let layout = TileLayout::new(self.width, self.height, block_width, block_height);
+        (0..layout.len()).map(move |i| {
+            let (pos, (w, h)) = layout.block(i);
+            (pos, self.view(pos.x, pos.y, w, h))
+        })
+    }
+}
+
+impl<T: AsMut<[u16]>> Plane<T> {
+    // The mutable counterpart of blocks. Blocks are handed to f one at a time rather than
+    // returned, since blocks side by side share the rows their samples are strided across, so
+    // their views can't safely exist at once.
+    pub fn for_each_block_mut<F: FnMut(BlockPos, Plane<&mut [u16]>)>(
+        &mut self,
+        block_width: usize,
+        block_height: usize,
+        mut f: F,
+    ) {
+        let layout = TileLayout::new(self.width, self.height, block_width, block_height);
+        for i in 0..layout.len() {
+            let (pos, (w, h)) = layout.block(i);
+            f(pos, self.view_mut(pos.x, pos.y, w, h));
+        }
+    }
+}
+
+// Encodes each tile of the plane independently. The output is a table of the tiles' encoded
+// lengths (4 bytes each, big-endian) followed by the tiles themselves. Since each tile gets its own
+// bitstream, every tile starts on a byte boundary. Tiles are coded as coding says, and the sum of
+// squared errors over all of them is returned (see Codec::encode_quantized).
+pub fn encode_plane<C: Codec, T: AsRef<[u16]>, W: Write>(
+    plane: &Plane<T>,
+    layout: &TileLayout,
+    coding: PlaneCoding,
+    mut dest: W,
+) -> Result<u64> {
+    let mut squared_error = 0;
+    let mut tiles = Vec::with_capacity(layout.len());
+    for i in 0..layout.len() {
+        let (x, y, w, h) = layout.tile_rect(i);
+        let mut encoded = Vec::new();
+        squared_error += coding.encode::<C, _, _>(&plane.view(x, y, w, h), &mut encoded)?;
+        if encoded.len() > u32::MAX as usize {
+            return Err(CodecError::InvalidInput("tile is too large".to_string()));
+        }
+        tiles.push(encoded);
+    }
+
+    for tile in &tiles {
+        dest.write_all(&(tile.len() as u32).to_be_bytes())?;
+    }
+    for tile in &tiles {
+        dest.write_all(tile)?;
+    }
+    Ok(squared_error)
+}
+
+// Reads the tile table. It isn't preallocated, since a corrupt header can claim any number of tiles
+// and the table should only grow as far as there's data to fill it.
+pub fn read_tile_table<R: Read>(mut source: R, layout: &TileLayout) -> Result<Vec<usize>> {
+    let mut table = Vec::new();
+    for _ in 0..layout.len() {
+        let mut len = [0; 4];
+        source.read_exact(&mut len)?;
+        table.push(u32::from_be_bytes(len) as usize);
+    }
+    Ok(table)
+}
+
+// Decodes a single tile's payload into a plane the size of the tile, verifying that the payload is
+// exactly as long as the tile table claimed. Truncation is reported relative to the tile.
+pub fn decode_tile<C: Codec, T: AsMut<[u16]>>(
+    payload: &[u8],
+    tile: &mut Plane<T>,
+    coding: PlaneCoding,
+    i: usize,
+) -> Result<()> {
+    let mut source = payload;
+    coding.decode::<C, _, _>(&mut source, tile)?;
+    if !source.is_empty() {
+        return Err(CodecError::InvalidData(format!(
+            "tile {} is {} bytes according to the tile table, but only {} were used",
+            i,
+            payload.len(),
+            payload.len() - source.len()
+        )));
+    }
+    Ok(())
+}
+
+// Reads tile i's payload, reporting truncation at the tile's top-left sample.
+fn read_tile_payload<R: Read>(
+    source: R,
+    layout: &TileLayout,
+    len: usize,
+    i: usize,
+    payload: &mut Vec<u8>,
+) -> Result<()> {
+    payload.clear();
+    source.take(len as u64).read_to_end(payload)?;
+    if payload.len() != len {
+        let (x, y, _, _) = layout.tile_rect(i);
+        return Err(CodecError::TruncatedStream {
+            plane: 0,
+            row: y,
+            col: x,
+        });
+    }
+    Ok(())
+}
+
+pub fn decode_plane<C: Codec, T: AsMut<[u16]>, R: Read>(
+    mut source: R,
+    plane: &mut Plane<T>,
+    layout: &TileLayout,
+    coding: PlaneCoding,
+) -> Result<()> {
+    let table = read_tile_table(&mut source, layout)?;
+    let mut payload = Vec::new();
+    for (i, &len) in table.iter().enumerate() {
+        read_tile_payload(&mut source, layout, len, i, &mut payload)?;
+        let (x, y, w, h) = layout.tile_rect(i);
+        decode_tile::<C, _>(&payload, &mut plane.view_mut(x, y, w, h), coding, i)
+            .map_err(|e| e.in_plane(0, x, y))?;
+    }
+    Ok(())
+}
+
+// Decodes only the (x, y, w, h) region of a tiled plane into dest, which must be w×h. Tiles that
+// don't intersect the region are skipped by seeking past them, so they're never read.
+pub fn decode_plane_region<C: Codec, T: AsMut<[u16]>, R: Read + Seek>(
+    mut source: R,
+    layout: &TileLayout,
+    rect: (usize, usize, usize, usize),
+    coding: PlaneCoding,
+    dest: &mut Plane<T>,
+) -> Result<()> {
+    let table = read_tile_table(&mut source, layout)?;
+    let mut payload = Vec::new();
+    let mut scratch = Vec::new();
+    let mut skip = 0;
+    for (i, &len) in table.iter().enumerate() {
+        if !layout.intersects(i, rect) {
+            skip += len as i64;
+            continue;
+        }
+        if skip > 0 {
+            source.seek(SeekFrom::Current(skip))?;
+            skip = 0;
+        }
+        read_tile_payload(&mut source, layout, len, i, &mut payload)?;
+
+        let (x, y, w, h) = layout.tile_rect(i);
+        scratch.clear();
+        scratch.resize(w * h, 0);
+        let mut tile = Plane {
+            data: &mut scratch[..],
+            width: w,
+            height: h,
+            sample_stride: 1,
+            row_stride: w,
+        };
+        decode_tile::<C, _>(&payload, &mut tile, coding, i).map_err(|e| e.in_plane(0, x, y))?;
+
+        // copy out the intersection of the tile and the region
+        let data = dest.data.as_mut();
+        for row in y.max(rect.1)..(y + h).min(rect.1 + rect.3) {
+            for col in x.max(rect.0)..(x + w).min(rect.0 + rect.2) {
+                data[(row - rect.1) * dest.row_stride + (col - rect.0) * dest.sample_stride] =
+                    tile.sample(col - x, row - y);
+            }
+        }
+    }
+    if skip > 0 {
+        source.seek(SeekFrom::Current(skip))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{
+            codec::Codec,
+            color::ColorTransform,
+            frame::{EncodeOptions, RGB48Frame},
+        },
+        *,
+    };
+    use std::io::Cursor;
+
+    fn test_frame(width: usize, height: usize) -> RGB48Frame {
+        let mut state = 7u32;
+        let mut data = Vec::with_capacity(width * height * 3);
+        for row in 0..height {
+            for col in 0..width {
+                for channel in 0..3 {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    data.push(
+                        (row * 300 + col * 200 + channel * 1000) as u16 + (state as u16 & 0xff),
+                    );
+                }
+            }
+        }
+        RGB48Frame {
+            data,
+            width,
+            height,
+            bit_depth: 16,
+        }
+    }
+
+    #[test]
+    fn test_blocks() {
+        let mut frame = test_frame(37, 21);
+        let original = frame.clone();
+        let plane = original.plane(1);
+        let total: u64 = plane.to_owned().data.iter().map(|&x| x as u64).sum();
+
+        let mut covered = vec![0; 37 * 21];
+        let mut block_total = 0;
+        let blocks: Vec<_> = plane.blocks(16, 8).collect();
+        assert_eq!(blocks.len(), 3 * 3);
+        for (pos, block) in blocks.iter() {
+            assert_eq!((pos.x, pos.y), (pos.col * 16, pos.row * 8));
+            let expected_width = if pos.col == 2 { 5 } else { 16 };
+            let expected_height = if pos.row == 2 { 5 } else { 8 };
+            assert_eq!(
+                (block.width, block.height),
+                (expected_width, expected_height)
+            );
+            block.for_each_sample(|x, y, sample| {
+                covered[(pos.y + y) * 37 + pos.x + x] += 1;
+                assert_eq!(sample, plane.sample(pos.x + x, pos.y + y));
+                block_total += sample as u64;
+            });
+        }
+        assert!(covered.iter().all(|&n| n == 1));
+        assert_eq!(block_total, total);
+
+        // mutating every block touches each of the plane's samples once, and nothing else
+        let mut visited = 0;
+        frame
+            .plane_mut(1)
+            .for_each_block_mut(16, 8, |pos, mut block| {
+                assert_eq!(pos, blocks[visited].0);
+                visited += 1;
+                block.map_samples(|x| x.wrapping_add(1));
+            });
+        assert_eq!(visited, 9);
+        for (i, (&a, &b)) in original.data.iter().zip(frame.data.iter()).enumerate() {
+            let expected = if i % 3 == 1 { a.wrapping_add(1) } else { a };
+            assert_eq!(b, expected);
+        }
+
+        // blocks larger than the plane give the whole plane, and empty planes have no blocks
+        let red = original.plane(0);
+        let whole: Vec<_> = red.blocks(64, 64).collect();
+        assert_eq!(whole.len(), 1);
+        assert_eq!((whole[0].1.width, whole[0].1.height), (37, 21));
+        assert_eq!(red.view(0, 0, 0, 21).blocks(4, 4).len(), 0);
+    }
+
+    #[test]
+    fn test_tile_layout() {
+        let layout = TileLayout::new(100, 50, 32, 20);
+        assert_eq!((layout.tiles_wide(), layout.tiles_high()), (4, 3));
+        assert_eq!(layout.tile_rect(0), (0, 0, 32, 20));
+        assert_eq!(layout.tile_rect(3), (96, 0, 4, 20));
+        assert_eq!(layout.tile_rect(11), (96, 40, 4, 10));
+    }
+
+    #[test]
+    fn test_tiled_roundtrip() {
+        let frame = test_frame(100, 50);
+        for &tile_size in [(100, 50), (32, 32), (33, 17), (3, 2), (7, 50), (128, 64)].iter() {
+            let mut encoded = Vec::new();
+            frame
+                .encode_with_header::<Codec, _>(
+                    &mut encoded,
+                    &EncodeOptions {
+                        tile_size: Some(tile_size),
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+            let decoded = RGB48Frame::decode_with_header::<Codec, _>(&*encoded).unwrap();
+            assert!(decoded == frame, "tile size = {:?}", tile_size);
+        }
+    }
+
+    #[test]
+    fn test_tile_table_validation() {
+        let frame = test_frame(40, 30);
+        let plane = &frame.plane(0);
+        let layout = TileLayout::new(40, 30, 16, 16);
+        let mut encoded = Vec::new();
+        encode_plane::<Codec, _, _>(plane, &layout, PlaneCoding::default(), &mut encoded).unwrap();
+
+        let mut decoded = vec![0; 40 * 30];
+        let mut decoded_plane = Plane {
+            data: &mut decoded[..],
+            width: 40,
+            height: 30,
+            sample_stride: 1,
+            row_stride: 40,
+        };
+        decode_plane::<Codec, _, _>(
+            &*encoded,
+            &mut decoded_plane,
+            &layout,
+            PlaneCoding::default(),
+        )
+        .unwrap();
+
+        // a truncated stream
+        let err = decode_plane::<Codec, _, _>(
+            &encoded[..encoded.len() - 1],
+            &mut decoded_plane,
+            &layout,
+            PlaneCoding::default(),
+        )
+        .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                CodecError::TruncatedStream {
+                    plane: 0,
+                    row: 16,
+                    col: 32
+                }
+            ),
+            "{:?}",
+            err
+        );
+
+        // a tile table that claims the first tile is a byte longer than it is
+        let mut corrupt = encoded.clone();
+        corrupt[3] += 1;
+        corrupt.push(0);
+        let err = decode_plane::<Codec, _, _>(
+            &*corrupt,
+            &mut decoded_plane,
+            &layout,
+            PlaneCoding::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, CodecError::InvalidData(_)), "{:?}", err);
+    }
+
+    // Counts the bytes actually read from the inner reader, so tests can check that skipped tiles
+    // are seeked past rather than read.
+    struct CountingReader<R> {
+        inner: R,
+        bytes_read: usize,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.bytes_read += n;
+            Ok(n)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_decode_region() {
+        let frame = test_frame(100, 50);
+        for &tile_size in [(32, 16), (100, 50), (7, 9)].iter() {
+            for &color_transform in [ColorTransform::None, ColorTransform::YCoCgR].iter() {
+                let mut encoded = Vec::new();
+                frame
+                    .encode_with_header::<Codec, _>(
+                        &mut encoded,
+                        &EncodeOptions {
+                            color_transform,
+                            tile_size: Some(tile_size),
+                            ..Default::default()
+                        },
+                    )
+                    .unwrap();
+                for &rect in [
+                    (0, 0, 32, 16),
+                    (30, 10, 10, 10),
+                    (90, 40, 10, 10),
+                    (0, 0, 100, 50),
+                    (99, 49, 1, 1),
+                ]
+                .iter()
+                {
+                    let region =
+                        RGB48Frame::decode_region::<Codec, _>(Cursor::new(&encoded), rect).unwrap();
+                    assert!(
+                        region == frame.crop(rect.0, rect.1, rect.2, rect.3).unwrap(),
+                        "tile size = {:?}, rect = {:?}",
+                        tile_size,
+                        rect
+                    );
+                }
+            }
+        }
+
+        // untiled frames work too, they just have to be decoded in full
+        let mut encoded = Vec::new();
+        frame
+            .encode_with_header::<Codec, _>(&mut encoded, &Default::default())
+            .unwrap();
+        let rect = (30, 10, 10, 10);
+        let region = RGB48Frame::decode_region::<Codec, _>(Cursor::new(&encoded), rect).unwrap();
+        assert!(region == frame.crop(rect.0, rect.1, rect.2, rect.3).unwrap());
+
+        for &rect in [(0, 0, 0, 1), (95, 0, 6, 1), (0, 50, 1, 1)].iter() {
+            match RGB48Frame::decode_region::<Codec, _>(Cursor::new(&encoded), rect) {
+                Err(e) => assert!(
+                    matches!(e, CodecError::InvalidInput(_)),
+                    "rect = {:?}",
+                    rect
+                ),
+                Ok(_) => panic!("rect = {:?} should be rejected", rect),
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_region_skips_tiles() {
+        let frame = test_frame(96, 96);
+        let mut encoded = Vec::new();
+        frame
+            .encode_with_header::<Codec, _>(
+                &mut encoded,
+                &EncodeOptions {
+                    tile_size: Some((24, 24)),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        // a region within a single tile only needs 1 of the 16 tiles in each plane
+        let mut source = CountingReader {
+            inner: Cursor::new(&encoded),
+            bytes_read: 0,
+        };
+        let rect = (30, 30, 16, 16);
+        let region = RGB48Frame::decode_region::<Codec, _>(&mut source, rect).unwrap();
+        assert!(region == frame.crop(rect.0, rect.1, rect.2, rect.3).unwrap());
+        assert!(
+            source.bytes_read < encoded.len() / 8,
+            "read {} of {} bytes",
+            source.bytes_read,
+            encoded.len()
+        );
+    }
+}
@@ -0,0 +1,720 @@
+use super::{
+    metadata::{ColorRange, SequenceMetadata},
+    subsampled::{SubsampledFrame, Subsampling},
+};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use thiserror::Error;
+
+// Reads and writes YUV4MPEG2 (y4m) streams, the uncompressed format ffmpeg, x264, and most other
+// video tools can produce and consume. A stream is a single line of header parameters followed by
+// frames, each introduced by a FRAME line and holding the Y, Cb, and Cr planes back to back.
+//
+// Frames are read as SubsampledFrames. 8-bit samples are widened to 16 bits by multiplying them by
+// 257, as for 8-bit images, so that 255 becomes 65535. Deeper samples, stored as 16-bit
+// little-endian words, are kept as they are. Writing reverses this, rounding to the nearest 8-bit
+// value.
+
+const SIGNATURE: &[u8] = b"YUV4MPEG2";
+
+// Header and FRAME lines longer than this are treated as corrupt rather than read indefinitely.
+const MAX_LINE_LENGTH: usize = 4096;
+
+#[derive(Error, Debug)]
+pub enum Y4mError {
+    #[error(transparent)]
+    IO(#[from] io::Error),
+    #[error("not a y4m stream")]
+    InvalidSignature,
+    #[error("invalid y4m header: {0}")]
+    InvalidHeader(String),
+    #[error("invalid frame header: {0}")]
+    InvalidFrameHeader(String),
+    #[error("unsupported y4m stream: {0}")]
+    Unsupported(String),
+    #[error("invalid frame: {0}")]
+    InvalidFrame(String),
+}
+
+fn invalid_header<S: Into<String>>(message: S) -> Y4mError {
+    Y4mError::InvalidHeader(message.into())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interlacing {
+    Progressive,
+    TopFieldFirst,
+    BottomFieldFirst,
+    // The field order varies from frame to frame.
+    Mixed,
+    Unknown,
+}
+
+impl Interlacing {
+    fn from_u8(b: u8) -> Option<Self> {
+        match b {
+            b'p' => Some(Self::Progressive),
+            b't' => Some(Self::TopFieldFirst),
+            b'b' => Some(Self::BottomFieldFirst),
+            b'm' => Some(Self::Mixed),
+            b'?' => Some(Self::Unknown),
+            _ => None,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::Progressive => b'p',
+            Self::TopFieldFirst => b't',
+            Self::BottomFieldFirst => b'b',
+            Self::Mixed => b'm',
+            Self::Unknown => b'?',
+        }
+    }
+}
+
+// The parameters of a y4m stream. The only X extension understood is XCOLORRANGE, as written by
+// ffmpeg. Parameters this doesn't understand are skipped when reading and aren't written.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Y4mHeader {
+    pub width: usize,
+    pub height: usize,
+    pub subsampling: Subsampling,
+    // 8, 10, 12, or 16.
+    pub bit_depth: u8,
+    // As a numerator and denominator, e.g. (30000, 1001) for 29.97 fps.
+    pub frame_rate: Option<(u32, u32)>,
+    pub pixel_aspect_ratio: Option<(u32, u32)>,
+    pub interlacing: Interlacing,
+    pub color_range: Option<ColorRange>,
+}
+
+impl Y4mHeader {
+    pub fn new(width: usize, height: usize, subsampling: Subsampling, bit_depth: u8) -> Self {
+        Self {
+            width,
+            height,
+            subsampling,
+            bit_depth,
+            frame_rate: None,
+            pixel_aspect_ratio: None,
+            interlacing: Interlacing::Progressive,
+            color_range: None,
+        }
+    }
+
+    pub fn with_frame_rate(self, numerator: u32, denominator: u32) -> Self {
+        Self {
+            frame_rate: Some((numerator, denominator)),
+            ..self
+        }
+    }
+
+    pub fn with_pixel_aspect_ratio(self, numerator: u32, denominator: u32) -> Self {
+        Self {
+            pixel_aspect_ratio: Some((numerator, denominator)),
+            ..self
+        }
+    }
+
+    pub fn with_interlacing(self, interlacing: Interlacing) -> Self {
+        Self {
+            interlacing,
+            ..self
+        }
+    }
+
+    pub fn with_color_range(self, color_range: ColorRange) -> Self {
+        Self {
+            color_range: Some(color_range),
+            ..self
+        }
+    }
+
+    // Takes the frame rate and color range from a sequence's metadata. y4m has nowhere to put the
+    // rest of it.
+    pub fn with_metadata(self, metadata: &SequenceMetadata) -> Self {
+        let (numerator, denominator) = metadata.frame_rate;
+        self.with_frame_rate(numerator, denominator)
+            .with_color_range(metadata.range)
+    }
+
+    // The number of bytes each frame's samples take up, not counting its FRAME line.
+    pub fn frame_size(&self) -> usize {
+        self.checked_frame_size()
+            .expect("the frame size overflows a usize")
+    }
+
+    // Like frame_size, but None if the dimensions are too large for it to be counted.
+    fn checked_frame_size(&self) -> Option<usize> {
+        let bytes_per_sample = if self.bit_depth > 8 { 2 } else { 1 };
+        (0..3).try_fold(0usize, |size, i| {
+            let (w, h) = self
+                .subsampling
+                .plane_dimensions(i, self.width, self.height);
+            w.checked_mul(h)?
+                .checked_mul(bytes_per_sample)?
+                .checked_add(size)
+        })
+    }
+
+    fn parse(line: &[u8]) -> Result<Self, Y4mError> {
+        let mut params = line.split(|&b| b == b' ');
+        if params.next() != Some(SIGNATURE) {
+            return Err(Y4mError::InvalidSignature);
+        }
+        let (mut width, mut height) = (None, None);
+        // streams without a colorspace parameter are 8-bit 4:2:0
+        let mut colorspace = (Subsampling::S420, 8);
+        let mut ret = Self::new(0, 0, Subsampling::S420, 8);
+        for param in params.filter(|p| !p.is_empty()) {
+            let value = std::str::from_utf8(&param[1..])
+                .map_err(|_| invalid_header("parameters must be ascii"))?;
+            match param[0] {
+                b'W' => width = Some(parse_number(value)?),
+                b'H' => height = Some(parse_number(value)?),
+                b'F' => ret.frame_rate = Some(parse_ratio(value)?),
+                b'A' => ret.pixel_aspect_ratio = Some(parse_ratio(value)?),
+                b'I' => {
+                    ret.interlacing = match value.as_bytes() {
+                        &[b] => Interlacing::from_u8(b),
+                        _ => None,
+                    }
+                    .ok_or_else(|| invalid_header(format!("unknown interlacing {}", value)))?
+                }
+                b'C' => colorspace = parse_colorspace(value)?,
+                b'X' => match value {
+                    "COLORRANGE=FULL" => ret.color_range = Some(ColorRange::Full),
+                    "COLORRANGE=LIMITED" => ret.color_range = Some(ColorRange::Limited),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        ret.subsampling = colorspace.0;
+        ret.bit_depth = colorspace.1;
+        match (width, height) {
+            (Some(w), Some(h)) if w > 0 && h > 0 => {
+                ret.width = w;
+                ret.height = h;
+            }
+            (Some(_), Some(_)) => return Err(invalid_header("dimensions must be nonzero")),
+            _ => return Err(invalid_header("missing width or height")),
+        }
+        if ret.checked_frame_size().is_none() {
+            return Err(invalid_header(format!(
+                "the dimensions {}x{} are too large",
+                ret.width, ret.height
+            )));
+        }
+        Ok(ret)
+    }
+
+    fn write<W: Write>(&self, mut dest: W) -> io::Result<()> {
+        write!(
+            dest,
+            "{} W{} H{}",
+            std::str::from_utf8(SIGNATURE).expect("the signature is ascii"),
+            self.width,
+            self.height
+        )?;
+        if let Some((n, d)) = self.frame_rate {
+            write!(dest, " F{}:{}", n, d)?;
+        }
+        write!(dest, " I{}", self.interlacing.to_u8() as char)?;
+        if let Some((n, d)) = self.pixel_aspect_ratio {
+            write!(dest, " A{}:{}", n, d)?;
+        }
+        let subsampling = match self.subsampling {
+            Subsampling::S444 => "444",
+            Subsampling::S422 => "422",
+            // 420jpeg is what tools assume when there's no C parameter at all
+            Subsampling::S420 if self.bit_depth == 8 => "420jpeg",
+            Subsampling::S420 => "420",
+        };
+        if self.bit_depth == 8 {
+            write!(dest, " C{}", subsampling)?;
+        } else {
+            write!(dest, " C{}p{}", subsampling, self.bit_depth)?;
+        }
+        match self.color_range {
+            Some(ColorRange::Full) => writeln!(dest, " XCOLORRANGE=FULL"),
+            Some(ColorRange::Limited) => writeln!(dest, " XCOLORRANGE=LIMITED"),
+            None => writeln!(dest),
+        }
+    }
+}
+
+fn parse_number(s: &str) -> Result<usize, Y4mError> {
+    s.parse()
+        .map_err(|_| invalid_header(format!("invalid number {}", s)))
+}
+
+fn parse_ratio(s: &str) -> Result<(u32, u32), Y4mError> {
+    let mut parts = s.splitn(2, ':').map(|p| p.parse::<u32>());
+    match (parts.next(), parts.next()) {
+        (Some(Ok(n)), Some(Ok(d))) => Ok((n, d)),
+        _ => Err(invalid_header(format!("invalid ratio {}", s))),
+    }
+}
+
+// Parses a C parameter like 420jpeg or 444p16 into its subsampling and bit depth. The 4:2:0
+// variants only differ in where the chroma samples are sited, which doesn't affect their layout.
+fn parse_colorspace(s: &str) -> Result<(Subsampling, u8), Y4mError> {
+    let (subsampling, depth) = match s.find('p') {
+        Some(i) if s[i + 1..].bytes().all(|b| b.is_ascii_digit()) && i + 1 < s.len() => {
+            (&s[..i], &s[i + 1..])
+        }
+        _ => (s, "8"),
+    };
+    let subsampling = match subsampling {
+        "420" | "420jpeg" | "420paldv" | "420mpeg2" => Subsampling::S420,
+        "422" => Subsampling::S422,
+        "444" => Subsampling::S444,
+        _ => return Err(Y4mError::Unsupported(format!("colorspace {}", s))),
+    };
+    match depth.parse() {
+        Ok(depth @ (8 | 10 | 12 | 16)) => Ok((subsampling, depth)),
+        _ => Err(Y4mError::Unsupported(format!("colorspace {}", s))),
+    }
+}
+
+// Reads a line, without its terminating newline. Returns None if source is already at its end.
+fn read_line<R: BufRead>(source: R, what: &str) -> Result<Option<Vec<u8>>, Y4mError> {
+    let mut line = Vec::new();
+    source
+        .take(MAX_LINE_LENGTH as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    match line.pop() {
+        None => Ok(None),
+        Some(b'\n') => Ok(Some(line)),
+        Some(_) if line.len() >= MAX_LINE_LENGTH => Err(Y4mError::InvalidFrameHeader(format!(
+            "the {} line is too long",
+            what
+        ))),
+        Some(_) => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("the stream ended in the {} line", what),
+        )
+        .into()),
+    }
+}
+
+// Reads the frames of a y4m stream. As an iterator, it stops after the last frame, or after
+// yielding the first error.
+pub struct Y4mReader<R> {
+    source: BufReader<R>,
+    header: Y4mHeader,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<R: Read> Y4mReader<R> {
+    // Reads the stream's header. The source is buffered internally.
+    pub fn new(source: R) -> Result<Self, Y4mError> {
+        let mut source = BufReader::new(source);
+        let line = match read_line(&mut source, "header") {
+            Ok(Some(line)) => line,
+            Ok(None) => return Err(Y4mError::InvalidSignature),
+            Err(Y4mError::InvalidFrameHeader(_)) => {
+                return Err(invalid_header("the header line is too long"))
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            header: Y4mHeader::parse(&line)?,
+            source,
+            buf: Vec::new(),
+            done: false,
+        })
+    }
+
+    pub fn header(&self) -> &Y4mHeader {
+        &self.header
+    }
+
+    // Reads the next frame, or returns None at the end of the stream. A stream that ends partway
+    // through a frame is an error.
+    pub fn read_frame(&mut self) -> Result<Option<SubsampledFrame>, Y4mError> {
+        let line = match read_line(&mut self.source, "FRAME")? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        // per-frame parameters may follow, but none of them affect the layout
+        if !line.starts_with(b"FRAME") || !matches!(line.get(5), None | Some(&b' ')) {
+            return Err(Y4mError::InvalidFrameHeader(
+                String::from_utf8_lossy(&line[..line.len().min(32)]).into_owned(),
+            ));
+        }
+
+        // the samples are read as they arrive rather than into a buffer of the size the header
+        // claims, which could be far more than the stream holds
+        let frame_size = self.header.frame_size();
+        self.buf.clear();
+        (&mut self.source)
+            .take(frame_size as u64)
+            .read_to_end(&mut self.buf)?;
+        if self.buf.len() != frame_size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the y4m stream ended partway through a frame",
+            )
+            .into());
+        }
+
+        let mut bytes = &self.buf[..];
+        let mut data: [Vec<u16>; 3] = Default::default();
+        for (i, plane) in data.iter_mut().enumerate() {
+            let (w, h) =
+                self.header
+                    .subsampling
+                    .plane_dimensions(i, self.header.width, self.header.height);
+            if self.header.bit_depth > 8 {
+                let (samples, rest) = bytes.split_at(w * h * 2);
+                *plane = samples
+                    .chunks_exact(2)
+                    .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                    .collect();
+                bytes = rest;
+            } else {
+                let (samples, rest) = bytes.split_at(w * h);
+                *plane = samples.iter().map(|&x| x as u16 * 257).collect();
+                bytes = rest;
+            }
+        }
+        Ok(Some(SubsampledFrame {
+            data,
+            width: self.header.width,
+            height: self.header.height,
+            subsampling: self.header.subsampling,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for Y4mReader<R> {
+    type Item = Result<SubsampledFrame, Y4mError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let ret = self.read_frame().transpose();
+        if !matches!(ret, Some(Ok(_))) {
+            self.done = true;
+        }
+        ret
+    }
+}
+
+// Writes frames to a y4m stream.
+pub struct Y4mWriter<W> {
+    dest: W,
+    header: Y4mHeader,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    // Writes the stream's header.
+    pub fn new(mut dest: W, header: Y4mHeader) -> Result<Self, Y4mError> {
+        if header.width == 0 || header.height == 0 {
+            return Err(invalid_header("dimensions must be nonzero"));
+        }
+        if ![8, 10, 12, 16].contains(&header.bit_depth) {
+            return Err(Y4mError::Unsupported(format!(
+                "{}-bit samples",
+                header.bit_depth
+            )));
+        }
+        header.write(&mut dest)?;
+        Ok(Self {
+            dest,
+            header,
+            buf: Vec::new(),
+        })
+    }
+
+    pub fn header(&self) -> &Y4mHeader {
+        &self.header
+    }
+
+    // Writes a frame, which must have the dimensions and subsampling given by the header. For
+    // 10- and 12-bit streams, samples must already be in range.
+    pub fn write_frame(&mut self, frame: &SubsampledFrame) -> Result<(), Y4mError> {
+        let header = &self.header;
+        if (frame.width, frame.height, frame.subsampling)
+            != (header.width, header.height, header.subsampling)
+        {
+            return Err(Y4mError::InvalidFrame(format!(
+                "expected a {}x{} {:?} frame, got a {}x{} {:?} frame",
+                header.width,
+                header.height,
+                header.subsampling,
+                frame.width,
+                frame.height,
+                frame.subsampling
+            )));
+        }
+        let max = ((1u32 << header.bit_depth) - 1) as u16;
+        self.buf.clear();
+        self.buf.extend_from_slice(b"FRAME\n");
+        for plane in frame.data.iter() {
+            if header.bit_depth > 8 {
+                if let Some(&x) = plane.iter().find(|&&x| x > max) {
+                    return Err(Y4mError::InvalidFrame(format!(
+                        "sample {} doesn't fit in {} bits",
+                        x, header.bit_depth
+                    )));
+                }
+                for x in plane {
+                    self.buf.extend_from_slice(&x.to_le_bytes());
+                }
+            } else {
+                self.buf
+                    .extend(plane.iter().map(|&x| ((x as u32 + 128) / 257) as u8));
+            }
+        }
+        self.dest.write_all(&self.buf)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Y4mError> {
+        self.dest.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.dest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::synth, *};
+
+    fn test_sequence(subsampling: Subsampling, bit_depth: u8) -> Vec<SubsampledFrame> {
+        let frame = synth::gradient(64, 32, synth::Direction::Diagonal);
+        (0..3)
+            .map(|i| {
+                let mut frame = frame
+                    .crop(i * 8, 4, 37, 23)
+                    .unwrap()
+                    .to_subsampled_ycbcr(subsampling);
+                for plane in frame.data.iter_mut() {
+                    for x in plane.iter_mut() {
+                        *x = match bit_depth {
+                            8 => *x / 257 * 257,
+                            16 => *x,
+                            _ => *x >> (16 - bit_depth),
+                        };
+                    }
+                }
+                frame
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_y4m_roundtrip() {
+        for &(subsampling, bit_depth) in [
+            (Subsampling::S420, 8),
+            (Subsampling::S422, 10),
+            (Subsampling::S444, 16),
+            (Subsampling::S420, 16),
+        ]
+        .iter()
+        {
+            let frames = test_sequence(subsampling, bit_depth);
+            let header = Y4mHeader::new(37, 23, subsampling, bit_depth)
+                .with_frame_rate(24000, 1001)
+                .with_pixel_aspect_ratio(1, 1)
+                .with_interlacing(Interlacing::TopFieldFirst);
+            let mut writer = Y4mWriter::new(Vec::new(), header.clone()).unwrap();
+            for frame in frames.iter() {
+                writer.write_frame(frame).unwrap();
+            }
+            let encoded = writer.into_inner();
+            assert_eq!(
+                encoded.len(),
+                encoded.iter().position(|&b| b == b'\n').unwrap()
+                    + 1
+                    + frames.len() * (6 + header.frame_size())
+            );
+
+            let reader = Y4mReader::new(&*encoded).unwrap();
+            assert_eq!(reader.header(), &header);
+            let decoded = reader.collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(decoded, frames, "{:?} {}", subsampling, bit_depth);
+        }
+    }
+
+    #[test]
+    fn test_y4m_metadata() {
+        let metadata = SequenceMetadata::new((60, 1)).with_range(ColorRange::Limited);
+        let header = Y4mHeader::new(37, 23, Subsampling::S444, 8).with_metadata(&metadata);
+        let mut writer = Y4mWriter::new(Vec::new(), header.clone()).unwrap();
+        for frame in test_sequence(Subsampling::S444, 8).iter() {
+            writer.write_frame(frame).unwrap();
+        }
+        let encoded = writer.into_inner();
+        assert!(encoded.starts_with(b"YUV4MPEG2 W37 H23 F60:1 Ip C444 XCOLORRANGE=LIMITED\n"));
+        let reader = Y4mReader::new(&*encoded).unwrap();
+        assert_eq!(reader.header(), &header);
+        assert_eq!(reader.header().color_range, Some(ColorRange::Limited));
+        assert_eq!(reader.count(), 3);
+
+        let full = Y4mReader::new(&b"YUV4MPEG2 W2 H2 XCOLORRANGE=FULL XOTHER=1\n"[..]).unwrap();
+        assert_eq!(full.header().color_range, Some(ColorRange::Full));
+    }
+
+    #[test]
+    fn test_y4m_fixtures() {
+        let mut reader =
+            Y4mReader::new(std::fs::File::open("src/testdata/tiny_420.y4m").unwrap()).unwrap();
+        assert_eq!(
+            reader.header(),
+            &Y4mHeader::new(4, 2, Subsampling::S420, 8)
+                .with_frame_rate(30000, 1001)
+                .with_pixel_aspect_ratio(1, 1)
+                .with_interlacing(Interlacing::TopFieldFirst)
+        );
+        let widen = |samples: &[u16]| samples.iter().map(|x| x * 257).collect::<Vec<_>>();
+        let frame = reader.next().unwrap().unwrap();
+        assert_eq!(frame.data[0], widen(&[0, 1, 2, 3, 128, 200, 254, 255]));
+        assert_eq!(frame.data[1], widen(&[64, 192]));
+        assert_eq!(frame.data[2], widen(&[10, 20]));
+        // the second frame has parameters on its FRAME line
+        let frame = reader.next().unwrap().unwrap();
+        assert_eq!(frame.data[0], widen(&[255, 254, 253, 252, 16, 17, 18, 19]));
+        assert_eq!(frame.data[1], widen(&[128, 128]));
+        assert_eq!(frame.data[2], widen(&[235, 240]));
+        assert!(reader.next().is_none());
+
+        let mut reader =
+            Y4mReader::new(std::fs::File::open("src/testdata/tiny_444p16.y4m").unwrap()).unwrap();
+        assert_eq!(
+            reader.header(),
+            &Y4mHeader::new(2, 1, Subsampling::S444, 16).with_frame_rate(25, 1)
+        );
+        let frame = reader.next().unwrap().unwrap();
+        assert_eq!(
+            frame.data,
+            [
+                vec![0x0102, 0xfffe],
+                vec![0x8000, 0x0001],
+                vec![0x1234, 0xabcd]
+            ]
+        );
+        assert!(reader.next().is_none());
+
+        // writing the frames back reproduces the fixtures byte for byte
+        for path in ["src/testdata/tiny_420.y4m", "src/testdata/tiny_444p16.y4m"].iter() {
+            let original = std::fs::read(path).unwrap();
+            let reader = Y4mReader::new(&*original).unwrap();
+            let mut writer = Y4mWriter::new(Vec::new(), reader.header().clone()).unwrap();
+            for frame in reader {
+                writer.write_frame(&frame.unwrap()).unwrap();
+            }
+            let written = writer.into_inner();
+            let mut expected =
+                original[original.iter().position(|&b| b == b'\n').unwrap()..].to_vec();
+            if let Some(i) = expected.windows(11).position(|w| w == b"FRAME Ixyz\n") {
+                expected.drain(i + 5..i + 10);
+            }
+            assert_eq!(
+                &written[written.iter().position(|&b| b == b'\n').unwrap()..],
+                &expected[..]
+            );
+        }
+    }
+
+    #[test]
+    fn test_y4m_errors() {
+        let original = std::fs::read("src/testdata/tiny_420.y4m").unwrap();
+        let header_len = original.iter().position(|&b| b == b'\n').unwrap() + 1;
+
+        // a stream that ends partway through a frame
+        let mut reader = Y4mReader::new(&original[..original.len() - 1]).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        match reader.next() {
+            Some(Err(Y4mError::IO(e))) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(reader.next().is_none());
+
+        // a header claiming enormous frames, with one FRAME line and nothing after it
+        let mut reader = Y4mReader::new(&b"YUV4MPEG2 W65535 H65535 C444p16\nFRAME\n"[..]).unwrap();
+        match reader.next() {
+            Some(Err(Y4mError::IO(e))) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // a stream that ends partway through a FRAME line
+        let mut reader = Y4mReader::new(&original[..header_len + 3]).unwrap();
+        assert!(matches!(reader.next(), Some(Err(Y4mError::IO(_)))));
+
+        // malformed FRAME lines
+        for bad in [&b"FRAMEX\n"[..], b"FRAM\n", b"frame\n"].iter() {
+            let mut stream = original[..header_len].to_vec();
+            stream.extend_from_slice(bad);
+            stream.extend_from_slice(&[0; 12]);
+            let mut reader = Y4mReader::new(&*stream).unwrap();
+            match reader.next() {
+                Some(Err(Y4mError::InvalidFrameHeader(_))) => {}
+                other => panic!("unexpected result: {:?}", other),
+            }
+            assert!(reader.next().is_none());
+        }
+
+        // bad headers
+        for (bad, expected) in [
+            (&b"YUV4MPEG W4 H2\n"[..], "not a y4m stream"),
+            (
+                b"YUV4MPEG2 W4\n",
+                "invalid y4m header: missing width or height",
+            ),
+            (
+                b"YUV4MPEG2 W4 H0\n",
+                "invalid y4m header: dimensions must be nonzero",
+            ),
+            (
+                b"YUV4MPEG2 W9999999999999 H9999999999999 C444p16\n",
+                "invalid y4m header: the dimensions 9999999999999x9999999999999 are too large",
+            ),
+            (
+                b"YUV4MPEG2 W4 H2 Ix\n",
+                "invalid y4m header: unknown interlacing x",
+            ),
+            (
+                b"YUV4MPEG2 W4 H2 F30\n",
+                "invalid y4m header: invalid ratio 30",
+            ),
+            (
+                b"YUV4MPEG2 W4 H2 Cmono\n",
+                "unsupported y4m stream: colorspace mono",
+            ),
+            (
+                b"YUV4MPEG2 W4 H2 C420p9\n",
+                "unsupported y4m stream: colorspace 420p9",
+            ),
+        ]
+        .iter()
+        {
+            let err = Y4mReader::new(&bad[..]).err().unwrap();
+            assert_eq!(err.to_string(), *expected);
+        }
+
+        // frames that don't match the header
+        let frames = test_sequence(Subsampling::S420, 8);
+        let mut writer =
+            Y4mWriter::new(Vec::new(), Y4mHeader::new(37, 23, Subsampling::S444, 8)).unwrap();
+        assert!(matches!(
+            writer.write_frame(&frames[0]),
+            Err(Y4mError::InvalidFrame(_))
+        ));
+        let mut writer =
+            Y4mWriter::new(Vec::new(), Y4mHeader::new(37, 23, Subsampling::S420, 10)).unwrap();
+        assert!(matches!(
+            writer.write_frame(&test_sequence(Subsampling::S420, 16)[0]),
+            Err(Y4mError::InvalidFrame(_))
+        ));
+    }
+}
@@ -0,0 +1,93 @@
+// Round trips through the crate-level conveniences, from image files to encoded ones and back.
+#![cfg(feature = "tiff")]
+
+use hello_video_codec::prelude::*;
+use std::path::PathBuf;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("hvc-api-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Encodes and decodes the image at input, saving it with the given extension, and checks that it
+// comes back exactly as it was.
+fn round_trip(name: &str, input: &str, extension: &str) {
+    let dir = temp_dir(name);
+    let encoded = dir.join("frame.hvc");
+    let decoded = dir.join(format!("frame.{}", extension));
+    let options = EncodeOptions::default();
+    let report = encode_file(input, &encoded, &options).unwrap();
+    decode_file(&encoded, &decoded, &DecodeOptions::default()).unwrap();
+
+    let original = Frame16::open(input).unwrap();
+    let frame = Frame16::open(&decoded).unwrap();
+    assert_eq!(
+        frame, original,
+        "{} changed going through {}",
+        input, extension
+    );
+    assert_eq!(report.input.as_deref(), Some(input));
+    assert_eq!(
+        (report.width, report.height, report.planes),
+        (frame.width, frame.height, frame.channels())
+    );
+    assert_eq!(
+        report.encoded_size as u64,
+        std::fs::metadata(&encoded).unwrap().len()
+    );
+    assert_eq!(report.options, options);
+
+    // the file holds just what encode_frame makes of the frame
+    let bytes = std::fs::read(&encoded).unwrap();
+    let original = RGB48Frame::open(input).unwrap();
+    assert_eq!(bytes, encode_frame(&original, &options).unwrap());
+    assert!(decode_frame(&bytes).unwrap() == original);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_tiff_round_trip() {
+    round_trip(
+        "tiff-rgb",
+        "src/testdata/tears_of_steel_12209_crop_planar.tif",
+        "tif",
+    );
+    round_trip("tiff-gray", "src/testdata/gray16.tif", "tiff");
+}
+
+#[cfg(feature = "png")]
+#[test]
+fn test_png_round_trip() {
+    round_trip("png-rgb", "src/testdata/gradient_rgb48.png", "png");
+    round_trip(
+        "png-crop",
+        "src/testdata/tears_of_steel_12209_crop.png",
+        "png",
+    );
+}
+
+#[test]
+fn test_errors() {
+    let dir = temp_dir("errors");
+    let options = EncodeOptions::default();
+    assert!(matches!(
+        encode_file("src/testdata/missing.tif", dir.join("frame.hvc"), &options),
+        Err(Error::Open(_))
+    ));
+
+    let encoded = dir.join("frame.hvc");
+    encode_file("src/testdata/gray16.tif", &encoded, &options).unwrap();
+    let err = decode_file(&encoded, dir.join("frame.bmp"), &DecodeOptions::default()).unwrap_err();
+    assert!(matches!(err, Error::Save(_)));
+    assert!(err
+        .to_string()
+        .starts_with("can't tell what format to save"));
+
+    let bytes = std::fs::read(&encoded).unwrap();
+    assert!(matches!(
+        decode_frame(&bytes[..bytes.len() / 2]),
+        Err(CodecError::TruncatedStream { .. })
+    ));
+    std::fs::remove_dir_all(dir).unwrap();
+}
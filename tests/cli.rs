@@ -0,0 +1,572 @@
+// The hvc binary is only built with the tiff feature.
+#![cfg(feature = "tiff")]
+
+use hello_video_codec::{
+    bench::BenchResult,
+    codec::Codec,
+    container::{VideoReader, VideoWriter},
+    frame::{EncodeOptions, RGB48Frame},
+    report::{read_records, Report},
+    synth,
+};
+use std::{
+    fs::File,
+    io::{BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+};
+
+fn hvc(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_hvc"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("hvc-cli-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn path_str(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn test_cli_roundtrip() {
+    let dir = temp_dir("roundtrip");
+    for input in [
+        "src/testdata/tears_of_steel_12130_crop_rgb24.tif",
+        "src/testdata/tears_of_steel_12209_crop_planar.tif",
+        "src/testdata/gray16.tif",
+    ]
+    .iter()
+    {
+        let encoded = dir.join("frame.hvc");
+        let decoded = dir.join("frame.tif");
+        let output = hvc(&["encode", input, path_str(&encoded)]);
+        assert!(output.status.success(), "{:?}", output);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(
+            stdout.contains(" bytes -> ") && stdout.contains(":1)"),
+            "{}",
+            stdout
+        );
+
+        let output = hvc(&["decode", "--quiet", path_str(&encoded), path_str(&decoded)]);
+        assert!(output.status.success(), "{:?}", output);
+        assert!(output.stdout.is_empty());
+
+        let original = RGB48Frame::open(input).unwrap();
+        let roundtripped = RGB48Frame::open(&decoded).unwrap();
+        assert_eq!(
+            (roundtripped.width, roundtripped.height),
+            (original.width, original.height)
+        );
+        assert!(roundtripped.data == original.data, "{} changed", input);
+
+        let output = hvc(&["info", path_str(&encoded)]);
+        assert!(output.status.success(), "{:?}", output);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let dimensions = format!("dimensions:      {}x{}\n", original.width, original.height);
+        assert!(stdout.contains(&dimensions), "{}", stdout);
+        assert!(
+            stdout.contains("quantization:    none (lossless)\n"),
+            "{}",
+            stdout
+        );
+    }
+
+    // --stats adds timing
+    let encoded = dir.join("stats.hvc");
+    let output = hvc(&[
+        "encode",
+        "--stats",
+        "src/testdata/gray16.tif",
+        path_str(&encoded),
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("bits per sample"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_headerless() {
+    let dir = temp_dir("headerless");
+    let frame = RGB48Frame::open("src/testdata/tears_of_steel_12130_crop_rgb24.tif").unwrap();
+    let mut encoded = Vec::new();
+    frame
+        .encode::<hello_video_codec::codec::Codec, _>(&mut encoded)
+        .unwrap();
+    let input = dir.join("legacy.bin");
+    std::fs::write(&input, &encoded).unwrap();
+    let decoded = dir.join("legacy.tif");
+    let (width, height) = (frame.width.to_string(), frame.height.to_string());
+    let output = hvc(&[
+        "decode",
+        "--width",
+        &width,
+        "--height",
+        &height,
+        path_str(&input),
+        path_str(&decoded),
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(RGB48Frame::open(&decoded).unwrap().data == frame.data);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_fixed_k() {
+    let dir = temp_dir("fixed-k");
+    let input = "src/testdata/tears_of_steel_12130_crop_rgb24.tif";
+    let original = RGB48Frame::open(input).unwrap();
+    let encoded = dir.join("frame.hvc");
+    let decoded = dir.join("frame.tif");
+    for (k, expected) in [
+        ("9", "k:               fixed [9, 9, 9]\n"),
+        ("auto", "k:               fixed ["),
+        ("adaptive", "k:               adaptive\n"),
+    ] {
+        let output = hvc(&["encode", "--quiet", "--k", k, input, path_str(&encoded)]);
+        assert!(output.status.success(), "{:?}", output);
+        let output = hvc(&["info", path_str(&encoded)]);
+        assert!(output.status.success(), "{:?}", output);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains(expected), "--k {}: {}", k, stdout);
+
+        let output = hvc(&["decode", "--quiet", path_str(&encoded), path_str(&decoded)]);
+        assert!(output.status.success(), "{:?}", output);
+        assert!(
+            RGB48Frame::open(&decoded).unwrap().data == original.data,
+            "--k {}",
+            k
+        );
+    }
+
+    for k in ["17", "-1", "fast"] {
+        let output = hvc(&["encode", "--k", k, input, path_str(&encoded)]);
+        assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_errors() {
+    let dir = temp_dir("errors");
+    let garbage = dir.join("garbage.hvc");
+    std::fs::write(&garbage, b"not a frame").unwrap();
+    for args in [
+        &["decode", path_str(&garbage), path_str(&dir.join("out.tif"))][..],
+        &["info", path_str(&garbage)],
+        &["encode", "src/testdata/missing.tif", path_str(&garbage)],
+    ]
+    .iter()
+    {
+        let output = hvc(args);
+        assert_eq!(output.status.code(), Some(1), "{:?}", output);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("hvc: "), "{}", stderr);
+    }
+    for args in [&[][..], &["transcode"], &["encode", "--fast", "a", "b"]].iter() {
+        let output = hvc(args);
+        assert_eq!(output.status.code(), Some(2), "{:?}", output);
+        assert!(String::from_utf8(output.stderr).unwrap().contains("usage:"));
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_bench() {
+    let dir = temp_dir("bench");
+    let report = dir.join("bench.jsonl");
+    let output = hvc(&[
+        "bench",
+        "--codecs",
+        "rice,store",
+        "--q",
+        "1,8",
+        "--report",
+        path_str(&report),
+        "src/testdata/tears_of_steel_12130_crop_rgb24.tif",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 5, "{}", stdout);
+    assert!(lines[1].starts_with("rice") && lines[1].ends_with("lossless"));
+    assert!(lines[2].contains("PSNR"), "{}", lines[2]);
+    assert!(
+        lines[4].contains("doesn't support quantization"),
+        "{}",
+        lines[4]
+    );
+
+    // a record for each codec and step that worked
+    let results = read_records::<BenchResult, _>(BufReader::new(File::open(&report).unwrap()))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let codecs: Vec<_> = results.iter().map(|result| result.codec.as_str()).collect();
+    assert_eq!(codecs, ["rice", "rice", "store"]);
+    assert!(results[0].lossless && !results[1].lossless && results[2].lossless);
+    assert_eq!(results[1].options.quantization, Some(vec![8; 3]));
+
+    let output = hvc(&["bench", "--codecs", "rice,rans", "frame.tif"]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_sequence() {
+    let dir = temp_dir("sequence");
+    let frames_dir = dir.join("frames");
+    std::fs::create_dir_all(&frames_dir).unwrap();
+    // frame 3 is missing
+    let numbers = [1, 2, 4];
+    let frames: Vec<_> = numbers
+        .iter()
+        .map(|&n| synth::noise(24, 16, n, 16))
+        .collect();
+    for (frame, &n) in frames.iter().zip(numbers.iter()) {
+        frame
+            .to_tiff(frames_dir.join(format!("{:04}.tif", n)))
+            .unwrap();
+    }
+
+    let container = dir.join("frames.hvcv");
+    let report = dir.join("report.jsonl");
+    let output = hvc(&[
+        "encode-seq",
+        "--fps",
+        "24000/1001",
+        "--jobs",
+        "2",
+        "--report",
+        path_str(&report),
+        path_str(&frames_dir.join("%04d.tif")),
+        path_str(&container),
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("frame 3/3"), "{}", stdout);
+    assert!(stdout.contains("3 frames, 1 to 4"), "{}", stdout);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("frames 3 to 3 are missing"), "{}", stderr);
+
+    // a record for each frame, in order
+    let records = read_records::<Report, _>(BufReader::new(File::open(&report).unwrap()))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(records.len(), 3);
+    for (i, (record, frame)) in records.iter().zip(frames.iter()).enumerate() {
+        let mut encoded = Vec::new();
+        frame
+            .encode_with_header::<Codec, _>(&mut encoded, &EncodeOptions::default())
+            .unwrap();
+        assert_eq!(record.frame, i);
+        assert!(record
+            .input
+            .as_ref()
+            .unwrap()
+            .ends_with(&format!("{:04}.tif", numbers[i])));
+        assert_eq!((record.width, record.height, record.planes), (24, 16, 3));
+        assert_eq!(record.encoded_size, encoded.len());
+    }
+
+    let output = hvc(&["verify", path_str(&container)]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "3 frames: 3 passed, 0 failed\n");
+
+    // damage the last frame, just before the index of 3 offsets that follows it
+    let mut damaged = std::fs::read(&container).unwrap();
+    let at = damaged.len() - (4 + 8 + 3 * 8) - 10;
+    damaged[at] ^= 0x01;
+    let damaged_path = dir.join("damaged.hvcv");
+    std::fs::write(&damaged_path, damaged).unwrap();
+    let output = hvc(&["verify", path_str(&damaged_path)]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("frame 2: "), "{}", stdout);
+    assert!(
+        stdout.ends_with("3 frames: 2 passed, 1 failed\n"),
+        "{}",
+        stdout
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("1 of 3 frames failed"), "{}", stderr);
+
+    let decoded_dir = dir.join("decoded");
+    let output = hvc(&[
+        "decode-seq",
+        "--quiet",
+        path_str(&container),
+        path_str(&decoded_dir),
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    for (i, frame) in frames.iter().enumerate() {
+        let decoded = RGB48Frame::open(decoded_dir.join(format!("{:06}.tif", i))).unwrap();
+        assert!(decoded.data == frame.data, "frame {} changed", i);
+    }
+    assert!(!decoded_dir.join("000003.tif").exists());
+
+    // only frames 2 through 4, which still straddle the gap, then only the last of them decoded
+    let output = hvc(&[
+        "encode-seq",
+        "--quiet",
+        "--start",
+        "2",
+        "--end",
+        "9",
+        path_str(&frames_dir),
+        path_str(&container),
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("frames 3 to 3 are missing"));
+    let output = hvc(&[
+        "decode-seq",
+        "--quiet",
+        "--start",
+        "1",
+        path_str(&container),
+        path_str(&dir.join("partial")),
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let decoded = RGB48Frame::open(dir.join("partial").join("000001.tif")).unwrap();
+    assert!(decoded.data == frames[2].data);
+    assert!(!dir.join("partial").join("000000.tif").exists());
+    assert!(!dir.join("partial").join("000002.tif").exists());
+
+    // outside of the sequence
+    let output = hvc(&[
+        "encode-seq",
+        "--start",
+        "5",
+        path_str(&frames_dir),
+        path_str(&container),
+    ]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Writes frames into a container at path.
+fn write_container(path: &Path, frames: &[RGB48Frame]) {
+    let frame = &frames[0];
+    let dest = File::create(path).unwrap();
+    let mut writer =
+        VideoWriter::<Codec, _>::new(dest, frame.width, frame.height, 3, (24, 1)).unwrap();
+    for frame in frames {
+        writer.write_frame(frame).unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+#[test]
+fn test_cli_splice() {
+    let dir = temp_dir("splice");
+    let frames: Vec<_> = (0..8).map(|i| synth::noise(24, 16, i, 12)).collect();
+    let (a, b) = (dir.join("a.hvcv"), dir.join("b.hvcv"));
+    write_container(&a, &frames[..5]);
+    write_container(&b, &frames[5..]);
+
+    let spliced = dir.join("spliced.hvcv");
+    let output = hvc(&[
+        "splice",
+        &format!("{}:1-3", path_str(&a)),
+        path_str(&b),
+        &format!("{}:0-0", path_str(&a)),
+        path_str(&spliced),
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "7 frames from 3 inputs\n"
+    );
+    let mut reader = VideoReader::<Codec, _>::open(File::open(&spliced).unwrap()).unwrap();
+    let decoded = reader.frames().collect::<Result<Vec<_>, _>>().unwrap();
+    assert!(decoded == [&frames[1..4], &frames[5..], &frames[..1]].concat());
+    let output = hvc(&["verify", "--quiet", path_str(&spliced)]);
+    assert!(output.status.success(), "{:?}", output);
+
+    // inputs that don't fit together, or frames that aren't there, leave no output behind
+    let other = dir.join("other.hvcv");
+    write_container(&other, &[synth::noise(16, 16, 0, 12)]);
+    let failed = dir.join("failed.hvcv");
+    for input in [
+        path_str(&other).to_string(),
+        format!("{}:3-5", path_str(&a)),
+    ]
+    .iter()
+    {
+        let output = hvc(&["splice", path_str(&a), input, path_str(&failed)]);
+        assert_eq!(output.status.code(), Some(1), "{:?}", output);
+        assert!(!failed.exists());
+    }
+    let output = hvc(&[
+        "splice",
+        &format!("{}:3-1", path_str(&a)),
+        path_str(&failed),
+    ]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("invalid frames"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Runs hvc with input on its standard input, returning what it wrote to standard output.
+fn hvc_piped(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_hvc"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    // written from another thread, so that neither side can block the other
+    let writer = std::thread::spawn(move || {
+        // hvc may stop reading early, so errors here are expected
+        let _ = stdin.write_all(&input);
+    });
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap();
+    output
+}
+
+#[test]
+fn test_cli_pipe() {
+    let dir = temp_dir("pipe");
+    let frames: Vec<_> = (0..3).map(|seed| synth::noise(24, 16, seed, 16)).collect();
+    let raw: Vec<u8> = frames
+        .iter()
+        .flat_map(|frame| frame.data.iter().flat_map(|x| x.to_le_bytes().to_vec()))
+        .collect();
+
+    let container = dir.join("piped.hvcv");
+    let encode = |extra: &[&str], input: &[u8]| {
+        let mut args = vec!["encode-pipe", "--width", "24", "--height", "16"];
+        args.extend_from_slice(extra);
+        args.push(path_str(&container));
+        hvc_piped(&args, input)
+    };
+    let output = encode(&["--fps", "30"], &raw);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("3 frames: 6912 bytes -> "), "{}", stdout);
+
+    let output = hvc_piped(&["decode-pipe", path_str(&container)], &[]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stdout == raw);
+    let output = hvc_piped(
+        &[
+            "decode-pipe",
+            "--pix-fmt",
+            "rgb48be",
+            "--start",
+            "1",
+            "--end",
+            "1",
+            path_str(&container),
+        ],
+        &[],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let be: Vec<u8> = frames[1]
+        .data
+        .iter()
+        .flat_map(|x| x.to_be_bytes().to_vec())
+        .collect();
+    assert!(output.stdout == be);
+
+    // big-endian gray input
+    let gray = synth::noise_gray(24, 16, 7, 16);
+    let gray_raw: Vec<u8> = gray
+        .data
+        .iter()
+        .flat_map(|x| x.to_be_bytes().to_vec())
+        .collect();
+    let output = encode(&["--quiet", "--pix-fmt", "gray16be"], &gray_raw);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stdout.is_empty());
+    let output = hvc_piped(&["decode-pipe", path_str(&container)], &[]);
+    let gray_le: Vec<u8> = gray
+        .data
+        .iter()
+        .flat_map(|x| x.to_le_bytes().to_vec())
+        .collect();
+    assert!(output.stdout == gray_le);
+    let output = hvc_piped(
+        &["decode-pipe", "--pix-fmt", "rgb48le", path_str(&container)],
+        &[],
+    );
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+
+    // a partial final frame
+    let partial = &raw[..raw.len() - 100];
+    let output = encode(&["--quiet"], partial);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("failed to read frame 2"), "{}", stderr);
+    let output = encode(&["--quiet", "--discard-partial"], partial);
+    assert!(output.status.success(), "{:?}", output);
+    let output = hvc_piped(&["decode-pipe", path_str(&container)], &[]);
+    assert!(output.stdout[..] == raw[..2 * 24 * 16 * 6]);
+
+    // nothing to encode
+    let output = encode(&[], &[]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let output = hvc_piped(&["encode-pipe", "--width", "24", path_str(&container)], &[]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_pipe_closed_early() {
+    let dir = temp_dir("pipe-closed");
+    // frames larger than a pipe's buffer, so that hvc is still writing when the reader goes away
+    let frames: Vec<_> = (0..6)
+        .map(|seed| synth::noise(128, 128, seed, 16))
+        .collect();
+    let raw: Vec<u8> = frames
+        .iter()
+        .flat_map(|frame| frame.data.iter().flat_map(|x| x.to_le_bytes().to_vec()))
+        .collect();
+    let container = dir.join("large.hvcv");
+    let output = hvc_piped(
+        &[
+            "encode-pipe",
+            "--quiet",
+            "--width",
+            "128",
+            "--height",
+            "128",
+            path_str(&container),
+        ],
+        &raw,
+    );
+    assert!(output.status.success(), "{:?}", output);
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_hvc"))
+        .args(["decode-pipe", path_str(&container)])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let mut first = vec![0; 128 * 128 * 6];
+    stdout.read_exact(&mut first).unwrap();
+    assert!(first[..] == raw[..first.len()]);
+    drop(stdout);
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stderr.is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,57 @@
+use hello_video_codec::{
+    codec::Codec,
+    dither::DitherMode,
+    error::CodecError,
+    frame::{DecodeOptions, Limits, RGB48Frame},
+    header::FrameHeader,
+};
+use js_sys::Uint8ClampedArray;
+use wasm_bindgen::prelude::*;
+
+// JavaScript bindings for previewing encoded frames in a browser. Build them with
+// `wasm-pack build --target web` from this directory, and see examples/viewer.html for a page that
+// uses them.
+
+// The largest frame that will be decoded, in bytes of 16-bit samples. A 4096x2160 RGBA frame needs
+// about 71 MB, and the RGBA8 copy handed to JavaScript half as much again, which leaves plenty of
+// room below wasm32's 4 GB address space.
+const MAX_DECODED_BYTES: usize = 256 << 20;
+
+fn to_js(error: CodecError) -> JsValue {
+    JsValue::from_str(&error.to_string())
+}
+
+// What frame_info found in a frame's header.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct FrameInfo {
+    pub width: usize,
+    pub height: usize,
+}
+
+// Reads the header of a frame written with a header (see RGB48Frame::encode_with_header).
+#[wasm_bindgen]
+pub fn frame_info(encoded: &[u8]) -> Result<FrameInfo, JsValue> {
+    let header = FrameHeader::read(encoded).map_err(to_js)?;
+    Ok(FrameInfo {
+        width: header.width,
+        height: header.height,
+    })
+}
+
+// Decodes a frame written with a header to 8-bit RGBA, rounding each sample to the nearest 8-bit
+// value, ready to be wrapped in an ImageData and drawn with putImageData. Gray frames come out gray
+// and frames without alpha opaque. Frames larger than MAX_DECODED_BYTES are rejected before
+// anything is allocated for them.
+#[wasm_bindgen]
+pub fn decode_to_rgba8(encoded: &[u8]) -> Result<Uint8ClampedArray, JsValue> {
+    let options = DecodeOptions {
+        limits: Limits {
+            max_decoded_bytes: MAX_DECODED_BYTES,
+        },
+    };
+    let frame =
+        RGB48Frame::decode_with_header_and_options::<Codec, _>(encoded, &options).map_err(to_js)?;
+    let rgba = frame.to_rgba8(DitherMode::None);
+    Ok(Uint8ClampedArray::from(&rgba[..]))
+}
@@ -0,0 +1,29 @@
+use hello_video_codec_wasm::{decode_to_rgba8, frame_info};
+use wasm_bindgen_test::*;
+
+// Run with `wasm-pack test --headless --firefox` (or --chrome) from the wasm directory.
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+// A 16x16 gray gradient whose samples are 4000 * x + 100 * y (see the codec's golden vectors).
+const GRADIENT: &[u8] = include_bytes!("../../src/testdata/golden/gradient.bin");
+
+#[wasm_bindgen_test]
+fn test_frame_info() {
+    let info = frame_info(GRADIENT).unwrap();
+    assert_eq!((info.width, info.height), (16, 16));
+    assert!(frame_info(b"not a frame").is_err());
+}
+
+#[wasm_bindgen_test]
+fn test_decode_to_rgba8() {
+    let rgba = decode_to_rgba8(GRADIENT).unwrap().to_vec();
+    assert_eq!(rgba.len(), 16 * 16 * 4);
+    let pixel = |x: usize, y: usize| &rgba[(y * 16 + x) * 4..][..4];
+    assert_eq!(pixel(0, 0), [0, 0, 0, 255]);
+    assert_eq!(pixel(1, 0), [16, 16, 16, 255]);
+    assert_eq!(pixel(7, 3), [110, 110, 110, 255]);
+    assert_eq!(pixel(15, 15), [239, 239, 239, 255]);
+
+    assert!(decode_to_rgba8(&GRADIENT[..GRADIENT.len() - 1]).is_err());
+}